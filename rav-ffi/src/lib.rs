@@ -1,6 +1,6 @@
-use std::{ffi::CStr, os::raw::{c_char, c_void}, ptr::null_mut};
+use std::{ffi::CStr, os::raw::{c_char, c_void}, ptr::null};
 
-use rav::{data::Packet, format::FormatContext};
+use rav::{data::Packet2, format::FormatContext};
 
 /// # Safety
 /// This function should receive a pointer to c string.
@@ -11,24 +11,111 @@ pub unsafe extern "C" fn rav_open_input(format_ctx: *mut *mut c_void, path: *mut
     }
 
     let path = CStr::from_ptr(path).to_string_lossy().into_owned();
-    let format = FormatContext::open_input(&path);
-    *format_ctx = Box::into_raw(Box::new(format)) as *mut c_void;
-    0
+    match FormatContext::open_input(&path) {
+        Ok(format) => {
+            *format_ctx = Box::into_raw(Box::new(format)) as *mut c_void;
+            0
+        }
+        Err(_) => -2,
+    }
+}
+
+
+/// Packet fields read by `rav_packet_info`, the packet layout itself is not part of the C API.
+#[repr(C)]
+pub struct RavPacketInfo {
+    /// Packet data, valid until the next `rav_read_packet` or `rav_packet_free` on the packet.
+    pub data: *const u8,
+    pub size: usize,
+    pub stream_id: usize,
+    pub pts: i64,
+    pub dts: i64,
+    pub duration: i64,
+    pub keyframe: bool,
+    pub pos: i64,
+}
+
+/// Allocates a packet for `rav_read_packet`, to free with `rav_packet_free`.
+#[no_mangle]
+pub extern "C" fn rav_packet_alloc() -> *mut c_void {
+    Box::into_raw(Box::<Packet2>::default()) as *mut c_void
 }
 
+/// # Safety
+/// This function should receive a pointer returned by `rav_packet_alloc` or null.
+#[no_mangle]
+pub unsafe extern "C" fn rav_packet_free(packet: *mut c_void) {
+    if !packet.is_null() {
+        drop(Box::from_raw(packet as *mut Packet2));
+    }
+}
 
 /// # Safety
-/// This function should receive a pointer to FormatContext and pointer to Packet.
+/// This function should receive a pointer returned by `rav_packet_alloc` and a pointer to RavPacketInfo.
+#[no_mangle]
+pub unsafe extern "C" fn rav_packet_info(packet: *const c_void, info: *mut RavPacketInfo) -> i32 {
+    if packet.is_null() || info.is_null() {
+        return -1;
+    }
+
+    let packet = &*(packet as *const Packet2);
+    let data = packet.data();
+    *info = RavPacketInfo {
+        data: if data.is_empty() { null() } else { data.as_ptr() },
+        size: data.len(),
+        stream_id: packet.stream_id,
+        pts: packet.pts,
+        dts: packet.dts,
+        duration: packet.duration,
+        keyframe: packet.keyframe,
+        pos: packet.pos,
+    };
+    0
+}
+
+/// # Safety
+/// This function should receive a pointer to FormatContext and a pointer returned by `rav_packet_alloc`.
 #[no_mangle]
 pub unsafe extern "C" fn rav_read_packet(format_ctx: *mut c_void, packet: *mut c_void) -> i32 {
     if format_ctx.is_null() || packet.is_null() {
         return -1;
     }
 
-    let format = Box::leak(Box::from_raw(format_ctx as *mut Box<FormatContext>));
-    let packet = Box::leak(Box::from_raw(format_ctx as *mut Box<Packet>));
+    let format = &mut *(format_ctx as *mut FormatContext);
+    let packet = &mut *(packet as *mut Packet2);
     match format.read_packet(packet) {
         Ok(()) => 0,
         Err(_) => -2,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CString, mem::MaybeUninit, ptr::null_mut};
+
+    use super::*;
+
+    #[test]
+    fn read_packet_info() {
+        let path = std::env::temp_dir().join(format!("rav-ffi-test-{}.wav", std::process::id()));
+        let format = [&[1, 0, 1, 0][..], &8000u32.to_le_bytes(), &16000u32.to_le_bytes(), &[2, 0, 16, 0]].concat();
+        let data = [&b"RIFF\x2C\0\0\0WAVEfmt \x10\0\0\0"[..], &format, b"data\x04\0\0\0", &[1, 0, 2, 0]].concat();
+        std::fs::write(&path, data).unwrap();
+        let path_c = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let mut format_ctx = null_mut();
+            assert_eq!(rav_open_input(&mut format_ctx, path_c.as_ptr() as *mut c_char), 0);
+            let packet = rav_packet_alloc();
+            assert_eq!(rav_read_packet(format_ctx, packet), 0);
+            let mut info = MaybeUninit::<RavPacketInfo>::uninit();
+            assert_eq!(rav_packet_info(packet, info.as_mut_ptr()), 0);
+            let info = info.assume_init();
+            assert_eq!((info.stream_id, info.pts, info.keyframe), (0, 0, true));
+            assert_eq!(std::slice::from_raw_parts(info.data, info.size), &[1, 0, 2, 0]);
+            rav_packet_free(packet);
+            drop(Box::from_raw(format_ctx as *mut FormatContext));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+/// Frequency of the program clock.
+pub const PCR_FREQUENCY: u64 = 27_000_000;
+
+/// The PCR wraps around with its 33-bit 90 kHz base.
+const PCR_WRAP: u64 = (1 << 33) * 300;
+
+/// A PCR jump larger than this is treated as a discontinuity, PCRs must be sent every 100 ms.
+const PCR_MAX_GAP: u64 = 10 * PCR_FREQUENCY;
+
+/// 27 MHz program clock recovered from the PCRs of a program.
+///
+/// Positions are the byte positions of the TS packets carrying the PCRs, the clock at other
+/// positions is interpolated assuming a constant rate between PCRs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgramClock {
+    /// Last PCR and its position.
+    last: Option<(u64, u64)>,
+    /// PCR preceding `last`, within the same continuous clock.
+    prev: Option<(u64, u64)>,
+}
+
+impl ProgramClock {
+    /// Last received PCR in 27 MHz units.
+    pub fn pcr(&self) -> Option<u64> {
+        self.last.map(|(pcr, _)| pcr)
+    }
+
+    /// Transport rate in bits per second, measured between the last two PCRs.
+    pub fn bitrate(&self) -> Option<u64> {
+        let ((pcr, pos), (prev_pcr, prev_pos)) = (self.last?, self.prev?);
+        let elapsed = (pcr + PCR_WRAP - prev_pcr) % PCR_WRAP;
+        if elapsed == 0 {
+            return None;
+        }
+        Some(((pos - prev_pos) as u128 * 8 * PCR_FREQUENCY as u128 / elapsed as u128) as u64)
+    }
+
+    /// Estimates the clock value when the byte at `pos` arrived.
+    pub fn pcr_at(&self, pos: u64) -> Option<u64> {
+        let (pcr, last_pos) = self.last?;
+        if pos == last_pos {
+            return Some(pcr);
+        }
+        let (prev_pcr, prev_pos) = self.prev?;
+        let elapsed = ((pcr + PCR_WRAP - prev_pcr) % PCR_WRAP) as i128;
+        let distance = (last_pos - prev_pos) as i128;
+        let offset = (pos as i128 - last_pos as i128) * elapsed / distance;
+        Some((pcr as i128 + offset).rem_euclid(PCR_WRAP as i128) as u64)
+    }
+
+    /// Adds a PCR, returns true if the clock is discontinuous with the previous PCR.
+    pub(crate) fn update(&mut self, pcr: u64, pos: u64, discontinuity_indicator: bool) -> bool {
+        let discontinuous = match self.last {
+            Some((last, last_pos)) => {
+                let elapsed = (pcr + PCR_WRAP - last) % PCR_WRAP;
+                discontinuity_indicator || pos <= last_pos || elapsed == 0 || elapsed > PCR_MAX_GAP
+            }
+            None => false,
+        };
+        self.prev = if discontinuous { None } else { self.last };
+        self.last = Some((pcr, pos));
+        discontinuous
+    }
+}
+
+/// Converts a 90 kHz timestamp to 27 MHz units, to compare it against a program clock.
+pub fn pts_to_pcr(ts: i64) -> u64 {
+    (ts as u64 & ((1 << 33) - 1)) * 300
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolation() {
+        let mut clock = ProgramClock::default();
+        assert!(!clock.update(PCR_WRAP - 1000, 0, false));
+        assert_eq!(clock.pcr_at(0), Some(PCR_WRAP - 1000));
+        assert_eq!(clock.pcr_at(188), None);
+
+        // 188 * 10 bytes in 2000 ticks, across the wrap
+        assert!(!clock.update(1000, 1880, false));
+        assert_eq!(clock.pcr_at(940), Some(0));
+        assert_eq!(clock.pcr_at(2820), Some(2000));
+        assert_eq!(clock.bitrate(), Some(1880 * 8 * PCR_FREQUENCY / 2000));
+
+        assert!(clock.update(5_000_000, 3760, true));
+        assert_eq!(clock.pcr_at(3760), Some(5_000_000));
+        assert_eq!(clock.pcr_at(4000), None);
+        assert!(clock.update(0, 5640, false));
+    }
+}
@@ -0,0 +1,77 @@
+/// Result of the continuity counter check of a TS packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CcCheck {
+    Ok,
+    /// The packet repeats the previous one and must be dropped.
+    Duplicate,
+    /// Packets were lost before this one.
+    Discontinuity { expected: u8 },
+}
+
+/// Tracks the continuity_counter of a PID.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ContinuityCounter {
+    last: Option<u8>,
+    /// The last packet was a duplicate, only one duplicate is allowed.
+    duplicate: bool,
+}
+
+impl ContinuityCounter {
+    pub(crate) fn check(&mut self, cc: u8, has_payload: bool, discontinuity_indicator: bool) -> CcCheck {
+        let Some(last) = self.last.replace(cc) else {
+            return CcCheck::Ok;
+        };
+        if discontinuity_indicator {
+            self.duplicate = false;
+            return CcCheck::Ok;
+        }
+        // the counter doesn't increment for packets without payload
+        if !has_payload {
+            return if cc == last { CcCheck::Ok } else { CcCheck::Discontinuity { expected: last } };
+        }
+        if cc == last {
+            if self.duplicate {
+                return CcCheck::Discontinuity { expected: (last + 1) & 0x0F };
+            }
+            self.duplicate = true;
+            return CcCheck::Duplicate;
+        }
+        self.duplicate = false;
+        let expected = (last + 1) & 0x0F;
+        if cc == expected { CcCheck::Ok } else { CcCheck::Discontinuity { expected } }
+    }
+}
+
+/// Packet counters of a PID.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PidStats {
+    pub packets: u64,
+    /// Continuity counter jumps, i.e. lost packets.
+    pub continuity_errors: u64,
+    /// Duplicate packets, dropped by the demuxer.
+    pub duplicates: u64,
+    /// Packets with the discontinuity_indicator set.
+    pub discontinuities: u64,
+    pub transport_errors: u64,
+    pub scrambled: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_checks() {
+        let mut cc = ContinuityCounter::default();
+        assert_eq!(cc.check(14, true, false), CcCheck::Ok);
+        assert_eq!(cc.check(15, true, false), CcCheck::Ok);
+        assert_eq!(cc.check(0, true, false), CcCheck::Ok);
+        assert_eq!(cc.check(0, true, false), CcCheck::Duplicate);
+        assert_eq!(cc.check(0, true, false), CcCheck::Discontinuity { expected: 1 });
+        assert_eq!(cc.check(1, true, false), CcCheck::Ok);
+        assert_eq!(cc.check(1, false, false), CcCheck::Ok);
+        assert_eq!(cc.check(3, true, false), CcCheck::Discontinuity { expected: 2 });
+        assert_eq!(cc.check(9, true, true), CcCheck::Ok);
+        assert_eq!(cc.check(10, true, false), CcCheck::Ok);
+    }
+}
@@ -0,0 +1,1220 @@
+use std::collections::{HashMap, VecDeque};
+
+use log::warn;
+use rav::codec::{CodecId, MediaType};
+use rav::data::{IoRef, Packet2, NOPTS_VALUE};
+use rav::error::{decode_error, Error, Result};
+use rav::format::{Demux, InputFormat, MediaIoBufRead, Stream, TimeBase};
+
+use crate::clock::ProgramClock;
+use crate::continuity::{CcCheck, ContinuityCounter, PidStats};
+use crate::packet::{
+    detect_packet_size, parse_arrival_time, parse_header, prefix_len, probe, TsHeader, PID_NULL, PID_PAT,
+    RS_PACKET_SIZE, SYNC_BYTE, TS_PACKET_SIZE,
+};
+use crate::pes::parse_pes_header;
+use crate::psi::{
+    crc32, parse_long_section, parse_pat, parse_pmt, EsInfo, PatEntry, SectionAssembler, TABLE_ID_PAT, TABLE_ID_PMT,
+};
+use crate::scte35::parse_splice_info;
+use crate::si::{
+    ServiceInformation, SiTable, PID_EIT, PID_NIT, PID_SDT, PID_TDT, TABLE_ID_EIT_SCHEDULE_LAST, TABLE_ID_NIT_ACTUAL,
+    TABLE_ID_TDT, TABLE_ID_TOT,
+};
+
+/// Maximum number of TS packets read by `DemuxerTs::new` while looking for PAT and PMTs.
+const MAX_PROBE_PACKETS: usize = 100_000;
+
+/// Time base of PTS/DTS and of the packets timestamps.
+const TS_TIME_BASE: TimeBase = TimeBase::new(1, 90_000);
+
+/// Number of packets of the largest size peeked to detect the packet size.
+const PACKET_SIZE_PROBE_PACKETS: usize = 8;
+
+/// Packets queued by `DemuxerTs::new` while waiting for the PMTs, beyond this the input is rejected.
+const MAX_PENDING_PACKETS: usize = 4096;
+
+/// Events kept until polled, the oldest ones are dropped beyond this.
+const MAX_EVENTS: usize = 1024;
+
+/// Transport stream events reported for monitoring.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TsEvent {
+    /// The continuity counter jumped, packets of the PID were lost.
+    ContinuityError { pid: u16, pos: u64, expected: u8, found: u8 },
+    /// The discontinuity_indicator was set in the adaptation field.
+    Discontinuity { pid: u16, pos: u64 },
+    /// The program clock restarted, signalled or detected from a PCR jump.
+    PcrDiscontinuity { program_number: u16, pos: u64, pcr: u64 },
+    /// A DVB service information table was received or changed.
+    SiUpdated(SiTable),
+}
+
+/// A program announced by the PAT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub number: u16,
+    pub pmt_pid: u16,
+    pub pcr_pid: u16,
+    /// PMT version, `None` until the PMT is received.
+    pub pmt_version: Option<u8>,
+    /// Elementary streams described by the PMT.
+    pub es_infos: Vec<EsInfo>,
+    /// Indexes of the demuxer streams carried by the program.
+    pub stream_ids: Vec<usize>,
+    /// Clock recovered from the PCRs on `pcr_pid`.
+    pub clock: ProgramClock,
+    /// PTS of the last audio or video packet of the program, `NOPTS_VALUE` if none.
+    pub last_pts: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PidKind {
+    Pat,
+    Pmt,
+    /// DVB service information tables.
+    Si,
+    /// SCTE-35 splice_info_sections.
+    Scte35 { stream_id: usize },
+    Pes { stream_id: usize },
+}
+
+/// Reassembles a PES packet from TS packet payloads. A PES carried by a single TS packet
+/// is referenced without copying, longer ones are copied once into a contiguous buffer.
+#[derive(Debug, Default)]
+struct PesAssembler {
+    first: Option<IoRef>,
+    buf: Vec<u8>,
+    len: usize,
+    random_access: bool,
+    /// Position of the TS packet starting the PES.
+    pos: u64,
+    /// M2TS arrival timestamp of the TS packet starting the PES.
+    arrival_time: Option<u32>,
+}
+
+impl PesAssembler {
+    fn start(&mut self, random_access: bool, pos: u64, arrival_time: Option<u32>) {
+        self.first = None;
+        self.buf.clear();
+        self.len = 0;
+        self.random_access = random_access;
+        self.pos = pos;
+        self.arrival_time = arrival_time;
+    }
+
+    fn push(&mut self, chunk: IoRef) {
+        self.len += chunk.len();
+        match self.first.take() {
+            None if self.buf.is_empty() => self.first = Some(chunk),
+            first => {
+                if let Some(first) = first {
+                    self.buf.extend_from_slice(first.data());
+                }
+                self.buf.extend_from_slice(chunk.data());
+            }
+        }
+    }
+
+    /// Returns the assembled data, if any, and resets the assembler.
+    fn take(&mut self) -> Option<IoRef> {
+        self.len = 0;
+        match self.first.take() {
+            Some(first) => Some(first),
+            None if !self.buf.is_empty() => Some(IoRef::from(std::mem::take(&mut self.buf))),
+            None => None,
+        }
+    }
+
+    /// Returns the PES header bytes received so far, enough to read PES_packet_length.
+    fn head(&self) -> &[u8] {
+        match &self.first {
+            Some(first) => first.data(),
+            None => &self.buf,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PidState {
+    kind: PidKind,
+    sections: SectionAssembler,
+    pes: PesAssembler,
+}
+
+impl PidState {
+    fn new(kind: PidKind) -> Self {
+        Self { kind, sections: SectionAssembler::default(), pes: PesAssembler::default() }
+    }
+}
+
+/// Transport stream format to pass to `rav::format::register_input_format` or `FormatOptions::with_format`.
+pub fn input_format() -> InputFormat {
+    InputFormat {
+        name: "ts",
+        extensions: &["ts", "m2ts", "mts", "trp"],
+        probe,
+        open: |stream| Ok(Box::new(DemuxerTs::new(stream)?)),
+    }
+}
+
+/// MPEG transport stream demuxer, supports 188-byte packets as well as 192-byte M2TS and
+/// 204-byte packets with Reed-Solomon parity.
+pub struct DemuxerTs<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    packet_size: usize,
+    /// Arrival timestamp of the current TS packet, for M2TS.
+    current_arrival_time: Option<u32>,
+    /// Arrival timestamp of the last returned packet.
+    packet_arrival_time: Option<u32>,
+    streams: Vec<Stream>,
+    programs: Vec<Program>,
+    pids: HashMap<u16, PidState>,
+    continuity: HashMap<u16, (ContinuityCounter, PidStats)>,
+    pat_version: Option<u8>,
+    si: ServiceInformation,
+    pending: VecDeque<(Packet2, Option<u32>)>,
+    events: VecDeque<TsEvent>,
+    eof: bool,
+}
+
+impl<S: MediaIoBufRead> DemuxerTs<S> {
+    /// Creates the demuxer, reading the stream until the PAT and all PMTs are received.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            packet_size: TS_PACKET_SIZE,
+            current_arrival_time: None,
+            packet_arrival_time: None,
+            streams: Vec::new(),
+            programs: Vec::new(),
+            pids: HashMap::from([
+                (PID_PAT, PidState::new(PidKind::Pat)),
+                (PID_NIT, PidState::new(PidKind::Si)),
+                (PID_SDT, PidState::new(PidKind::Si)),
+                (PID_EIT, PidState::new(PidKind::Si)),
+                (PID_TDT, PidState::new(PidKind::Si)),
+            ]),
+            continuity: HashMap::new(),
+            pat_version: None,
+            si: ServiceInformation::default(),
+            pending: VecDeque::new(),
+            events: VecDeque::new(),
+            eof: false,
+        };
+
+        demuxer.detect_packet_size()?;
+        for _ in 0..MAX_PROBE_PACKETS {
+            if demuxer.pat_version.is_some() && demuxer.programs.iter().all(|program| program.pmt_version.is_some()) {
+                break;
+            }
+            match demuxer.read_ts_packet() {
+                Ok(()) => {}
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            }
+            if demuxer.pending.len() > MAX_PENDING_PACKETS {
+                return decode_error("ts: too many packets before the PMTs");
+            }
+        }
+
+        if demuxer.streams.is_empty() {
+            return decode_error("ts: no streams found in PAT/PMT");
+        }
+        Ok(demuxer)
+    }
+
+    /// Size of the packets in the input: 188, 192 or 204 bytes.
+    pub fn packet_size(&self) -> usize {
+        self.packet_size
+    }
+
+    /// M2TS arrival timestamp, in 27 MHz units, of the first TS packet of the last returned packet.
+    pub fn arrival_time(&self) -> Option<u32> {
+        self.packet_arrival_time
+    }
+
+    /// Programs announced by the PAT.
+    pub fn programs(&self) -> &[Program] {
+        &self.programs
+    }
+
+    /// DVB service information received so far, `TsEvent::SiUpdated` notifies the changes.
+    pub fn service_information(&self) -> &ServiceInformation {
+        &self.si
+    }
+
+    /// Packet counters of a PID, `None` if no packet was received on it.
+    pub fn pid_stats(&self, pid: u16) -> Option<&PidStats> {
+        self.continuity.get(&pid).map(|(_, stats)| stats)
+    }
+
+    /// Returns the oldest pending event.
+    pub fn poll_event(&mut self) -> Option<TsEvent> {
+        self.events.pop_front()
+    }
+
+    /// Estimates the program clock, in 27 MHz units, when the packet arrived.
+    pub fn packet_pcr(&self, packet: &Packet2) -> Option<u64> {
+        let program = self.programs.iter().find(|program| program.stream_ids.contains(&packet.stream_id))?;
+        program.clock.pcr_at(u64::try_from(packet.pos).ok()?)
+    }
+
+    fn push_event(&mut self, event: TsEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Detects the packet size and skips the data preceding the first packet.
+    fn detect_packet_size(&mut self) -> Result<()> {
+        let mut probe = vec![0u8; PACKET_SIZE_PROBE_PACKETS * RS_PACKET_SIZE];
+        while probe.len() >= 2 * RS_PACKET_SIZE {
+            match self.iobuf_reader.peek_bytes(&mut probe) {
+                Ok(()) => break,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => probe.truncate(probe.len() / 2),
+                Err(err) => return Err(err),
+            }
+        }
+        if probe.len() < 2 * RS_PACKET_SIZE {
+            // too short to check the periodicity, resync handles 188-byte packets
+            return Ok(());
+        }
+        if let Some((packet_size, start)) = detect_packet_size(&probe) {
+            self.packet_size = packet_size;
+            self.iobuf_reader.skip(start)?;
+        }
+        Ok(())
+    }
+
+    /// Finds the next sync byte, confirmed by the sync byte of the following packet when available.
+    /// Returns immediately when the stream is aligned on a packet.
+    fn sync(&mut self) -> Result<()> {
+        let prefix = prefix_len(self.packet_size);
+        let mut probe = [0u8; RS_PACKET_SIZE + 5];
+        let probe = &mut probe[..prefix + self.packet_size + 1];
+        let mut skipped = 0;
+        loop {
+            match self.iobuf_reader.peek_bytes(probe) {
+                Ok(()) if probe[prefix] == SYNC_BYTE && probe[prefix + self.packet_size] == SYNC_BYTE => break,
+                Ok(()) => {}
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => {
+                    // last packet of the stream
+                    self.iobuf_reader.peek_bytes(&mut probe[..prefix + 1])?;
+                    if probe[prefix] == SYNC_BYTE {
+                        break;
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+            self.iobuf_reader.skip(1)?;
+            skipped += 1;
+        }
+        if skipped > 0 {
+            warn!("ts: skipped {} bytes to resync", skipped);
+        }
+        Ok(())
+    }
+
+    /// Reads and handles the next TS packet.
+    fn read_ts_packet(&mut self) -> Result<()> {
+        self.sync()?;
+
+        let pos = self.iobuf_reader.pos() as u64;
+        let mut ts_packet = IoRef::default();
+        self.iobuf_reader.get_ioref(&mut ts_packet, self.packet_size)?;
+        let prefix = prefix_len(self.packet_size);
+        if prefix > 0 {
+            self.current_arrival_time = Some(parse_arrival_time(ts_packet.data()));
+            ts_packet = ts_packet.slice(prefix, TS_PACKET_SIZE);
+        }
+        let header = parse_header(ts_packet.data())?;
+        if header.pid == PID_NULL {
+            return Ok(());
+        }
+        if !self.check_continuity(&header, pos) {
+            return Ok(());
+        }
+        if let Some(field) = header.adaptation_field {
+            if let Some(pcr) = field.pcr {
+                self.update_clocks(header.pid, pcr, pos, field.discontinuity);
+            }
+        }
+        if header.scrambled || !header.has_payload() {
+            return Ok(());
+        }
+        let Some(kind) = self.pids.get(&header.pid).map(|state| state.kind) else {
+            return Ok(());
+        };
+
+        let payload = ts_packet.slice(header.payload_offset, TS_PACKET_SIZE - header.payload_offset);
+        match kind {
+            PidKind::Pat | PidKind::Pmt | PidKind::Si => {
+                let Some(state) = self.pids.get_mut(&header.pid) else {
+                    return Ok(());
+                };
+                let sections = state.sections.push(payload.data(), header.unit_start);
+                for section in sections {
+                    self.handle_section(header.pid, &section);
+                }
+            }
+            PidKind::Scte35 { stream_id } => {
+                let Some(state) = self.pids.get_mut(&header.pid) else {
+                    return Ok(());
+                };
+                let sections = state.sections.push(payload.data(), header.unit_start);
+                for section in sections {
+                    self.handle_splice_section(stream_id, section, pos);
+                }
+            }
+            PidKind::Pes { stream_id } => self.handle_pes_payload(&header, stream_id, payload, pos),
+        }
+        Ok(())
+    }
+
+    /// Updates the PID counters, returns false if the packet must be dropped.
+    fn check_continuity(&mut self, header: &TsHeader, pos: u64) -> bool {
+        let (counter, stats) = self.continuity.entry(header.pid).or_default();
+        stats.packets += 1;
+        if header.transport_error {
+            stats.transport_errors += 1;
+            return false;
+        }
+        if header.scrambled {
+            stats.scrambled += 1;
+        }
+        let discontinuity = header.adaptation_field.is_some_and(|field| field.discontinuity);
+        if discontinuity {
+            stats.discontinuities += 1;
+        }
+
+        match counter.check(header.continuity_counter, header.has_payload(), discontinuity) {
+            CcCheck::Ok => {}
+            CcCheck::Duplicate => {
+                stats.duplicates += 1;
+                return false;
+            }
+            CcCheck::Discontinuity { expected } => {
+                stats.continuity_errors += 1;
+                warn!("ts: continuity error on pid {}, expected {} got {}", header.pid, expected, header.continuity_counter);
+                self.push_event(TsEvent::ContinuityError {
+                    pid: header.pid,
+                    pos,
+                    expected,
+                    found: header.continuity_counter,
+                });
+                // the data being reassembled is incomplete
+                if let Some(state) = self.pids.get_mut(&header.pid) {
+                    state.sections.reset();
+                    state.pes.start(false, pos, None);
+                }
+            }
+        }
+        if discontinuity {
+            self.push_event(TsEvent::Discontinuity { pid: header.pid, pos });
+        }
+        true
+    }
+
+    fn update_clocks(&mut self, pid: u16, pcr: u64, pos: u64, discontinuity: bool) {
+        let mut events = Vec::new();
+        for program in self.programs.iter_mut().filter(|program| program.pcr_pid == pid) {
+            if program.clock.update(pcr, pos, discontinuity) {
+                events.push(TsEvent::PcrDiscontinuity { program_number: program.number, pos, pcr });
+            }
+        }
+        for event in events {
+            self.push_event(event);
+        }
+    }
+
+    fn handle_section(&mut self, pid: u16, section: &[u8]) {
+        // TDT and TOT use the short section syntax, only the TOT has a CRC
+        match section[0] {
+            TABLE_ID_TDT | TABLE_ID_TOT => {
+                if section[0] == TABLE_ID_TOT && crc32(section) != 0 {
+                    warn!("ts: invalid TOT on pid {}", pid);
+                } else if let Some(table) = self.si.handle_time_section(section) {
+                    self.push_event(TsEvent::SiUpdated(table));
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        let Some((header, data)) = parse_long_section(section) else {
+            warn!("ts: invalid section on pid {}", pid);
+            return;
+        };
+        if !header.current_next {
+            return;
+        }
+
+        match header.table_id {
+            TABLE_ID_PAT if pid == PID_PAT => {
+                if self.pat_version == Some(header.version) && header.section_number == 0 {
+                    return;
+                }
+                // a PAT in several sections only lists part of the programs in each
+                let complete = header.section_number == 0 && header.last_section_number == 0;
+                self.handle_pat(header.version, parse_pat(data), complete);
+            }
+            TABLE_ID_PMT => {
+                let Some(program_idx) = self.programs.iter().position(|program| {
+                    program.pmt_pid == pid && program.number == header.id_extension
+                }) else {
+                    return;
+                };
+                if self.programs[program_idx].pmt_version == Some(header.version) {
+                    return;
+                }
+                let Some(pmt) = parse_pmt(data) else {
+                    warn!("ts: invalid PMT on pid {}", pid);
+                    return;
+                };
+                self.handle_pmt(program_idx, header.version, pmt.pcr_pid, pmt.streams);
+            }
+            TABLE_ID_NIT_ACTUAL..=TABLE_ID_EIT_SCHEDULE_LAST => {
+                if let Some(table) = self.si.handle_section(&header, data) {
+                    self.push_event(TsEvent::SiUpdated(table));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Adds the programs of the PAT, a `complete` PAT also removes the programs it no longer lists.
+    fn handle_pat(&mut self, version: u8, entries: Vec<PatEntry>, complete: bool) {
+        self.pat_version = Some(version);
+        if complete {
+            let listed = |program: &Program| {
+                entries.iter().any(|entry| entry.program_number == program.number && entry.pid == program.pmt_pid)
+            };
+            let (kept, removed): (Vec<Program>, Vec<Program>) = std::mem::take(&mut self.programs)
+                .into_iter()
+                .partition(listed);
+            self.programs = kept;
+            for program in removed {
+                self.remove_streams(&program.stream_ids);
+                if !self.programs.iter().any(|other| other.pmt_pid == program.pmt_pid) {
+                    self.pids.remove(&program.pmt_pid);
+                }
+            }
+        }
+
+        for entry in entries {
+            // program 0 points to the NIT
+            if entry.program_number == 0 {
+                self.pids.entry(entry.pid).or_insert_with(|| PidState::new(PidKind::Si));
+                continue;
+            }
+            if self.programs.iter().any(|program| program.number == entry.program_number) {
+                continue;
+            }
+            self.programs.push(Program {
+                number: entry.program_number,
+                pmt_pid: entry.pid,
+                pcr_pid: PID_NULL,
+                pmt_version: None,
+                es_infos: Vec::new(),
+                stream_ids: Vec::new(),
+                clock: ProgramClock::default(),
+                last_pts: NOPTS_VALUE,
+            });
+            self.pids.entry(entry.pid).or_insert_with(|| PidState::new(PidKind::Pmt));
+        }
+    }
+
+    /// Stops demuxing the PIDs of streams `stream_ids`, the streams stay listed without packets.
+    fn remove_streams(&mut self, stream_ids: &[usize]) {
+        self.pids.retain(|_, state| match state.kind {
+            PidKind::Pes { stream_id } | PidKind::Scte35 { stream_id } => !stream_ids.contains(&stream_id),
+            _ => true,
+        });
+    }
+
+    fn handle_pmt(&mut self, program_idx: usize, version: u8, pcr_pid: u16, es_infos: Vec<EsInfo>) {
+        // streams of a previous version whose PID is gone or carries another codec are replaced
+        let stream_ids = std::mem::take(&mut self.programs[program_idx].stream_ids);
+        let (kept, removed): (Vec<usize>, Vec<usize>) = stream_ids.into_iter().partition(|&stream_id| {
+            let pid = self.pids.iter().find_map(|(&pid, state)| match state.kind {
+                PidKind::Pes { stream_id: id } | PidKind::Scte35 { stream_id: id } if id == stream_id => Some(pid),
+                _ => None,
+            });
+            let codec_id = self.streams[stream_id].codec_id;
+            pid.is_some_and(|pid| es_infos.iter().any(|es| es.pid == pid && es.codec_id == codec_id))
+        });
+        self.remove_streams(&removed);
+        self.programs[program_idx].stream_ids = kept;
+
+        for es in es_infos.iter() {
+            if self.pids.contains_key(&es.pid) || es.codec_id == CodecId::Unknown {
+                continue;
+            }
+            let stream_id = self.streams.len();
+            self.streams.push(Stream {
+                id: stream_id,
+                codec_id: es.codec_id,
+                media_type: es.codec_id.media_type(),
+                time_base: TS_TIME_BASE,
+                language: es.language.clone(),
+                ..Default::default()
+            });
+            let kind = match es.codec_id {
+                CodecId::Scte35 => PidKind::Scte35 { stream_id },
+                _ => PidKind::Pes { stream_id },
+            };
+            self.pids.insert(es.pid, PidState::new(kind));
+            self.programs[program_idx].stream_ids.push(stream_id);
+        }
+
+        let program = &mut self.programs[program_idx];
+        program.pmt_version = Some(version);
+        program.pcr_pid = pcr_pid;
+        program.es_infos = es_infos;
+    }
+
+    fn handle_pes_payload(&mut self, header: &TsHeader, stream_id: usize, payload: IoRef, pos: u64) {
+        let Some(state) = self.pids.get_mut(&header.pid) else {
+            return;
+        };
+        let mut finished = Vec::new();
+        if header.unit_start {
+            finished.extend(Self::finish_pes(&mut state.pes, stream_id, &self.streams));
+            let random_access = header.adaptation_field.is_some_and(|field| field.random_access);
+            state.pes.start(random_access, pos, self.current_arrival_time);
+        } else if state.pes.len == 0 {
+            // PES start not seen yet
+            return;
+        }
+        state.pes.push(payload);
+
+        // bounded PES packets are complete once PES_packet_length bytes are received
+        let head = state.pes.head();
+        if head.len() >= 6 {
+            let packet_length = u16::from_be_bytes([head[4], head[5]]) as usize;
+            if packet_length > 0 && state.pes.len >= 6 + packet_length {
+                finished.extend(Self::finish_pes(&mut state.pes, stream_id, &self.streams));
+            }
+        }
+        for (packet, arrival_time) in finished {
+            self.queue_packet(packet, arrival_time);
+        }
+    }
+
+    /// Queues a demuxed packet, the program PTS follows the audio and video packets.
+    fn queue_packet(&mut self, packet: Packet2, arrival_time: Option<u32>) {
+        let media_type = self.streams[packet.stream_id].media_type;
+        if packet.pts != NOPTS_VALUE && matches!(media_type, MediaType::Video | MediaType::Audio) {
+            for program in self.programs.iter_mut().filter(|program| program.stream_ids.contains(&packet.stream_id)) {
+                program.last_pts = packet.pts;
+            }
+        }
+        self.pending.push_back((packet, arrival_time));
+    }
+
+    /// Emits a SCTE-35 cue, timestamped with its splice time or else the program PTS.
+    fn handle_splice_section(&mut self, stream_id: usize, section: Vec<u8>, pos: u64) {
+        let info = match parse_splice_info(&section) {
+            Ok(info) => info,
+            Err(err) => {
+                warn!("ts: dropping splice_info_section on stream {}: {:?}", stream_id, err);
+                return;
+            }
+        };
+        let program_pts = self.programs.iter()
+            .find(|program| program.stream_ids.contains(&stream_id))
+            .map_or(NOPTS_VALUE, |program| program.last_pts);
+        let packet = Packet2 {
+            ioref: IoRef::from(section),
+            stream_id,
+            pts: info.splice_pts().map_or(program_pts, |pts| pts as i64),
+            dts: program_pts,
+            keyframe: true,
+            pos: pos as i64,
+            ..Default::default()
+        };
+        self.queue_packet(packet, self.current_arrival_time);
+    }
+
+    /// Turns the assembled PES into a packet.
+    fn finish_pes(pes: &mut PesAssembler, stream_id: usize, streams: &[Stream]) -> Option<(Packet2, Option<u32>)> {
+        let random_access = pes.random_access;
+        let arrival_time = pes.arrival_time;
+        let pos = pes.pos as i64;
+        let data = pes.take()?;
+        let Some(pes_header) = parse_pes_header(data.data()) else {
+            warn!("ts: invalid PES header on stream {}", stream_id);
+            return None;
+        };
+        let mut end = data.len();
+        if pes_header.packet_length > 0 {
+            end = end.min(6 + pes_header.packet_length);
+        }
+        let len = end.saturating_sub(pes_header.header_len);
+        if len == 0 {
+            return None;
+        }
+
+        let dts = if pes_header.dts != NOPTS_VALUE { pes_header.dts } else { pes_header.pts };
+        let packet = Packet2 {
+            ioref: data.slice(pes_header.header_len, len),
+            stream_id,
+            pts: pes_header.pts,
+            dts,
+            duration: 0,
+            keyframe: random_access || streams[stream_id].media_type != MediaType::Video,
+            pos,
+        };
+        Some((packet, arrival_time))
+    }
+
+    /// Emits the PES packets still being assembled at the end of the stream.
+    fn flush(&mut self) {
+        let mut pids: Vec<(u16, usize)> = self.pids.iter()
+            .filter_map(|(&pid, state)| match state.kind {
+                PidKind::Pes { stream_id } => Some((pid, stream_id)),
+                _ => None,
+            })
+            .collect();
+        pids.sort_unstable();
+        for (pid, stream_id) in pids {
+            let Some(state) = self.pids.get_mut(&pid) else {
+                continue;
+            };
+            if let Some((packet, arrival_time)) = Self::finish_pes(&mut state.pes, stream_id, &self.streams) {
+                self.queue_packet(packet, arrival_time);
+            }
+        }
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerTs<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        loop {
+            if let Some((next, arrival_time)) = self.pending.pop_front() {
+                *packet = next;
+                self.packet_arrival_time = arrival_time;
+                return Ok(());
+            }
+            if self.eof {
+                return rav::error::end_of_stream_error();
+            }
+            match self.read_ts_packet() {
+                Ok(()) => {}
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => {
+                    self.eof = true;
+                    self.flush();
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::psi::crc32;
+    use crate::scte35::SpliceCommand;
+    use rav::format::{
+        register_input_format, DemuxerSegmented, FormatContext, FormatOptions, IoBufSupplierHls, IoBufSupplierMem,
+        IoBufSupply, MediaSourceStream, ProbeScore, PROBE_SCORE_MAX,
+    };
+
+    fn ts_packet(pid: u16, unit_start: bool, cc: u8, random_access: bool, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![SYNC_BYTE, (unit_start as u8) << 6 | (pid >> 8) as u8, pid as u8, 0x10 | cc & 0x0F];
+        if payload.len() < TS_PACKET_SIZE - 4 || random_access {
+            let stuffing = TS_PACKET_SIZE - 6 - payload.len();
+            packet[3] |= 0x20;
+            packet.push(1 + stuffing as u8);
+            packet.push(if random_access { 0x40 } else { 0x00 });
+            packet.extend(std::iter::repeat_n(0xFF, stuffing));
+        }
+        packet.extend_from_slice(payload);
+        assert_eq!(packet.len(), TS_PACKET_SIZE);
+        packet
+    }
+
+    fn section(table_id: u8, id_extension: u16, version: u8, body: &[u8]) -> Vec<u8> {
+        let len = 5 + body.len() + 4;
+        let mut section = vec![table_id, 0xB0 | (len >> 8) as u8, len as u8];
+        section.extend_from_slice(&id_extension.to_be_bytes());
+        section.extend_from_slice(&[0xC1 | version << 1, 0, 0]);
+        section.extend_from_slice(body);
+        section.extend_from_slice(&crc32(&section).to_be_bytes());
+        section
+    }
+
+    /// Splits a payload starting a unit over as many TS packets as needed.
+    fn packetize(pid: u16, payload: &[u8], random_access: bool, cc: &mut u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut rest = payload;
+        let mut first = true;
+        while !rest.is_empty() {
+            let max = if first && random_access { TS_PACKET_SIZE - 6 } else { TS_PACKET_SIZE - 4 };
+            let (chunk, tail) = rest.split_at(rest.len().min(max));
+            out.extend(ts_packet(pid, first, *cc, first && random_access, chunk));
+            *cc = (*cc + 1) & 0x0F;
+            rest = tail;
+            first = false;
+        }
+        out
+    }
+
+    fn psi(pid: u16, section: &[u8], cc: &mut u8) -> Vec<u8> {
+        let mut payload = vec![0];
+        payload.extend_from_slice(section);
+        packetize(pid, &payload, false, cc)
+    }
+
+    fn pat(programs: &[(u16, u16)]) -> Vec<u8> {
+        let body: Vec<u8> = programs.iter()
+            .flat_map(|&(number, pid)| [number.to_be_bytes(), (0xE000 | pid).to_be_bytes()].concat())
+            .collect();
+        section(TABLE_ID_PAT, 1, 0, &body)
+    }
+
+    fn pmt(program: u16, pcr_pid: u16, streams: &[(u8, u16, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(0xE000 | pcr_pid).to_be_bytes());
+        body.extend_from_slice(&0xF000u16.to_be_bytes());
+        for &(stream_type, pid, descriptors) in streams {
+            body.push(stream_type);
+            body.extend_from_slice(&(0xE000 | pid).to_be_bytes());
+            body.extend_from_slice(&(0xF000 | descriptors.len() as u16).to_be_bytes());
+            body.extend_from_slice(descriptors);
+        }
+        section(TABLE_ID_PMT, program, 0, &body)
+    }
+
+    fn timestamp(prefix: u8, ts: i64) -> [u8; 5] {
+        [
+            prefix << 4 | ((ts >> 29) & 0x0E) as u8 | 1,
+            (ts >> 22) as u8,
+            ((ts >> 14) & 0xFE) as u8 | 1,
+            (ts >> 7) as u8,
+            ((ts << 1) & 0xFE) as u8 | 1,
+        ]
+    }
+
+    fn pes(stream_id: u8, pts: i64, dts: Option<i64>, bounded: bool, payload: &[u8]) -> Vec<u8> {
+        let mut header = vec![0x80, 0x80, 5];
+        header.extend(timestamp(if dts.is_some() { 3 } else { 2 }, pts));
+        if let Some(dts) = dts {
+            header[1] = 0xC0;
+            header[2] = 10;
+            header.extend(timestamp(1, dts));
+        }
+        let len = if bounded { header.len() + payload.len() } else { 0 };
+        let mut pes = vec![0, 0, 1, stream_id];
+        pes.extend_from_slice(&(len as u16).to_be_bytes());
+        pes.extend(header);
+        pes.extend_from_slice(payload);
+        pes
+    }
+
+    type TestDemuxer = DemuxerTs<MediaSourceStream<IoBufSupplierMem>>;
+
+    fn open(data: Vec<u8>) -> Result<TestDemuxer> {
+        DemuxerTs::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 1000)))
+    }
+
+    fn read_all(demuxer: &mut TestDemuxer) -> Vec<(usize, i64, i64, bool, Vec<u8>)> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.stream_id, packet.pts, packet.dts, packet.keyframe, packet.data().to_vec()));
+        }
+        packets
+    }
+
+    #[test]
+    fn pat_pmt_and_pes() {
+        let video: Vec<u8> = (0..400u32).map(|i| i as u8).collect();
+        let audio = vec![0xAA; 100];
+        let lang: &[u8] = &[0x0A, 4, b'e', b'n', b'g', 0];
+        let (mut cc_pat, mut cc_pmt, mut cc_video, mut cc_audio) = (0, 0, 0, 0);
+
+        let mut data = psi(PID_PAT, &pat(&[(0, 0x10), (1, 0x100)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x101, &[(0x1B, 0x101, &[]), (0x0F, 0x102, lang)]), &mut cc_pmt));
+        data.extend(packetize(0x101, &pes(0xE0, 9000, Some(6000), false, &video), true, &mut cc_video));
+        data.extend(packetize(0x102, &pes(0xC0, 8000, None, true, &audio), false, &mut cc_audio));
+        data.extend(packetize(0x101, &pes(0xE0, 12000, None, false, &video[..10]), false, &mut cc_video));
+
+        let mut demuxer = open(data).unwrap();
+        let streams = demuxer.streams();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].codec_id, CodecId::H264);
+        assert_eq!(streams[0].time_base, TimeBase::new(1, 90_000));
+        assert_eq!(streams[1].codec_id, CodecId::Aac);
+        assert_eq!(streams[1].media_type, MediaType::Audio);
+        assert_eq!(streams[1].language.as_deref(), Some("eng"));
+
+        let program = &demuxer.programs()[0];
+        assert_eq!((program.number, program.pmt_pid, program.pcr_pid), (1, 0x100, 0x101));
+        assert_eq!(program.stream_ids, vec![0, 1]);
+        assert_eq!(program.es_infos[1].pid, 0x102);
+
+        let packets = read_all(&mut demuxer);
+        assert_eq!(packets, vec![
+            (1, 8000, 8000, true, audio),
+            (0, 9000, 6000, true, video.clone()),
+            (0, 12000, 12000, false, video[..10].to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn pmt_split_across_packets_and_resync() {
+        // a long descriptor loop makes the PMT span two TS packets
+        let mut descriptors = vec![0x05, 4, b'A', b'C', b'-', b'3'];
+        descriptors.extend([0x80, 200]);
+        descriptors.extend([0u8; 200]);
+        let (mut cc_pat, mut cc_pmt, mut cc_audio) = (0, 0, 0);
+
+        let mut data = vec![0x00, SYNC_BYTE, 0x12, 0x34, 0x56];
+        data.extend(psi(PID_PAT, &pat(&[(1, 0x200)]), &mut cc_pat));
+        data.extend([SYNC_BYTE, 0xFF]);
+        data.extend(psi(0x200, &pmt(1, 0x201, &[(0x06, 0x201, &descriptors), (0x05, 0x202, &[])]), &mut cc_pmt));
+        data.extend(packetize(0x201, &pes(0xBD, 1234, None, true, &[1, 2, 3]), false, &mut cc_audio));
+
+        let mut demuxer = open(data).unwrap();
+        assert_eq!(demuxer.streams().len(), 1);
+        assert_eq!(demuxer.streams()[0].codec_id, CodecId::Ac3);
+        assert_eq!(demuxer.programs()[0].es_infos.len(), 2);
+        assert_eq!(read_all(&mut demuxer), vec![(0, 1234, 1234, true, vec![1, 2, 3])]);
+    }
+
+    fn pcr_packet(pid: u16, cc: u8, pcr: u64) -> Vec<u8> {
+        let (base, extension) = (pcr / 300, pcr % 300);
+        let mut packet = vec![SYNC_BYTE, (pid >> 8) as u8, pid as u8, 0x20 | cc, 183, 0x10];
+        packet.extend([
+            (base >> 25) as u8,
+            (base >> 17) as u8,
+            (base >> 9) as u8,
+            (base >> 1) as u8,
+            ((base & 1) as u8) << 7 | 0x7E | (extension >> 8) as u8,
+            extension as u8,
+        ]);
+        packet.resize(TS_PACKET_SIZE, 0xFF);
+        packet
+    }
+
+    #[test]
+    fn continuity_and_pcr() {
+        let (mut cc_pat, mut cc_pmt) = (0, 0);
+        let mut data = psi(PID_PAT, &pat(&[(1, 0x100)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x1F0, &[(0x0F, 0x102, &[])]), &mut cc_pmt));
+        data.extend(pcr_packet(0x1F0, 0, 27_000_000));
+        let audio = ts_packet(0x102, true, 0, false, &pes(0xC0, 100, None, true, &[1]));
+        data.extend(&audio);
+        data.extend(&audio);
+        data.extend(pcr_packet(0x1F0, 0, 27_005_640));
+        let lost = pes(0xC0, 200, None, true, &[2; 300]);
+        data.extend(ts_packet(0x102, true, 1, false, &lost[..184]));
+        data.extend(ts_packet(0x102, false, 3, false, &lost[184..]));
+        data.extend(ts_packet(0x102, true, 4, false, &pes(0xC0, 300, None, true, &[3])));
+
+        let mut demuxer = open(data).unwrap();
+        let mut packet = Packet2::default();
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!((packet.pts, packet.pos), (100, 3 * 188));
+        assert_eq!(read_all(&mut demuxer), vec![(0, 300, 300, true, vec![3])]);
+
+        assert_eq!(demuxer.programs()[0].clock.pcr(), Some(27_005_640));
+        assert_eq!(demuxer.packet_pcr(&packet), Some(27_001_880));
+        assert_eq!(demuxer.poll_event(), Some(TsEvent::ContinuityError { pid: 0x102, pos: 7 * 188, expected: 2, found: 3 }));
+        assert_eq!(demuxer.poll_event(), None);
+        assert_eq!(demuxer.pid_stats(0x102), Some(&PidStats {
+            packets: 5,
+            continuity_errors: 1,
+            duplicates: 1,
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn m2ts_and_rs_packets() {
+        let (mut cc_pat, mut cc_pmt, mut cc_audio) = (0, 0, 0);
+        let mut data = psi(PID_PAT, &pat(&[(1, 0x100)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x102, &[(0x81, 0x102, &[])]), &mut cc_pmt));
+        for pts in [0, 2880] {
+            data.extend(packetize(0x102, &pes(0xBD, pts, None, true, &[pts as u8; 200]), false, &mut cc_audio));
+        }
+
+        let mut m2ts = vec![0xAB; 50];
+        for (i, packet) in data.chunks(TS_PACKET_SIZE).enumerate() {
+            m2ts.extend((0xC000_0000u32 | (i as u32 * 1000)).to_be_bytes());
+            m2ts.extend(packet);
+        }
+        let mut demuxer = open(m2ts).unwrap();
+        assert_eq!(demuxer.packet_size(), 192);
+        let mut packet = Packet2::default();
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!((packet.pts, packet.data().len(), packet.pos), (0, 200, 50 + 2 * 192));
+        assert_eq!(demuxer.arrival_time(), Some(2000));
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!((packet.pts, demuxer.arrival_time()), (2880, Some(4000)));
+
+        let mut rs = Vec::new();
+        for packet in data.chunks(TS_PACKET_SIZE) {
+            rs.extend(packet);
+            rs.extend([SYNC_BYTE; 16]);
+        }
+        let mut demuxer = open(rs).unwrap();
+        assert_eq!(demuxer.packet_size(), 204);
+        assert_eq!(demuxer.arrival_time(), None);
+        let packets = read_all(&mut demuxer);
+        assert_eq!(packets, vec![(0, 0, 0, true, vec![0; 200]), (0, 2880, 2880, true, vec![64; 200])]);
+    }
+
+    #[test]
+    fn service_information() {
+        let (mut cc_pat, mut cc_pmt, mut cc_sdt, mut cc_eit, mut cc_tdt) = (0, 0, 0, 0, 0);
+        let mut data = psi(PID_PAT, &pat(&[(1, 0x100)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x101, &[(0x1B, 0x101, &[])]), &mut cc_pmt));
+
+        let mut sdt = vec![0x00, 0x01, 0xFF, 0x00, 0x01, 0xFD, 0x80, 16, 0x48, 14, 0x01, 4];
+        sdt.extend(b"ACME\x07\x15Kan\xC3\xA4l");
+        data.extend(psi(PID_SDT, &section(0x42, 0x0002, 3, &sdt), &mut cc_sdt));
+        // repeated SDT isn't notified again
+        data.extend(psi(PID_SDT, &section(0x42, 0x0002, 3, &sdt), &mut cc_sdt));
+
+        let mut eit = vec![0x00, 0x02, 0x00, 0x01, 0x00, 0x4E];
+        eit.extend([0x12, 0x34, 0xC0, 0x79, 0x12, 0x45, 0x00, 0x00, 0x30, 0x00, 0x80, 12]);
+        eit.extend([0x4D, 10]);
+        eit.extend(b"deu\x04Tor!\x01?");
+        data.extend(psi(PID_EIT, &section(0x4E, 0x0001, 0, &eit), &mut cc_eit));
+        data.extend(psi(PID_TDT, &[0x70, 0x70, 0x05, 0xC0, 0x79, 0x12, 0x45, 0x10], &mut cc_tdt));
+
+        let mut demuxer = open(data).unwrap();
+        while demuxer.read_packet(&mut Packet2::default()).is_ok() {}
+
+        let mut events = Vec::new();
+        while let Some(event) = demuxer.poll_event() {
+            events.push(event);
+        }
+        assert_eq!(events, vec![
+            TsEvent::SiUpdated(SiTable::Sdt { original_network_id: 1, transport_stream_id: 2, actual: true }),
+            TsEvent::SiUpdated(SiTable::Eit {
+                original_network_id: 1,
+                transport_stream_id: 2,
+                service_id: 1,
+                actual: true,
+                schedule: false,
+            }),
+            TsEvent::SiUpdated(SiTable::Time),
+        ]);
+
+        let si = demuxer.service_information();
+        let service = &si.sdts[0].services[0];
+        assert_eq!((service.service_id, service.running_status, service.eit_present_following), (1, 4, true));
+        assert_eq!(service.service_type, Some(1));
+        assert_eq!(service.provider_name.as_deref(), Some("ACME"));
+        assert_eq!(service.name.as_deref(), Some("Kanäl"));
+
+        let present = si.service_events[0].present.as_ref().unwrap();
+        assert_eq!((present.event_id, present.start_time, present.duration), (0x1234, Some(750_516_300), Some(1800)));
+        assert_eq!(present.short_events[0].name, "Tor!");
+        assert_eq!(si.utc_time, Some(750_516_310));
+    }
+
+    /// Builds a splice_info_section with a pts_adjustment of 1000 and no descriptors.
+    fn splice_info(command_type: u8, command: &[u8]) -> Vec<u8> {
+        let mut section = vec![0xFC, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xE8, 0x00, 0xFF, 0xF0, command.len() as u8];
+        section.push(command_type);
+        section.extend_from_slice(command);
+        section.extend([0x00, 0x00]);
+        let len = section.len() + 4 - 3;
+        section[2] = len as u8;
+        section.extend(crc32(&section).to_be_bytes());
+        section
+    }
+
+    #[test]
+    fn scte35_cues() {
+        let (mut cc_pat, mut cc_pmt, mut cc_video, mut cc_cue) = (0, 0, 0, 0);
+        let mut data = psi(PID_PAT, &pat(&[(1, 0x100)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x101, &[(0x1B, 0x101, &[]), (0x86, 0x1F5, &[])]), &mut cc_pmt));
+        data.extend(packetize(0x101, &pes(0xE0, 9000, None, true, &[0; 10]), true, &mut cc_video));
+        // time_signal at 20000, and an immediate splice_insert
+        data.extend(psi(0x1F5, &splice_info(0x06, &[0xFE, 0x00, 0x00, 0x4E, 0x20]), &mut cc_cue));
+        let insert = [0x00, 0x00, 0x00, 0x07, 0x7F, 0x5F, 0x00, 0x01, 0x00, 0x00];
+        let mut cue = splice_info(0x05, &insert);
+        data.extend(psi(0x1F5, &cue, &mut cc_cue));
+        // corrupted cue
+        cue[20] ^= 0xFF;
+        data.extend(psi(0x1F5, &cue, &mut cc_cue));
+
+        let mut demuxer = open(data).unwrap();
+        assert_eq!(demuxer.streams()[1].codec_id, CodecId::Scte35);
+        assert_eq!(demuxer.streams()[1].media_type, MediaType::Data);
+
+        let packets = read_all(&mut demuxer);
+        assert_eq!(packets.len(), 3);
+        assert_eq!((packets[1].0, packets[1].1, packets[1].2), (1, 21000, 9000));
+        assert_eq!((packets[2].0, packets[2].1, packets[2].2), (1, 9000, 9000));
+
+        let info = parse_splice_info(&packets[2].4).unwrap();
+        let SpliceCommand::Insert(insert) = info.command else { panic!("expected splice_insert") };
+        assert!(insert.immediate && !insert.out_of_network);
+        assert_eq!(insert.event_id, 7);
+    }
+
+    #[test]
+    fn subtitle_streams() {
+        let subtitling: &[u8] = &[0x59, 8, b'd', b'e', b'u', 0x10, 0x00, 0x02, 0x00, 0x03];
+        let teletext: &[u8] = &[0x56, 10, b'f', b'r', b'a', 0x09, 0x01, b'e', b'n', b'g', 0x10, 0x88];
+        let (mut cc_pat, mut cc_pmt, mut cc_sub, mut cc_ttx) = (0, 0, 0, 0);
+
+        let mut data = psi(PID_PAT, &pat(&[(1, 0x100)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x101, &[(0x06, 0x101, subtitling), (0x06, 0x102, teletext)]), &mut cc_pmt));
+        data.extend(packetize(0x101, &pes(0xBD, 1000, None, true, &[0x20, 0x00, 0x0F]), false, &mut cc_sub));
+        data.extend(packetize(0x102, &pes(0xBD, 2000, None, true, &[0x10, 0xFF]), false, &mut cc_ttx));
+
+        let mut demuxer = open(data).unwrap();
+        let streams = demuxer.streams();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].codec_id, CodecId::DvbSubtitle);
+        assert_eq!(streams[0].media_type, MediaType::Subtitle);
+        assert_eq!(streams[0].language.as_deref(), Some("deu"));
+        assert_eq!(streams[1].codec_id, CodecId::DvbTeletext);
+        assert_eq!(streams[1].language.as_deref(), Some("fra"));
+
+        let es_infos = &demuxer.programs()[0].es_infos;
+        let subtitle = &es_infos[0].subtitles[0];
+        assert_eq!((subtitle.subtitling_type, subtitle.composition_page_id, subtitle.ancillary_page_id), (0x10, 2, 3));
+        let pages: Vec<_> = es_infos[1].teletext_pages.iter()
+            .map(|page| (page.language.as_str(), page.teletext_type, page.page()))
+            .collect();
+        assert_eq!(pages, vec![("fra", 0x01, 101), ("eng", 0x02, 888)]);
+
+        assert_eq!(read_all(&mut demuxer), vec![
+            (0, 1000, 1000, true, vec![0x20, 0x00, 0x0F]),
+            (1, 2000, 2000, true, vec![0x10, 0xFF]),
+        ]);
+    }
+
+    #[test]
+    fn no_pat() {
+        let mut cc = 0;
+        let data = packetize(0x100, &pes(0xE0, 0, None, false, &[0; 10]), false, &mut cc);
+        assert!(open(data).is_err());
+    }
+
+    /// Changes the version of a PAT or PMT section.
+    fn versioned(mut section: Vec<u8>, version: u8) -> Vec<u8> {
+        section[5] = 0xC1 | version << 1;
+        section.truncate(section.len() - 4);
+        let crc = crc32(&section);
+        section.extend(crc.to_be_bytes());
+        section
+    }
+
+    #[test]
+    fn program_changes() {
+        let (mut cc_pat, mut cc_pmt, mut cc_pmt2, mut cc_video, mut cc_audio) = (0, 0, 0, 0, 0);
+        let mut data = psi(PID_PAT, &pat(&[(1, 0x100)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x101, &[(0x1B, 0x101, &[]), (0x0F, 0x102, &[])]), &mut cc_pmt));
+        data.extend(packetize(0x102, &pes(0xC0, 1000, None, true, &[1]), false, &mut cc_audio));
+        // version 1 keeps the video PID and moves the audio PID from AAC to MPEG audio
+        let pmt_v1 = versioned(pmt(1, 0x101, &[(0x1B, 0x101, &[]), (0x03, 0x102, &[])]), 1);
+        data.extend(psi(0x100, &pmt_v1, &mut cc_pmt));
+        data.extend(packetize(0x102, &pes(0xC0, 2000, None, true, &[2]), false, &mut cc_audio));
+        // the PAT replaces program 1 by program 2
+        data.extend(psi(PID_PAT, &versioned(pat(&[(2, 0x200)]), 1), &mut cc_pat));
+        data.extend(psi(0x200, &pmt(2, 0x201, &[(0x0F, 0x201, &[])]), &mut cc_pmt2));
+        data.extend(packetize(0x101, &pes(0xE0, 3000, None, true, &[3]), true, &mut cc_video));
+        data.extend(packetize(0x102, &pes(0xC0, 3000, None, true, &[3]), false, &mut cc_audio));
+
+        let mut demuxer = open(data).unwrap();
+        assert_eq!(read_all(&mut demuxer), vec![(1, 1000, 1000, true, vec![1]), (2, 2000, 2000, true, vec![2])]);
+        let codecs: Vec<_> = demuxer.streams().iter().map(|stream| stream.codec_id).collect();
+        assert_eq!(codecs, vec![CodecId::H264, CodecId::Aac, CodecId::Mp2, CodecId::Aac]);
+        let programs: Vec<_> = demuxer.programs().iter().map(|program| (program.number, &program.stream_ids)).collect();
+        assert_eq!(programs, vec![(2, &vec![3])]);
+    }
+
+    #[test]
+    fn pending_packets_limit() {
+        let (mut cc_pat, mut cc_pmt, mut cc_audio) = (0, 0, 0);
+        // the PMT of program 2 never arrives
+        let mut data = psi(PID_PAT, &pat(&[(1, 0x100), (2, 0x200)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x101, &[(0x0F, 0x101, &[])]), &mut cc_pmt));
+        for _ in 0..=MAX_PENDING_PACKETS {
+            data.extend(packetize(0x101, &pes(0xC0, 0, None, true, &[0]), false, &mut cc_audio));
+        }
+        // a single IoBuf, the queued packets reference it
+        let result = DemuxerTs::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 1 << 20)));
+        assert!(matches!(result, Err(Error::DecodeError(_))));
+    }
+
+    #[test]
+    fn registered_input_format() {
+        let (mut cc_pat, mut cc_pmt, mut cc_audio) = (0, 0, 0);
+        let mut data = psi(PID_PAT, &pat(&[(1, 0x100)]), &mut cc_pat);
+        data.extend(psi(0x100, &pmt(1, 0x102, &[(0x81, 0x102, &[])]), &mut cc_pmt));
+        for pts in [0, 2880, 5760] {
+            data.extend(packetize(0x102, &pes(0xBD, pts, None, true, &[pts as u8; 200]), false, &mut cc_audio));
+        }
+        let path = std::env::temp_dir().join(format!("rav-ts-probe-test-{}.bin", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let uri = path.to_str().unwrap();
+
+        let options = FormatOptions::default().with_format(input_format());
+        assert_eq!(options.probe(uri).unwrap()[0], ProbeScore { name: "ts", score: PROBE_SCORE_MAX });
+        // registered once, the format is probed by content with the default options, the extension is .bin
+        register_input_format(input_format());
+        register_input_format(input_format());
+        assert_eq!(FormatOptions::default().formats().iter().filter(|format| format.name == "ts").count(), 1);
+        let mut context = FormatContext::open_input(uri).unwrap();
+        assert_eq!(context.format_name(), "ts");
+        let mut packet = Packet2::default();
+        context.read_packet(&mut packet).unwrap();
+        assert_eq!(packet.data(), &[0; 200]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hls_playlist() {
+        // independently muxed segments, the timestamps restart after the discontinuity
+        let segment = |pts: i64, fill: u8| {
+            let (mut cc_pat, mut cc_pmt, mut cc_audio) = (0, 0, 0);
+            let mut data = psi(PID_PAT, &pat(&[(1, 0x100)]), &mut cc_pat);
+            data.extend(psi(0x100, &pmt(1, 0x102, &[(0x81, 0x102, &[])]), &mut cc_pmt));
+            for i in 0..2 {
+                let pes = pes(0xBD, pts + i * 2880, None, true, &[fill + i as u8; 100]);
+                data.extend(packetize(0x102, &pes, false, &mut cc_audio));
+            }
+            data
+        };
+        let dir = std::env::temp_dir().join(format!("rav-ts-hls-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXTINF:2.0,\n0.ts\n\
+            #EXT-X-DISCONTINUITY\n#EXTINF:2.0,\n1.ts\n#EXT-X-ENDLIST\n";
+        std::fs::write(dir.join("media.m3u8"), playlist).unwrap();
+        std::fs::write(dir.join("0.ts"), segment(900_000, 1)).unwrap();
+        std::fs::write(dir.join("1.ts"), segment(0, 3)).unwrap();
+
+        let mut supplier = IoBufSupplierHls::default();
+        supplier.open_input(dir.join("media.m3u8").to_str().unwrap()).unwrap();
+        let timeline = supplier.timeline();
+        let demuxer = DemuxerTs::new(MediaSourceStream::new(supplier)).unwrap();
+        let mut demuxer = DemuxerSegmented::new(demuxer, timeline);
+        assert_eq!(demuxer.streams()[0].codec_id, CodecId::Ac3);
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.stream_id, packet.pts, packet.data()[0]));
+        }
+        assert_eq!(packets, vec![(0, 900_000, 1), (0, 902_880, 2), (0, 1_080_000, 3), (0, 1_082_880, 4)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
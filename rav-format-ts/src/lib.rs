@@ -0,0 +1,26 @@
+//! MPEG transport stream demuxer.
+
+mod clock;
+mod continuity;
+mod demuxer;
+mod packet;
+mod pes;
+mod psi;
+mod scte35;
+mod si;
+mod teletext;
+mod text;
+
+pub use clock::{pts_to_pcr, ProgramClock, PCR_FREQUENCY};
+pub use continuity::PidStats;
+pub use demuxer::{input_format, DemuxerTs, Program, TsEvent};
+pub use psi::{DvbSubtitleInfo, EsInfo, TeletextInfo};
+pub use scte35::{
+    parse_splice_info, BreakDuration, DeliveryRestrictions, SegmentationDescriptor, SpliceCommand, SpliceDescriptor,
+    SpliceInfo, SpliceInsert,
+};
+pub use si::{
+    EitEvent, ExtendedEvent, LocalTimeOffset, Nit, NitTransportStream, ParentalRating, Sdt, Service, ServiceEvents,
+    ServiceInformation, ShortEvent, SiTable,
+};
+pub use teletext::{TeletextCaption, TeletextDecoder};
@@ -0,0 +1,171 @@
+use rav::error::{decode_error, Result};
+use rav::format::{PROBE_SCORE_EXTENSION, PROBE_SCORE_MAX};
+
+pub(crate) const TS_PACKET_SIZE: usize = 188;
+/// Blu-ray/AVCHD packets, a 4-byte TP_extra_header precedes the TS packet.
+pub(crate) const M2TS_PACKET_SIZE: usize = 192;
+/// DVB packets followed by 16 Reed-Solomon parity bytes.
+pub(crate) const RS_PACKET_SIZE: usize = 204;
+pub(crate) const SYNC_BYTE: u8 = 0x47;
+
+pub(crate) const PID_PAT: u16 = 0x0000;
+pub(crate) const PID_NULL: u16 = 0x1FFF;
+
+/// Returns the number of bytes preceding the TS packet for a given packet size.
+pub(crate) fn prefix_len(packet_size: usize) -> usize {
+    if packet_size == M2TS_PACKET_SIZE { 4 } else { 0 }
+}
+
+/// Finds the packet size from the sync byte periodicity, returns the size and the position
+/// of the first packet in `data`.
+pub(crate) fn detect_packet_size(data: &[u8]) -> Option<(usize, usize)> {
+    let mut best = None;
+    let mut best_count = 1;
+    for size in [TS_PACKET_SIZE, M2TS_PACKET_SIZE, RS_PACKET_SIZE] {
+        let prefix = prefix_len(size);
+        for start in 0..size.min(data.len()) {
+            let count = (start + prefix..data.len()).step_by(size).take_while(|&i| data[i] == SYNC_BYTE).count();
+            if count > best_count {
+                best = Some((size, start));
+                best_count = count;
+            }
+        }
+    }
+    best
+}
+
+/// Number of consecutive packets that make a probe certain.
+const PROBE_PACKETS: usize = 5;
+
+/// Scores `data` as the start of a transport stream from the sync byte periodicity. A few packets are enough when
+/// they cover all of `data`, data preceding the first packet lowers the score.
+pub(crate) fn probe(data: &[u8]) -> u32 {
+    let Some((size, start)) = detect_packet_size(data) else {
+        return 0;
+    };
+    let first = start + prefix_len(size);
+    let count = (first..data.len()).step_by(size).take_while(|&i| data[i] == SYNC_BYTE).count();
+    match count {
+        PROBE_PACKETS.. if start == 0 => PROBE_SCORE_MAX,
+        PROBE_PACKETS.. => PROBE_SCORE_MAX * 3 / 4,
+        _ if first + count * size >= data.len() => PROBE_SCORE_EXTENSION,
+        _ => 0,
+    }
+}
+
+/// Decodes the 30-bit arrival_time_stamp of a M2TS TP_extra_header, in 27 MHz units.
+pub(crate) fn parse_arrival_time(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]]) & 0x3FFF_FFFF
+}
+
+/// Adaptation field properties used by the demuxer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct AdaptationField {
+    pub discontinuity: bool,
+    pub random_access: bool,
+    /// Program clock reference in 27 MHz units.
+    pub pcr: Option<u64>,
+}
+
+/// Header of a 188-byte TS packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TsHeader {
+    pub transport_error: bool,
+    pub unit_start: bool,
+    pub pid: u16,
+    pub scrambled: bool,
+    pub continuity_counter: u8,
+    pub adaptation_field: Option<AdaptationField>,
+    /// Offset of the payload in the packet, equal to the packet size when there is no payload.
+    pub payload_offset: usize,
+}
+
+impl TsHeader {
+    pub(crate) fn has_payload(&self) -> bool {
+        self.payload_offset < TS_PACKET_SIZE
+    }
+}
+
+pub(crate) fn parse_header(data: &[u8]) -> Result<TsHeader> {
+    if data.len() < TS_PACKET_SIZE || data[0] != SYNC_BYTE {
+        return decode_error("ts: lost sync");
+    }
+    let adaptation_control = (data[3] >> 4) & 0x03;
+    let mut header = TsHeader {
+        transport_error: data[1] & 0x80 != 0,
+        unit_start: data[1] & 0x40 != 0,
+        pid: u16::from_be_bytes([data[1], data[2]]) & 0x1FFF,
+        scrambled: data[3] & 0xC0 != 0,
+        continuity_counter: data[3] & 0x0F,
+        adaptation_field: None,
+        payload_offset: 4,
+    };
+
+    if adaptation_control & 0x02 != 0 {
+        let len = data[4] as usize;
+        if 5 + len > TS_PACKET_SIZE {
+            return decode_error("ts: invalid adaptation field length");
+        }
+        let mut field = AdaptationField::default();
+        if len > 0 {
+            let flags = data[5];
+            field.discontinuity = flags & 0x80 != 0;
+            field.random_access = flags & 0x40 != 0;
+            if flags & 0x10 != 0 && len >= 7 {
+                field.pcr = Some(parse_pcr(&data[6..12]));
+            }
+        }
+        header.adaptation_field = Some(field);
+        header.payload_offset = 5 + len;
+    }
+    if adaptation_control & 0x01 == 0 {
+        header.payload_offset = TS_PACKET_SIZE;
+    }
+    Ok(header)
+}
+
+/// Decodes a PCR, the 90 kHz base and the 27 MHz extension, into 27 MHz units.
+pub(crate) fn parse_pcr(data: &[u8]) -> u64 {
+    let base = (data[0] as u64) << 25
+        | (data[1] as u64) << 17
+        | (data[2] as u64) << 9
+        | (data[3] as u64) << 1
+        | (data[4] >> 7) as u64;
+    let extension = ((data[4] & 0x01) as u64) << 8 | data[5] as u64;
+    base * 300 + extension
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(packet_size: usize, garbage: usize, count: usize) -> Vec<u8> {
+        let mut data = vec![0u8; garbage];
+        for _ in 0..count {
+            let mut packet = vec![0u8; packet_size];
+            packet[prefix_len(packet_size)] = SYNC_BYTE;
+            data.extend(packet);
+        }
+        data
+    }
+
+    #[test]
+    fn packet_size_detection() {
+        assert_eq!(detect_packet_size(&stream(TS_PACKET_SIZE, 0, 5)), Some((TS_PACKET_SIZE, 0)));
+        assert_eq!(detect_packet_size(&stream(M2TS_PACKET_SIZE, 7, 5)), Some((M2TS_PACKET_SIZE, 7)));
+        assert_eq!(detect_packet_size(&stream(RS_PACKET_SIZE, 100, 5)), Some((RS_PACKET_SIZE, 100)));
+        assert_eq!(detect_packet_size(&stream(TS_PACKET_SIZE, 0, 1)), None);
+    }
+
+    #[test]
+    fn probe_scores() {
+        assert_eq!(probe(&stream(TS_PACKET_SIZE, 0, 6)), PROBE_SCORE_MAX);
+        assert_eq!(probe(&stream(M2TS_PACKET_SIZE, 0, 5)), PROBE_SCORE_MAX);
+        assert_eq!(probe(&stream(RS_PACKET_SIZE, 10, 5)), PROBE_SCORE_MAX * 3 / 4);
+        assert_eq!(probe(&stream(TS_PACKET_SIZE, 0, 2)), PROBE_SCORE_EXTENSION);
+        let mut truncated = stream(TS_PACKET_SIZE, 0, 3);
+        truncated.extend([0u8; 1000]);
+        assert_eq!(probe(&truncated), 0);
+        assert_eq!(probe(b"RIFF\0\0\0\0WAVE"), 0);
+    }
+}
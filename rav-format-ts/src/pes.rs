@@ -0,0 +1,72 @@
+use rav::data::NOPTS_VALUE;
+
+// stream_id values without the optional PES header fields
+const STREAM_ID_PROGRAM_STREAM_MAP: u8 = 0xBC;
+const STREAM_ID_PADDING: u8 = 0xBE;
+const STREAM_ID_PRIVATE_2: u8 = 0xBF;
+const STREAM_ID_ECM: u8 = 0xF0;
+const STREAM_ID_EMM: u8 = 0xF1;
+const STREAM_ID_DSMCC: u8 = 0xF2;
+const STREAM_ID_H222_E: u8 = 0xF8;
+const STREAM_ID_DIRECTORY: u8 = 0xFF;
+
+/// Fixed part of a PES packet header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PesHeader {
+    pub stream_id: u8,
+    /// PES_packet_length, 0 means unbounded (video in TS).
+    pub packet_length: usize,
+    /// 90kHz presentation timestamp, `NOPTS_VALUE` if absent.
+    pub pts: i64,
+    /// 90kHz decoding timestamp, `NOPTS_VALUE` if absent.
+    pub dts: i64,
+    /// Length of the whole header, the payload follows it.
+    pub header_len: usize,
+}
+
+/// Decodes a 33-bit timestamp coded in 5 bytes with marker bits.
+pub(crate) fn parse_timestamp(data: &[u8]) -> i64 {
+    (((data[0] >> 1) & 0x07) as i64) << 30
+        | (data[1] as i64) << 22
+        | ((data[2] >> 1) as i64) << 15
+        | (data[3] as i64) << 7
+        | (data[4] >> 1) as i64
+}
+
+/// Parses the header at the start of a PES packet, `None` if the data isn't a valid PES header
+/// or is too short to contain it.
+pub(crate) fn parse_pes_header(data: &[u8]) -> Option<PesHeader> {
+    if data.len() < 6 || data[..3] != [0x00, 0x00, 0x01] {
+        return None;
+    }
+    let stream_id = data[3];
+    let mut header = PesHeader {
+        stream_id,
+        packet_length: u16::from_be_bytes([data[4], data[5]]) as usize,
+        pts: NOPTS_VALUE,
+        dts: NOPTS_VALUE,
+        header_len: 6,
+    };
+    if matches!(stream_id, STREAM_ID_PROGRAM_STREAM_MAP | STREAM_ID_PADDING | STREAM_ID_PRIVATE_2
+        | STREAM_ID_ECM | STREAM_ID_EMM | STREAM_ID_DSMCC | STREAM_ID_H222_E | STREAM_ID_DIRECTORY)
+    {
+        return Some(header);
+    }
+
+    // MPEG-2 PES header
+    if data.len() < 9 || data[6] & 0xC0 != 0x80 {
+        return None;
+    }
+    let pts_dts_flags = data[7] >> 6;
+    header.header_len = 9 + data[8] as usize;
+    if data.len() < header.header_len {
+        return None;
+    }
+    if pts_dts_flags & 0x02 != 0 && header.header_len >= 14 {
+        header.pts = parse_timestamp(&data[9..14]);
+    }
+    if pts_dts_flags == 0x03 && header.header_len >= 19 {
+        header.dts = parse_timestamp(&data[14..19]);
+    }
+    Some(header)
+}
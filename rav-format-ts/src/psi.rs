@@ -0,0 +1,326 @@
+use rav::codec::CodecId;
+
+pub(crate) const TABLE_ID_PAT: u8 = 0x00;
+pub(crate) const TABLE_ID_PMT: u8 = 0x02;
+
+// descriptor tags
+const DESC_REGISTRATION: u8 = 0x05;
+const DESC_ISO_639_LANGUAGE: u8 = 0x0A;
+const DESC_VBI_TELETEXT: u8 = 0x46;
+const DESC_TELETEXT: u8 = 0x56;
+const DESC_SUBTITLING: u8 = 0x59;
+const DESC_AC3: u8 = 0x6A;
+const DESC_EAC3: u8 = 0x7A;
+const DESC_DTS: u8 = 0x7B;
+
+/// CRC-32/MPEG-2 of `data`, a section including its CRC_32 field gives 0.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &b in data {
+        crc ^= (b as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Reassembles PSI sections from TS packet payloads.
+#[derive(Debug, Default)]
+pub(crate) struct SectionAssembler {
+    buf: Vec<u8>,
+    /// A section start was seen, following payloads continue it.
+    started: bool,
+}
+
+impl SectionAssembler {
+    /// Adds the payload of a TS packet and returns the sections completed by it.
+    pub(crate) fn push(&mut self, payload: &[u8], unit_start: bool) -> Vec<Vec<u8>> {
+        let mut sections = Vec::new();
+        let mut payload = payload;
+        if unit_start {
+            let Some((&pointer, rest)) = payload.split_first() else {
+                return sections;
+            };
+            let pointer = (pointer as usize).min(rest.len());
+            // bytes before the pointer finish the previous section
+            if self.started {
+                self.buf.extend_from_slice(&rest[..pointer]);
+                self.take_sections(&mut sections);
+            }
+            self.buf.clear();
+            self.started = true;
+            payload = &rest[pointer..];
+        } else if !self.started {
+            return sections;
+        }
+
+        self.buf.extend_from_slice(payload);
+        self.take_sections(&mut sections);
+        sections
+    }
+
+    /// Drops a partially received section, e.g. after a discontinuity.
+    pub(crate) fn reset(&mut self) {
+        self.buf.clear();
+        self.started = false;
+    }
+
+    fn take_sections(&mut self, sections: &mut Vec<Vec<u8>>) {
+        while let Some(&table_id) = self.buf.first() {
+            // stuffing bytes after the last section, the next one starts in a new unit
+            if table_id == 0xFF {
+                self.reset();
+                return;
+            }
+            if self.buf.len() < 3 {
+                return;
+            }
+            let len = 3 + (((self.buf[1] & 0x0F) as usize) << 8 | self.buf[2] as usize);
+            if self.buf.len() < len {
+                return;
+            }
+            sections.push(self.buf.drain(..len).collect());
+        }
+    }
+}
+
+/// Common header of sections using the long syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SectionHeader {
+    pub table_id: u8,
+    /// transport_stream_id, program_number, service_id, ... depending on the table.
+    pub id_extension: u16,
+    pub version: u8,
+    pub current_next: bool,
+    pub section_number: u8,
+    pub last_section_number: u8,
+}
+
+/// Validates a long syntax section and returns its header and the data between the header and the CRC.
+pub(crate) fn parse_long_section(section: &[u8]) -> Option<(SectionHeader, &[u8])> {
+    if section.len() < 12 || section[1] & 0x80 == 0 || crc32(section) != 0 {
+        return None;
+    }
+    let header = SectionHeader {
+        table_id: section[0],
+        id_extension: u16::from_be_bytes([section[3], section[4]]),
+        version: (section[5] >> 1) & 0x1F,
+        current_next: section[5] & 0x01 != 0,
+        section_number: section[6],
+        last_section_number: section[7],
+    };
+    Some((header, &section[8..section.len() - 4]))
+}
+
+/// Iterates over the (tag, data) pairs of a descriptor loop.
+pub(crate) fn descriptors(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 2 {
+            return None;
+        }
+        let len = (data[1] as usize).min(data.len() - 2);
+        let descriptor = (data[0], &data[2..2 + len]);
+        data = &data[2 + len..];
+        Some(descriptor)
+    })
+}
+
+/// Program association table entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PatEntry {
+    pub program_number: u16,
+    pub pid: u16,
+}
+
+pub(crate) fn parse_pat(data: &[u8]) -> Vec<PatEntry> {
+    data.chunks_exact(4)
+        .map(|entry| PatEntry {
+            program_number: u16::from_be_bytes([entry[0], entry[1]]),
+            pid: u16::from_be_bytes([entry[2], entry[3]]) & 0x1FFF,
+        })
+        .collect()
+}
+
+/// A DVB subtitling service, from the subtitling descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DvbSubtitleInfo {
+    pub language: String,
+    /// 0x10-0x15 for normal subtitles, 0x20-0x25 for the hard of hearing.
+    pub subtitling_type: u8,
+    pub composition_page_id: u16,
+    pub ancillary_page_id: u16,
+}
+
+/// A teletext page, from the teletext descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TeletextInfo {
+    pub language: String,
+    /// 0x01 initial page, 0x02 subtitle page, 0x05 subtitle page for the hard of hearing.
+    pub teletext_type: u8,
+    /// Magazine number, 1-8.
+    pub magazine: u8,
+    /// Page number in the magazine, two BCD digits.
+    pub page_number: u8,
+}
+
+impl TeletextInfo {
+    /// Page number as displayed, e.g. 888.
+    pub fn page(&self) -> u16 {
+        self.magazine as u16 * 100 + (self.page_number >> 4) as u16 * 10 + (self.page_number & 0x0F) as u16
+    }
+}
+
+/// Elementary stream described by a PMT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EsInfo {
+    pub stream_type: u8,
+    pub pid: u16,
+    pub codec_id: CodecId,
+    pub language: Option<String>,
+    pub subtitles: Vec<DvbSubtitleInfo>,
+    pub teletext_pages: Vec<TeletextInfo>,
+    /// Raw ES_info descriptors.
+    pub descriptors: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Pmt {
+    pub pcr_pid: u16,
+    pub streams: Vec<EsInfo>,
+}
+
+pub(crate) fn parse_pmt(data: &[u8]) -> Option<Pmt> {
+    if data.len() < 4 {
+        return None;
+    }
+    let pcr_pid = u16::from_be_bytes([data[0], data[1]]) & 0x1FFF;
+    let program_info_len = (u16::from_be_bytes([data[2], data[3]]) & 0x0FFF) as usize;
+    let program_info = data.get(4..4 + program_info_len)?;
+    // Blu-ray streams reuse some of the user private stream types
+    let hdmv = descriptors(program_info).any(|(tag, desc)| tag == DESC_REGISTRATION && desc.starts_with(b"HDMV"));
+    let mut data = &data[4 + program_info_len..];
+
+    let mut streams = Vec::new();
+    while data.len() >= 5 {
+        let stream_type = data[0];
+        let pid = u16::from_be_bytes([data[1], data[2]]) & 0x1FFF;
+        let es_info_len = (u16::from_be_bytes([data[3], data[4]]) & 0x0FFF) as usize;
+        let descriptors = data.get(5..5 + es_info_len)?;
+
+        let mut language = None;
+        let mut subtitles = Vec::new();
+        let mut teletext_pages = Vec::new();
+        for (tag, desc) in self::descriptors(descriptors) {
+            match tag {
+                DESC_ISO_639_LANGUAGE if desc.len() >= 3 => language = Some(iso639(desc)),
+                DESC_SUBTITLING => {
+                    subtitles.extend(desc.chunks_exact(8).map(|entry| DvbSubtitleInfo {
+                        language: iso639(entry),
+                        subtitling_type: entry[3],
+                        composition_page_id: u16::from_be_bytes([entry[4], entry[5]]),
+                        ancillary_page_id: u16::from_be_bytes([entry[6], entry[7]]),
+                    }));
+                }
+                DESC_TELETEXT | DESC_VBI_TELETEXT => {
+                    teletext_pages.extend(desc.chunks_exact(5).map(|entry| TeletextInfo {
+                        language: iso639(entry),
+                        teletext_type: entry[3] >> 3,
+                        magazine: match entry[3] & 0x07 {
+                            0 => 8,
+                            magazine => magazine,
+                        },
+                        page_number: entry[4],
+                    }));
+                }
+                _ => {}
+            }
+        }
+        if language.is_none() {
+            language = subtitles.first().map(|subtitle| subtitle.language.clone())
+                .or_else(|| teletext_pages.first().map(|page| page.language.clone()));
+        }
+        streams.push(EsInfo {
+            stream_type,
+            pid,
+            codec_id: codec_from_stream_type(stream_type, descriptors, hdmv),
+            language,
+            subtitles,
+            teletext_pages,
+            descriptors: descriptors.to_vec(),
+        });
+        data = &data[5 + es_info_len..];
+    }
+    Some(Pmt { pcr_pid, streams })
+}
+
+fn iso639(data: &[u8]) -> String {
+    String::from_utf8_lossy(&data[..3]).into_owned()
+}
+
+/// Maps a PMT stream_type, and for private streams the descriptors, to a codec.
+fn codec_from_stream_type(stream_type: u8, es_descriptors: &[u8], hdmv: bool) -> CodecId {
+    match stream_type {
+        0x01 => CodecId::Mpeg1Video,
+        0x02 => CodecId::Mpeg2Video,
+        0x03 | 0x04 => CodecId::Mp2,
+        0x0F => CodecId::Aac,
+        0x11 => CodecId::AacLatm,
+        0x10 => CodecId::Mpeg4,
+        0x1B => CodecId::H264,
+        0x24 => CodecId::Hevc,
+        // ATSC
+        0x81 => CodecId::Ac3,
+        0x87 => CodecId::Eac3,
+        // Blu-ray, only with an HDMV registration descriptor
+        0x80 if hdmv => CodecId::PcmS16Be,
+        0x82 | 0x85 | 0x86 if hdmv => CodecId::Dts,
+        0x83 if hdmv => CodecId::TrueHd,
+        0x84 if hdmv => CodecId::Eac3,
+        0x90 if hdmv => CodecId::Pgs,
+        0x86 => CodecId::Scte35,
+        0x06 => {
+            for (tag, desc) in descriptors(es_descriptors) {
+                match tag {
+                    DESC_AC3 => return CodecId::Ac3,
+                    DESC_EAC3 => return CodecId::Eac3,
+                    DESC_DTS => return CodecId::Dts,
+                    DESC_SUBTITLING => return CodecId::DvbSubtitle,
+                    DESC_TELETEXT | DESC_VBI_TELETEXT => return CodecId::DvbTeletext,
+                    DESC_REGISTRATION => match desc.get(..4) {
+                        Some(b"AC-3") => return CodecId::Ac3,
+                        Some(b"EAC3") => return CodecId::Eac3,
+                        Some(b"DTS1" | b"DTS2" | b"DTS3") => return CodecId::Dts,
+                        Some(b"Opus") => return CodecId::Opus,
+                        Some(b"AV01") => return CodecId::Av1,
+                        Some(b"HEVC") => return CodecId::Hevc,
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            CodecId::Unknown
+        }
+        _ => CodecId::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_stream_types() {
+        let codecs = |hdmv| [0x0F, 0x11, 0x80, 0x81, 0x83, 0x86, 0x87, 0x90].map(|stream_type| {
+            codec_from_stream_type(stream_type, &[], hdmv)
+        });
+        assert_eq!(codecs(false), [
+            CodecId::Aac, CodecId::AacLatm, CodecId::Unknown, CodecId::Ac3, CodecId::Unknown, CodecId::Scte35,
+            CodecId::Eac3, CodecId::Unknown,
+        ]);
+        assert_eq!(codecs(true), [
+            CodecId::Aac, CodecId::AacLatm, CodecId::PcmS16Be, CodecId::Ac3, CodecId::TrueHd, CodecId::Dts,
+            CodecId::Eac3, CodecId::Pgs,
+        ]);
+    }
+}
@@ -0,0 +1,360 @@
+//! SCTE-35 splice information, ANSI/SCTE 35.
+
+use rav::error::{decode_error, Result};
+
+use crate::psi::crc32;
+
+pub(crate) const TABLE_ID_SPLICE_INFO: u8 = 0xFC;
+
+const SPLICE_NULL: u8 = 0x00;
+const SPLICE_INSERT: u8 = 0x05;
+const TIME_SIGNAL: u8 = 0x06;
+const BANDWIDTH_RESERVATION: u8 = 0x07;
+const PRIVATE_COMMAND: u8 = 0xFF;
+
+const AVAIL_DESCRIPTOR: u8 = 0x00;
+const SEGMENTATION_DESCRIPTOR: u8 = 0x02;
+
+const PTS_MASK: u64 = (1 << 33) - 1;
+
+/// A decoded `splice_info_section`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpliceInfo {
+    pub protocol_version: u8,
+    /// The command and descriptors are encrypted and not decoded.
+    pub encrypted: bool,
+    /// 90 kHz offset to add to the splice times.
+    pub pts_adjustment: u64,
+    pub tier: u16,
+    pub command: SpliceCommand,
+    pub descriptors: Vec<SpliceDescriptor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpliceCommand {
+    Null,
+    Insert(SpliceInsert),
+    /// Splice time in 90 kHz units without the pts_adjustment, `None` for an immediate signal.
+    TimeSignal { pts_time: Option<u64> },
+    BandwidthReservation,
+    Private { identifier: u32, data: Vec<u8> },
+    /// splice_schedule, encrypted or unknown commands.
+    Other { command_type: u8, data: Vec<u8> },
+}
+
+/// Break duration of a splice_insert, in 90 kHz units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakDuration {
+    pub auto_return: bool,
+    pub duration: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpliceInsert {
+    pub event_id: u32,
+    pub cancel: bool,
+    pub out_of_network: bool,
+    pub immediate: bool,
+    /// Splice time of the whole program, in 90 kHz units without the pts_adjustment.
+    pub pts_time: Option<u64>,
+    /// Component tags and their splice times, for component splice mode.
+    pub components: Vec<(u8, Option<u64>)>,
+    pub break_duration: Option<BreakDuration>,
+    pub unique_program_id: u16,
+    pub avail_num: u8,
+    pub avails_expected: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryRestrictions {
+    pub web_delivery_allowed: bool,
+    pub no_regional_blackout: bool,
+    pub archive_allowed: bool,
+    pub device_restrictions: u8,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SegmentationDescriptor {
+    pub event_id: u32,
+    pub cancel: bool,
+    /// `None` when delivery isn't restricted.
+    pub delivery_restrictions: Option<DeliveryRestrictions>,
+    /// Component tags and their PTS offsets, for component segmentation mode.
+    pub components: Vec<(u8, u64)>,
+    /// Duration in 90 kHz units.
+    pub duration: Option<u64>,
+    pub upid_type: u8,
+    pub upid: Vec<u8>,
+    pub type_id: u8,
+    pub segment_num: u8,
+    pub segments_expected: u8,
+    pub sub_segment_num: Option<u8>,
+    pub sub_segments_expected: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpliceDescriptor {
+    Avail { provider_avail_id: u32 },
+    Segmentation(SegmentationDescriptor),
+    Other { tag: u8, identifier: u32, data: Vec<u8> },
+}
+
+impl SpliceInfo {
+    /// Splice time of the command with the pts_adjustment applied, in the program 90 kHz timeline.
+    pub fn splice_pts(&self) -> Option<u64> {
+        let pts_time = match &self.command {
+            SpliceCommand::Insert(insert) => insert.pts_time.or_else(|| insert.components.iter().find_map(|c| c.1)),
+            SpliceCommand::TimeSignal { pts_time } => *pts_time,
+            _ => None,
+        }?;
+        Some((pts_time + self.pts_adjustment) & PTS_MASK)
+    }
+}
+
+/// Byte reader over the splice command and descriptors.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        match self.data.get(self.pos..self.pos + len) {
+            Some(bytes) => {
+                self.pos += len;
+                Ok(bytes)
+            }
+            None => decode_error("scte35: truncated splice_info_section"),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads the 33 low bits of a 5-byte field.
+    fn u33(&mut self) -> Result<u64> {
+        let bytes = self.bytes(5)?;
+        Ok(bytes.iter().fold(0u64, |value, &b| (value << 8) | b as u64) & PTS_MASK)
+    }
+
+    fn splice_time(&mut self) -> Result<Option<u64>> {
+        if self.data.get(self.pos).is_some_and(|&b| b & 0x80 != 0) {
+            Ok(Some(self.u33()?))
+        } else {
+            self.u8()?;
+            Ok(None)
+        }
+    }
+}
+
+fn parse_splice_insert(r: &mut Reader) -> Result<SpliceInsert> {
+    let mut insert = SpliceInsert { event_id: r.u32()?, cancel: r.u8()? & 0x80 != 0, ..Default::default() };
+    if insert.cancel {
+        return Ok(insert);
+    }
+    let flags = r.u8()?;
+    insert.out_of_network = flags & 0x80 != 0;
+    let program_splice = flags & 0x40 != 0;
+    let has_duration = flags & 0x20 != 0;
+    insert.immediate = flags & 0x10 != 0;
+    if program_splice && !insert.immediate {
+        insert.pts_time = r.splice_time()?;
+    }
+    if !program_splice {
+        for _ in 0..r.u8()? {
+            let tag = r.u8()?;
+            let pts_time = if insert.immediate { None } else { r.splice_time()? };
+            insert.components.push((tag, pts_time));
+        }
+    }
+    if has_duration {
+        let auto_return = r.data.get(r.pos).is_some_and(|&b| b & 0x80 != 0);
+        insert.break_duration = Some(BreakDuration { auto_return, duration: r.u33()? });
+    }
+    insert.unique_program_id = r.u16()?;
+    insert.avail_num = r.u8()?;
+    insert.avails_expected = r.u8()?;
+    Ok(insert)
+}
+
+fn parse_segmentation_descriptor(r: &mut Reader) -> Result<SegmentationDescriptor> {
+    let mut desc = SegmentationDescriptor { event_id: r.u32()?, cancel: r.u8()? & 0x80 != 0, ..Default::default() };
+    if desc.cancel {
+        return Ok(desc);
+    }
+    let flags = r.u8()?;
+    let program_segmentation = flags & 0x80 != 0;
+    let has_duration = flags & 0x40 != 0;
+    if flags & 0x20 == 0 {
+        desc.delivery_restrictions = Some(DeliveryRestrictions {
+            web_delivery_allowed: flags & 0x10 != 0,
+            no_regional_blackout: flags & 0x08 != 0,
+            archive_allowed: flags & 0x04 != 0,
+            device_restrictions: flags & 0x03,
+        });
+    }
+    if !program_segmentation {
+        for _ in 0..r.u8()? {
+            let tag = r.u8()?;
+            desc.components.push((tag, r.u33()?));
+        }
+    }
+    if has_duration {
+        let bytes = r.bytes(5)?;
+        desc.duration = Some(bytes.iter().fold(0u64, |value, &b| (value << 8) | b as u64));
+    }
+    desc.upid_type = r.u8()?;
+    let upid_len = r.u8()? as usize;
+    desc.upid = r.bytes(upid_len)?.to_vec();
+    desc.type_id = r.u8()?;
+    desc.segment_num = r.u8()?;
+    desc.segments_expected = r.u8()?;
+    // sub segments of the provider and distributor placement opportunities
+    if matches!(desc.type_id, 0x34 | 0x36 | 0x38 | 0x3A) && r.pos + 2 <= r.data.len() {
+        desc.sub_segment_num = Some(r.u8()?);
+        desc.sub_segments_expected = Some(r.u8()?);
+    }
+    Ok(desc)
+}
+
+fn parse_descriptors(mut data: &[u8]) -> Result<Vec<SpliceDescriptor>> {
+    let mut descriptors = Vec::new();
+    while data.len() >= 2 {
+        let tag = data[0];
+        let len = data[1] as usize;
+        let Some(body) = data.get(2..2 + len).filter(|body| body.len() >= 4) else {
+            return decode_error("scte35: invalid splice descriptor length");
+        };
+        let identifier = u32::from_be_bytes([body[0], body[1], body[2], body[3]]);
+        let mut r = Reader { data: body, pos: 4 };
+        descriptors.push(match tag {
+            AVAIL_DESCRIPTOR => SpliceDescriptor::Avail { provider_avail_id: r.u32()? },
+            SEGMENTATION_DESCRIPTOR => SpliceDescriptor::Segmentation(parse_segmentation_descriptor(&mut r)?),
+            _ => SpliceDescriptor::Other { tag, identifier, data: body[4..].to_vec() },
+        });
+        data = &data[2 + len..];
+    }
+    Ok(descriptors)
+}
+
+/// Decodes a `splice_info_section`, the CRC is checked.
+pub fn parse_splice_info(section: &[u8]) -> Result<SpliceInfo> {
+    if section.len() < 20 || section[0] != TABLE_ID_SPLICE_INFO {
+        return decode_error("scte35: invalid splice_info_section");
+    }
+    let len = 3 + (u16::from_be_bytes([section[1], section[2]]) & 0x0FFF) as usize;
+    // the header up to splice_command_type and the CRC
+    if len < 18 {
+        return decode_error("scte35: invalid splice_info_section");
+    }
+    if len > section.len() || crc32(&section[..len]) != 0 {
+        return decode_error("scte35: splice_info_section crc mismatch");
+    }
+    let section = &section[..len];
+
+    let encrypted = section[4] & 0x80 != 0;
+    let pts_adjustment = ((section[4] & 0x01) as u64) << 32
+        | u32::from_be_bytes([section[5], section[6], section[7], section[8]]) as u64;
+    let tier = u16::from_be_bytes([section[10], section[11]]) >> 4;
+    let command_len = (u16::from_be_bytes([section[11], section[12]]) & 0x0FFF) as usize;
+    let command_type = section[13];
+    let mut info = SpliceInfo {
+        protocol_version: section[3],
+        encrypted,
+        pts_adjustment,
+        tier,
+        command: SpliceCommand::Null,
+        descriptors: Vec::new(),
+    };
+
+    // the data following the command, up to the CRC
+    let body = &section[14..len - 4];
+    if encrypted {
+        info.command = SpliceCommand::Other { command_type, data: body.to_vec() };
+        return Ok(info);
+    }
+    let mut r = Reader { data: body, pos: 0 };
+    info.command = match command_type {
+        SPLICE_NULL => SpliceCommand::Null,
+        SPLICE_INSERT => SpliceCommand::Insert(parse_splice_insert(&mut r)?),
+        TIME_SIGNAL => SpliceCommand::TimeSignal { pts_time: r.splice_time()? },
+        BANDWIDTH_RESERVATION => SpliceCommand::BandwidthReservation,
+        PRIVATE_COMMAND => {
+            let identifier = r.u32()?;
+            // 0xFFF is the legacy unknown command length
+            let data_len = if command_len == 0xFFF { 0 } else { command_len.saturating_sub(4) };
+            SpliceCommand::Private { identifier, data: r.bytes(data_len)?.to_vec() }
+        }
+        _ => {
+            if command_len == 0xFFF {
+                return decode_error("scte35: unknown length of splice command");
+            }
+            SpliceCommand::Other { command_type, data: r.bytes(command_len)?.to_vec() }
+        }
+    };
+    if command_len != 0xFFF {
+        r.pos = command_len;
+    }
+
+    let descriptors_len = r.u16()? as usize;
+    info.descriptors = parse_descriptors(r.bytes(descriptors_len)?)?;
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rav::error::Error;
+
+    #[test]
+    fn splice_insert() {
+        let mut section = vec![0xFC, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xF0, 0x14, SPLICE_INSERT];
+        section.extend([0x00, 0x00, 0x10, 0x01, 0x7F, 0xEF, 0xFE, 0x00, 0x2E, 0xA5, 0x14, 0xFE, 0x00, 0x52, 0xCC, 0xF5]);
+        section.extend([0x00, 0x01, 0x02, 0x03]);
+        section.extend([0x00, 0x0A, AVAIL_DESCRIPTOR, 0x08]);
+        section.extend(b"CUEI");
+        section.extend([0x00, 0x00, 0x01, 0x35]);
+        let len = section.len() + 4 - 3;
+        section[1] = 0x30 | (len >> 8) as u8;
+        section[2] = len as u8;
+        let crc = crc32(&section);
+        section.extend(crc.to_be_bytes());
+
+        let info = parse_splice_info(&section).unwrap();
+        assert_eq!(info.tier, 0xFFF);
+        let SpliceCommand::Insert(insert) = &info.command else { panic!("expected splice_insert") };
+        assert_eq!(insert.event_id, 0x1001);
+        assert!(insert.out_of_network);
+        assert_eq!(insert.pts_time, Some(0x2EA514));
+        assert_eq!(insert.break_duration, Some(BreakDuration { auto_return: true, duration: 0x52CCF5 }));
+        assert_eq!((insert.unique_program_id, insert.avail_num, insert.avails_expected), (1, 2, 3));
+        assert_eq!(info.descriptors, vec![SpliceDescriptor::Avail { provider_avail_id: 0x135 }]);
+        assert_eq!(info.splice_pts(), Some(0x2EA514));
+
+        let last = section.len() - 1;
+        section[last] ^= 1;
+        assert_eq!(parse_splice_info(&section), Err(Error::DecodeError("scte35: splice_info_section crc mismatch")));
+    }
+
+    #[test]
+    fn short_section() {
+        // a section_length of 6 with a valid CRC, followed by stuffing
+        let mut section = vec![0xFC, 0x30, 0x06, 0x00, 0x00];
+        let crc = crc32(&section);
+        section.extend(crc.to_be_bytes());
+        section.resize(24, 0xFF);
+        assert_eq!(crc32(&section[..9]), 0);
+        assert_eq!(parse_splice_info(&section), Err(Error::DecodeError("scte35: invalid splice_info_section")));
+    }
+}
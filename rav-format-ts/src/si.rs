@@ -0,0 +1,554 @@
+//! DVB service information, ETSI EN 300 468.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::psi::{descriptors, SectionHeader};
+use crate::text::decode_dvb_text;
+
+pub(crate) const PID_NIT: u16 = 0x0010;
+pub(crate) const PID_SDT: u16 = 0x0011;
+pub(crate) const PID_EIT: u16 = 0x0012;
+pub(crate) const PID_TDT: u16 = 0x0014;
+
+pub(crate) const TABLE_ID_NIT_ACTUAL: u8 = 0x40;
+pub(crate) const TABLE_ID_NIT_OTHER: u8 = 0x41;
+pub(crate) const TABLE_ID_SDT_ACTUAL: u8 = 0x42;
+pub(crate) const TABLE_ID_SDT_OTHER: u8 = 0x46;
+pub(crate) const TABLE_ID_EIT_PF_ACTUAL: u8 = 0x4E;
+pub(crate) const TABLE_ID_EIT_SCHEDULE_ACTUAL: u8 = 0x50;
+pub(crate) const TABLE_ID_EIT_SCHEDULE_OTHER: u8 = 0x60;
+pub(crate) const TABLE_ID_EIT_SCHEDULE_LAST: u8 = 0x6F;
+pub(crate) const TABLE_ID_TDT: u8 = 0x70;
+pub(crate) const TABLE_ID_TOT: u8 = 0x73;
+
+// descriptor tags
+const DESC_NETWORK_NAME: u8 = 0x40;
+const DESC_SERVICE: u8 = 0x48;
+const DESC_SHORT_EVENT: u8 = 0x4D;
+const DESC_EXTENDED_EVENT: u8 = 0x4E;
+const DESC_CONTENT: u8 = 0x54;
+const DESC_PARENTAL_RATING: u8 = 0x55;
+const DESC_LOCAL_TIME_OFFSET: u8 = 0x58;
+
+/// Modified Julian Date of the Unix epoch.
+const MJD_UNIX_EPOCH: i64 = 40_587;
+
+/// Identifies an updated SI table in change notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiTable {
+    Nit { network_id: u16, actual: bool },
+    Sdt { original_network_id: u16, transport_stream_id: u16, actual: bool },
+    Eit { original_network_id: u16, transport_stream_id: u16, service_id: u16, actual: bool, schedule: bool },
+    /// TDT or TOT, the current time.
+    Time,
+}
+
+/// A transport stream described by a NIT.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NitTransportStream {
+    pub transport_stream_id: u16,
+    pub original_network_id: u16,
+    /// Raw transport descriptors, e.g. delivery system and service list.
+    pub descriptors: Vec<u8>,
+}
+
+/// Network information table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Nit {
+    pub network_id: u16,
+    /// Describes the network carrying this transport stream.
+    pub actual: bool,
+    pub version: u8,
+    pub name: Option<String>,
+    /// Raw network descriptors.
+    pub descriptors: Vec<u8>,
+    pub transport_streams: Vec<NitTransportStream>,
+}
+
+/// A service described by a SDT.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Service {
+    pub service_id: u16,
+    pub eit_schedule: bool,
+    pub eit_present_following: bool,
+    pub running_status: u8,
+    pub free_ca_mode: bool,
+    pub service_type: Option<u8>,
+    pub provider_name: Option<String>,
+    pub name: Option<String>,
+    /// Raw service descriptors.
+    pub descriptors: Vec<u8>,
+}
+
+/// Service description table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sdt {
+    pub original_network_id: u16,
+    pub transport_stream_id: u16,
+    /// Describes this transport stream.
+    pub actual: bool,
+    pub version: u8,
+    pub services: Vec<Service>,
+}
+
+/// Event name and short description.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShortEvent {
+    pub language: String,
+    pub name: String,
+    pub text: String,
+}
+
+/// Event description, consecutive extended event descriptors of a language are merged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtendedEvent {
+    pub language: String,
+    /// Description and value pairs, e.g. cast or director.
+    pub items: Vec<(String, String)>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParentalRating {
+    pub country: String,
+    /// Minimum age is `rating + 3`, 0 is undefined.
+    pub rating: u8,
+}
+
+/// An event of an EIT.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EitEvent {
+    pub event_id: u16,
+    /// Start time in seconds since the Unix epoch, `None` if undefined.
+    pub start_time: Option<i64>,
+    /// Duration in seconds, `None` if undefined.
+    pub duration: Option<u32>,
+    pub running_status: u8,
+    pub free_ca_mode: bool,
+    pub short_events: Vec<ShortEvent>,
+    pub extended_events: Vec<ExtendedEvent>,
+    /// Content genre, as content_nibble_level_1 << 4 | content_nibble_level_2.
+    pub content: Vec<u8>,
+    pub parental_ratings: Vec<ParentalRating>,
+    /// Raw event descriptors.
+    pub descriptors: Vec<u8>,
+}
+
+/// Events of a service, from the EIT present/following and schedule tables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceEvents {
+    pub original_network_id: u16,
+    pub transport_stream_id: u16,
+    pub service_id: u16,
+    /// The service is carried by this transport stream.
+    pub actual: bool,
+    pub present: Option<EitEvent>,
+    pub following: Option<EitEvent>,
+    /// Schedule events by table_id and section_number.
+    schedule_sections: BTreeMap<(u8, u8), Vec<EitEvent>>,
+}
+
+impl ServiceEvents {
+    /// Events of the EIT schedule, sorted by start time.
+    pub fn schedule(&self) -> Vec<&EitEvent> {
+        let mut events: Vec<&EitEvent> = self.schedule_sections.values().flatten().collect();
+        events.sort_by_key(|event| (event.start_time, event.event_id));
+        events
+    }
+}
+
+/// Local time offset of a country region, from the TOT.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalTimeOffset {
+    pub country: String,
+    pub region_id: u8,
+    /// Offset from UTC in minutes.
+    pub offset: i32,
+    /// Time of the next offset change in seconds since the Unix epoch.
+    pub time_of_change: Option<i64>,
+    /// Offset from UTC in minutes after the change.
+    pub next_offset: i32,
+}
+
+/// DVB service information tables received so far.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceInformation {
+    pub networks: Vec<Nit>,
+    pub sdts: Vec<Sdt>,
+    pub service_events: Vec<ServiceEvents>,
+    /// Current time from the TDT/TOT, in seconds since the Unix epoch.
+    pub utc_time: Option<i64>,
+    pub local_time_offsets: Vec<LocalTimeOffset>,
+    /// Versions of the received sections, by table_id, table id_extension, table specific ids and section_number.
+    versions: HashMap<(u8, u16, u32, u8), u8>,
+}
+
+fn bcd(b: u8) -> u32 {
+    (b >> 4) as u32 * 10 + (b & 0x0F) as u32
+}
+
+/// Decodes a 40-bit MJD and BCD UTC time, returns seconds since the Unix epoch.
+pub(crate) fn parse_utc_time(data: &[u8]) -> Option<i64> {
+    if data[..5] == [0xFF; 5] {
+        return None;
+    }
+    let mjd = u16::from_be_bytes([data[0], data[1]]) as i64;
+    let secs = bcd(data[2]) * 3600 + bcd(data[3]) * 60 + bcd(data[4]);
+    Some((mjd - MJD_UNIX_EPOCH) * 86_400 + secs as i64)
+}
+
+/// Decodes a 24-bit BCD duration in seconds.
+fn parse_duration(data: &[u8]) -> Option<u32> {
+    if data[..3] == [0xFF; 3] {
+        return None;
+    }
+    Some(bcd(data[0]) * 3600 + bcd(data[1]) * 60 + bcd(data[2]))
+}
+
+fn language(data: &[u8]) -> String {
+    String::from_utf8_lossy(&data[..3]).into_owned()
+}
+
+/// Returns the descriptor loop prefixed with a 12-bit length and the data following it.
+fn descriptor_loop(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = (u16::from_be_bytes([*data.first()?, *data.get(1)?]) & 0x0FFF) as usize;
+    Some((data.get(2..2 + len)?, &data[2 + len..]))
+}
+
+/// Splits a string prefixed with its 8-bit length.
+fn text_field(data: &[u8]) -> Option<(String, &[u8])> {
+    let len = *data.first()? as usize;
+    Some((decode_dvb_text(data.get(1..1 + len)?), &data[1 + len..]))
+}
+
+fn parse_nit(header: &SectionHeader, data: &[u8], nit: &mut Nit) -> Option<()> {
+    let (network_descriptors, rest) = descriptor_loop(data)?;
+    if header.section_number == 0 || nit.descriptors.is_empty() {
+        nit.descriptors = network_descriptors.to_vec();
+    }
+    for (tag, desc) in descriptors(network_descriptors) {
+        if tag == DESC_NETWORK_NAME {
+            nit.name = Some(decode_dvb_text(desc));
+        }
+    }
+
+    let (mut streams, _) = descriptor_loop(rest)?;
+    while streams.len() >= 6 {
+        let transport_stream_id = u16::from_be_bytes([streams[0], streams[1]]);
+        let original_network_id = u16::from_be_bytes([streams[2], streams[3]]);
+        let (descriptors, rest) = descriptor_loop(&streams[4..])?;
+        nit.transport_streams.retain(|ts| {
+            (ts.transport_stream_id, ts.original_network_id) != (transport_stream_id, original_network_id)
+        });
+        nit.transport_streams.push(NitTransportStream {
+            transport_stream_id,
+            original_network_id,
+            descriptors: descriptors.to_vec(),
+        });
+        streams = rest;
+    }
+    Some(())
+}
+
+fn parse_sdt(data: &[u8], sdt: &mut Sdt) -> Option<()> {
+    let mut services = data.get(3..)?;
+    while services.len() >= 5 {
+        let service_id = u16::from_be_bytes([services[0], services[1]]);
+        let flags = services[2];
+        let status = services[3];
+        let (descriptors, rest) = descriptor_loop(&services[3..])?;
+        let mut service = Service {
+            service_id,
+            eit_schedule: flags & 0x02 != 0,
+            eit_present_following: flags & 0x01 != 0,
+            running_status: status >> 5,
+            free_ca_mode: status & 0x10 != 0,
+            descriptors: descriptors.to_vec(),
+            ..Default::default()
+        };
+        for (tag, desc) in self::descriptors(descriptors) {
+            if tag == DESC_SERVICE && !desc.is_empty() {
+                service.service_type = Some(desc[0]);
+                if let Some((provider_name, rest)) = text_field(&desc[1..]) {
+                    service.provider_name = Some(provider_name);
+                    service.name = text_field(rest).map(|(name, _)| name);
+                }
+            }
+        }
+        sdt.services.retain(|s| s.service_id != service_id);
+        sdt.services.push(service);
+        services = rest;
+    }
+    Some(())
+}
+
+fn parse_event_descriptors(event: &mut EitEvent, data: &[u8]) {
+    for (tag, desc) in descriptors(data) {
+        match tag {
+            DESC_SHORT_EVENT if desc.len() >= 4 => {
+                let Some((name, rest)) = text_field(&desc[3..]) else {
+                    continue;
+                };
+                let text = text_field(rest).map(|(text, _)| text).unwrap_or_default();
+                event.short_events.push(ShortEvent { language: language(desc), name, text });
+            }
+            DESC_EXTENDED_EVENT if desc.len() >= 5 => {
+                let descriptor_number = desc[0] >> 4;
+                let language = language(&desc[1..]);
+                let items_len = desc[4] as usize;
+                let Some(mut items_data) = desc.get(5..5 + items_len) else {
+                    continue;
+                };
+                let mut items = Vec::new();
+                while let Some((description, rest)) = text_field(items_data) {
+                    let Some((item, rest)) = text_field(rest) else {
+                        break;
+                    };
+                    items.push((description, item));
+                    items_data = rest;
+                }
+                let text = text_field(&desc[5 + items_len..]).map(|(text, _)| text).unwrap_or_default();
+
+                match event.extended_events.last_mut() {
+                    Some(extended) if descriptor_number > 0 && extended.language == language => {
+                        extended.items.extend(items);
+                        extended.text.push_str(&text);
+                    }
+                    _ => event.extended_events.push(ExtendedEvent { language, items, text }),
+                }
+            }
+            DESC_CONTENT => event.content.extend(desc.chunks_exact(2).map(|content| content[0])),
+            DESC_PARENTAL_RATING => {
+                for rating in desc.chunks_exact(4) {
+                    event.parental_ratings.push(ParentalRating { country: language(rating), rating: rating[3] });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_eit_events(mut data: &[u8]) -> Option<Vec<EitEvent>> {
+    let mut events = Vec::new();
+    while data.len() >= 12 {
+        let (descriptors, rest) = descriptor_loop(&data[10..])?;
+        let mut event = EitEvent {
+            event_id: u16::from_be_bytes([data[0], data[1]]),
+            start_time: parse_utc_time(&data[2..7]),
+            duration: parse_duration(&data[7..10]),
+            running_status: data[10] >> 5,
+            free_ca_mode: data[10] & 0x10 != 0,
+            descriptors: descriptors.to_vec(),
+            ..Default::default()
+        };
+        parse_event_descriptors(&mut event, descriptors);
+        events.push(event);
+        data = rest;
+    }
+    Some(events)
+}
+
+fn parse_local_time_offsets(data: &[u8]) -> Vec<LocalTimeOffset> {
+    let offset = |sign_negative: bool, data: &[u8]| {
+        let minutes = (bcd(data[0]) * 60 + bcd(data[1])) as i32;
+        if sign_negative { -minutes } else { minutes }
+    };
+    let mut offsets = Vec::new();
+    for (tag, desc) in descriptors(data) {
+        if tag != DESC_LOCAL_TIME_OFFSET {
+            continue;
+        }
+        for entry in desc.chunks_exact(13) {
+            let negative = entry[3] & 0x01 != 0;
+            offsets.push(LocalTimeOffset {
+                country: language(entry),
+                region_id: entry[3] >> 2,
+                offset: offset(negative, &entry[4..6]),
+                time_of_change: parse_utc_time(&entry[6..11]),
+                next_offset: offset(negative, &entry[11..13]),
+            });
+        }
+    }
+    offsets
+}
+
+impl ServiceInformation {
+    /// Returns true if the section was already parsed with the same version.
+    fn is_known(&self, header: &SectionHeader, ids: u32) -> bool {
+        let key = (header.table_id, header.id_extension, ids, header.section_number);
+        self.versions.get(&key) == Some(&header.version)
+    }
+
+    /// Records the version of a section parsed successfully, so a corrupt section is parsed again.
+    fn set_known(&mut self, header: &SectionHeader, ids: u32) {
+        self.versions.insert((header.table_id, header.id_extension, ids, header.section_number), header.version);
+    }
+
+    /// Handles a long syntax SI section, returns the updated table.
+    pub(crate) fn handle_section(&mut self, header: &SectionHeader, data: &[u8]) -> Option<SiTable> {
+        match header.table_id {
+            TABLE_ID_NIT_ACTUAL | TABLE_ID_NIT_OTHER => {
+                if self.is_known(header, 0) {
+                    return None;
+                }
+                let network_id = header.id_extension;
+                let actual = header.table_id == TABLE_ID_NIT_ACTUAL;
+                let idx = match self.networks.iter().position(|nit| nit.network_id == network_id && nit.actual == actual) {
+                    Some(idx) => idx,
+                    None => {
+                        self.networks.push(Nit { network_id, actual, ..Default::default() });
+                        self.networks.len() - 1
+                    }
+                };
+                let nit = &mut self.networks[idx];
+                if nit.version != header.version {
+                    *nit = Nit { network_id, actual, version: header.version, ..Default::default() };
+                }
+                parse_nit(header, data, nit)?;
+                self.set_known(header, 0);
+                Some(SiTable::Nit { network_id, actual })
+            }
+            TABLE_ID_SDT_ACTUAL | TABLE_ID_SDT_OTHER => {
+                let original_network_id = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+                if self.is_known(header, original_network_id as u32) {
+                    return None;
+                }
+                let transport_stream_id = header.id_extension;
+                let actual = header.table_id == TABLE_ID_SDT_ACTUAL;
+                let idx = match self.sdts.iter().position(|sdt| {
+                    (sdt.original_network_id, sdt.transport_stream_id) == (original_network_id, transport_stream_id)
+                }) {
+                    Some(idx) => idx,
+                    None => {
+                        self.sdts.push(Sdt { original_network_id, transport_stream_id, actual, ..Default::default() });
+                        self.sdts.len() - 1
+                    }
+                };
+                let sdt = &mut self.sdts[idx];
+                if sdt.version != header.version {
+                    sdt.version = header.version;
+                    sdt.services.clear();
+                }
+                parse_sdt(data, sdt)?;
+                self.set_known(header, original_network_id as u32);
+                Some(SiTable::Sdt { original_network_id, transport_stream_id, actual })
+            }
+            TABLE_ID_EIT_PF_ACTUAL..=TABLE_ID_EIT_SCHEDULE_LAST => {
+                let ids = data.get(..4)?;
+                let known_ids = u32::from_be_bytes([ids[0], ids[1], ids[2], ids[3]]);
+                if self.is_known(header, known_ids) {
+                    return None;
+                }
+                let transport_stream_id = u16::from_be_bytes([ids[0], ids[1]]);
+                let original_network_id = u16::from_be_bytes([ids[2], ids[3]]);
+                let service_id = header.id_extension;
+                let actual = header.table_id == TABLE_ID_EIT_PF_ACTUAL
+                    || (TABLE_ID_EIT_SCHEDULE_ACTUAL..TABLE_ID_EIT_SCHEDULE_OTHER).contains(&header.table_id);
+                let events = parse_eit_events(data.get(6..)?)?;
+                self.set_known(header, known_ids);
+
+                let idx = match self.service_events.iter().position(|service| {
+                    (service.original_network_id, service.transport_stream_id, service.service_id)
+                        == (original_network_id, transport_stream_id, service_id)
+                }) {
+                    Some(idx) => idx,
+                    None => {
+                        self.service_events.push(ServiceEvents {
+                            original_network_id,
+                            transport_stream_id,
+                            service_id,
+                            actual,
+                            ..Default::default()
+                        });
+                        self.service_events.len() - 1
+                    }
+                };
+                let service = &mut self.service_events[idx];
+                let schedule = header.table_id >= TABLE_ID_EIT_SCHEDULE_ACTUAL;
+                if schedule {
+                    service.schedule_sections.insert((header.table_id, header.section_number), events);
+                } else {
+                    let event = events.into_iter().next();
+                    match header.section_number {
+                        0 => service.present = event,
+                        1 => service.following = event,
+                        _ => {}
+                    }
+                }
+                Some(SiTable::Eit { original_network_id, transport_stream_id, service_id, actual, schedule })
+            }
+            _ => None,
+        }
+    }
+
+    /// Handles a TDT or TOT section, the TOT CRC must have been checked.
+    pub(crate) fn handle_time_section(&mut self, section: &[u8]) -> Option<SiTable> {
+        let utc_time = parse_utc_time(section.get(3..8)?)?;
+        if section[0] == TABLE_ID_TOT {
+            let (descriptors, _) = descriptor_loop(section.get(8..)?)?;
+            self.local_time_offsets = parse_local_time_offsets(descriptors);
+        }
+        self.utc_time = Some(utc_time);
+        Some(SiTable::Time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc_time_and_duration() {
+        // 1993-10-13 12:45:00, the example of EN 300 468 Annex C
+        assert_eq!(parse_utc_time(&[0xC0, 0x79, 0x12, 0x45, 0x00]), Some(750_516_300));
+        assert_eq!(parse_utc_time(&[0xFF; 5]), None);
+        assert_eq!(parse_duration(&[0x01, 0x45, 0x30]), Some(6330));
+    }
+
+    #[test]
+    fn event_descriptors() {
+        let mut data = vec![DESC_SHORT_EVENT, 14];
+        data.extend(b"eng\x04News\x05Daily");
+        data.extend([DESC_EXTENDED_EVENT, 18, 0x01]);
+        data.extend(b"eng\x0A\x04Cast\x04Anna");
+        data.extend(b"\x02Hi");
+        data.extend([DESC_EXTENDED_EVENT, 10, 0x11]);
+        data.extend(b"eng\x00\x04 all");
+        data.extend([DESC_CONTENT, 2, 0x23, 0x00, DESC_PARENTAL_RATING, 4]);
+        data.extend(b"DEU\x09");
+
+        let mut event = EitEvent::default();
+        parse_event_descriptors(&mut event, &data);
+        assert_eq!(event.short_events, vec![ShortEvent {
+            language: "eng".into(),
+            name: "News".into(),
+            text: "Daily".into(),
+        }]);
+        assert_eq!(event.extended_events, vec![ExtendedEvent {
+            language: "eng".into(),
+            items: vec![("Cast".into(), "Anna".into())],
+            text: "Hi all".into(),
+        }]);
+        assert_eq!(event.content, vec![0x23]);
+        assert_eq!(event.parental_ratings, vec![ParentalRating { country: "DEU".into(), rating: 9 }]);
+    }
+
+    #[test]
+    fn corrupt_section_not_recorded() {
+        let header = SectionHeader {
+            table_id: TABLE_ID_SDT_ACTUAL,
+            id_extension: 1,
+            version: 3,
+            current_next: true,
+            section_number: 0,
+            last_section_number: 0,
+        };
+        let mut si = ServiceInformation::default();
+        // the descriptor loop of the service overruns the section
+        assert_eq!(si.handle_section(&header, &[0, 2, 0xFF, 0, 7, 0x01, 0x0F, 0xFF]), None);
+        let sdt = [0, 2, 0xFF, 0, 7, 0x01, 0x80, 0x00];
+        assert!(si.handle_section(&header, &sdt).is_some());
+        assert_eq!(si.sdts[0].services[0].service_id, 7);
+        assert_eq!(si.handle_section(&header, &sdt), None);
+    }
+}
@@ -0,0 +1,293 @@
+//! Teletext subtitle pages carried in DVB PES packets, ETSI EN 300 472 and EN 300 706.
+
+use std::collections::HashMap;
+
+const DATA_UNIT_EBU_TELETEXT: u8 = 0x02;
+const DATA_UNIT_EBU_TELETEXT_SUBTITLE: u8 = 0x03;
+const DATA_UNIT_LEN: usize = 44;
+const FRAMING_CODE: u8 = 0xE4;
+
+/// Rows 1 to 23 carry the page content.
+const ROWS: usize = 24;
+const COLUMNS: usize = 40;
+
+/// Characters of the G0 Latin national option subsets, replacing 0x23, 0x24, 0x40, 0x5B-0x60 and 0x7B-0x7E.
+const NATIONAL_POSITIONS: [u8; 13] = [0x23, 0x24, 0x40, 0x5B, 0x5C, 0x5D, 0x5E, 0x5F, 0x60, 0x7B, 0x7C, 0x7D, 0x7E];
+
+/// National option subsets indexed by the C12-C14 page header bits.
+const NATIONAL_SUBSETS: [[char; 13]; 8] = [
+    // English
+    ['£', '$', '@', '←', '½', '→', '↑', '#', '—', '¼', '‖', '¾', '÷'],
+    // French
+    ['é', 'ï', 'à', 'ë', 'ê', 'ù', 'î', '#', 'è', 'â', 'ô', 'û', 'ç'],
+    // Swedish, Finnish, Hungarian
+    ['#', '¤', 'É', 'Ä', 'Ö', 'Å', 'Ü', '_', 'é', 'ä', 'ö', 'å', 'ü'],
+    // Czech, Slovak
+    ['#', 'ů', 'č', 'ť', 'ž', 'ý', 'í', 'ř', 'é', 'á', 'ě', 'ú', 'š'],
+    // German
+    ['#', '$', '§', 'Ä', 'Ö', 'Ü', '^', '_', '°', 'ä', 'ö', 'ü', 'ß'],
+    // Portuguese, Spanish
+    ['ç', '$', '¡', 'á', 'é', 'í', 'ó', 'ú', '¿', 'ü', 'ñ', 'è', 'à'],
+    // Italian
+    ['£', '$', 'é', '°', 'ç', '→', '↑', '#', 'ù', 'à', 'ò', 'è', 'ì'],
+    // unassigned, English is used
+    ['£', '$', '@', '←', '½', '→', '↑', '#', '—', '¼', '‖', '¾', '÷'],
+];
+
+/// A subtitle page with the time it was displayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TeletextCaption {
+    /// Page number as displayed, e.g. 888.
+    pub page: u16,
+    /// Display start, in the PTS units of the decoded packets.
+    pub start: i64,
+    pub end: i64,
+    /// Non-empty rows, separated by newlines.
+    pub text: String,
+}
+
+/// Decodes the data bits of a Hamming 8/4 coded byte, transmission errors aren't corrected.
+fn hamming_8_4(b: u8) -> u8 {
+    (b >> 1 & 0x01) | (b >> 2 & 0x02) | (b >> 3 & 0x04) | (b >> 4 & 0x08)
+}
+
+/// A page being received.
+#[derive(Debug, Clone)]
+struct PageBuffer {
+    page: u16,
+    /// PTS of the page header.
+    pts: i64,
+    national_subset: usize,
+    rows: [[u8; COLUMNS]; ROWS],
+}
+
+impl PageBuffer {
+    fn text(&self) -> String {
+        let subset = &NATIONAL_SUBSETS[self.national_subset];
+        let mut lines = Vec::new();
+        for row in &self.rows[1..] {
+            let line: String = row.iter()
+                .map(|&c| match NATIONAL_POSITIONS.iter().position(|&p| p == c) {
+                    Some(idx) => subset[idx],
+                    // spacing attributes are displayed as spaces
+                    None if c < 0x20 || c == 0x7F => ' ',
+                    None => c as char,
+                })
+                .collect();
+            let line = line.trim();
+            if !line.is_empty() {
+                lines.push(line.to_string());
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// Turns the teletext subtitle pages of a stream into timed captions.
+#[derive(Debug, Default)]
+pub struct TeletextDecoder {
+    /// Page to decode, all subtitle pages if `None`.
+    page: Option<u16>,
+    /// Pages being received, by magazine.
+    magazines: [Option<PageBuffer>; 8],
+    /// Pages on screen with their start time.
+    displayed: HashMap<u16, (i64, PageBuffer)>,
+}
+
+impl TeletextDecoder {
+    /// Creates a decoder for a page number, e.g. 888, or for all pages flagged as subtitles.
+    pub fn new(page: Option<u16>) -> Self {
+        Self { page, ..Default::default() }
+    }
+
+    /// Decodes the payload of a teletext PES packet, returns the captions which ended.
+    pub fn decode(&mut self, pts: i64, data: &[u8]) -> Vec<TeletextCaption> {
+        let mut captions = Vec::new();
+        // EBU data identifiers
+        if !matches!(data.first(), Some(0x10..=0x1F)) {
+            return captions;
+        }
+        let mut units = &data[1..];
+        while units.len() >= 2 {
+            let (unit_id, len) = (units[0], units[1] as usize);
+            let Some(unit) = units.get(2..2 + len) else {
+                break;
+            };
+            if matches!(unit_id, DATA_UNIT_EBU_TELETEXT | DATA_UNIT_EBU_TELETEXT_SUBTITLE)
+                && len == DATA_UNIT_LEN
+                && unit[1] == FRAMING_CODE
+            {
+                // bits are transmitted in the reverse order
+                let mut packet = [0u8; DATA_UNIT_LEN - 2];
+                for (dst, src) in packet.iter_mut().zip(&unit[2..]) {
+                    *dst = src.reverse_bits();
+                }
+                self.decode_packet(pts, &packet, &mut captions);
+            }
+            units = &units[2 + len..];
+        }
+        captions
+    }
+
+    /// Ends the displayed pages at `pts`, e.g. at the end of the stream.
+    pub fn flush(&mut self, pts: i64) -> Vec<TeletextCaption> {
+        let mut captions = Vec::new();
+        for magazine in 0..self.magazines.len() {
+            self.finish_page(magazine, &mut captions);
+        }
+        let mut displayed: Vec<_> = self.displayed.drain().collect();
+        displayed.sort_by_key(|(page, (start, _))| (*start, *page));
+        for (page, (start, buffer)) in displayed {
+            captions.push(TeletextCaption { page, start, end: pts, text: buffer.text() });
+        }
+        captions
+    }
+
+    fn decode_packet(&mut self, pts: i64, packet: &[u8], captions: &mut Vec<TeletextCaption>) {
+        let address = hamming_8_4(packet[0]) | hamming_8_4(packet[1]) << 4;
+        let magazine = (address & 0x07) as usize;
+        let row = (address >> 3) as usize;
+        let data = &packet[2..];
+
+        if row == 0 {
+            let units = hamming_8_4(data[0]);
+            let tens = hamming_8_4(data[1]);
+            let control = [hamming_8_4(data[3]), hamming_8_4(data[5]), hamming_8_4(data[6]), hamming_8_4(data[7])];
+            let serial = control[3] & 0x01 != 0;
+
+            // a header ends the page being received on the magazine, or all of them in serial mode
+            if serial {
+                for magazine in 0..self.magazines.len() {
+                    self.finish_page(magazine, captions);
+                }
+            } else {
+                self.finish_page(magazine, captions);
+            }
+
+            // 0xFF is a time filling header
+            if units > 9 || tens > 9 {
+                return;
+            }
+            let page = if magazine == 0 { 800 } else { magazine as u16 * 100 } + tens as u16 * 10 + units as u16;
+            let subtitle = control[1] & 0x08 != 0;
+            if self.page.map_or(!subtitle, |selected| selected != page) {
+                return;
+            }
+            let mut rows = [[b' '; COLUMNS]; ROWS];
+            if control[0] & 0x08 == 0 {
+                // without the erase flag, the page updates the displayed one
+                if let Some((_, displayed)) = self.displayed.get(&page) {
+                    rows = displayed.rows;
+                }
+            }
+            self.magazines[magazine] = Some(PageBuffer {
+                page,
+                pts,
+                national_subset: (control[3] >> 1) as usize & 0x07,
+                rows,
+            });
+        } else if row < ROWS {
+            if let Some(buffer) = self.magazines[magazine].as_mut() {
+                for (dst, &c) in buffer.rows[row].iter_mut().zip(data) {
+                    *dst = c & 0x7F;
+                }
+            }
+        }
+    }
+
+    /// Displays the page received on a magazine, ending the caption it replaces.
+    fn finish_page(&mut self, magazine: usize, captions: &mut Vec<TeletextCaption>) {
+        let Some(buffer) = self.magazines[magazine].take() else {
+            return;
+        };
+        let text = buffer.text();
+        if let Some((start, displayed)) = self.displayed.get(&buffer.page) {
+            let displayed_text = displayed.text();
+            // retransmission of the displayed page
+            if displayed_text == text {
+                return;
+            }
+            captions.push(TeletextCaption { page: buffer.page, start: *start, end: buffer.pts, text: displayed_text });
+            self.displayed.remove(&buffer.page);
+        }
+        if !text.is_empty() {
+            self.displayed.insert(buffer.page, (buffer.pts, buffer));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hamming(value: u8) -> u8 {
+        (value & 0x01) << 1 | (value & 0x02) << 2 | (value & 0x04) << 3 | (value & 0x08) << 4
+    }
+
+    fn data_unit(magazine: u8, row: u8, data: &[u8; 40]) -> Vec<u8> {
+        let address = magazine & 0x07 | row << 3;
+        let mut packet = vec![hamming(address & 0x0F), hamming(address >> 4)];
+        packet.extend_from_slice(data);
+        let mut unit = vec![DATA_UNIT_EBU_TELETEXT_SUBTITLE, DATA_UNIT_LEN as u8, 0xC0, FRAMING_CODE];
+        unit.extend(packet.iter().map(|b| b.reverse_bits()));
+        unit
+    }
+
+    fn header(magazine: u8, page: u8, erase: bool, subtitle: bool, national_subset: u8) -> Vec<u8> {
+        let mut data = [b' '; 40];
+        data[..8].copy_from_slice(&[
+            hamming(page & 0x0F),
+            hamming(page >> 4),
+            hamming(0),
+            hamming((erase as u8) << 3),
+            hamming(0),
+            hamming((subtitle as u8) << 3),
+            hamming(0),
+            hamming(national_subset << 1),
+        ]);
+        data_unit(magazine, 0, &data)
+    }
+
+    fn row(magazine: u8, row: u8, text: &str) -> Vec<u8> {
+        let mut data = [b' '; 40];
+        // start box and double height attributes before the text
+        data[..3].copy_from_slice(&[0x0B, 0x0B, 0x0D]);
+        data[3..3 + text.len()].copy_from_slice(text.as_bytes());
+        data_unit(magazine, row, &data)
+    }
+
+    fn pes(units: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0x10];
+        units.iter().for_each(|unit| data.extend(unit));
+        data
+    }
+
+    #[test]
+    fn subtitle_pages() {
+        let mut decoder = TeletextDecoder::new(Some(888));
+        // the caption is displayed once the next header of magazine 8 arrives
+        let first = pes(&[header(0, 0x88, true, true, 0), row(0, 20, "Hello"), row(0, 22, "[world]")]);
+        assert!(decoder.decode(1000, &first).is_empty());
+        // other pages are ignored
+        assert!(decoder.decode(1500, &pes(&[header(1, 0x00, true, false, 0), row(1, 1, "Index")])).is_empty());
+        assert!(decoder.decode(2000, &pes(&[header(0, 0x88, true, true, 4), row(0, 22, "[Grusse]")])).is_empty());
+        // a retransmission keeps the caption on screen
+        let captions = decoder.decode(3000, &pes(&[header(0, 0x88, true, true, 4), row(0, 22, "[Grusse]")]));
+        assert_eq!(captions, vec![TeletextCaption { page: 888, start: 1000, end: 2000, text: "Hello\n←world→".into() }]);
+        // an empty page clears the screen
+        assert!(decoder.decode(4000, &pes(&[header(0, 0x88, true, true, 0)])).is_empty());
+        let captions = decoder.decode(5000, &pes(&[header(0, 0xFF, false, false, 0)]));
+        assert_eq!(captions, vec![TeletextCaption { page: 888, start: 2000, end: 4000, text: "ÄGrusseÜ".into() }]);
+        assert!(decoder.flush(6000).is_empty());
+
+        // without a page, subtitle pages are decoded and the update of a page keeps its other rows
+        let mut decoder = TeletextDecoder::new(None);
+        decoder.decode(1000, &pes(&[header(0, 0x88, true, true, 0), row(0, 1, "Top")]));
+        decoder.decode(2000, &pes(&[header(0, 0x88, false, true, 0), row(0, 2, "Bottom")]));
+        decoder.decode(2500, &pes(&[header(1, 0x00, true, false, 0), row(1, 1, "Index")]));
+        assert_eq!(decoder.flush(3000), vec![
+            TeletextCaption { page: 888, start: 1000, end: 2000, text: "Top".into() },
+            TeletextCaption { page: 888, start: 2000, end: 3000, text: "Top\nBottom".into() },
+        ]);
+    }
+}
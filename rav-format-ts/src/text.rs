@@ -0,0 +1,266 @@
+//! DVB text strings, ETSI EN 300 468 Annex A.
+
+/// Character table selected by the first bytes of a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Charset {
+    /// Table 00, ISO/IEC 6937 with the DVB changes.
+    Iso6937,
+    /// ISO/IEC 8859 part.
+    Iso8859(u8),
+    /// ISO/IEC 10646 Basic Multilingual Plane, as UCS-2 big endian.
+    Ucs2,
+    Utf8,
+    /// KS X 1001, GB-2312, Big5 and encoding_type_id, not decoded.
+    Unsupported,
+}
+
+/// Combining diacritical marks of table 00, in the 0xC1-0xCF range.
+const DIACRITICS: [Option<char>; 15] = [
+    Some('\u{0300}'),
+    Some('\u{0301}'),
+    Some('\u{0302}'),
+    Some('\u{0303}'),
+    Some('\u{0304}'),
+    Some('\u{0306}'),
+    Some('\u{0307}'),
+    Some('\u{0308}'),
+    None,
+    Some('\u{030A}'),
+    Some('\u{0327}'),
+    None,
+    Some('\u{030B}'),
+    Some('\u{0328}'),
+    Some('\u{030C}'),
+];
+
+/// Precomposed characters for the common diacritic and letter pairs of table 00.
+const COMPOSED: &[(u8, &str, &str)] = &[
+    (0xC1, "AEIOUaeiou", "ÀÈÌÒÙàèìòù"),
+    (0xC2, "ACEINOSUYZacegilnosuyz", "ÁĆÉÍŃÓŚÚÝŹáćéģíĺńóśúýź"),
+    (0xC3, "ACEGHIJOSUWYaceghijosuwy", "ÂĈÊĜĤÎĴÔŜÛŴŶâĉêĝĥîĵôŝûŵŷ"),
+    (0xC4, "AINOUainou", "ÃĨÑÕŨãĩñõũ"),
+    (0xC5, "AEIOUaeiou", "ĀĒĪŌŪāēīōū"),
+    (0xC6, "AGUagu", "ĂĞŬăğŭ"),
+    (0xC7, "CEGIZcegz", "ĊĖĠİŻċėġż"),
+    (0xC8, "AEIOUYaeiouy", "ÄËÏÖÜŸäëïöüÿ"),
+    (0xCA, "AUau", "ÅŮåů"),
+    (0xCB, "CGKLNRSTcklnrst", "ÇĢĶĻŅŖŞŢçķļņŗşţ"),
+    (0xCD, "OUou", "ŐŰőű"),
+    (0xCE, "AEIUaeiu", "ĄĘĮŲąęįų"),
+    (0xCF, "CDELNRSTZcdelnrstz", "ČĎĚĽŇŘŠŤŽčďěľňřšťž"),
+];
+
+/// Table 00 characters in the 0xA0-0xFF range, `\0` for unassigned codes.
+const ISO6937_HIGH: [char; 96] = [
+    '\u{00A0}', '¡', '¢', '£', '\0', '¥', '\0', '§', '¤', '‘', '“', '«', '←', '↑', '→', '↓',
+    '°', '±', '²', '³', '×', 'µ', '¶', '·', '÷', '’', '”', '»', '¼', '½', '¾', '¿',
+    '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0', '\0',
+    '―', '¹', '®', '©', '™', '♪', '¬', '¦', '\0', '\0', '\0', '\0', '⅛', '⅜', '⅝', '⅞',
+    'Ω', 'Æ', 'Đ', 'ª', 'Ħ', '\0', 'Ĳ', 'Ŀ', 'Ł', 'Ø', 'Œ', 'º', 'Þ', 'Ŧ', 'Ŋ', 'ŉ',
+    'ĸ', 'æ', 'đ', 'ð', 'ħ', 'ı', 'ĳ', 'ŀ', 'ł', 'ø', 'œ', 'ß', 'þ', 'ŧ', 'ŋ', '\u{00AD}',
+];
+
+/// ISO/IEC 8859-2 characters in the 0xA0-0xFF range.
+const ISO8859_2_HIGH: [char; 96] = [
+    '\u{00A0}', 'Ą', '˘', 'Ł', '¤', 'Ľ', 'Ś', '§', '¨', 'Š', 'Ş', 'Ť', 'Ź', '\u{00AD}', 'Ž', 'Ż',
+    '°', 'ą', '˛', 'ł', '´', 'ľ', 'ś', 'ˇ', '¸', 'š', 'ş', 'ť', 'ź', '˝', 'ž', 'ż',
+    'Ŕ', 'Á', 'Â', 'Ă', 'Ä', 'Ĺ', 'Ć', 'Ç', 'Č', 'É', 'Ę', 'Ë', 'Ě', 'Í', 'Î', 'Ď',
+    'Đ', 'Ń', 'Ň', 'Ó', 'Ô', 'Ő', 'Ö', '×', 'Ř', 'Ů', 'Ú', 'Ű', 'Ü', 'Ý', 'Ţ', 'ß',
+    'ŕ', 'á', 'â', 'ă', 'ä', 'ĺ', 'ć', 'ç', 'č', 'é', 'ę', 'ë', 'ě', 'í', 'î', 'ď',
+    'đ', 'ń', 'ň', 'ó', 'ô', 'ő', 'ö', '÷', 'ř', 'ů', 'ú', 'ű', 'ü', 'ý', 'ţ', '˙',
+];
+
+/// Selects the character table, returns it with the number of selection bytes.
+fn charset(data: &[u8]) -> (Charset, usize) {
+    match data.first() {
+        None => (Charset::Iso6937, 0),
+        Some(&b) if b >= 0x20 => (Charset::Iso6937, 0),
+        Some(&b @ 0x01..=0x0B) => (Charset::Iso8859(b + 4), 1),
+        Some(0x10) => match data.get(1..3) {
+            Some(&[0x00, part]) if (1..=15).contains(&part) => (Charset::Iso8859(part), 3),
+            _ => (Charset::Unsupported, data.len().min(3)),
+        },
+        Some(0x11) => (Charset::Ucs2, 1),
+        Some(0x15) => (Charset::Utf8, 1),
+        Some(0x1F) => (Charset::Unsupported, data.len().min(2)),
+        Some(_) => (Charset::Unsupported, 1),
+    }
+}
+
+/// Maps the 0x80-0x9F control codes, emphasis codes are dropped and the CR/LF code becomes a newline.
+fn control(code: u32) -> Option<char> {
+    (code == 0x8A).then_some('\n')
+}
+
+fn iso8859(part: u8, b: u8) -> Option<char> {
+    let c = b as u32;
+    if b < 0xA0 {
+        return char::from_u32(c);
+    }
+    let unicode = match part {
+        1 => c,
+        2 => return Some(ISO8859_2_HIGH[(b - 0xA0) as usize]),
+        5 => match b {
+            0xA0 | 0xAD => c,
+            0xF0 => 0x2116,
+            0xFD => 0xA7,
+            _ => c + 0x360,
+        },
+        6 => match b {
+            0xA0 | 0xA4 | 0xAD => c,
+            0xAC | 0xBB | 0xBF | 0xC1..=0xDA | 0xE0..=0xF2 => c + 0x560,
+            _ => return None,
+        },
+        7 => match b {
+            0xA1 => 0x2018,
+            0xA2 => 0x2019,
+            0xA4 => 0x20AC,
+            0xA5 => 0x20AF,
+            0xAA => 0x37A,
+            0xAF => 0x2015,
+            0xA0 | 0xA3 | 0xA6..=0xA9 | 0xAB..=0xAD | 0xB0..=0xB3 | 0xB7 | 0xBB | 0xBD => c,
+            0xAE | 0xD2 | 0xFF => return None,
+            _ => c + 0x2D0,
+        },
+        8 => match b {
+            0xAA => 0xD7,
+            0xBA => 0xF7,
+            0xDF => 0x2017,
+            0xE0..=0xFA => c + 0x4F0,
+            0xFD => 0x200E,
+            0xFE => 0x200F,
+            0xA0 | 0xA2..=0xB9 | 0xBB..=0xBE => c,
+            _ => return None,
+        },
+        9 => match b {
+            0xD0 => 0x11E,
+            0xDD => 0x130,
+            0xDE => 0x15E,
+            0xF0 => 0x11F,
+            0xFD => 0x131,
+            0xFE => 0x15F,
+            _ => c,
+        },
+        11 => match b {
+            0xA0 => c,
+            0xA1..=0xDA | 0xDF..=0xFB => c + 0xD60,
+            _ => return None,
+        },
+        15 => match b {
+            0xA4 => 0x20AC,
+            0xA6 => 0x160,
+            0xA8 => 0x161,
+            0xB4 => 0x17D,
+            0xB8 => 0x17E,
+            0xBC => 0x152,
+            0xBD => 0x153,
+            0xBE => 0x178,
+            _ => c,
+        },
+        _ => return None,
+    };
+    char::from_u32(unicode)
+}
+
+fn decode_iso6937(data: &[u8], out: &mut String) {
+    let mut i = 0;
+    while i < data.len() {
+        let b = data[i];
+        i += 1;
+        match b {
+            0x20..=0x7E => out.push(b as char),
+            0x80..=0x9F => out.extend(control(b as u32)),
+            0xC1..=0xCF => {
+                let Some(mark) = DIACRITICS[(b - 0xC1) as usize] else {
+                    continue;
+                };
+                let Some(&base) = data.get(i).filter(|base| base.is_ascii_alphabetic()) else {
+                    out.push(mark);
+                    continue;
+                };
+                i += 1;
+                let composed = COMPOSED.iter()
+                    .find(|(code, _, _)| *code == b)
+                    .and_then(|(_, bases, chars)| bases.find(base as char).and_then(|idx| chars.chars().nth(idx)));
+                match composed {
+                    Some(c) => out.push(c),
+                    None => {
+                        out.push(base as char);
+                        out.push(mark);
+                    }
+                }
+            }
+            0xA0..=0xFF => match ISO6937_HIGH[(b - 0xA0) as usize] {
+                '\0' => {}
+                c => out.push(c),
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Decodes a DVB text string, unsupported character tables keep the ASCII characters only.
+pub(crate) fn decode_dvb_text(data: &[u8]) -> String {
+    let (charset, skip) = charset(data);
+    let data = &data[skip..];
+    let mut out = String::with_capacity(data.len());
+    match charset {
+        Charset::Iso6937 => decode_iso6937(data, &mut out),
+        Charset::Iso8859(part) => {
+            for &b in data {
+                match b {
+                    0x80..=0x9F => out.extend(control(b as u32)),
+                    0x00..=0x1F | 0x7F => {}
+                    _ => out.push(iso8859(part, b).unwrap_or(char::REPLACEMENT_CHARACTER)),
+                }
+            }
+        }
+        Charset::Ucs2 => {
+            let units = data.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]]));
+            for c in char::decode_utf16(units) {
+                match c.map(|c| c as u32) {
+                    Ok(code @ 0xE080..=0xE09F) => out.extend(control(code - 0xE000)),
+                    Ok(code) if code < 0x20 => {}
+                    Ok(code) => out.extend(char::from_u32(code)),
+                    Err(_) => out.push(char::REPLACEMENT_CHARACTER),
+                }
+            }
+        }
+        Charset::Utf8 => {
+            for c in String::from_utf8_lossy(data).chars() {
+                match c as u32 {
+                    code @ 0x80..=0x9F => out.extend(control(code)),
+                    code @ 0xE080..=0xE09F => out.extend(control(code - 0xE000)),
+                    code if code < 0x20 => {}
+                    _ => out.push(c),
+                }
+            }
+        }
+        Charset::Unsupported => {
+            for &b in data {
+                out.push(if b.is_ascii_graphic() || b == b' ' { b as char } else { char::REPLACEMENT_CHARACTER });
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn character_tables() {
+        assert_eq!(decode_dvb_text(b"News \x86at\x87 10\x8Anow"), "News at 10\nnow");
+        // table 00 diacritics precede the letter
+        assert_eq!(decode_dvb_text(b"M\xC8unchen \xCFcesky \xC2a\xCF\x67 \xA9Hi\xB9 \xE8"), "München česky á\u{0067}\u{030C} ‘Hi’ Ł");
+        assert_eq!(decode_dvb_text(b"\x01\xBF\xE0\xD8\xD2\xD5\xE2"), "Привет");
+        assert_eq!(decode_dvb_text(b"\x10\x00\x02\xB9\xE8\xED"), "ščí");
+        assert_eq!(decode_dvb_text(b"\x0B\xA4 5"), "€ 5");
+        assert_eq!(decode_dvb_text(b"\x03\xC1\xE8\xE7\xED\xE1"), "Αθηνα");
+        assert_eq!(decode_dvb_text(b"\x11\x04\x1F\x00\x20\xE0\x8A\x00\x41"), "П \nA");
+        assert_eq!(decode_dvb_text("\x15Zürich".as_bytes()), "Zürich");
+        assert_eq!(decode_dvb_text(b"\x13ab\xB0\xA1"), "ab\u{FFFD}\u{FFFD}");
+        assert_eq!(decode_dvb_text(b""), "");
+    }
+}
@@ -1,4 +1,4 @@
-use std::{hint::black_box, sync::Arc};
+use std::hint::black_box;
 
 use criterion::{criterion_group, criterion_main, Criterion};
 use rav::data::{IoBuf, IoRef};
@@ -6,7 +6,7 @@ use rav::format::{IoBufRing, IoBufSupplierIoUring, MediaIoBufRead, MediaSourceSt
 
 fn new_iobuf(data: &[u8]) -> IoBuf {
     IoBuf {
-        buf: Arc::from(data),
+        buf: data.into(),
         len: data.len(),
     }
 }
@@ -21,7 +21,7 @@ fn read_ioref_benchmark(c: &mut Criterion) {
             let ioref = IoRef::default();
             (stream, ioref)
         }, |(mut stream, mut ioref)| {
-            stream.get_ioref(black_box(&mut ioref), 3);
+            stream.get_ioref(black_box(&mut ioref), 3).unwrap();
         });
     });
 
@@ -33,7 +33,7 @@ fn read_ioref_benchmark(c: &mut Criterion) {
             let ioref = IoRef::default();
             (stream, ioref)
         }, |(mut stream, mut ioref)| {
-            stream.get_ioref(black_box(&mut ioref), 8);
+            stream.get_ioref(black_box(&mut ioref), 8).unwrap();
         });
     });
 }
@@ -12,7 +12,7 @@
 //     Ok(())
 // }
 
-use std::{fmt::Error, fs, io::{self, Cursor, Read}};
+use std::io::Read;
 
 #[derive(Debug)]
 pub struct Packet<'a> {
@@ -0,0 +1,112 @@
+/// Type of media carried by a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MediaType {
+    #[default]
+    Unknown,
+    Video,
+    Audio,
+    Subtitle,
+    Data,
+    Attachment,
+}
+
+/// Identifies the codec used to encode a stream.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CodecId {
+    #[default]
+    Unknown,
+
+    // video
+    H264,
+    Hevc,
+    Vp8,
+    Vp9,
+    Av1,
+    Mpeg1Video,
+    Mpeg2Video,
+    Mpeg4,
+    Theora,
+
+    // audio
+    Aac,
+    /// AAC in LATM/LOAS framing.
+    AacLatm,
+    Mp1,
+    Mp2,
+    Mp3,
+    Ac3,
+    Eac3,
+    Dts,
+    TrueHd,
+    Opus,
+    Vorbis,
+    Flac,
+    PcmS8,
+    PcmU8,
+    PcmS16Le,
+    PcmS16Be,
+    PcmS24Le,
+    PcmS24Be,
+    PcmS32Le,
+    PcmS32Be,
+    PcmF32Le,
+    PcmF32Be,
+    PcmF64Le,
+    PcmF64Be,
+
+    // subtitle
+    SubRip,
+    Ass,
+    WebVtt,
+    Pgs,
+    DvdSubtitle,
+    DvbSubtitle,
+    DvbTeletext,
+
+    // data
+    /// SCTE-35 splice_info_section.
+    Scte35,
+}
+
+impl CodecId {
+    /// Returns the type of media produced by the codec.
+    pub fn media_type(&self) -> MediaType {
+        use CodecId::*;
+        match self {
+            Unknown => MediaType::Unknown,
+            H264 | Hevc | Vp8 | Vp9 | Av1 | Mpeg1Video | Mpeg2Video | Mpeg4 | Theora => MediaType::Video,
+            SubRip | Ass | WebVtt | Pgs | DvdSubtitle | DvbSubtitle | DvbTeletext => MediaType::Subtitle,
+            Scte35 => MediaType::Data,
+            _ => MediaType::Audio,
+        }
+    }
+}
+
+/// Sample rates indexed by the AAC sampling_frequency_index.
+pub const AAC_SAMPLE_RATES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+
+/// Returns the sample rate and the channel count of an AAC AudioSpecificConfig. The channel count is 0
+/// when the channels are described by a program_config_element.
+pub fn parse_aac_config(config: &[u8]) -> Option<(u32, u16)> {
+    let mut bits = [0u8; 8];
+    let len = config.len().min(8);
+    bits[..len].copy_from_slice(&config[..len]);
+    let bits = u64::from_be_bytes(bits);
+    let available = 8 * len as u32;
+    let mut pos = 0;
+    let mut read = |count: u32| {
+        pos += count;
+        (pos <= available).then(|| (bits >> (64 - pos)) as u32 & ((1 << count) - 1))
+    };
+
+    let object_type = read(5)?;
+    if object_type == 31 {
+        read(6)?;
+    }
+    let sample_rate = match read(4)? {
+        15 => read(24)?,
+        index => *AAC_SAMPLE_RATES.get(index as usize)?,
+    };
+    Some((sample_rate, read(4)? as u16))
+}
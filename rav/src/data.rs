@@ -1,4 +1,6 @@
-use std::{ffi::c_void, sync::Arc};
+use std::{ffi::c_void, ops::{Deref, Range}, sync::Arc};
+
+use memmap2::Mmap;
 
 /// Represents a compressed data packet. C-friendly layout.
 #[repr(C)]
@@ -23,7 +25,7 @@ impl<'a> Packet<'a> {
 
     // extract data slice, if there are more than 1 slices, merge them in memory and return a single slice to that memory,
     // cache the merged memory in the merged field.
-    fn data(&self) -> &[u8] {
+    pub fn data(&self) -> &[u8] {
         match self.slices.len() {
             0 => &[],
             _ => self.slices[0],
@@ -48,13 +50,63 @@ pub enum MediaError {
 
 // --- Data Structures ---
 
+/// Immutable memory shared by an IoBuf and the IoRefs into it, cloning it doesn't copy the data.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuf(Shared);
+
+#[derive(Debug, Clone)]
+enum Shared {
+    Heap(Arc<[u8]>),
+    /// A range of a memory-mapped file, with its own count of references.
+    Mapped(Arc<(Arc<Mmap>, Range<usize>)>),
+}
+
+impl Default for Shared {
+    fn default() -> Self {
+        Shared::Heap(Arc::default())
+    }
+}
+
+impl SharedBuf {
+    /// References `range` of a memory-mapped file without copying it.
+    pub fn mapped(map: Arc<Mmap>, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= map.len(), "range out of the mapping");
+        SharedBuf(Shared::Mapped(Arc::new((map, range))))
+    }
+
+    /// Returns true if no other SharedBuf references this memory.
+    pub fn is_unique(&self) -> bool {
+        match &self.0 {
+            Shared::Heap(buf) => Arc::strong_count(buf) == 1,
+            Shared::Mapped(mapped) => Arc::strong_count(mapped) == 1,
+        }
+    }
+}
+
+impl Deref for SharedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Shared::Heap(buf) => buf,
+            Shared::Mapped(mapped) => &mapped.0[mapped.1.clone()],
+        }
+    }
+}
+
+impl<T: Into<Arc<[u8]>>> From<T> for SharedBuf {
+    fn from(data: T) -> Self {
+        SharedBuf(Shared::Heap(data.into()))
+    }
+}
+
 /// A reference to a segment of a shared buffer.
 /// This is the core of the zero-copy mechanism, as it allows passing
 /// around references to data without copying the data itself.
 #[derive(Debug, Clone, Default)]
 pub struct IoRef {
     /// A shared, immutable reference to the underlying byte buffer.
-    pub(crate) shared_buf: Option<Arc<[u8]>>,
+    pub(crate) shared_buf: Option<SharedBuf>,
     /// Owned buf in case the data was copied
     pub(crate) buf: Option<Box<[u8]>>,
     /// The starting position of this reference within the buffer.
@@ -63,20 +115,113 @@ pub struct IoRef {
     pub(crate) len: usize,
 }
 
+impl IoRef {
+    /// Returns the referenced data.
+    pub fn data(&self) -> &[u8] {
+        if let Some(buf) = &self.shared_buf {
+            &buf[self.offset..self.offset + self.len]
+        } else if let Some(buf) = &self.buf {
+            &buf[self.offset..self.offset + self.len]
+        } else {
+            &[]
+        }
+    }
+
+    /// Returns the length of the referenced data.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no data is referenced.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Moves an owned buffer into a shared one, so that sub-references can be taken without copying.
+    pub fn make_shared(&mut self) {
+        if let Some(buf) = self.buf.take() {
+            self.shared_buf = Some(SharedBuf::from(buf));
+        }
+    }
+
+    /// Returns a reference to `len` bytes starting at `offset` within this reference.
+    /// Shared data is not copied, owned data is.
+    pub fn slice(&self, offset: usize, len: usize) -> IoRef {
+        assert!(offset + len <= self.len, "slice out of IoRef bounds");
+        match &self.shared_buf {
+            Some(buf) => IoRef {
+                shared_buf: Some(buf.clone()),
+                buf: None,
+                offset: self.offset + offset,
+                len,
+            },
+            None => IoRef {
+                shared_buf: None,
+                buf: Some(self.data()[offset..offset + len].into()),
+                offset: 0,
+                len,
+            },
+        }
+    }
+}
+
+impl From<Vec<u8>> for IoRef {
+    fn from(data: Vec<u8>) -> Self {
+        let len = data.len();
+        IoRef { shared_buf: None, buf: Some(data.into_boxed_slice()), offset: 0, len }
+    }
+}
+
+/// Timestamp value used when the timestamp is unknown.
+pub const NOPTS_VALUE: i64 = i64::MIN;
+
 /// A packet of data that can be composed of up to 4 non-contiguous buffer segments.
 /// This allows a single logical data packet to be read even if it spans multiple
 /// IoBufs in the stream's ring buffer.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Packet2 {
     /// An array of buffer references that constitute the packet's data.
     pub ioref: IoRef,
+    /// Index of the stream this packet belongs to.
+    pub stream_id: usize,
+    /// Presentation timestamp in stream time base units, `NOPTS_VALUE` if unknown.
+    pub pts: i64,
+    /// Decoding timestamp in stream time base units, `NOPTS_VALUE` if unknown.
+    pub dts: i64,
+    /// Duration in stream time base units, 0 if unknown.
+    pub duration: i64,
+    /// The packet can be decoded without reference to previous packets.
+    pub keyframe: bool,
+    /// Byte position of the packet in the input, -1 if unknown.
+    pub pos: i64,
+}
+
+impl Default for Packet2 {
+    fn default() -> Self {
+        Self {
+            ioref: IoRef::default(),
+            stream_id: 0,
+            pts: NOPTS_VALUE,
+            dts: NOPTS_VALUE,
+            duration: 0,
+            keyframe: false,
+            pos: -1,
+        }
+    }
+}
+
+impl Packet2 {
+    /// Returns the packet data.
+    pub fn data(&self) -> &[u8] {
+        self.ioref.data()
+    }
 }
 
-/// A contiguous block of memory, owned and shared via an Arc.
+/// A contiguous block of memory, shared with the IoRefs into it.
 #[derive(Debug, Default)]
 pub struct IoBuf {
-    /// The shared buffer. `None` if the IoBuf is empty/uninitialized.
-    pub buf: Arc<[u8]>,
+    /// The shared buffer, empty if the IoBuf is uninitialized.
+    pub buf: SharedBuf,
     /// The length of the actual content in the buffer.
     pub len: usize,
 }
@@ -0,0 +1,207 @@
+use crate::error::{decode_error, Result};
+use crate::format::MediaIoBufRead;
+
+/// Seconds between the Unix epoch and the EBML date epoch, 2001-01-01T00:00:00 UTC.
+const EBML_EPOCH_UNIX_SECS: i64 = 978_307_200;
+
+/// Header of an EBML element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementHeader {
+    /// Element ID, including the width marker bits.
+    pub id: u32,
+    /// Size of the element data, `None` if the size is unknown (all VINT_DATA bits set).
+    pub size: Option<u64>,
+}
+
+impl ElementHeader {
+    /// Returns the data size of an element which must have a known size.
+    pub fn data_size(&self) -> Result<usize> {
+        match self.size {
+            Some(size) => Ok(size as usize),
+            None => decode_error("ebml: unexpected unknown element size"),
+        }
+    }
+}
+
+/// Parses a vint from the start of `data`, returns the value with the width marker removed and its width.
+pub fn parse_vint(data: &[u8]) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let width = first.leading_zeros() as usize + 1;
+    if width > 8 || data.len() < width {
+        return None;
+    }
+    let mut value = first as u64 & (0xFFu64 >> width);
+    for b in &data[1..width] {
+        value = (value << 8) | *b as u64;
+    }
+    Some((value, width))
+}
+
+/// EBML primitives on top of a `MediaIoBufRead`.
+pub trait EbmlRead: MediaIoBufRead {
+    /// Reads a vint, returns the value with the width marker removed and its width.
+    fn read_vint(&mut self) -> Result<(u64, usize)> {
+        let first = self.get_u8()?;
+        let width = first.leading_zeros() as usize;
+        if width >= 8 {
+            return decode_error("ebml: invalid vint width");
+        }
+        let mut value = first as u64 & (0xFFu64 >> (width + 1));
+        for _ in 0..width {
+            value = (value << 8) | self.get_u8()? as u64;
+        }
+        Ok((value, width + 1))
+    }
+
+    /// Reads an element ID, the width marker bits are kept as part of the ID.
+    fn read_element_id(&mut self) -> Result<u32> {
+        let first = self.get_u8()?;
+        let width = first.leading_zeros() as usize + 1;
+        if width > 4 {
+            return decode_error("ebml: invalid element id width");
+        }
+        let mut id = first as u32;
+        for _ in 1..width {
+            id = (id << 8) | self.get_u8()? as u32;
+        }
+        // IDs with all VINT_DATA bits set are reserved, all zero bits are tolerated as Matroska
+        // uses 0x80 for ChapterDisplay
+        let data_mask = (1u32 << (7 * width)) - 1;
+        if id & data_mask == data_mask {
+            return decode_error("ebml: invalid element id");
+        }
+        Ok(id)
+    }
+
+    /// Reads an element data size, `None` is returned for the unknown size.
+    fn read_element_size(&mut self) -> Result<Option<u64>> {
+        let (size, width) = self.read_vint()?;
+        let unknown = (1u64 << (7 * width)) - 1;
+        Ok(if size == unknown { None } else { Some(size) })
+    }
+
+    fn read_element_header(&mut self) -> Result<ElementHeader> {
+        let id = self.read_element_id()?;
+        let size = self.read_element_size()?;
+        Ok(ElementHeader { id, size })
+    }
+
+    /// Reads an unsigned integer element of `size` bytes.
+    fn read_unsigned(&mut self, size: usize) -> Result<u64> {
+        if size > 8 {
+            return decode_error("ebml: invalid unsigned integer width");
+        }
+        let mut value = 0u64;
+        for _ in 0..size {
+            value = (value << 8) | self.get_u8()? as u64;
+        }
+        Ok(value)
+    }
+
+    /// Reads a signed integer element of `size` bytes.
+    fn read_signed(&mut self, size: usize) -> Result<i64> {
+        if size > 8 {
+            return decode_error("ebml: invalid signed integer width");
+        }
+        if size == 0 {
+            return Ok(0);
+        }
+        let value = self.read_unsigned(size)?;
+        let shift = 64 - 8 * size as u32;
+        Ok(((value << shift) as i64) >> shift)
+    }
+
+    /// Reads a float element of 0, 4 or 8 bytes.
+    fn read_float(&mut self, size: usize) -> Result<f64> {
+        match size {
+            0 => Ok(0.0),
+            4 => Ok(f32::from_bits(self.read_unsigned(4)? as u32) as f64),
+            8 => Ok(f64::from_bits(self.read_unsigned(8)?)),
+            _ => decode_error("ebml: invalid float width"),
+        }
+    }
+
+    /// Reads a date element, returns nanoseconds since the Unix epoch.
+    fn read_date(&mut self, size: usize) -> Result<i64> {
+        match size {
+            0 => Ok(EBML_EPOCH_UNIX_SECS * 1_000_000_000),
+            8 => Ok(self.read_signed(8)? + EBML_EPOCH_UNIX_SECS * 1_000_000_000),
+            _ => decode_error("ebml: invalid date width"),
+        }
+    }
+
+    fn read_binary(&mut self, size: usize) -> Result<Vec<u8>> {
+        let mut data = vec![0u8; size];
+        self.get_bytes(&mut data)?;
+        Ok(data)
+    }
+
+    /// Reads an ASCII or UTF-8 string element, trailing zero padding is removed.
+    fn read_string(&mut self, size: usize) -> Result<String> {
+        let mut data = self.read_binary(size)?;
+        if let Some(end) = data.iter().position(|&b| b == 0) {
+            data.truncate(end);
+        }
+        match String::from_utf8(data) {
+            Ok(string) => Ok(string),
+            Err(_) => decode_error("ebml: invalid utf-8 string"),
+        }
+    }
+}
+
+impl<R: MediaIoBufRead + ?Sized> EbmlRead for R {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+
+    fn reader(data: &[u8]) -> MediaSourceStream<IoBufSupplierMem> {
+        MediaSourceStream::new(IoBufSupplierMem::new(data, 3))
+    }
+
+    #[test]
+    fn element_ids_keep_marker() {
+        let mut r = reader(&[0x1A, 0x45, 0xDF, 0xA3, 0xA3, 0x42, 0x82]);
+        assert_eq!(r.read_element_id(), Ok(0x1A45DFA3));
+        assert_eq!(r.read_element_id(), Ok(0xA3));
+        assert_eq!(r.read_element_id(), Ok(0x4282));
+
+        assert!(reader(&[0xFF]).read_element_id().is_err());
+        assert!(reader(&[0x7F, 0xFF]).read_element_id().is_err());
+        assert_eq!(reader(&[0x80]).read_element_id(), Ok(0x80));
+        assert!(reader(&[0x08, 0, 0, 0, 1]).read_element_id().is_err());
+    }
+
+    #[test]
+    fn element_sizes() {
+        let mut r = reader(&[0x81, 0x40, 0x02, 0xFF, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0xFF]);
+        assert_eq!(r.read_element_size(), Ok(Some(1)));
+        assert_eq!(r.read_element_size(), Ok(Some(2)));
+        assert_eq!(r.read_element_size(), Ok(None));
+        assert_eq!(r.read_element_size(), Ok(None));
+        assert_eq!(r.read_element_size(), Ok(None));
+        assert_eq!(reader(&[0x00]).read_element_size(), Err(Error::DecodeError("ebml: invalid vint width")));
+    }
+
+    #[test]
+    fn element_values() {
+        let mut r = reader(&[
+            0x01, 0x00, 0x00,
+            0xFF, 0xFE,
+            0x3F, 0xC0, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            b'a', b'b', 0x00, 0x00,
+        ]);
+        assert_eq!(r.read_unsigned(3), Ok(0x010000));
+        assert_eq!(r.read_signed(2), Ok(-2));
+        assert_eq!(r.read_float(4), Ok(1.5));
+        assert_eq!(r.read_date(8), Ok(978_307_200_000_000_001));
+        assert_eq!(r.read_string(4), Ok("ab".to_string()));
+
+        assert!(reader(&[0; 9]).read_unsigned(9).is_err());
+        assert!(reader(&[0; 9]).read_float(2).is_err());
+        assert!(reader(&[0; 9]).read_date(4).is_err());
+    }
+}
@@ -7,7 +7,7 @@ use std::result;
 #[derive(Debug, PartialEq)]
 pub enum Error {
     /// An IO error occured while reading, writing, or seeking the stream.
-//    IoError(std::io::Error),
+    IoError(io::ErrorKind),
     /// The stream contained malformed data and could not be decoded or demuxed.
     DecodeError(&'static str),
     /// An unsupported container or codec feature was encounted.
@@ -26,7 +26,7 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-//            Error::IoError(ref err) => err.fmt(f),
+            Error::IoError(kind) => kind.fmt(f),
             Error::DecodeError(msg) => {
                 write!(f, "malformed stream: {}", msg)
             }
@@ -49,11 +49,11 @@ impl fmt::Display for Error {
     }
 }
 
-// impl From<io::Error> for Error {
-//     fn from(err: io::Error) -> Error {
-//         Error::IoError(err)
-//     }
-// }
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::IoError(err.kind())
+    }
+}
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -87,3 +87,7 @@ pub fn retry_later_error<T>() -> Result<T> {
     Err(Error::RetryLater)
 }
 
+/// Convenience function to create an end of stream error.
+pub fn end_of_stream_error<T>() -> Result<T> {
+    Err(Error::IoError(io::ErrorKind::UnexpectedEof))
+}
@@ -1,12 +1,96 @@
 
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::sync::Arc;
 
-use crate::data::{IoBuf, IoRef, Packet};
-use crate::error::{invalid_input_error, retry_later_error, Result, Error};
+use memmap2::Mmap;
+
+use crate::codec::{CodecId, MediaType};
+use crate::data::{IoBuf, IoRef, Packet2, SharedBuf};
+use crate::metadata::{Attachment, Edition, Tag};
+use crate::error::{end_of_stream_error, invalid_input_error, retry_later_error, unsupported_error, Result, Error};
+
+mod adts;
+mod annexb;
+mod av1;
+mod avi;
+mod dash;
+mod flac;
+mod flv;
+mod hls;
+mod id3;
+mod ivf;
+mod mkv;
+mod mp3;
+mod mp4;
+mod obu;
+mod ogg;
+mod probe;
+mod ps;
+mod riff;
+mod segmented;
+mod wav;
+
+pub use adts::DemuxerAdts;
+pub use annexb::DemuxerAnnexB;
+pub use avi::DemuxerAvi;
+pub use dash::{AdaptationSet, DashSegment, IoBufSupplierDash, Mpd, Period, Representation};
+pub use flac::DemuxerFlac;
+pub use flv::{AmfValue, DemuxerFlv};
+pub use hls::{IoBufSupplierHls, MasterPlaylist, MediaPlaylist, MediaSegment, Playlist, Variant};
+pub use ivf::DemuxerIvf;
+pub use mkv::DemuxerMkv;
+pub use mp3::DemuxerMp3;
+pub use mp4::{DemuxerMp4, EditListEntry};
+pub use obu::{DemuxerObu, ObuFormat};
+pub use ogg::DemuxerOgg;
+pub use probe::{
+    input_formats, register_input_format, FormatOptions, InputFormat, ProbeScore, ProbeStream, PROBE_SCORE_EXTENSION,
+    PROBE_SCORE_MAX,
+};
+pub use ps::DemuxerPs;
+pub use segmented::{ByteRange, DemuxerSegmented, InitSection, SegmentTimeline};
+pub use wav::DemuxerWav;
+
+/// Time base of stream timestamps, a timestamp `ts` is `ts * num / den` seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBase {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl TimeBase {
+    pub const fn new(num: u32, den: u32) -> Self {
+        Self { num, den }
+    }
 
+    /// Converts a timestamp from this time base into another one.
+    pub fn rescale(&self, ts: i64, to: TimeBase) -> i64 {
+        (ts as i128 * self.num as i128 * to.den as i128 / (self.den as i128 * to.num as i128)) as i64
+    }
+}
+
+impl Default for TimeBase {
+    fn default() -> Self {
+        Self { num: 1, den: 1000 }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Stream {
     pub id: usize,
     pub codec_params: Vec<u8>,
+    pub codec_id: CodecId,
+    pub media_type: MediaType,
+    pub time_base: TimeBase,
+    /// Stream duration in time base units, 0 if unknown.
+    pub duration: i64,
+    pub language: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
 }
 
 pub trait IoBufRing {
@@ -17,6 +101,16 @@ pub trait IoBufRing {
 pub trait IoBufSupply {
     fn open_input(&mut self, uri: &str) -> Result<()>;
     fn supply_iobufs(&mut self, len_bytes: usize, parsed_iobufs: &[IoBuf], new_iobufs: &mut [IoBuf]) -> Result<usize>;
+
+    /// Repositions the supplier, the next supplied IoBuf starts at the absolute position `pos`.
+    fn seek(&mut self, _pos: usize) -> Result<()> {
+        unsupported_error("seek")
+    }
+
+    /// Total length of the input if known.
+    fn stream_len(&self) -> Option<usize> {
+        None
+    }
 }
 
 
@@ -26,60 +120,304 @@ pub struct IoBufSupplierIoUring {
 }
 
 impl IoBufSupply for IoBufSupplierIoUring {
-    fn open_input(&mut self, uri: &str) -> Result<()> {
+    fn open_input(&mut self, _uri: &str) -> Result<()> {
 //        self.src = std::fs::File::open(uri)?;
         Ok(())
     }
 
-    fn supply_iobufs(&mut self, len_bytes: usize, parsed_iobufs: &[IoBuf], new_iobufs: &mut [IoBuf]) -> Result<usize> {
+    fn supply_iobufs(&mut self, _len_bytes: usize, _parsed_iobufs: &[IoBuf], _new_iobufs: &mut [IoBuf]) -> Result<usize> {
         // iobuf_ring.remove_iobuf()?;
         // iobuf_ring.add_iobuf(iobuf);
         Ok(0)
     }
 }
 
+/// Default size of the IoBufs allocated by the suppliers.
+const IOBUF_SIZE: usize = 1_048_576;
+
+/// Supplies IoBufs by synchronously reading a file.
+#[derive(Debug, Default)]
+pub struct IoBufSupplierFile {
+    src: Option<File>,
+    len: usize,
+}
+
+impl IoBufSupply for IoBufSupplierFile {
+    fn open_input(&mut self, uri: &str) -> Result<()> {
+        let src = File::open(uri)?;
+        self.len = src.metadata()?.len() as usize;
+        self.src = Some(src);
+        Ok(())
+    }
+
+    fn supply_iobufs(&mut self, len_bytes: usize, _parsed_iobufs: &[IoBuf], new_iobufs: &mut [IoBuf]) -> Result<usize> {
+        let Some(src) = self.src.as_mut() else {
+            return invalid_input_error();
+        };
+        if new_iobufs.is_empty() {
+            return retry_later_error();
+        }
+
+        let mut buf = vec![0u8; len_bytes.max(IOBUF_SIZE)];
+        let mut len = 0;
+        while len < buf.len() {
+            match src.read(&mut buf[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if len == 0 {
+            return end_of_stream_error();
+        }
+
+        new_iobufs[0] = IoBuf { buf: buf.into(), len };
+        Ok(1)
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        let Some(src) = self.src.as_mut() else {
+            return invalid_input_error();
+        };
+        src.seek(SeekFrom::Start(pos as u64))?;
+        Ok(())
+    }
+
+    fn stream_len(&self) -> Option<usize> {
+        self.src.as_ref().map(|_| self.len)
+    }
+}
+
+/// Supplies IoBufs from a memory-mapped file, random access input without read calls. The IoBufs reference
+/// the mapping, the data is not copied.
+#[derive(Debug, Default)]
+pub struct IoBufSupplierMmap {
+    map: Option<Arc<Mmap>>,
+    pos: usize,
+}
+
+impl IoBufSupply for IoBufSupplierMmap {
+    fn open_input(&mut self, uri: &str) -> Result<()> {
+        let src = File::open(uri)?;
+        // the file must not be truncated while mapped
+        self.map = Some(Arc::new(unsafe { Mmap::map(&src)? }));
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn supply_iobufs(&mut self, len_bytes: usize, _parsed_iobufs: &[IoBuf], new_iobufs: &mut [IoBuf]) -> Result<usize> {
+        let Some(map) = self.map.as_ref() else {
+            return invalid_input_error();
+        };
+        if self.pos >= map.len() {
+            return end_of_stream_error();
+        }
+        if new_iobufs.is_empty() {
+            return retry_later_error();
+        }
+
+        let len = len_bytes.max(IOBUF_SIZE).min(map.len() - self.pos);
+        new_iobufs[0] = IoBuf { buf: SharedBuf::mapped(map.clone(), self.pos..self.pos + len), len };
+        self.pos += len;
+        Ok(1)
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        match self.map.as_ref() {
+            Some(map) if pos <= map.len() => {
+                self.pos = pos;
+                Ok(())
+            }
+            _ => invalid_input_error(),
+        }
+    }
+
+    fn stream_len(&self) -> Option<usize> {
+        self.map.as_ref().map(|map| map.len())
+    }
+}
+
+/// Supplies IoBufs from data already in memory, copying at most `chunk_size` bytes per IoBuf
+/// unless more is requested at once.
+#[derive(Debug)]
+pub struct IoBufSupplierMem {
+    data: Arc<[u8]>,
+    pos: usize,
+    chunk_size: usize,
+}
+
+impl IoBufSupplierMem {
+    pub fn new(data: impl Into<Arc<[u8]>>, chunk_size: usize) -> Self {
+        Self { data: data.into(), pos: 0, chunk_size: chunk_size.max(1) }
+    }
+}
+
+impl IoBufSupply for IoBufSupplierMem {
+    fn open_input(&mut self, _uri: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn supply_iobufs(&mut self, len_bytes: usize, _parsed_iobufs: &[IoBuf], new_iobufs: &mut [IoBuf]) -> Result<usize> {
+        if self.pos >= self.data.len() {
+            return end_of_stream_error();
+        }
+        if new_iobufs.is_empty() {
+            return retry_later_error();
+        }
+
+        let len = len_bytes.max(self.chunk_size).min(self.data.len() - self.pos);
+        new_iobufs[0] = IoBuf { buf: SharedBuf::from(&self.data[self.pos..self.pos + len]), len };
+        self.pos += len;
+        Ok(1)
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        if pos > self.data.len() {
+            return invalid_input_error();
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    fn stream_len(&self) -> Option<usize> {
+        Some(self.data.len())
+    }
+}
+
+/// Supplier of an input that can't be seeked and has no known length, like a pipe.
+#[cfg(test)]
+pub(crate) struct StreamingSupplier(pub(crate) IoBufSupplierMem);
+
+#[cfg(test)]
+impl IoBufSupply for StreamingSupplier {
+    fn open_input(&mut self, uri: &str) -> Result<()> {
+        self.0.open_input(uri)
+    }
+
+    fn supply_iobufs(&mut self, len_bytes: usize, parsed_iobufs: &[IoBuf], new_iobufs: &mut [IoBuf]) -> Result<usize> {
+        self.0.supply_iobufs(len_bytes, parsed_iobufs, new_iobufs)
+    }
+}
+
 pub trait MediaIoBufRead {
     fn get_u8(&mut self) -> Result<u8>;
     fn get_ioref(&mut self, ioref: &mut IoRef, len: usize) -> Result<()>;
+
+    /// Fills `buf` with the next bytes of the stream.
+    fn get_bytes(&mut self, buf: &mut [u8]) -> Result<()>;
+
+    /// Fills `buf` with the next bytes of the stream without advancing the position.
+    fn peek_bytes(&mut self, buf: &mut [u8]) -> Result<()>;
+
+    /// Advances the position by `len` bytes without reading the data.
+    fn skip(&mut self, len: usize) -> Result<()>;
+
+    /// Moves to the absolute stream position `pos`.
+    fn seek(&mut self, pos: usize) -> Result<()>;
+
+    /// Returns the absolute stream position.
+    fn pos(&self) -> usize;
+
+    /// Total length of the stream if known.
+    fn stream_len(&self) -> Option<usize>;
 }
 
 pub trait Demux {
-    fn read_packet(&mut self, packet: &mut Packet) -> Result<()>;
+    /// Reads the next packet into `packet`. Implementations release the data of the previous packet first, so the
+    /// IoBufs it referenced can be recycled, clone `packet.ioref` beforehand to keep it.
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()>;
+
+    /// Streams found in the input.
+    fn streams(&self) -> &[Stream];
+
+    /// Seeks to the keyframe at or before `timestamp` of stream `stream_id`, timestamp is in stream time base units.
+    /// Returns the timestamp where reading resumes.
+    fn seek(&mut self, _stream_id: usize, _timestamp: i64) -> Result<i64> {
+        unsupported_error("seek")
+    }
+
+    /// Chapter editions found in the input.
+    fn chapters(&self) -> &[Edition] {
+        &[]
+    }
+
+    /// Tags found in the input.
+    fn tags(&self) -> &[Tag] {
+        &[]
+    }
+
+    /// Files attached to the input.
+    fn attachments(&self) -> &[Attachment] {
+        &[]
+    }
 }
 
 pub struct FormatContext {
     demuxer: Box<dyn Demux>,
+    format_name: &'static str,
 }
 
 impl FormatContext {
-    pub fn open_input(uri: &str) -> Self {
-        let mut iobuf_supplier = IoBufSupplierIoUring {};
-        iobuf_supplier.open_input(uri);
-        let stream = MediaSourceStream::new(iobuf_supplier);
-        let demuxer = DemuxerMkv { iobuf_reader: stream };
-        Self { demuxer: Box::new(demuxer) }
+    /// Opens the file at `uri` with the demuxer of this crate that probes best.
+    pub fn open_input(uri: &str) -> Result<Self> {
+        Self::open_input_with(uri, &FormatOptions::default())
     }
 
-    pub fn read_packet(&mut self, packet: &mut Packet) -> Result<()> {
+    /// Opens the file at `uri` with the forced format of `options`, or tries its formats from the highest probe
+    /// score until one opens the input.
+    pub fn open_input_with(uri: &str, options: &FormatOptions) -> Result<Self> {
+        let mut stream = probe::open_file(uri)?;
+        let candidates = options.candidates(&mut stream, uri)?;
+        // the probed stream goes to the first candidate, a failed demuxer consumed its input
+        let mut stream = Some(stream);
+        let mut first_error = None;
+        for format in candidates {
+            let input = match stream.take() {
+                Some(stream) => stream,
+                None => probe::open_file(uri)?,
+            };
+            match (format.open)(input) {
+                Ok(demuxer) => return Ok(Self { demuxer, format_name: format.name }),
+                Err(err) => first_error = first_error.or(Some(err)),
+            }
+        }
+        match first_error {
+            Some(err) => Err(err),
+            None => unsupported_error("format: unknown input format"),
+        }
+    }
+
+    /// Name of the `InputFormat` that opened the input.
+    pub fn format_name(&self) -> &'static str {
+        self.format_name
+    }
+
+    pub fn streams(&self) -> &[Stream] {
+        self.demuxer.streams()
+    }
+
+    pub fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
         self.demuxer.read_packet(packet)
     }
-}
 
-pub struct IoBuf2 {
-    // buff allocated space
-    data: Arc<[u8]>,
-    // how much data is actually there   
-    len: u64
-}
+    /// Seeks stream `stream_id` to the keyframe at or before `timestamp` (in stream time base units).
+    /// Returns the timestamp where reading resumes.
+    pub fn seek(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        if stream_id >= self.streams().len() {
+            return invalid_input_error();
+        }
+        self.demuxer.seek(stream_id, timestamp)
+    }
 
-pub struct DemuxerMkv<S: MediaIoBufRead> {
-    iobuf_reader: S
-}
+    pub fn chapters(&self) -> &[Edition] {
+        self.demuxer.chapters()
+    }
 
-impl<S: MediaIoBufRead> Demux for DemuxerMkv<S> {
-    fn read_packet(&mut self, packet: &mut Packet) -> Result<()> {
-        let b = self.iobuf_reader.get_u8()?;
-        Ok(())
+    pub fn tags(&self) -> &[Tag] {
+        self.demuxer.tags()
+    }
+
+    pub fn attachments(&self) -> &[Attachment] {
+        self.demuxer.attachments()
     }
 }
 
@@ -111,6 +449,7 @@ pub struct MediaSourceStream<S: IoBufSupply> {
 
 impl<S: IoBufSupply> MediaSourceStream<S> {
     pub fn new(iobuf_supplier: S) -> Self {
+        let stream_len = iobuf_supplier.stream_len().unwrap_or(0);
         Self {
             iobuf_supplier,
             ring: Default::default(),
@@ -120,9 +459,10 @@ impl<S: IoBufSupply> MediaSourceStream<S> {
             ring_cur_idx: 0,
             ring_cur_pos: 0,
             stream_pos: 0,
-            stream_len: 0 }
+            stream_len }
     }
     
+    /// Asks the supplier for more data, at least one new IoBuf is added or an error is returned.
     fn supply_iobufs(&mut self, len_bytes: usize) -> Result<()> {
         // get all parsed iobufs into array, can be 0
         let mut parsed_iobufs: [IoBuf; RING_SIZE] = Default::default();
@@ -138,7 +478,7 @@ impl<S: IoBufSupply> MediaSourceStream<S> {
         }
 
         // if we don't have space in ring - it means that non of the buffers were parsed
-        let max_new_iobufs = RING_SIZE - (self.ring_add_idx - self.ring_remove_idx + RING_SIZE) % RING_SIZE;
+        let max_new_iobufs = RING_SIZE - 1 - (self.ring_add_idx + RING_SIZE - self.ring_remove_idx) % RING_SIZE;
         if max_new_iobufs == 0 {
             return retry_later_error();  // all existing buffers are still referenced and cannot be released and there is no more space in ring buf
         }
@@ -153,15 +493,50 @@ impl<S: IoBufSupply> MediaSourceStream<S> {
         let mut new_bytes = 0;
         for iobuf in new_iobufs.iter_mut().take(count_new_iobufs) {
             new_bytes += iobuf.len;
-            self.add_iobuf(std::mem::take(iobuf))?;  // TODO check that it doesn't fail - otherwise iobuf will be dealocated from heap.
+            self.add_iobuf(std::mem::take(iobuf))?;
         }
 
-        if new_bytes < len_bytes {
+        if new_bytes == 0 {
             return retry_later_error();
         }
         
         Ok(())
     }
+
+    /// Number of bytes available in the ring from the current position.
+    fn buffered_len(&self) -> usize {
+        let mut len = 0;
+        let mut idx = self.ring_cur_idx;
+        while idx != self.ring_add_idx {
+            len += self.ring[idx].len;
+            idx = (idx + 1) & self.ring_mask;
+        }
+        len - self.ring_cur_pos
+    }
+
+    /// Makes sure that at least `len` bytes are available in the ring from the current position.
+    fn ensure_buffered(&mut self, len: usize) -> Result<()> {
+        let mut available = self.buffered_len();
+        while available < len {
+            self.supply_iobufs(len - available)?;
+            available = self.buffered_len();
+        }
+        Ok(())
+    }
+
+    /// Advances the current position by `len` bytes, all of them should be already in the ring.
+    fn advance(&mut self, mut len: usize) {
+        self.stream_pos += len;
+        while len > 0 {
+            let step = (self.ring[self.ring_cur_idx].len - self.ring_cur_pos).min(len);
+            len -= step;
+            self.ring_cur_pos += step;
+            if self.ring_cur_pos == self.ring[self.ring_cur_idx].len {
+                self.ring_cur_idx = (self.ring_cur_idx + 1) & self.ring_mask;
+                self.ring_cur_pos = 0;
+            }
+        }
+    }
 }
 
 impl<S: IoBufSupply> IoBufRing for MediaSourceStream<S> {
@@ -187,11 +562,12 @@ impl<S: IoBufSupply> IoBufRing for MediaSourceStream<S> {
 
     /// Removes a parsed IoBuf from the stream's ring buffer. IoBuf should not have Packets referencing it
     fn remove_iobuf(&mut self) -> Result<IoBuf> {
-        if self.ring_remove_idx == self.ring_add_idx {
+        // the IoBuf currently being read is not parsed yet
+        if self.ring_remove_idx == self.ring_add_idx || self.ring_remove_idx == self.ring_cur_idx {
             return retry_later_error();
         }
 
-        if Arc::strong_count(&self.ring[self.ring_remove_idx].buf) == 1 {
+        if self.ring[self.ring_remove_idx].buf.is_unique() {
             let iobuf = std::mem::take(&mut self.ring[self.ring_remove_idx]);
             self.ring_remove_idx = (self.ring_remove_idx + 1) % RING_SIZE;
             return Ok(iobuf);
@@ -227,7 +603,7 @@ impl<S: IoBufSupply> MediaIoBufRead for MediaSourceStream<S> {
         }
 
         if self.ring_cur_idx == self.ring_add_idx {
-            return retry_later_error();
+            self.supply_iobufs(len)?;
         }
 
         let cur_buf_remaining = self.ring[self.ring_cur_idx].len - self.ring_cur_pos;
@@ -251,17 +627,7 @@ impl<S: IoBufSupply> MediaIoBufRead for MediaSourceStream<S> {
         }
 
         // Check if total available data is enough
-        let mut total_available = cur_buf_remaining;
-        let mut idx = (self.ring_cur_idx + 1) & self.ring_mask;
-        while idx != self.ring_add_idx && total_available < len {
-            total_available += self.ring[idx].len;
-            idx = (idx + 1) & self.ring_mask;
-        }
-
-        if total_available < len {
-            // TODO need to read more data
-            return retry_later_error();
-        }
+        self.ensure_buffered(len)?;
 
         // Allocate and copy data from multiple IoBufs
         let mut new_buf  = Vec::with_capacity(len);
@@ -300,16 +666,89 @@ impl<S: IoBufSupply> MediaIoBufRead for MediaSourceStream<S> {
 
         Ok(())
     }
+
+    fn get_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.peek_bytes(buf)?;
+        self.advance(buf.len());
+        Ok(())
+    }
+
+    fn peek_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.ensure_buffered(buf.len())?;
+
+        let mut copied = 0;
+        let mut idx = self.ring_cur_idx;
+        let mut pos = self.ring_cur_pos;
+        while copied < buf.len() {
+            let to_copy = (self.ring[idx].len - pos).min(buf.len() - copied);
+            buf[copied..copied + to_copy].copy_from_slice(&self.ring[idx].buf[pos..pos + to_copy]);
+            copied += to_copy;
+            idx = (idx + 1) & self.ring_mask;
+            pos = 0;
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        let buffered = self.buffered_len();
+        if len <= buffered {
+            self.advance(len);
+            return Ok(());
+        }
+
+        // jump over the data if the supplier can seek, otherwise read through it
+        match self.seek(self.stream_pos + len) {
+            Err(Error::Unsupported(_)) => {}
+            result => return result,
+        }
+
+        let mut remaining = len;
+        while remaining > 0 {
+            if self.ring_cur_idx == self.ring_add_idx {
+                self.supply_iobufs(remaining)?;
+            }
+            let step = self.buffered_len().min(remaining);
+            self.advance(step);
+            remaining -= step;
+        }
+        Ok(())
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        if pos >= self.stream_pos && pos - self.stream_pos <= self.buffered_len() {
+            self.advance(pos - self.stream_pos);
+            return Ok(());
+        }
+
+        self.iobuf_supplier.seek(pos)?;
+
+        // drop buffered data, packets still referencing it keep their own Arc
+        while self.ring_remove_idx != self.ring_add_idx {
+            self.ring[self.ring_remove_idx] = IoBuf::default();
+            self.ring_remove_idx = (self.ring_remove_idx + 1) & self.ring_mask;
+        }
+        self.ring_cur_idx = self.ring_add_idx;
+        self.ring_cur_pos = 0;
+        self.stream_pos = pos;
+        Ok(())
+    }
+
+    fn pos(&self) -> usize {
+        self.stream_pos
+    }
+
+    fn stream_len(&self) -> Option<usize> {
+        (self.stream_len > 0).then_some(self.stream_len)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::Arc;
 
     fn new_iobuf(data: &[u8]) -> IoBuf {
         IoBuf {
-            buf: Arc::from(data),
+            buf: data.into(),
             len: data.len(),
         }
     }
@@ -562,5 +1001,55 @@ mod tests {
         assert_eq!(stream.ring_cur_idx, 1);
         assert_eq!(stream.ring_cur_pos, 1);
     }
-}
 
+    #[test]
+    fn get_bytes_across_supplied_bufs() {
+        let mut stream = MediaSourceStream::new(IoBufSupplierMem::new(&b"abcdefghij"[..], 3));
+        let mut buf = [0u8; 5];
+        stream.get_bytes(&mut buf).unwrap();
+        assert_eq!(&buf, b"abcde");
+        assert_eq!(stream.pos(), 5);
+        let mut buf = [0u8; 4];
+        stream.peek_bytes(&mut buf).unwrap();
+        assert_eq!(&buf, b"fghi");
+        assert_eq!(stream.get_u8().unwrap(), b'f');
+        let mut buf = [0u8; 5];
+        assert_eq!(stream.get_bytes(&mut buf), Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)));
+    }
+
+    #[test]
+    fn skip_and_seek() {
+        let mut stream = MediaSourceStream::new(IoBufSupplierMem::new(&b"abcdefghijklmnop"[..], 4));
+        assert_eq!(stream.stream_len(), Some(16));
+        stream.skip(2).unwrap();
+        assert_eq!(stream.get_u8().unwrap(), b'c');
+
+        // beyond the buffered data
+        stream.skip(10).unwrap();
+        assert_eq!(stream.pos(), 13);
+        assert_eq!(stream.get_u8().unwrap(), b'n');
+
+        // backwards
+        stream.seek(1).unwrap();
+        let mut ioref = IoRef::default();
+        stream.get_ioref(&mut ioref, 6).unwrap();
+        assert_eq!(ioref.data(), b"bcdefg");
+        assert_eq!(stream.pos(), 7);
+    }
+
+    #[test]
+    fn mmap_iobufs_reference_the_mapping() {
+        let path = std::env::temp_dir().join(format!("rav-mmap-test-{}.bin", std::process::id()));
+        std::fs::write(&path, b"abcdefgh").unwrap();
+        let mut supplier = IoBufSupplierMmap::default();
+        supplier.open_input(path.to_str().unwrap()).unwrap();
+        let map_start = supplier.map.as_ref().unwrap().as_ptr();
+        let mut stream = MediaSourceStream::new(supplier);
+        stream.skip(2).unwrap();
+        let mut ioref = IoRef::default();
+        stream.get_ioref(&mut ioref, 3).unwrap();
+        assert_eq!(ioref.data(), b"cde");
+        assert_eq!(ioref.data().as_ptr(), map_start.wrapping_add(2));
+        std::fs::remove_file(path).unwrap();
+    }
+}
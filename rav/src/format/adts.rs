@@ -0,0 +1,311 @@
+use crate::codec::{CodecId, MediaType, AAC_SAMPLE_RATES};
+use crate::data::{IoRef, Packet2};
+use crate::error::{end_of_stream_error, Error, Result};
+use crate::metadata::Tag;
+
+use super::id3::{read_id3v2, skip_id3v2};
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+const HEADER_LEN: usize = 7;
+/// Header length with the CRC.
+const HEADER_CRC_LEN: usize = 9;
+
+/// Samples of a raw data block.
+const BLOCK_SAMPLES: i64 = 1024;
+
+/// Bytes of the raw data block covered by the CRC, for single block frames.
+const CRC_DATA_LEN: usize = 192 / 8;
+
+/// CRC-16 with polynomial 0x8005 and an initial value of 0xFFFF.
+fn crc16(crc: u16, data: &[u8]) -> u16 {
+    data.iter().fold(crc, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| if crc & 0x8000 != 0 { crc << 1 ^ 0x8005 } else { crc << 1 })
+    })
+}
+
+/// Fields of an ADTS header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AdtsHeader {
+    /// Audio object type minus one.
+    profile: u8,
+    sampling_index: u8,
+    channel_config: u8,
+    /// Frame length including the header.
+    frame_len: usize,
+    has_crc: bool,
+    blocks: usize,
+}
+
+impl AdtsHeader {
+    /// Parses and validates a header, `None` if it doesn't start with a valid sync word and fields.
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN || data[0] != 0xFF || data[1] & 0xF6 != 0xF0 {
+            return None;
+        }
+        let header = AdtsHeader {
+            profile: data[2] >> 6,
+            sampling_index: (data[2] >> 2) & 0x0F,
+            channel_config: (data[2] & 0x01) << 2 | data[3] >> 6,
+            frame_len: ((data[3] & 0x03) as usize) << 11 | (data[4] as usize) << 3 | (data[5] >> 5) as usize,
+            has_crc: data[1] & 0x01 == 0,
+            blocks: (data[6] & 0x03) as usize + 1,
+        };
+        let valid = header.profile != 3 && (header.sampling_index as usize) < AAC_SAMPLE_RATES.len()
+            && header.frame_len > header.header_len();
+        valid.then_some(header)
+    }
+
+    fn header_len(&self) -> usize {
+        if self.has_crc { HEADER_CRC_LEN } else { HEADER_LEN }
+    }
+
+    /// True if `other` is a header of the same stream.
+    fn same_stream(&self, other: &AdtsHeader) -> bool {
+        self.profile == other.profile && self.sampling_index == other.sampling_index
+            && self.channel_config == other.channel_config
+    }
+
+    /// Checks the CRC of a frame, only the CRC of single block frames is verified.
+    fn crc_matches(&self, frame: &[u8]) -> bool {
+        if !self.has_crc || self.blocks > 1 {
+            return true;
+        }
+        let data = &frame[HEADER_CRC_LEN..];
+        let crc = crc16(crc16(0xFFFF, &frame[..HEADER_LEN]), &data[..data.len().min(CRC_DATA_LEN)]);
+        crc == u16::from_be_bytes([frame[7], frame[8]])
+    }
+
+    /// AudioSpecificConfig of the stream.
+    fn audio_specific_config(&self) -> Vec<u8> {
+        let object_type = self.profile as u16 + 1;
+        let config = object_type << 11 | (self.sampling_index as u16) << 7 | (self.channel_config as u16) << 3;
+        config.to_be_bytes().to_vec()
+    }
+}
+
+/// Probe score of the first bytes of an input, from the number of consecutive frames of one stream.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    let data = skip_id3v2(data);
+    let Some(first) = AdtsHeader::parse(data) else {
+        return 0;
+    };
+    let mut pos = 0;
+    let mut frames = 0;
+    while let Some(header) = data.get(pos..).and_then(AdtsHeader::parse).filter(|header| header.same_stream(&first)) {
+        frames += 1;
+        pos += header.frame_len;
+    }
+    // a truncated last frame is expected at the end of the probe data
+    match frames {
+        0 => 0,
+        _ if pos >= data.len() || frames >= 4 => PROBE_SCORE_MAX * 9 / 10,
+        _ => PROBE_SCORE_MAX / 4,
+    }
+}
+
+/// Demuxer for AAC in ADTS, as in `.aac` files. Each frame is a packet, with its ADTS header unless
+/// `with_stripped_headers` is set. The sync is checked with the following frame header after data
+/// was skipped, and frames with a CRC mismatch are dropped.
+pub struct DemuxerAdts<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    /// ID3v2 tags preceding the frames.
+    tags: Vec<Tag>,
+    strip_headers: bool,
+    /// False after data was skipped, until a frame is confirmed by the next header.
+    synced: bool,
+    next_pts: i64,
+    /// Frame copy used to check the CRC and the next header.
+    frame: Vec<u8>,
+    crc_errors: u64,
+}
+
+impl<S: MediaIoBufRead> DemuxerAdts<S> {
+    /// Creates the demuxer, syncing on the first frame.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            tags: Vec::new(),
+            strip_headers: false,
+            synced: false,
+            next_pts: 0,
+            frame: Vec::new(),
+            crc_errors: 0,
+        };
+        let simple_tags = read_id3v2(&mut demuxer.iobuf_reader)?;
+        if !simple_tags.is_empty() {
+            demuxer.tags = vec![Tag { simple_tags, ..Default::default() }];
+        }
+        let header = demuxer.sync()?;
+        let sample_rate = AAC_SAMPLE_RATES[header.sampling_index as usize];
+        demuxer.streams.push(Stream {
+            codec_id: CodecId::Aac,
+            media_type: MediaType::Audio,
+            time_base: TimeBase::new(1, sample_rate),
+            sample_rate,
+            // channel configuration 7 has 8 channels, 0 is described in the stream
+            channels: if header.channel_config == 7 { 8 } else { header.channel_config as u16 },
+            codec_params: header.audio_specific_config(),
+            ..Default::default()
+        });
+        Ok(demuxer)
+    }
+
+    /// Emits the frames without their ADTS header, as raw AAC described by the codec parameters.
+    pub fn with_stripped_headers(mut self, strip: bool) -> Self {
+        self.strip_headers = strip;
+        self
+    }
+
+    /// Number of frames dropped because their CRC didn't match.
+    pub fn crc_errors(&self) -> u64 {
+        self.crc_errors
+    }
+
+    /// Moves to the next valid frame header and copies the frame, followed by the next header if available.
+    fn sync(&mut self) -> Result<AdtsHeader> {
+        loop {
+            let mut data = [0u8; HEADER_LEN];
+            self.iobuf_reader.peek_bytes(&mut data)?;
+            let Some(header) = AdtsHeader::parse(&data) else {
+                self.synced = false;
+                self.iobuf_reader.skip(1)?;
+                continue;
+            };
+
+            self.frame.resize(header.frame_len + HEADER_LEN, 0);
+            let next = match self.iobuf_reader.peek_bytes(&mut self.frame) {
+                Ok(()) => AdtsHeader::parse(&self.frame[header.frame_len..]),
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => {
+                    self.frame.truncate(header.frame_len);
+                    match self.iobuf_reader.peek_bytes(&mut self.frame) {
+                        // the last frame
+                        Ok(()) => Some(header),
+                        // a false sync word near the end
+                        Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) if !self.synced => None,
+                        Err(err) => return Err(err),
+                    }
+                }
+                Err(err) => return Err(err),
+            };
+            if self.synced || next.is_some_and(|next| next.same_stream(&header)) {
+                self.synced = true;
+                return Ok(header);
+            }
+            self.iobuf_reader.skip(1)?;
+        }
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerAdts<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        let header = loop {
+            let header = match self.sync() {
+                Ok(header) => header,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return end_of_stream_error(),
+                Err(err) => return Err(err),
+            };
+            if header.crc_matches(&self.frame) {
+                break header;
+            }
+            self.crc_errors += 1;
+            self.iobuf_reader.skip(header.frame_len)?;
+        };
+
+        packet.pos = self.iobuf_reader.pos() as i64;
+        if self.strip_headers {
+            self.iobuf_reader.skip(header.header_len())?;
+            self.iobuf_reader.get_ioref(&mut packet.ioref, header.frame_len - header.header_len())?;
+        } else {
+            self.iobuf_reader.get_ioref(&mut packet.ioref, header.frame_len)?;
+        }
+        packet.stream_id = 0;
+        packet.pts = self.next_pts;
+        packet.dts = self.next_pts;
+        packet.duration = BLOCK_SAMPLES * header.blocks as i64;
+        packet.keyframe = true;
+        self.next_pts += packet.duration;
+        Ok(())
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+
+    /// AAC LC, 44.1 kHz stereo frame, with the given CRC.
+    fn adts_frame(payload: &[u8], crc: Option<u16>) -> Vec<u8> {
+        let len = payload.len() + if crc.is_some() { HEADER_CRC_LEN } else { HEADER_LEN };
+        let mut frame = vec![
+            0xFF,
+            if crc.is_some() { 0xF0 } else { 0xF1 },
+            0x50,
+            0x80 | (len >> 11) as u8,
+            (len >> 3) as u8,
+            (len << 5) as u8 | 0x1F,
+            0xFC,
+        ];
+        if let Some(crc) = crc {
+            frame.extend_from_slice(&crc.to_be_bytes());
+        }
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerAdts<S>) -> Vec<(i64, i64, Vec<u8>)> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.pts, packet.pos, packet.data().to_vec()));
+        }
+        packets
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        // CRC-16/CMS catalogue check value
+        assert_eq!(crc16(0xFFFF, b"123456789"), 0xAEE7);
+    }
+
+    #[test]
+    fn sync_and_crc() {
+        let payloads: Vec<Vec<u8>> = (0..4u8).map(|i| vec![0x21 + i; 30 + i as usize]).collect();
+        // CRCs of the header and the first 192 bits of the payloads, computed separately
+        let crcs = [None, Some(0xD9C8), None, Some(0x67FA)];
+        let frames: Vec<Vec<u8>> = payloads.iter().zip(crcs).map(|(payload, crc)| adts_frame(payload, crc)).collect();
+        let mut damaged = frames[3].clone();
+        damaged[12] ^= 0x01;
+        let id3 = [&b"ID3\x04\x00\x00\x00\x00\x00\x10TIT2\x00\x00\x00\x06\x00\x00\x03Title"[..]].concat();
+        // a false sync word precedes the first frame
+        let data = [&id3[..], &[0xFF, 0xF1, 0x00], &frames[0], &frames[1], b"junk", &frames[2], &damaged].concat();
+        let positions = [id3.len() + 3, id3.len() + 3 + frames[0].len(), id3.len() + 7 + frames[0].len() + frames[1].len()];
+
+        let mut demuxer = DemuxerAdts::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 64))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.sample_rate, stream.channels), (CodecId::Aac, 44100, 2));
+        assert_eq!((stream.codec_params.clone(), stream.time_base), (vec![0x12, 0x10], TimeBase::new(1, 44100)));
+        assert_eq!(demuxer.tags()[0].simple_tags[0].value.as_deref(), Some("Title"));
+        assert_eq!(read_all(&mut demuxer), vec![
+            (0, positions[0] as i64, frames[0].clone()),
+            (1024, positions[1] as i64, frames[1].clone()),
+            (2048, positions[2] as i64, frames[2].clone()),
+        ]);
+        assert_eq!(demuxer.crc_errors(), 1);
+
+        let demuxer = DemuxerAdts::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 64))).unwrap();
+        let mut demuxer = demuxer.with_stripped_headers(true);
+        let packets: Vec<Vec<u8>> = read_all(&mut demuxer).into_iter().map(|packet| packet.2).collect();
+        assert_eq!(packets, payloads[..3]);
+    }
+}
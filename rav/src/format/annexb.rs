@@ -0,0 +1,408 @@
+use crate::codec::{CodecId, MediaType};
+use crate::data::{IoRef, Packet2};
+use crate::error::{decode_error, end_of_stream_error, limit_error, unsupported_error, Error, Result};
+
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+/// Bytes read at once when scanning for start codes.
+const READ_SIZE: usize = 64 * 1024;
+
+/// Largest NAL unit or access unit.
+const MAX_UNIT_SIZE: usize = 64 * 1024 * 1024;
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// Frame rate used until `with_frame_rate` is called.
+const DEFAULT_FRAME_RATE: u32 = 25;
+
+/// Returns the position of the next `00 00 01` start code at or after `from`.
+fn find_start_code(data: &[u8], from: usize) -> Option<usize> {
+    let mut i = from + 2;
+    while i < data.len() {
+        match data[i] {
+            1 if data[i - 1] == 0 && data[i - 2] == 0 => return Some(i - 2),
+            // neither this byte nor the next one can end a start code
+            1.. => i += 3,
+            0 => i += 1,
+        }
+    }
+    None
+}
+
+/// Role of a NAL unit in the access unit grouping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NalKind {
+    /// Slice data, `first` is set for the first slice of a picture.
+    Vcl { first: bool, keyframe: bool },
+    ParameterSet,
+    /// Non VCL unit that precedes the slices of an access unit, like AUD or prefix SEI.
+    Prefix,
+    /// Non VCL unit that follows the slices, like suffix SEI or end of sequence.
+    Suffix,
+}
+
+/// Classifies a NAL unit, `first_mb_in_slice == 0` and `first_slice_segment_in_pic_flag` are
+/// both signalled by the first bit following the header.
+fn nal_kind(codec_id: CodecId, nal: &[u8]) -> NalKind {
+    if codec_id == CodecId::Hevc {
+        let first = nal.get(2).is_some_and(|&byte| byte & 0x80 != 0);
+        match (nal[0] >> 1) & 0x3F {
+            nal_type @ 0..=31 => NalKind::Vcl { first, keyframe: (16..=23).contains(&nal_type) },
+            32..=34 => NalKind::ParameterSet,
+            35 | 39 | 41..=44 | 48..=55 => NalKind::Prefix,
+            _ => NalKind::Suffix,
+        }
+    } else {
+        let first = nal.get(1).is_some_and(|&byte| byte & 0x80 != 0);
+        match nal[0] & 0x1F {
+            nal_type @ 1..=5 => NalKind::Vcl { first, keyframe: nal_type == 5 },
+            7 | 8 => NalKind::ParameterSet,
+            6 | 9 | 14..=18 => NalKind::Prefix,
+            _ => NalKind::Suffix,
+        }
+    }
+}
+
+/// Probe score of the first bytes of an input, a start code followed by a parameter set or an access
+/// unit delimiter of `codec_id`.
+pub(super) fn probe(data: &[u8], codec_id: CodecId) -> u32 {
+    let nal = data.strip_prefix(&[0, 0, 0, 1]).or(data.strip_prefix(&[0, 0, 1]));
+    let Some(&header) = nal.and_then(|nal| nal.first()) else {
+        return 0;
+    };
+    let start = match codec_id {
+        // SPS, PPS or AUD without the forbidden bit
+        CodecId::H264 => header & 0x80 == 0 && matches!(header & 0x1F, 7..=9),
+        // VPS, SPS, PPS or AUD of the base layer
+        _ => header & 0x81 == 0 && matches!((header >> 1) & 0x3F, 32..=35),
+    };
+    if start { PROBE_SCORE_MAX / 2 } else { 0 }
+}
+
+/// Demuxer for raw H.264 and H.265 elementary streams in the Annex B byte stream format. NAL units
+/// are grouped into access units, each emitted as a packet keeping its start codes. Timestamps are
+/// generated from the frame rate in decoding order, so `pts` equals `dts`.
+pub struct DemuxerAnnexB<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    /// Data read from the input, scanned from `buf_start`.
+    buf: Vec<u8>,
+    buf_start: usize,
+    /// Input position of `buf[0]`.
+    buf_pos: usize,
+    eof: bool,
+    /// Access unit being assembled.
+    au: Vec<u8>,
+    au_pos: usize,
+    au_has_vcl: bool,
+    au_keyframe: bool,
+    frame_count: i64,
+}
+
+impl<S: MediaIoBufRead> DemuxerAnnexB<S> {
+    /// Creates the demuxer for `codec_id`, `H264` or `Hevc`, reading the NAL units up to the first slice.
+    pub fn new(iobuf_reader: S, codec_id: CodecId) -> Result<Self> {
+        if !matches!(codec_id, CodecId::H264 | CodecId::Hevc) {
+            return unsupported_error("annexb: codec");
+        }
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: vec![Stream {
+                codec_id,
+                media_type: MediaType::Video,
+                time_base: TimeBase::new(1, DEFAULT_FRAME_RATE),
+                ..Default::default()
+            }],
+            buf: Vec::new(),
+            buf_start: 0,
+            buf_pos: 0,
+            eof: false,
+            au: Vec::new(),
+            au_pos: 0,
+            au_has_vcl: false,
+            au_keyframe: false,
+            frame_count: 0,
+        };
+        // the parameter sets preceding the first slice are the codec parameters
+        while !demuxer.au_has_vcl {
+            let Some((pos, nal)) = demuxer.next_nal()? else {
+                break;
+            };
+            let codec_params = &mut demuxer.streams[0].codec_params;
+            if nal_kind(codec_id, &nal) == NalKind::ParameterSet && !contains_nal(codec_params, &nal) {
+                codec_params.extend_from_slice(&START_CODE);
+                codec_params.extend_from_slice(&nal);
+            }
+            demuxer.add_nal(pos, &nal)?;
+        }
+        if demuxer.au.is_empty() {
+            return decode_error("annexb: no NAL units");
+        }
+        Ok(demuxer)
+    }
+
+    /// Sets the frame rate `num / den` used to generate the timestamps, 25 fps by default.
+    pub fn with_frame_rate(mut self, num: u32, den: u32) -> Self {
+        if num != 0 && den != 0 {
+            self.streams[0].time_base = TimeBase::new(den, num);
+        }
+        self
+    }
+
+    /// Reads more data into the scan buffer, returns false at the end of the input.
+    fn fill(&mut self) -> Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        self.buf.drain(..self.buf_start);
+        self.buf_pos += self.buf_start;
+        self.buf_start = 0;
+        if self.buf.len() > MAX_UNIT_SIZE {
+            return limit_error("annexb: NAL unit too large");
+        }
+
+        let mut len = READ_SIZE;
+        if let Some(stream_len) = self.iobuf_reader.stream_len() {
+            len = len.min(stream_len.saturating_sub(self.iobuf_reader.pos()));
+        }
+        // the length is unknown when streaming, the last read is shortened to the available data
+        while len > 0 {
+            let start = self.buf.len();
+            self.buf.resize(start + len, 0);
+            match self.iobuf_reader.get_bytes(&mut self.buf[start..]) {
+                Ok(()) => return Ok(true),
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => {
+                    self.buf.truncate(start);
+                    len /= 2;
+                }
+                Err(err) => {
+                    self.buf.truncate(start);
+                    return Err(err);
+                }
+            }
+        }
+        self.eof = true;
+        Ok(false)
+    }
+
+    /// Returns the next NAL unit without its start code, and the position of the start code.
+    fn next_nal(&mut self) -> Result<Option<(usize, Vec<u8>)>> {
+        loop {
+            let start = loop {
+                if let Some(start) = find_start_code(&self.buf, self.buf_start) {
+                    break start;
+                }
+                // data before the first start code is dropped, the last bytes may begin one
+                self.buf_start = self.buf.len().saturating_sub(2).max(self.buf_start);
+                if !self.fill()? {
+                    return Ok(None);
+                }
+            };
+            let pos = self.buf_pos + start;
+            self.buf_start = start + 3;
+
+            let mut from = self.buf_start;
+            let end = loop {
+                if let Some(end) = find_start_code(&self.buf, from) {
+                    break end;
+                }
+                from = self.buf.len().saturating_sub(2).max(self.buf_start);
+                let scanned = from - self.buf_start;
+                if !self.fill()? {
+                    break self.buf.len();
+                }
+                from = self.buf_start + scanned;
+            };
+            // the zero bytes before a start code are either trailing zeros or part of a 4 byte start code
+            let data = &self.buf[self.buf_start..end];
+            let nal = data[..data.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1)].to_vec();
+            self.buf_start = end;
+            // empty NAL units between consecutive start codes are skipped
+            if nal.is_empty() {
+                continue;
+            }
+            return Ok(Some((pos, nal)));
+        }
+    }
+
+    /// Appends a NAL unit to the current access unit, returns the previous access unit if the NAL unit
+    /// starts a new one.
+    fn add_nal(&mut self, pos: usize, nal: &[u8]) -> Result<Option<Packet2>> {
+        let kind = nal_kind(self.streams[0].codec_id, nal);
+        let starts_au = match kind {
+            NalKind::Vcl { first, .. } => first,
+            NalKind::ParameterSet | NalKind::Prefix => true,
+            NalKind::Suffix => false,
+        };
+        let previous = if starts_au && self.au_has_vcl { self.finish_au() } else { None };
+        if self.au.is_empty() {
+            self.au_pos = pos;
+        }
+        if self.au.len() + nal.len() > MAX_UNIT_SIZE {
+            return limit_error("annexb: access unit too large");
+        }
+        self.au.extend_from_slice(&START_CODE);
+        self.au.extend_from_slice(nal);
+        if let NalKind::Vcl { keyframe, .. } = kind {
+            self.au_keyframe |= keyframe;
+            self.au_has_vcl = true;
+        }
+        Ok(previous)
+    }
+
+    fn finish_au(&mut self) -> Option<Packet2> {
+        if self.au.is_empty() {
+            return None;
+        }
+        let packet = Packet2 {
+            ioref: IoRef::from(std::mem::take(&mut self.au)),
+            stream_id: 0,
+            pts: self.frame_count,
+            dts: self.frame_count,
+            duration: 1,
+            keyframe: self.au_keyframe,
+            pos: self.au_pos as i64,
+        };
+        self.frame_count += 1;
+        self.au_has_vcl = false;
+        self.au_keyframe = false;
+        Some(packet)
+    }
+}
+
+/// True if the Annex B `data` holds the NAL unit `nal`.
+fn contains_nal(data: &[u8], nal: &[u8]) -> bool {
+    let mut rest = data;
+    while let Some(next) = rest.get(START_CODE.len()..) {
+        let end = next.windows(START_CODE.len()).position(|window| window == START_CODE).unwrap_or(next.len());
+        if &next[..end] == nal {
+            return true;
+        }
+        rest = &next[end..];
+    }
+    false
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerAnnexB<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        loop {
+            let Some((pos, nal)) = self.next_nal()? else {
+                return match self.finish_au() {
+                    Some(next) => {
+                        *packet = next;
+                        Ok(())
+                    }
+                    None => end_of_stream_error(),
+                };
+            };
+            if let Some(next) = self.add_nal(pos, &nal)? {
+                *packet = next;
+                return Ok(());
+            }
+        }
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream, StreamingSupplier};
+
+    fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter().flat_map(|nal| [&START_CODE[..], nal].concat()).collect()
+    }
+
+    type PacketInfo = (i64, bool, Vec<u8>, i64);
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerAnnexB<S>) -> Vec<PacketInfo> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            assert_eq!(packet.pts, packet.dts);
+            packets.push((packet.pts, packet.keyframe, packet.data().to_vec(), packet.pos));
+        }
+        packets
+    }
+
+    #[test]
+    fn h264_access_units() {
+        let sps: &[u8] = &[0x67, 0x64, 0x00, 0x1F, 0xAC];
+        let pps: &[u8] = &[0x68, 0xEE, 0x3C, 0x80];
+        let idr = [&[0x65, 0x88][..], &vec![0xAB; 100_000], &[0x00, 0x00, 0x03, 0x01]].concat();
+        // first_mb_in_slice is not 0 in the second slice of the picture
+        let idr_second: &[u8] = &[0x65, 0x40, 0x11];
+        let sei: &[u8] = &[0x06, 0x05, 0x01, 0x80];
+        let p1: &[u8] = &[0x41, 0x9A, 0x01];
+        let p2: &[u8] = &[0x41, 0x9A, 0x02];
+        let first_au = annexb(&[&[0x09, 0xF0], sps, pps, &idr, idr_second]);
+        let data = [
+            &b"\x12\x00\x00"[..],
+            &first_au,
+            // trailing zeros and a 3 byte start code
+            &[0, 0],
+            &[0, 0, 1],
+            sei,
+            &annexb(&[p1, p2, sps]),
+        ].concat();
+        let expected = vec![
+            // positions of the 3 byte start codes
+            (0, true, first_au.clone(), 4),
+            (1, false, annexb(&[sei, p1]), (3 + first_au.len() + 2) as i64),
+            (2, false, annexb(&[p2]), (3 + first_au.len() + 5 + sei.len() + 4 + p1.len() + 1) as i64),
+            (3, false, annexb(&[sps]), (data.len() - sps.len() - 3) as i64),
+        ];
+
+        let supplier = IoBufSupplierMem::new(data.clone(), 5);
+        let mut demuxer = DemuxerAnnexB::new(MediaSourceStream::new(supplier), CodecId::H264).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.media_type, stream.time_base), (CodecId::H264, MediaType::Video, TimeBase::new(1, 25)));
+        assert_eq!(stream.codec_params, annexb(&[sps, pps]));
+        assert_eq!(read_all(&mut demuxer), expected);
+
+        let supplier = StreamingSupplier(IoBufSupplierMem::new(data, 7));
+        let mut demuxer = DemuxerAnnexB::new(MediaSourceStream::new(supplier), CodecId::H264).unwrap();
+        assert_eq!(read_all(&mut demuxer), expected);
+    }
+
+    #[test]
+    fn empty_nal_units() {
+        let sps: &[u8] = &[0x67, 0x64, 0x00, 0x1F, 0xAC];
+        let idr: &[u8] = &[0x65, 0x88, 0x84];
+        let data = [START_CODE.repeat(200_000), annexb(&[sps, idr])].concat();
+        let supplier = IoBufSupplierMem::new(data, 1 << 16);
+        let mut demuxer = DemuxerAnnexB::new(MediaSourceStream::new(supplier), CodecId::H264).unwrap();
+        let packets = read_all(&mut demuxer);
+        assert_eq!(packets.into_iter().map(|(_, key, data, _)| (key, data)).collect::<Vec<_>>(), vec![
+            (true, annexb(&[sps, idr])),
+        ]);
+    }
+
+    #[test]
+    fn hevc_access_units() {
+        let vps: &[u8] = &[0x40, 0x01, 0x0C];
+        let sps: &[u8] = &[0x42, 0x01, 0x01];
+        let pps: &[u8] = &[0x44, 0x01, 0xC1];
+        let idr: &[u8] = &[0x26, 0x01, 0xAF, 0x01];
+        let idr_segment: &[u8] = &[0x26, 0x01, 0x20, 0x02];
+        let suffix_sei: &[u8] = &[0x50, 0x01, 0x81];
+        let trail: &[u8] = &[0x02, 0x01, 0xD0, 0x03];
+        let data = annexb(&[vps, sps, pps, idr, idr_segment, suffix_sei, trail, trail]);
+
+        let supplier = IoBufSupplierMem::new(data, 16);
+        let mut demuxer = DemuxerAnnexB::new(MediaSourceStream::new(supplier), CodecId::Hevc).unwrap().with_frame_rate(30000, 1001);
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.time_base), (CodecId::Hevc, TimeBase::new(1001, 30000)));
+        assert_eq!(stream.codec_params, annexb(&[vps, sps, pps]));
+        let packets = read_all(&mut demuxer);
+        assert_eq!(packets.into_iter().map(|(pts, key, data, _)| (pts, key, data)).collect::<Vec<_>>(), vec![
+            (0, true, annexb(&[vps, sps, pps, idr, idr_segment, suffix_sei])),
+            (1, false, annexb(&[trail])),
+            (2, false, annexb(&[trail])),
+        ]);
+    }
+}
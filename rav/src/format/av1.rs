@@ -0,0 +1,261 @@
+use super::TimeBase;
+
+// OBU types
+pub(super) const OBU_SEQUENCE_HEADER: u8 = 1;
+pub(super) const OBU_TEMPORAL_DELIMITER: u8 = 2;
+const OBU_FRAME_HEADER: u8 = 3;
+const OBU_FRAME: u8 = 6;
+
+/// Reads a leb128 value, returns it and its length in bytes.
+pub(super) fn leb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7F) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Fields of an OBU header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ObuHeader {
+    pub(super) obu_type: u8,
+    /// Temporal and spatial layer IDs.
+    pub(super) extension: Option<u8>,
+    /// The header is followed by the leb128 size of the payload.
+    pub(super) has_size: bool,
+}
+
+impl ObuHeader {
+    /// Parses an OBU header, returns it and its length without the size field.
+    pub(super) fn parse(data: &[u8]) -> Option<(Self, usize)> {
+        let &first = data.first()?;
+        // forbidden bit
+        if first & 0x80 != 0 {
+            return None;
+        }
+        let extension = if first & 0x04 != 0 { Some(*data.get(1)?) } else { None };
+        let header = ObuHeader { obu_type: (first >> 3) & 0x0F, extension, has_size: first & 0x02 != 0 };
+        Some((header, 1 + extension.is_some() as usize))
+    }
+}
+
+/// Appends an OBU in the low overhead bitstream format, with a size field.
+pub(super) fn write_obu(out: &mut Vec<u8>, header: &ObuHeader, payload: &[u8]) {
+    out.push(header.obu_type << 3 | (header.extension.is_some() as u8) << 2 | 0x02);
+    out.extend(header.extension);
+    write_leb128(out, payload.len() as u64);
+    out.extend_from_slice(payload);
+}
+
+/// Splits data in the low overhead bitstream format into OBU headers and payloads, `None` if an OBU
+/// has no size field or is truncated.
+pub(super) fn split_obus(mut data: &[u8]) -> Option<Vec<(ObuHeader, &[u8])>> {
+    let mut obus = Vec::new();
+    while !data.is_empty() {
+        let (header, header_len) = ObuHeader::parse(data)?;
+        if !header.has_size {
+            return None;
+        }
+        let (size, size_len) = leb128(&data[header_len..])?;
+        let start = header_len + size_len;
+        let payload = data.get(start..start.checked_add(usize::try_from(size).ok()?)?)?;
+        data = &data[start + payload.len()..];
+        obus.push((header, payload));
+    }
+    Some(obus)
+}
+
+/// MSB first bit reader.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = *self.data.get(self.pos / 8)?;
+            value = value << 1 | ((byte >> (7 - self.pos % 8)) & 1) as u32;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        self.bits(1).map(|bit| bit != 0)
+    }
+
+    fn uvlc(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.flag()? {
+            leading_zeros += 1;
+            if leading_zeros == 32 {
+                return Some(u32::MAX);
+            }
+        }
+        Some(((1u64 << leading_zeros) - 1 + self.bits(leading_zeros)? as u64) as u32)
+    }
+}
+
+/// Fields of a sequence header used by the demuxers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SequenceHeader {
+    pub(super) width: u32,
+    pub(super) height: u32,
+    /// Frame headers are omitted, every frame is a key frame.
+    reduced_still_picture_header: bool,
+    /// Duration of a picture from the timing info, when the pictures have a constant interval.
+    pub(super) frame_duration: Option<TimeBase>,
+    /// AV1CodecConfigurationRecord (`av1C`) holding the sequence header OBU, as in MP4 and Matroska.
+    pub(super) av1c: Vec<u8>,
+}
+
+impl SequenceHeader {
+    /// Parses a sequence header OBU, which is stored in the configuration record.
+    pub(super) fn parse(header: &ObuHeader, payload: &[u8]) -> Option<Self> {
+        let mut reader = BitReader { data: payload, pos: 0 };
+        let profile = reader.bits(3)?;
+        let _still_picture = reader.flag()?;
+        let reduced_still_picture_header = reader.flag()?;
+        let mut frame_duration = None;
+        let (level, tier) = if reduced_still_picture_header {
+            (reader.bits(5)?, 0)
+        } else {
+            let mut decoder_model_info = false;
+            let mut buffer_delay_len = 0;
+            if reader.flag()? {
+                // timing info
+                let units_in_tick = reader.bits(32)?;
+                let time_scale = reader.bits(32)?;
+                if reader.flag()? {
+                    let ticks = reader.uvlc()?.saturating_add(1);
+                    if units_in_tick != 0 && time_scale != 0 {
+                        frame_duration = Some(TimeBase::new(units_in_tick.saturating_mul(ticks), time_scale));
+                    }
+                }
+                decoder_model_info = reader.flag()?;
+                if decoder_model_info {
+                    buffer_delay_len = reader.bits(5)? + 1;
+                    // decoding tick units, buffer removal and frame presentation time lengths
+                    reader.bits(32)?;
+                    reader.bits(10)?;
+                }
+            }
+            let initial_display_delay = reader.flag()?;
+            let mut first = (0, 0);
+            for i in 0..reader.bits(5)? + 1 {
+                // operating point idc
+                reader.bits(12)?;
+                let level = reader.bits(5)?;
+                let tier = if level > 7 { reader.bits(1)? } else { 0 };
+                if i == 0 {
+                    first = (level, tier);
+                }
+                if decoder_model_info && reader.flag()? {
+                    reader.bits(2 * buffer_delay_len + 1)?;
+                }
+                if initial_display_delay && reader.flag()? {
+                    reader.bits(4)?;
+                }
+            }
+            first
+        };
+
+        let width_bits = reader.bits(4)? + 1;
+        let height_bits = reader.bits(4)? + 1;
+        let width = reader.bits(width_bits)? + 1;
+        let height = reader.bits(height_bits)? + 1;
+        if !reduced_still_picture_header && reader.flag()? {
+            // delta and additional frame ID lengths
+            reader.bits(7)?;
+        }
+        // 128x128 superblock, filter intra and intra edge filter
+        reader.bits(3)?;
+        if !reduced_still_picture_header {
+            // interintra compound, masked compound, warped motion and dual filter
+            reader.bits(4)?;
+            let order_hint = reader.flag()?;
+            if order_hint {
+                reader.bits(2)?;
+            }
+            let force_screen_content_tools = if reader.flag()? { 2 } else { reader.bits(1)? };
+            if force_screen_content_tools > 0 && !reader.flag()? {
+                reader.bits(1)?;
+            }
+            if order_hint {
+                reader.bits(3)?;
+            }
+        }
+        // superres, CDEF and loop restoration
+        reader.bits(3)?;
+
+        // color config
+        let high_bitdepth = reader.flag()?;
+        let twelve_bit = profile == 2 && high_bitdepth && reader.flag()?;
+        let monochrome = profile != 1 && reader.flag()?;
+        let (mut primaries, mut transfer, mut matrix) = (2, 2, 2);
+        if reader.flag()? {
+            primaries = reader.bits(8)?;
+            transfer = reader.bits(8)?;
+            matrix = reader.bits(8)?;
+        }
+        let (subsampling_x, subsampling_y, chroma_position) = if monochrome {
+            reader.bits(1)?;
+            (1, 1, 0)
+        } else if (primaries, transfer, matrix) == (1, 13, 0) {
+            // sRGB
+            (0, 0, 0)
+        } else {
+            // color range
+            reader.bits(1)?;
+            let (x, y) = match profile {
+                0 => (1, 1),
+                1 => (0, 0),
+                _ if twelve_bit => {
+                    let x = reader.bits(1)?;
+                    (x, if x == 1 { reader.bits(1)? } else { 0 })
+                }
+                _ => (1, 0),
+            };
+            (x, y, if (x, y) == (1, 1) { reader.bits(2)? } else { 0 })
+        };
+
+        let flags = tier << 7 | (high_bitdepth as u32) << 6 | (twelve_bit as u32) << 5 | (monochrome as u32) << 4
+            | subsampling_x << 3 | subsampling_y << 2 | chroma_position;
+        let mut av1c = vec![0x81, (profile << 5 | level) as u8, flags as u8, 0];
+        write_obu(&mut av1c, header, payload);
+        Some(SequenceHeader { width, height, reduced_still_picture_header, frame_duration, av1c })
+    }
+
+    /// Finds the first sequence header of a temporal unit in the low overhead format.
+    pub(super) fn find(temporal_unit: &[u8]) -> Option<Self> {
+        let obus = split_obus(temporal_unit)?;
+        let (header, payload) = obus.iter().find(|(header, _)| header.obu_type == OBU_SEQUENCE_HEADER)?;
+        Self::parse(header, payload)
+    }
+
+    /// True if a temporal unit in the low overhead format holds a key frame.
+    pub(super) fn is_keyframe(&self, temporal_unit: &[u8]) -> bool {
+        split_obus(temporal_unit).into_iter().flatten().any(|(header, payload)| {
+            // show_existing_frame is clear and frame_type is KEY_FRAME
+            matches!(header.obu_type, OBU_FRAME_HEADER | OBU_FRAME)
+                && (self.reduced_still_picture_header || payload.first().is_some_and(|&first| first & 0xE0 == 0))
+        })
+    }
+}
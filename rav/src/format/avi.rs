@@ -0,0 +1,602 @@
+use crate::codec::{CodecId, MediaType};
+use crate::data::{IoRef, Packet2};
+use crate::error::{
+    decode_error, end_of_stream_error, invalid_input_error, limit_error, unsupported_error, Error, Result,
+};
+use crate::metadata::Tag;
+
+use super::riff::{chunks, le_u16, le_u32, le_u64, parse_info_list, read_chunk_header, WaveFormat};
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+/// AVIIF_KEYFRAME flag of `idx1` entries.
+const AVIIF_KEYFRAME: u32 = 0x10;
+/// Flag of the OpenDML standard index entry sizes marking a non key frame.
+const ODML_NON_KEYFRAME: u32 = 0x8000_0000;
+const AVI_INDEX_OF_INDEXES: u8 = 0;
+const AVI_INDEX_OF_CHUNKS: u8 = 1;
+
+/// Largest header list or index chunk loaded in memory.
+const MAX_CHUNK_SIZE: u32 = 256 * 1024 * 1024;
+
+fn video_codec_from_fourcc(fourcc: &[u8; 4]) -> CodecId {
+    let mut fourcc = *fourcc;
+    fourcc.make_ascii_uppercase();
+    match &fourcc {
+        b"H264" | b"X264" | b"AVC1" | b"DAVC" => CodecId::H264,
+        b"HEVC" | b"H265" | b"X265" | b"HVC1" => CodecId::Hevc,
+        b"XVID" | b"DIVX" | b"DX50" | b"FMP4" | b"MP4V" => CodecId::Mpeg4,
+        b"MPG1" => CodecId::Mpeg1Video,
+        b"MPG2" => CodecId::Mpeg2Video,
+        b"VP80" => CodecId::Vp8,
+        b"VP90" => CodecId::Vp9,
+        b"AV01" => CodecId::Av1,
+        _ => CodecId::Unknown,
+    }
+}
+
+/// Stream number of a `movi` chunk ID, e.g. 1 for `01wb`.
+fn stream_number(id: &[u8; 4]) -> Option<usize> {
+    (id[0].is_ascii_digit() && id[1].is_ascii_digit()).then(|| ((id[0] - b'0') * 10 + id[1] - b'0') as usize)
+}
+
+/// Padded size of a chunk.
+fn padded(size: u32) -> usize {
+    size as usize + (size & 1) as usize
+}
+
+/// Timing of an `strl` stream.
+#[derive(Debug, Default)]
+struct AviStream {
+    /// Size of an audio sample in bytes, 0 if each chunk holds one sample.
+    sample_size: u32,
+    /// Start time in stream time base units.
+    start: i64,
+    /// Time of the next chunk read sequentially, from the start of the stream.
+    next_ts: i64,
+    /// Position and size of the OpenDML standard indexes.
+    super_index: Vec<(u64, u32)>,
+}
+
+impl AviStream {
+    /// Returns the timestamp and duration of a chunk and advances to the next one.
+    fn advance(&mut self, next_ts: &mut i64, size: u32) -> (i64, i64) {
+        let duration = size.checked_div(self.sample_size).map_or(1, i64::from);
+        let ts = self.start + *next_ts;
+        *next_ts += duration;
+        (ts, duration)
+    }
+}
+
+/// A chunk of the `idx1` or OpenDML indexes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexEntry {
+    /// Position of the chunk data.
+    pos: u64,
+    size: u32,
+    stream_id: usize,
+    keyframe: bool,
+    ts: i64,
+    duration: i64,
+}
+
+/// Probe score of the first bytes of an input, a RIFF header of AVI form type.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    if data.starts_with(b"RIFF") && matches!(data.get(8..12), Some(b"AVI " | b"AVIX")) { PROBE_SCORE_MAX } else { 0 }
+}
+
+/// AVI demuxer, including OpenDML files with `indx` super indexes and `AVIX` extensions.
+/// The index is read when the input is seekable and gives the keyframe flags, otherwise the
+/// `movi` chunks are read in sequence and reported as keyframes.
+pub struct DemuxerAvi<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    /// Stream index of each `strl`, `None` for unsupported stream types.
+    stream_map: Vec<Option<usize>>,
+    avi_streams: Vec<AviStream>,
+    tags: Vec<Tag>,
+    /// Position of the `movi` list type, the base of relative `idx1` offsets.
+    movi_pos: usize,
+    /// Position following the first `movi` list.
+    movi_end: usize,
+    /// Chunks in file order, empty if the input has no index or isn't seekable.
+    index: Vec<IndexEntry>,
+    next: usize,
+    /// After a seek, chunks of each stream ending before these timestamps are dropped.
+    start_ts: Vec<i64>,
+}
+
+impl<S: MediaIoBufRead> DemuxerAvi<S> {
+    /// Creates the demuxer, reading the headers up to the `movi` list and the index.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            stream_map: Vec::new(),
+            avi_streams: Vec::new(),
+            tags: Vec::new(),
+            movi_pos: 0,
+            movi_end: 0,
+            index: Vec::new(),
+            next: 0,
+            start_ts: Vec::new(),
+        };
+        demuxer.read_headers()?;
+        Ok(demuxer)
+    }
+
+    /// True when the chunks are read from the index, which enables seeking.
+    pub fn has_index(&self) -> bool {
+        !self.index.is_empty()
+    }
+
+    fn read_list(&mut self, size: u32) -> Result<Vec<u8>> {
+        if size > MAX_CHUNK_SIZE {
+            return limit_error("avi: chunk too large");
+        }
+        let mut data = vec![0u8; padded(size)];
+        self.iobuf_reader.get_bytes(&mut data)?;
+        data.truncate(size as usize);
+        Ok(data)
+    }
+
+    fn read_headers(&mut self) -> Result<()> {
+        let (id, _) = read_chunk_header(&mut self.iobuf_reader)?;
+        let mut form = [0u8; 4];
+        self.iobuf_reader.get_bytes(&mut form)?;
+        if &id != b"RIFF" || &form != b"AVI " {
+            return decode_error("avi: missing RIFF header");
+        }
+
+        loop {
+            let (id, size) = match read_chunk_header(&mut self.iobuf_reader) {
+                Ok(header) => header,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return decode_error("avi: missing movi"),
+                Err(err) => return Err(err),
+            };
+            if &id != b"LIST" || size < 4 {
+                self.iobuf_reader.skip(padded(size))?;
+                continue;
+            }
+            let mut list_type = [0u8; 4];
+            self.iobuf_reader.get_bytes(&mut list_type)?;
+            match &list_type {
+                b"hdrl" => {
+                    let hdrl = self.read_list(size - 4)?;
+                    self.parse_hdrl(&hdrl)?;
+                }
+                b"INFO" => {
+                    let info = self.read_list(size - 4)?;
+                    self.tags = vec![Tag { simple_tags: parse_info_list(&info), ..Default::default() }];
+                }
+                b"movi" => {
+                    self.movi_pos = self.iobuf_reader.pos() - 4;
+                    self.movi_end = self.movi_pos + size as usize;
+                    break;
+                }
+                _ => self.iobuf_reader.skip(padded(size) - 4)?,
+            }
+        }
+        if self.streams.is_empty() {
+            return decode_error("avi: no streams");
+        }
+        self.start_ts = vec![i64::MIN; self.streams.len()];
+
+        // the index is read only when the input is seekable
+        if self.iobuf_reader.stream_len().is_none() {
+            return Ok(());
+        }
+        let data_start = self.iobuf_reader.pos();
+        let has_super_index = self.stream_map.iter()
+            .zip(&self.avi_streams)
+            .all(|(stream_id, stream)| stream_id.is_none() || !stream.super_index.is_empty());
+        let entries = if has_super_index { self.read_odml_index() } else { self.read_idx1() };
+        match entries {
+            Ok(entries) => self.build_index(entries),
+            Err(Error::Unsupported(_) | Error::IoError(std::io::ErrorKind::UnexpectedEof)) => {}
+            Err(err) => return Err(err),
+        }
+        self.iobuf_reader.seek(data_start)
+    }
+
+    fn parse_hdrl(&mut self, hdrl: &[u8]) -> Result<()> {
+        for (id, data) in chunks(hdrl) {
+            if &id == b"LIST" && data.starts_with(b"strl") {
+                self.parse_strl(&data[4..])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_strl(&mut self, strl: &[u8]) -> Result<()> {
+        let find = |kind: &[u8; 4]| chunks(strl).find(|(id, _)| id == kind).map(|(_, data)| data);
+        let Some(strh) = find(b"strh").filter(|strh| strh.len() >= 48) else {
+            return decode_error("avi: missing strh");
+        };
+        let strf = find(b"strf").unwrap_or_default();
+        let (scale, rate) = (le_u32(strh, 20), le_u32(strh, 24));
+        let mut stream = Stream {
+            id: self.streams.len(),
+            time_base: if scale == 0 || rate == 0 { TimeBase::default() } else { TimeBase::new(scale, rate) },
+            duration: le_u32(strh, 32) as i64,
+            ..Default::default()
+        };
+        let mut avi_stream = AviStream { start: le_u32(strh, 28) as i64, ..Default::default() };
+
+        match &strh[..4] {
+            b"vids" => {
+                stream.media_type = MediaType::Video;
+                if strf.len() >= 40 {
+                    stream.width = (le_u32(strf, 4) as i32).unsigned_abs();
+                    stream.height = (le_u32(strf, 8) as i32).unsigned_abs();
+                    stream.codec_id = video_codec_from_fourcc(strf[16..20].try_into().unwrap());
+                    // biSize often includes the codec data following the header
+                    stream.codec_params = strf[40..].to_vec();
+                }
+            }
+            b"auds" => {
+                let format = WaveFormat::parse(strf)?;
+                stream.media_type = MediaType::Audio;
+                stream.codec_id = format.codec_id();
+                stream.sample_rate = format.sample_rate;
+                stream.channels = format.channels;
+                stream.bits_per_sample = format.bits_per_sample;
+                stream.codec_params = format.extra;
+                avi_stream.sample_size = le_u32(strh, 44);
+            }
+            _ => {
+                self.stream_map.push(None);
+                self.avi_streams.push(avi_stream);
+                return Ok(());
+            }
+        }
+
+        if let Some(indx) = find(b"indx").filter(|indx| indx.len() >= 24 && indx[3] == AVI_INDEX_OF_INDEXES) {
+            let count = le_u32(indx, 4) as usize;
+            avi_stream.super_index = indx[24..].chunks_exact(16)
+                .take(count)
+                .map(|entry| (le_u64(entry, 0), le_u32(entry, 8)))
+                .collect();
+        }
+        self.stream_map.push(Some(stream.id));
+        self.avi_streams.push(avi_stream);
+        self.streams.push(stream);
+        Ok(())
+    }
+
+    /// Reads the `idx1` chunk following the first `movi` list, returns the entries with their stream numbers.
+    fn read_idx1(&mut self) -> Result<Vec<(usize, IndexEntry)>> {
+        self.iobuf_reader.seek(self.movi_end)?;
+        let idx1 = loop {
+            let (id, size) = read_chunk_header(&mut self.iobuf_reader)?;
+            match &id {
+                b"idx1" => break self.read_list(size)?,
+                // the index precedes the OpenDML extensions
+                b"RIFF" => return unsupported_error("avi: missing idx1"),
+                _ => self.iobuf_reader.skip(padded(size))?,
+            }
+        };
+
+        let entries: Vec<&[u8]> = idx1.chunks_exact(16).collect();
+        let Some(first) = entries.iter().find(|entry| stream_number(entry[..4].try_into().unwrap()).is_some()) else {
+            return Ok(Vec::new());
+        };
+        // offsets are relative to the movi list type, but some muxers write absolute positions
+        let base = if (le_u32(first, 8) as usize) < self.movi_pos { self.movi_pos as u64 } else { 0 };
+        Ok(entries.iter()
+            .filter_map(|entry| {
+                let number = stream_number(entry[..4].try_into().unwrap())?;
+                Some((number, IndexEntry {
+                    pos: base + le_u32(entry, 8) as u64 + 8,
+                    size: le_u32(entry, 12),
+                    stream_id: 0,
+                    keyframe: le_u32(entry, 4) & AVIIF_KEYFRAME != 0,
+                    ts: 0,
+                    duration: 0,
+                }))
+            })
+            .collect())
+    }
+
+    /// Reads the OpenDML standard indexes of the streams, returns the entries with their stream numbers.
+    fn read_odml_index(&mut self) -> Result<Vec<(usize, IndexEntry)>> {
+        let mut entries = Vec::new();
+        for number in 0..self.avi_streams.len() {
+            for i in 0..self.avi_streams[number].super_index.len() {
+                let (pos, _) = self.avi_streams[number].super_index[i];
+                self.iobuf_reader.seek(pos as usize)?;
+                let (_, size) = read_chunk_header(&mut self.iobuf_reader)?;
+                let ix = self.read_list(size)?;
+                if ix.len() < 24 || ix[3] != AVI_INDEX_OF_CHUNKS {
+                    return decode_error("avi: invalid standard index");
+                }
+                let entry_size = 4 * (le_u16(&ix, 0) as usize).max(2);
+                let base = le_u64(&ix, 12);
+                let count = le_u32(&ix, 4) as usize;
+                entries.extend(ix[24..].chunks_exact(entry_size).take(count).map(|entry| {
+                    let size = le_u32(entry, 4);
+                    (number, IndexEntry {
+                        pos: base + le_u32(entry, 0) as u64,
+                        size: size & !ODML_NON_KEYFRAME,
+                        stream_id: 0,
+                        keyframe: size & ODML_NON_KEYFRAME == 0,
+                        ts: 0,
+                        duration: 0,
+                    })
+                }));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Sorts the entries in file order and computes their timestamps.
+    fn build_index(&mut self, mut entries: Vec<(usize, IndexEntry)>) {
+        entries.sort_by_key(|(_, entry)| entry.pos);
+        let mut next_ts = vec![0; self.avi_streams.len()];
+        for (number, mut entry) in entries {
+            let Some(Some(stream_id)) = self.stream_map.get(number).copied() else {
+                continue;
+            };
+            (entry.ts, entry.duration) = self.avi_streams[number].advance(&mut next_ts[number], entry.size);
+            entry.stream_id = stream_id;
+            self.index.push(entry);
+        }
+    }
+
+    /// Reads the next chunk of the `movi` lists in sequence.
+    fn read_next_chunk(&mut self, packet: &mut Packet2) -> Result<()> {
+        loop {
+            let pos = self.iobuf_reader.pos();
+            let (id, size) = match read_chunk_header(&mut self.iobuf_reader) {
+                Ok(header) => header,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return end_of_stream_error(),
+                Err(err) => return Err(err),
+            };
+            match &id {
+                // OpenDML extension, or a list of chunks
+                b"RIFF" | b"LIST" => {
+                    let mut list_type = [0u8; 4];
+                    self.iobuf_reader.get_bytes(&mut list_type)?;
+                    if !matches!(&list_type, b"AVIX" | b"movi" | b"rec ") {
+                        self.iobuf_reader.skip(padded(size).saturating_sub(4))?;
+                    }
+                }
+                _ => {
+                    let stream = stream_number(&id).and_then(|number| Some((number, (*self.stream_map.get(number)?)?)));
+                    let Some((number, stream_id)) = stream.filter(|_| &id[2..] != b"pc") else {
+                        self.iobuf_reader.skip(padded(size))?;
+                        continue;
+                    };
+                    let avi_stream = &mut self.avi_streams[number];
+                    let mut next_ts = avi_stream.next_ts;
+                    let (ts, duration) = avi_stream.advance(&mut next_ts, size);
+                    avi_stream.next_ts = next_ts;
+                    if size == 0 {
+                        continue;
+                    }
+                    self.iobuf_reader.get_ioref(&mut packet.ioref, size as usize)?;
+                    if size & 1 != 0 {
+                        self.iobuf_reader.skip(1)?;
+                    }
+                    packet.stream_id = stream_id;
+                    packet.pts = ts;
+                    packet.dts = ts;
+                    packet.duration = duration;
+                    packet.keyframe = true;
+                    packet.pos = pos as i64;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerAvi<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        if self.index.is_empty() {
+            return self.read_next_chunk(packet);
+        }
+
+        while let Some(&entry) = self.index.get(self.next) {
+            self.next += 1;
+            if entry.size == 0 || entry.ts + entry.duration.max(1) <= self.start_ts[entry.stream_id] {
+                continue;
+            }
+            let pos = entry.pos as usize;
+            let current = self.iobuf_reader.pos();
+            if pos >= current {
+                self.iobuf_reader.skip(pos - current)?;
+            } else {
+                self.iobuf_reader.seek(pos)?;
+            }
+            self.iobuf_reader.get_ioref(&mut packet.ioref, entry.size as usize)?;
+            packet.stream_id = entry.stream_id;
+            packet.pts = entry.ts;
+            packet.dts = entry.ts;
+            packet.duration = entry.duration;
+            packet.keyframe = entry.keyframe;
+            // position of the chunk header
+            packet.pos = pos as i64 - 8;
+            return Ok(());
+        }
+        end_of_stream_error()
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn seek(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        if stream_id >= self.streams.len() {
+            return invalid_input_error();
+        }
+        if self.index.is_empty() {
+            return unsupported_error("avi: seek without index");
+        }
+        let keyframes = || self.index.iter().filter(|entry| entry.stream_id == stream_id && entry.keyframe);
+        let Some(time) = keyframes().rfind(|entry| entry.ts <= timestamp)
+            .or_else(|| keyframes().next())
+            .map(|entry| entry.ts)
+        else {
+            return decode_error("avi: no keyframe to seek to");
+        };
+
+        // the other streams resume with the chunk playing at the keyframe time
+        let time_base = self.streams[stream_id].time_base;
+        for (i, start_ts) in self.start_ts.iter_mut().enumerate() {
+            *start_ts = time_base.rescale(time, self.streams[i].time_base);
+        }
+        self.next = self.index.iter()
+            .position(|entry| entry.ts + entry.duration.max(1) > self.start_ts[entry.stream_id])
+            .unwrap_or(self.index.len());
+        Ok(time)
+    }
+
+    fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream, StreamingSupplier};
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let padding = vec![0; data.len() & 1];
+        [&id[..], &(data.len() as u32).to_le_bytes(), data, &padding].concat()
+    }
+
+    fn list(id: &[u8; 4], list_type: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+        chunk(id, &[&list_type[..], &children.concat()].concat())
+    }
+
+    fn le32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    fn strh(kind: &[u8; 4], scale: u32, rate: u32, length: u32, sample_size: u32) -> Vec<u8> {
+        chunk(b"strh", &[&kind[..], &[0; 16], &le32s(&[scale, rate, 0, length, 0, 0, sample_size]), &[0; 8]].concat())
+    }
+
+    fn video_strl(extra: Vec<Vec<u8>>) -> Vec<u8> {
+        let strf = [le32s(&[44, 320]), (-240i32).to_le_bytes().to_vec(), vec![1, 0, 24, 0], b"XVID".to_vec(), vec![0; 20], vec![0xE0; 4]].concat();
+        list(b"LIST", b"strl", &[&[strh(b"vids", 1, 100, 4, 0), chunk(b"strf", &strf)][..], &extra].concat())
+    }
+
+    fn audio_strl() -> Vec<u8> {
+        let strf = [vec![1, 0, 2, 0], le32s(&[8000, 32000]), vec![4, 0, 16, 0, 0, 0]].concat();
+        list(b"LIST", b"strl", &[strh(b"auds", 1, 100, 6, 4), chunk(b"strf", &strf)])
+    }
+
+    type PacketInfo = (usize, i64, i64, bool, Vec<u8>);
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerAvi<S>) -> Vec<PacketInfo> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.stream_id, packet.pts, packet.duration, packet.keyframe, packet.data().to_vec()));
+        }
+        packets
+    }
+
+    /// Video at 1/100 and PCM audio with 4-byte samples at 1/100, indexed by `idx1`.
+    /// The second and third video frames are not keyframes, the third is dropped.
+    fn build_file() -> Vec<u8> {
+        let mut movi = Vec::new();
+        let mut idx1 = Vec::new();
+        let mut add = |id: &[u8; 4], data: &[u8], flags: Option<u32>, in_rec: bool| {
+            let offset = 4 + movi.len() as u32 + if in_rec { 12 } else { 0 };
+            if let Some(flags) = flags {
+                idx1.extend([&id[..], &le32s(&[flags, offset, data.len() as u32])].concat());
+            }
+            let data = chunk(id, data);
+            movi.extend(if in_rec { list(b"LIST", b"rec ", &[data]) } else { data });
+        };
+        add(b"00dc", &[0xA1; 5], Some(AVIIF_KEYFRAME), false);
+        add(b"01wb", &[0xB1; 8], Some(AVIIF_KEYFRAME), false);
+        add(b"00dc", &[0xA2; 3], Some(0), true);
+        add(b"00dc", &[], Some(0), false);
+        add(b"JUNK", &[0; 4], None, false);
+        add(b"01wb", &[0xB2; 16], Some(AVIIF_KEYFRAME), false);
+        add(b"00dc", &[0xA3; 2], Some(AVIIF_KEYFRAME), false);
+
+        let hdrl = list(b"LIST", b"hdrl", &[chunk(b"avih", &[0; 56]), video_strl(Vec::new()), audio_strl()]);
+        let info = list(b"LIST", b"INFO", &[chunk(b"INAM", b"title\0")]);
+        list(b"RIFF", b"AVI ", &[hdrl, info, chunk(b"LIST", &[&b"movi"[..], &movi].concat()), chunk(b"idx1", &idx1)])
+    }
+
+    #[test]
+    fn idx1_index() {
+        let data = build_file();
+        let mut demuxer = DemuxerAvi::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 7))).unwrap();
+        assert!(demuxer.has_index());
+        let streams = demuxer.streams();
+        assert_eq!((streams[0].codec_id, streams[0].width, streams[0].height), (CodecId::Mpeg4, 320, 240));
+        assert_eq!((streams[0].time_base, streams[0].duration), (TimeBase::new(1, 100), 4));
+        assert_eq!(streams[0].codec_params, vec![0xE0; 4]);
+        assert_eq!((streams[1].codec_id, streams[1].sample_rate, streams[1].channels), (CodecId::PcmS16Le, 8000, 2));
+        assert_eq!(demuxer.tags()[0].simple_tags[0].value.as_deref(), Some("title"));
+
+        let expected = vec![
+            (0, 0, 1, true, vec![0xA1; 5]),
+            (1, 0, 2, true, vec![0xB1; 8]),
+            (0, 1, 1, false, vec![0xA2; 3]),
+            (1, 2, 4, true, vec![0xB2; 16]),
+            (0, 3, 1, true, vec![0xA3; 2]),
+        ];
+        assert_eq!(read_all(&mut demuxer), expected);
+
+        // the audio resumes with the chunk playing at the keyframe
+        assert_eq!(demuxer.seek(0, 2), Ok(0));
+        assert_eq!(read_all(&mut demuxer), expected);
+        assert_eq!(demuxer.seek(0, 5), Ok(3));
+        assert_eq!(read_all(&mut demuxer), expected[3..].to_vec());
+        assert_eq!(demuxer.seek(2, 0), Err(Error::InvalidInput));
+
+        // read in sequence without the index, every chunk is reported as a keyframe
+        let mut demuxer = DemuxerAvi::new(MediaSourceStream::new(StreamingSupplier(IoBufSupplierMem::new(data, 7)))).unwrap();
+        assert!(!demuxer.has_index());
+        let expected: Vec<PacketInfo> = expected.into_iter().map(|(id, ts, duration, _, data)| (id, ts, duration, true, data)).collect();
+        assert_eq!(read_all(&mut demuxer), expected);
+        assert!(matches!(demuxer.seek(0, 0), Err(Error::Unsupported(_))));
+    }
+
+    fn odml_ix(entries: &[(u64, u32)]) -> Vec<u8> {
+        let entries: Vec<u32> = entries.iter().flat_map(|&(pos, size)| [pos as u32, size]).collect();
+        chunk(b"ix00", &[&[2, 0, 0, AVI_INDEX_OF_CHUNKS][..], &le32s(&[entries.len() as u32 / 2]), b"00dc", &[0; 12], &le32s(&entries)].concat())
+    }
+
+    #[test]
+    fn odml_index() {
+        let hdrl = |ix_positions: [u64; 2]| {
+            let entries: Vec<u8> = ix_positions.iter().flat_map(|&pos| [&pos.to_le_bytes()[..], &le32s(&[0, 0])].concat()).collect();
+            let indx = chunk(b"indx", &[&[4, 0, 0, AVI_INDEX_OF_INDEXES][..], &le32s(&[2]), b"00dc", &[0; 12], &entries].concat());
+            list(b"LIST", b"hdrl", &[chunk(b"avih", &[0; 56]), video_strl(vec![indx])])
+        };
+        // positions of the chunks following the RIFF and movi list headers
+        let first = 12 + hdrl([0; 2]).len() as u64 + 12;
+        let ix_positions = [first + 22, first + 22 + 48 + 24 + 12];
+        let frames = [(first + 8, 4), (first + 20, 2 | ODML_NON_KEYFRAME), (ix_positions[1] - 4, 3)];
+
+        let movi = list(b"LIST", b"movi", &[chunk(b"00dc", &[0xA1; 4]), chunk(b"00dc", &[0xA2; 2]), odml_ix(&frames[..2])]);
+        let avix_movi = list(b"LIST", b"movi", &[chunk(b"00dc", &[0xA3; 3]), odml_ix(&frames[2..])]);
+        let data = [list(b"RIFF", b"AVI ", &[hdrl(ix_positions), movi]), list(b"RIFF", b"AVIX", &[avix_movi])].concat();
+
+        let mut demuxer = DemuxerAvi::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 9))).unwrap();
+        assert!(demuxer.has_index());
+        let expected = vec![
+            (0, 0, 1, true, vec![0xA1; 4]),
+            (0, 1, 1, false, vec![0xA2; 2]),
+            (0, 2, 1, true, vec![0xA3; 3]),
+        ];
+        assert_eq!(read_all(&mut demuxer), expected);
+        assert_eq!(demuxer.seek(0, 1), Ok(0));
+        assert_eq!(demuxer.seek(0, 2), Ok(2));
+        assert_eq!(read_all(&mut demuxer), expected[2..].to_vec());
+
+        // the AVIX extension is read in sequence too
+        let mut demuxer = DemuxerAvi::new(MediaSourceStream::new(StreamingSupplier(IoBufSupplierMem::new(data, 9)))).unwrap();
+        assert_eq!(read_all(&mut demuxer).len(), 3);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::codec::MediaType;
+use crate::data::IoBuf;
+use crate::error::{decode_error, end_of_stream_error, invalid_input_error, retry_later_error, Result};
+
+use super::segmented::{fetch, supply_segments, SegmentConcat, SegmentTimeline};
+use super::IoBufSupply;
+
+mod mpd;
+
+pub use mpd::{AdaptationSet, DashSegment, Mpd, Period, Representation};
+
+/// Supplies the initialization and media segments of one representation per period of a DASH MPD, from local files
+/// or an `http://` server, for the fragmented MP4 demuxer. An initialization segment precedes the first media
+/// segment and every segment of a period with another one. Wrap the demuxer in a
+/// [`DemuxerSegmented`](super::DemuxerSegmented) with the [`timeline`](Self::timeline) to keep the timestamps
+/// continuous across periods. Dynamic MPDs are read as a snapshot, without updates.
+#[derive(Debug, Default)]
+pub struct IoBufSupplierDash {
+    /// Media type of the adaptation set to select, video if available when `None`.
+    media_type: Option<MediaType>,
+    /// Highest representation bandwidth to select, the highest bandwidth representation if `None`.
+    max_bandwidth: Option<u64>,
+    mpd: Option<Mpd>,
+    /// Adaptation set and representation selected in each period.
+    selection: Vec<Option<(usize, usize)>>,
+    period: usize,
+    /// Index of the next segment to supply in the selected representation of `period`.
+    next_segment: usize,
+    concat: SegmentConcat,
+}
+
+impl IoBufSupplierDash {
+    /// Selects the first adaptation set of `media_type` in each period.
+    pub fn with_media_type(mut self, media_type: MediaType) -> Self {
+        self.media_type = Some(media_type);
+        self
+    }
+
+    /// Selects the highest bandwidth representation not above `max_bandwidth` bits per second, or the lowest one.
+    pub fn with_max_bandwidth(mut self, max_bandwidth: u64) -> Self {
+        self.max_bandwidth = Some(max_bandwidth);
+        self
+    }
+
+    /// The MPD, once opened.
+    pub fn mpd(&self) -> Option<&Mpd> {
+        self.mpd.as_ref()
+    }
+
+    /// The representation selected in period `period`.
+    pub fn representation(&self, period: usize) -> Option<&Representation> {
+        let (set, representation) = (*self.selection.get(period)?)?;
+        Some(&self.mpd.as_ref()?.periods[period].adaptation_sets[set].representations[representation])
+    }
+
+    /// Segment positions to pass to [`DemuxerSegmented`](super::DemuxerSegmented).
+    pub fn timeline(&self) -> SegmentTimeline {
+        self.concat.timeline()
+    }
+
+    fn select(&self, period: &Period) -> Option<(usize, usize)> {
+        let sets = &period.adaptation_sets;
+        let find = |media_type: Option<MediaType>| sets.iter().position(|set| {
+            !set.representations.is_empty() && media_type.is_none_or(|media_type| set.media_type == media_type)
+        });
+        let set = match self.media_type {
+            Some(media_type) => find(Some(media_type)),
+            None => find(Some(MediaType::Video)).or_else(|| find(None)),
+        }?;
+
+        let representations = &sets[set].representations;
+        let fits = |(_, representation): &(usize, &Representation)| {
+            self.max_bandwidth.is_none_or(|max| representation.bandwidth <= max)
+        };
+        let representation = representations.iter().enumerate().filter(fits)
+            .max_by_key(|(_, representation)| representation.bandwidth)
+            .or_else(|| representations.iter().enumerate().min_by_key(|(_, representation)| representation.bandwidth));
+        representation.map(|(representation, _)| (set, representation))
+    }
+
+    /// Reads the next segment with the initialization segment if it changed.
+    fn next_segment_data(&mut self) -> Result<Vec<u8>> {
+        let Some(mpd) = self.mpd.as_mut() else {
+            return invalid_input_error();
+        };
+        let first_start = mpd.periods.first().map_or(0.0, |period| period.start);
+        loop {
+            let Some(period) = mpd.periods.get_mut(self.period) else {
+                return end_of_stream_error();
+            };
+            let Some((set, representation)) = self.selection[self.period] else {
+                self.period += 1;
+                continue;
+            };
+            let start = period.start - first_start;
+            let representation = &mut period.adaptation_sets[set].representations[representation];
+            if representation.index_range.is_some() {
+                load_index(representation)?;
+            }
+
+            let Some(segment) = representation.segments.get(self.next_segment) else {
+                self.period += 1;
+                self.next_segment = 0;
+                continue;
+            };
+            let data = self.concat.read(representation.init.as_ref(), &segment.uri, segment.byte_range,
+                self.period as u64, start + segment.time)?;
+            self.next_segment += 1;
+            return Ok(data);
+        }
+    }
+}
+
+/// Replaces the single segment of a `SegmentBase` representation by the subsegments of its `sidx` box.
+fn load_index(representation: &mut Representation) -> Result<()> {
+    let (Some(index_range), Some(segment)) = (representation.index_range, representation.segments.first()) else {
+        return Ok(());
+    };
+    let uri = segment.uri.clone();
+    let subsegments = mpd::parse_sidx(&fetch(&uri, Some(index_range))?, index_range.offset)?;
+    let first_time = subsegments.first().map_or(0.0, |&(_, time, _)| time);
+    representation.segments = subsegments.into_iter().map(|(byte_range, time, duration)| {
+        DashSegment { uri: uri.clone(), byte_range: Some(byte_range), time: time - first_time, duration }
+    }).collect();
+    representation.index_range = None;
+    Ok(())
+}
+
+impl IoBufSupply for IoBufSupplierDash {
+    fn open_input(&mut self, uri: &str) -> Result<()> {
+        let text = match String::from_utf8(fetch(uri, None)?) {
+            Ok(text) => text,
+            Err(_) => return decode_error("dash: mpd is not utf-8"),
+        };
+        let mpd = Mpd::parse(&text, uri)?;
+        self.selection = mpd.periods.iter().map(|period| self.select(period)).collect();
+        if self.selection.iter().all(Option::is_none) {
+            return decode_error("dash: no representation to select");
+        }
+        self.mpd = Some(mpd);
+        self.period = 0;
+        self.next_segment = 0;
+        self.concat = SegmentConcat::default();
+        Ok(())
+    }
+
+    fn supply_iobufs(&mut self, len_bytes: usize, _parsed_iobufs: &[IoBuf], new_iobufs: &mut [IoBuf]) -> Result<usize> {
+        if self.mpd.is_none() {
+            return invalid_input_error();
+        }
+        if new_iobufs.is_empty() {
+            return retry_later_error();
+        }
+        supply_segments(len_bytes, new_iobufs, || self.next_segment_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{MediaIoBufRead, MediaSourceStream};
+
+    #[test]
+    fn periods_from_local_files() {
+        let dir = std::env::temp_dir().join(format!("rav-dash-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("v1")).unwrap();
+        let mut sidx = [&[0, 0, 0, 56][..], b"sidx", &[0; 8]].concat();
+        for value in [1000u32, 900, 0, 2, 3, 1000, 0x9000_0000, 2, 1000, 0x9000_0000] {
+            sidx.extend_from_slice(&value.to_be_bytes());
+        }
+        let files: [(&str, Vec<u8>); 5] = [
+            ("v1/init.mp4", b"INI1".to_vec()),
+            ("v1/0.m4s", b"aa".to_vec()),
+            ("v1/2000.m4s", b"bb".to_vec()),
+            ("p2.mp4", b"INI2ccdd".to_vec()),
+            ("base.mp4", [&b"INI3"[..], &sidx, b"eeeff"].concat()),
+        ];
+        for (name, data) in &files {
+            std::fs::write(dir.join(name), data).unwrap();
+        }
+        let mpd = r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static">
+              <Period duration="PT4S"><AdaptationSet contentType="video">
+                <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/$Time$.m4s">
+                  <SegmentTimeline><S t="0" d="2000" r="1"/></SegmentTimeline>
+                </SegmentTemplate>
+                <Representation id="v1" bandwidth="500000"/><Representation id="v2" bandwidth="1000000"/>
+              </AdaptationSet></Period>
+              <Period duration="PT3S">
+                <AdaptationSet contentType="audio"><Representation id="a" bandwidth="1"/></AdaptationSet>
+                <AdaptationSet contentType="video"><Representation id="p2" bandwidth="500000">
+                  <BaseURL>p2.mp4</BaseURL>
+                  <SegmentList timescale="2" duration="3">
+                    <Initialization range="0-3"/><SegmentURL mediaRange="4-5"/><SegmentURL mediaRange="6-7"/>
+                  </SegmentList>
+                </Representation></AdaptationSet>
+              </Period>
+              <Period><AdaptationSet contentType="video"><Representation id="p3" bandwidth="500000">
+                <BaseURL>base.mp4</BaseURL>
+                <SegmentBase indexRange="4-59"><Initialization range="0-3"/></SegmentBase>
+              </Representation></AdaptationSet></Period>
+            </MPD>"#;
+        std::fs::write(dir.join("manifest.mpd"), mpd).unwrap();
+
+        let mut supplier = IoBufSupplierDash::default().with_max_bandwidth(600_000);
+        supplier.open_input(dir.join("manifest.mpd").to_str().unwrap()).unwrap();
+        assert_eq!(supplier.mpd().unwrap().periods.len(), 3);
+        assert_eq!(supplier.representation(0).unwrap().id, "v1");
+        assert_eq!(supplier.representation(1).unwrap().id, "p2");
+        let timeline = supplier.timeline();
+        let mut stream = MediaSourceStream::new(supplier);
+        // more segments than the ring holds at once
+        let mut data = Vec::new();
+        while let Ok(byte) = stream.get_u8() {
+            data.push(byte);
+        }
+        assert_eq!(data, b"INI1aabbINI2ccddINI3eeeff");
+        let segments: Vec<_> = [0, 6, 8, 14, 16, 23].iter().map(|&pos| timeline.segment_at(pos).unwrap()).collect();
+        assert_eq!(segments.iter().map(|segment| (segment.pos, segment.discontinuity_sequence)).collect::<Vec<_>>(),
+            vec![(0, 0), (6, 0), (8, 1), (14, 1), (16, 2), (23, 2)]);
+        assert_eq!(segments.iter().map(|segment| segment.time).collect::<Vec<_>>(), vec![0.0, 2.0, 4.0, 5.5, 7.0, 8.0]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,509 @@
+use roxmltree::{Document, Node};
+
+use crate::codec::MediaType;
+use crate::error::{decode_error, unsupported_error, Result};
+use crate::format::segmented::{resolve_uri, ByteRange, InitSection};
+
+/// Most segments listed for a representation.
+const MAX_SEGMENTS: u64 = 1_000_000;
+
+/// A media segment of a representation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashSegment {
+    pub uri: String,
+    pub byte_range: Option<ByteRange>,
+    /// Start time in seconds from the period start.
+    pub time: f64,
+    /// Duration in seconds, 0 if unknown.
+    pub duration: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Representation {
+    pub id: String,
+    /// Bit rate in bits per second.
+    pub bandwidth: u64,
+    /// RFC 6381 codec string, e.g. `avc1.64001f`.
+    pub codecs: Option<String>,
+    pub mime_type: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub init: Option<InitSection>,
+    pub segments: Vec<DashSegment>,
+    /// Byte range of the `sidx` box of a `SegmentBase` representation, whose single segment is the whole resource
+    /// until the index is loaded.
+    pub index_range: Option<ByteRange>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdaptationSet {
+    pub id: Option<String>,
+    pub media_type: MediaType,
+    pub lang: Option<String>,
+    pub representations: Vec<Representation>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Period {
+    pub id: Option<String>,
+    /// Start in seconds from the presentation start.
+    pub start: f64,
+    /// Duration in seconds, if known.
+    pub duration: Option<f64>,
+    pub adaptation_sets: Vec<AdaptationSet>,
+}
+
+/// A DASH media presentation description.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mpd {
+    /// A live presentation (`type="dynamic"`).
+    pub dynamic: bool,
+    /// `mediaPresentationDuration` in seconds.
+    pub duration: Option<f64>,
+    pub periods: Vec<Period>,
+}
+
+/// Parses an ISO 8601 duration such as `PT1H2M3.5S` into seconds, years and months are 365 and 30 days.
+fn parse_duration(value: &str) -> Option<f64> {
+    let value = value.trim().strip_prefix('P')?;
+    let mut seconds = 0.0;
+    let mut time = false;
+    let mut number = String::new();
+    for c in value.chars() {
+        let unit = match c {
+            '0'..='9' | '.' => {
+                number.push(c);
+                continue;
+            }
+            'T' => {
+                time = true;
+                continue;
+            }
+            'Y' => 365.0 * 86400.0,
+            'M' if !time => 30.0 * 86400.0,
+            'W' => 7.0 * 86400.0,
+            'D' => 86400.0,
+            'H' => 3600.0,
+            'M' => 60.0,
+            'S' => 1.0,
+            _ => return None,
+        };
+        seconds += number.parse::<f64>().ok()? * unit;
+        number.clear();
+    }
+    number.is_empty().then_some(seconds)
+}
+
+/// Parses a `first-last` byte range.
+fn parse_range(value: &str) -> Result<ByteRange> {
+    let range: Option<(u64, u64)> = value.split_once('-')
+        .and_then(|(first, last)| Some((first.trim().parse().ok()?, last.trim().parse().ok()?)));
+    match range {
+        Some((first, last)) if last >= first => Ok(ByteRange { offset: first, len: last - first + 1 }),
+        _ => decode_error("dash: invalid byte range"),
+    }
+}
+
+/// Substitutes the identifiers of a `SegmentTemplate` URL, with their optional `%0<width>d` format tags.
+fn fill_template(template: &str, representation: &Representation, number: u64, time: u64) -> String {
+    let mut url = String::new();
+    let mut parts = template.split('$');
+    url += parts.next().unwrap_or("");
+    while let Some(identifier) = parts.next() {
+        let (name, format) = identifier.split_once('%').unwrap_or((identifier, ""));
+        let width = format.strip_prefix('0').and_then(|format| format.strip_suffix('d')?.parse().ok()).unwrap_or(0);
+        match name {
+            "" => url.push('$'),
+            "RepresentationID" => url += &representation.id,
+            "Number" => url += &format!("{number:0width$}"),
+            "Time" => url += &format!("{time:0width$}"),
+            "Bandwidth" => url += &format!("{:0width$}", representation.bandwidth),
+            // not an identifier, keep it as is
+            _ => {
+                url.push('$');
+                url += identifier;
+                url.push('$');
+            }
+        }
+        url += parts.next().unwrap_or("");
+    }
+    url
+}
+
+fn child<'a>(node: Node<'a, 'a>, name: &str) -> Option<Node<'a, 'a>> {
+    node.children().find(|child| child.tag_name().name() == name)
+}
+
+fn children<'a>(node: Node<'a, 'a>, name: &'a str) -> impl Iterator<Item = Node<'a, 'a>> + 'a {
+    node.children().filter(move |child| child.tag_name().name() == name)
+}
+
+fn attribute<T: std::str::FromStr>(node: Node, name: &str) -> Option<T> {
+    node.attribute(name)?.trim().parse().ok()
+}
+
+/// Resolves the `BaseURL` child of `node` against `base`.
+fn base_url(node: Node, base: &str) -> String {
+    match child(node, "BaseURL").and_then(|url| url.text()) {
+        Some(url) => resolve_uri(base, url.trim()),
+        None => base.to_string(),
+    }
+}
+
+/// The media type of an adaptation set from its content type or MIME types.
+fn media_type(set: Node) -> MediaType {
+    let mime_type = set.attribute("mimeType")
+        .or_else(|| children(set, "Representation").find_map(|representation| representation.attribute("mimeType")));
+    let content_type = set.attribute("contentType").or(mime_type.and_then(|mime_type| mime_type.split('/').next()));
+    match content_type {
+        Some("video") => MediaType::Video,
+        Some("audio") => MediaType::Audio,
+        Some("text") => MediaType::Subtitle,
+        _ if mime_type.is_some_and(|mime_type| mime_type.contains("ttml") || mime_type.contains("vtt")) => {
+            MediaType::Subtitle
+        }
+        _ => MediaType::Unknown,
+    }
+}
+
+/// Segment information elements of a representation, inherited from the adaptation set and the period.
+struct Inherited<'a> {
+    /// The representation, its adaptation set and its period.
+    chain: [Node<'a, 'a>; 3],
+}
+
+impl<'a> Inherited<'a> {
+    /// The closest `element` (`SegmentTemplate`, `SegmentList` or `SegmentBase`).
+    fn element(&self, element: &str) -> Option<Node<'a, 'a>> {
+        self.chain.iter().find_map(|&node| child(node, element))
+    }
+
+    /// An attribute of the closest `element` holding it.
+    fn attribute<T: std::str::FromStr>(&self, element: &str, name: &str) -> Option<T> {
+        self.chain.iter().filter_map(|&node| child(node, element)).find_map(|node| attribute(node, name))
+    }
+
+    /// A child of the closest `element` holding it.
+    fn child(&self, element: &str, name: &str) -> Option<Node<'a, 'a>> {
+        self.chain.iter().filter_map(|&node| child(node, element)).find_map(|node| child(node, name))
+    }
+}
+
+/// Lists the segments of a `SegmentTemplate`.
+fn template_segments(representation: &mut Representation, inherited: &Inherited, base: &str,
+    period_duration: Option<f64>) -> Result<()> {
+    let timescale = inherited.attribute("SegmentTemplate", "timescale").unwrap_or(1u64).max(1);
+    let offset: u64 = inherited.attribute("SegmentTemplate", "presentationTimeOffset").unwrap_or(0);
+    let start_number: u64 = inherited.attribute("SegmentTemplate", "startNumber").unwrap_or(1);
+    let Some(media) = inherited.attribute::<String>("SegmentTemplate", "media") else {
+        return decode_error("dash: SegmentTemplate without media");
+    };
+    if let Some(init) = inherited.attribute::<String>("SegmentTemplate", "initialization") {
+        let uri = resolve_uri(base, &fill_template(&init, representation, 0, 0));
+        representation.init = Some(InitSection { uri, byte_range: None });
+    }
+    let seconds = |time: u64| (time as f64 - offset as f64) / timescale as f64;
+    let period_end = period_duration.map(|duration| offset + (duration * timescale as f64).round() as u64);
+
+    // (time, duration) in timescale units
+    let mut times = Vec::new();
+    if let Some(timeline) = inherited.child("SegmentTemplate", "SegmentTimeline") {
+        let entries: Vec<Node> = children(timeline, "S").collect();
+        let mut time: u64 = 0;
+        for (i, entry) in entries.iter().enumerate() {
+            time = attribute(*entry, "t").unwrap_or(time);
+            let duration: u64 = attribute(*entry, "d").unwrap_or(0);
+            if duration == 0 {
+                return decode_error("dash: SegmentTimeline entry without duration");
+            }
+            let repeat: i64 = attribute(*entry, "r").unwrap_or(0);
+            // a negative repeat count lasts until the next entry or the period end
+            let end = match repeat {
+                0.. if repeat as u64 >= MAX_SEGMENTS => return decode_error("dash: too many segments"),
+                0.. => match duration.checked_mul(repeat as u64 + 1).and_then(|len| time.checked_add(len)) {
+                    Some(end) => end,
+                    None => return decode_error("dash: SegmentTimeline overflow"),
+                },
+                _ => match entries.get(i + 1).and_then(|next| attribute(*next, "t")).or(period_end) {
+                    Some(end) => end,
+                    None => return unsupported_error("dash: open ended SegmentTimeline"),
+                },
+            };
+            while time < end {
+                if times.len() as u64 >= MAX_SEGMENTS {
+                    return decode_error("dash: too many segments");
+                }
+                times.push((time, duration));
+                let Some(next) = time.checked_add(duration) else {
+                    return decode_error("dash: SegmentTimeline overflow");
+                };
+                time = next;
+            }
+        }
+    } else {
+        let duration: u64 = inherited.attribute("SegmentTemplate", "duration").unwrap_or(0);
+        let Some(period_end) = period_end.filter(|_| duration > 0) else {
+            return unsupported_error("dash: SegmentTemplate without timeline or period duration");
+        };
+        let count = (period_end - offset).div_ceil(duration);
+        if count > MAX_SEGMENTS {
+            return decode_error("dash: too many segments");
+        }
+        times.extend((0..count).map(|i| (offset + i * duration, duration)));
+    }
+
+    for (i, (time, duration)) in times.into_iter().enumerate() {
+        representation.segments.push(DashSegment {
+            uri: resolve_uri(base, &fill_template(&media, representation, start_number + i as u64, time)),
+            byte_range: None,
+            time: seconds(time),
+            duration: duration as f64 / timescale as f64,
+        });
+    }
+    Ok(())
+}
+
+/// Lists the segments of a `SegmentList`.
+fn list_segments(representation: &mut Representation, inherited: &Inherited, base: &str) -> Result<()> {
+    let timescale = inherited.attribute("SegmentList", "timescale").unwrap_or(1u64).max(1) as f64;
+    let duration = inherited.attribute("SegmentList", "duration").unwrap_or(0u64) as f64 / timescale;
+    if let Some(init) = inherited.child("SegmentList", "Initialization") {
+        let uri = init.attribute("sourceURL").map_or(base.to_string(), |uri| resolve_uri(base, uri));
+        let byte_range = init.attribute("range").map(parse_range).transpose()?;
+        representation.init = Some(InitSection { uri, byte_range });
+    }
+    let Some(list) = inherited.element("SegmentList") else {
+        return Ok(());
+    };
+    for (i, url) in children(list, "SegmentURL").enumerate() {
+        representation.segments.push(DashSegment {
+            uri: url.attribute("media").map_or(base.to_string(), |uri| resolve_uri(base, uri)),
+            byte_range: url.attribute("mediaRange").map(parse_range).transpose()?,
+            time: i as f64 * duration,
+            duration,
+        });
+    }
+    Ok(())
+}
+
+/// Parses a representation and lists its segments.
+fn parse_representation(inherited: &Inherited, base: &str, period_duration: Option<f64>) -> Result<Representation> {
+    let [node, set, _] = inherited.chain;
+    let mut representation = Representation {
+        id: node.attribute("id").unwrap_or_default().to_string(),
+        bandwidth: attribute(node, "bandwidth").unwrap_or(0),
+        codecs: node.attribute("codecs").or(set.attribute("codecs")).map(str::to_string),
+        mime_type: node.attribute("mimeType").or(set.attribute("mimeType")).map(str::to_string),
+        width: attribute(node, "width").or(attribute(set, "width")).unwrap_or(0),
+        height: attribute(node, "height").or(attribute(set, "height")).unwrap_or(0),
+        ..Default::default()
+    };
+
+    // the closest segment information element applies
+    let element = inherited.chain.iter().find_map(|&node| {
+        node.children().map(|child| child.tag_name().name())
+            .find(|name| matches!(*name, "SegmentTemplate" | "SegmentList" | "SegmentBase"))
+    });
+    match element {
+        Some("SegmentTemplate") => template_segments(&mut representation, inherited, base, period_duration)?,
+        Some("SegmentList") => list_segments(&mut representation, inherited, base)?,
+        _ => {
+            // SegmentBase or a single segment, the whole resource
+            representation.index_range = inherited.attribute::<String>("SegmentBase", "indexRange")
+                .map(|range| parse_range(&range)).transpose()?;
+            if representation.index_range.is_some() {
+                if let Some(init) = inherited.child("SegmentBase", "Initialization") {
+                    let uri = init.attribute("sourceURL").map_or(base.to_string(), |uri| resolve_uri(base, uri));
+                    let byte_range = init.attribute("range").map(parse_range).transpose()?;
+                    representation.init = Some(InitSection { uri, byte_range });
+                }
+            }
+            let duration = period_duration.unwrap_or(0.0);
+            representation.segments.push(DashSegment { uri: base.to_string(), byte_range: None, time: 0.0, duration });
+        }
+    }
+    Ok(representation)
+}
+
+impl Mpd {
+    /// Parses an MPD, URLs are resolved relative to `uri`, the location of the MPD, and its `BaseURL` elements.
+    pub fn parse(text: &str, uri: &str) -> Result<Mpd> {
+        let Ok(document) = Document::parse(text) else {
+            return decode_error("dash: invalid xml");
+        };
+        let root = document.root_element();
+        if root.tag_name().name() != "MPD" {
+            return decode_error("dash: no MPD element");
+        }
+        let mut mpd = Mpd {
+            dynamic: root.attribute("type") == Some("dynamic"),
+            duration: root.attribute("mediaPresentationDuration").and_then(parse_duration),
+            periods: Vec::new(),
+        };
+        let base = base_url(root, uri);
+
+        // period timing first, a period lasts until the next one starts
+        let nodes: Vec<Node> = children(root, "Period").collect();
+        let mut timing: Vec<(f64, Option<f64>)> = Vec::new();
+        for node in &nodes {
+            let start = node.attribute("start").and_then(parse_duration)
+                .or(timing.last().and_then(|&(start, duration)| Some(start + duration?)))
+                .unwrap_or(0.0);
+            timing.push((start, node.attribute("duration").and_then(parse_duration)));
+        }
+        for i in 0..timing.len() {
+            let end = timing.get(i + 1).map(|&(start, _)| start).or(mpd.duration);
+            let (start, duration) = &mut timing[i];
+            *duration = duration.or(end.map(|end| end - *start));
+        }
+
+        for (node, (start, duration)) in nodes.into_iter().zip(timing) {
+            let period_base = base_url(node, &base);
+            let id = node.attribute("id").map(str::to_string);
+            let mut period = Period { id, start, duration, adaptation_sets: Vec::new() };
+            for set in children(node, "AdaptationSet") {
+                let set_base = base_url(set, &period_base);
+                let mut adaptation_set = AdaptationSet {
+                    id: set.attribute("id").map(str::to_string),
+                    media_type: media_type(set),
+                    lang: set.attribute("lang").map(str::to_string),
+                    representations: Vec::new(),
+                };
+                for representation in children(set, "Representation") {
+                    let inherited = Inherited { chain: [representation, set, node] };
+                    let base = base_url(representation, &set_base);
+                    adaptation_set.representations.push(parse_representation(&inherited, &base, duration)?);
+                }
+                period.adaptation_sets.push(adaptation_set);
+            }
+            mpd.periods.push(period);
+        }
+        Ok(mpd)
+    }
+}
+
+/// Lists the subsegments of a `sidx` box at `offset` as byte ranges and times in seconds.
+pub(super) fn parse_sidx(sidx: &[u8], offset: u64) -> Result<Vec<(ByteRange, f64, f64)>> {
+    let u32_at = |pos: usize| {
+        sidx.get(pos..pos + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()) as u64)
+    };
+    let Some(size) = u32_at(0).filter(|&size| size as usize <= sidx.len() && sidx.get(4..8) == Some(b"sidx")) else {
+        return decode_error("dash: no sidx box at index range");
+    };
+    let version = sidx.get(8).copied().unwrap_or(0);
+    let (timescale, time, first_offset, mut pos) = if version == 0 {
+        (u32_at(16), u32_at(20), u32_at(24), 28)
+    } else {
+        let u64_at = |pos| Some(u32_at(pos)? << 32 | u32_at(pos + 4)?);
+        (u32_at(16), u64_at(20), u64_at(28), 36)
+    };
+    let timescale = timescale.filter(|&timescale| timescale > 0);
+    let (Some(timescale), Some(mut time), Some(first_offset)) = (timescale, time, first_offset) else {
+        return decode_error("dash: invalid sidx box");
+    };
+    let count = u32_at(pos).unwrap_or(0) & 0xFFFF;
+    pos += 4;
+
+    let mut subsegments = Vec::new();
+    let mut start = offset + size + first_offset;
+    for _ in 0..count {
+        let (Some(reference), Some(duration)) = (u32_at(pos), u32_at(pos + 4)) else {
+            return decode_error("dash: truncated sidx box");
+        };
+        let len = reference & 0x7FFF_FFFF;
+        subsegments.push((ByteRange { offset: start, len }, time as f64 / timescale as f64,
+            duration as f64 / timescale as f64));
+        start += len;
+        time += duration;
+        pos += 12;
+    }
+    Ok(subsegments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn segment_addressing() {
+        let mpd = r#"<?xml version="1.0"?>
+            <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT1M0.5S">
+              <BaseURL>http://127.0.0.1:8000/content/</BaseURL>
+              <Period id="1" duration="PT4S">
+                <AdaptationSet contentType="video" codecs="avc1.64001f">
+                  <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4"
+                      media="$RepresentationID$/$Time$.m4s">
+                    <SegmentTimeline><S t="0" d="1500" r="1"/><S d="1000"/></SegmentTimeline>
+                  </SegmentTemplate>
+                  <Representation id="v1" bandwidth="500000" width="640" height="360"/>
+                </AdaptationSet>
+                <AdaptationSet mimeType="audio/mp4" lang="en">
+                  <SegmentTemplate duration="2" startNumber="5" media="a/$Bandwidth$-$Number%03d$.m4s"/>
+                  <Representation id="a1" bandwidth="64000"/>
+                </AdaptationSet>
+              </Period>
+              <Period id="2">
+                <AdaptationSet contentType="video">
+                  <Representation id="v2" bandwidth="800000">
+                    <BaseURL>/other/p2.mp4</BaseURL>
+                    <SegmentList timescale="10" duration="30">
+                      <Initialization range="0-99"/>
+                      <SegmentURL mediaRange="100-199"/><SegmentURL media="p2b.mp4"/>
+                    </SegmentList>
+                  </Representation>
+                  <Representation id="v3" bandwidth="900000">
+                    <BaseURL>base.mp4</BaseURL>
+                    <SegmentBase indexRange="800-851"><Initialization range="0-799"/></SegmentBase>
+                  </Representation>
+                </AdaptationSet>
+              </Period>
+            </MPD>"#;
+        let mpd = Mpd::parse(mpd, "/srv/manifest.mpd").unwrap();
+        assert_eq!((mpd.dynamic, mpd.duration, mpd.periods.len()), (false, Some(60.5), 2));
+        assert_eq!((mpd.periods[1].start, mpd.periods[1].duration), (4.0, Some(56.5)));
+
+        let video = &mpd.periods[0].adaptation_sets[0];
+        let v1 = &video.representations[0];
+        assert_eq!((video.media_type, v1.codecs.as_deref(), v1.width, v1.height), (MediaType::Video, Some("avc1.64001f"), 640, 360));
+        let base = "http://127.0.0.1:8000/content/";
+        assert_eq!(v1.init, Some(InitSection { uri: format!("{base}v1/init.mp4"), byte_range: None }));
+        let segments: Vec<(String, f64, f64)> = v1.segments.iter().map(|s| (s.uri.clone(), s.time, s.duration)).collect();
+        assert_eq!(segments, vec![
+            (format!("{base}v1/0.m4s"), 0.0, 1.5),
+            (format!("{base}v1/1500.m4s"), 1.5, 1.5),
+            (format!("{base}v1/3000.m4s"), 3.0, 1.0),
+        ]);
+
+        let audio = &mpd.periods[0].adaptation_sets[1];
+        assert_eq!((audio.media_type, audio.lang.as_deref()), (MediaType::Audio, Some("en")));
+        let uris: Vec<&str> = audio.representations[0].segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, [format!("{base}a/64000-005.m4s"), format!("{base}a/64000-006.m4s")]);
+
+        let v2 = &mpd.periods[1].adaptation_sets[0].representations[0];
+        let p2 = "http://127.0.0.1:8000/other/p2.mp4";
+        assert_eq!(v2.init, Some(InitSection { uri: p2.into(), byte_range: Some(ByteRange { offset: 0, len: 100 }) }));
+        assert_eq!(v2.segments, vec![
+            DashSegment { uri: p2.into(), byte_range: Some(ByteRange { offset: 100, len: 100 }), time: 0.0, duration: 3.0 },
+            DashSegment { uri: "http://127.0.0.1:8000/other/p2b.mp4".into(), byte_range: None, time: 3.0, duration: 3.0 },
+        ]);
+        let v3 = &mpd.periods[1].adaptation_sets[0].representations[1];
+        assert_eq!(v3.index_range, Some(ByteRange { offset: 800, len: 52 }));
+        assert_eq!(v3.init.as_ref().unwrap().byte_range, Some(ByteRange { offset: 0, len: 800 }));
+        assert_eq!(v3.segments.len(), 1);
+
+        assert_eq!(parse_duration("P1DT2H3M4.5S"), Some(93784.5));
+        assert_eq!(fill_template("$$x$Number%05d$-$Time$.$Ext$", v1, 42, 900), "$x00042-900.$Ext$");
+    }
+
+    #[test]
+    fn timeline_overflow() {
+        let timeline = |entries: &str| format!(r#"<MPD type="static"><Period duration="PT4S"><AdaptationSet>
+            <SegmentTemplate media="$Time$.m4s"><SegmentTimeline>{entries}</SegmentTimeline></SegmentTemplate>
+            <Representation id="v" bandwidth="1"/></AdaptationSet></Period></MPD>"#);
+        for entries in [r#"<S d="2" r="9223372036854775807"/>"#, r#"<S d="2" r="1000000"/>"#,
+                        r#"<S t="18446744073709551615" d="1"/>"#] {
+            assert!(matches!(Mpd::parse(&timeline(entries), "/m.mpd"), Err(Error::DecodeError(_))));
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use crate::codec::{CodecId, MediaType};
+use crate::data::{IoRef, Packet2};
+use crate::error::{
+    decode_error, end_of_stream_error, invalid_input_error, limit_error, unsupported_error, Error, Result,
+};
+use crate::metadata::{Attachment, Tag};
+
+use super::id3::{read_id3v2, skip_id3v2};
+use super::ogg::parse_vorbis_comment;
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+mod header;
+
+use header::{crc16, FrameHeader, StreamInfo, MAX_HEADER_LEN, STREAMINFO_LEN};
+
+const READ_SIZE: usize = 64 * 1024;
+
+/// Largest frame, the frame sizes of STREAMINFO have 24 bits.
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+// metadata block types
+const STREAMINFO: u8 = 0;
+const SEEKTABLE: u8 = 3;
+const VORBIS_COMMENT: u8 = 4;
+const PICTURE: u8 = 6;
+
+/// Sample number of a placeholder seek point.
+const PLACEHOLDER_POINT: u64 = u64::MAX;
+const SEEK_POINT_LEN: usize = 18;
+
+/// Parses a PICTURE metadata block into an attachment.
+fn parse_picture(data: &[u8], uid: u64) -> Option<Attachment> {
+    let field = |pos: &mut usize| {
+        let len = u32::from_be_bytes(data.get(*pos..*pos + 4)?.try_into().unwrap()) as usize;
+        let value = data.get(*pos + 4..(*pos + 4).checked_add(len)?)?;
+        *pos += 4 + len;
+        Some(value)
+    };
+    // the picture type precedes the MIME type
+    let mut pos = 4;
+    let mime_type = String::from_utf8_lossy(field(&mut pos)?).into_owned();
+    let description = String::from_utf8_lossy(field(&mut pos)?).into_owned();
+    // width, height, color depth and number of colors
+    pos += 16;
+    let picture = field(&mut pos)?;
+    Some(Attachment {
+        uid,
+        mime_type,
+        description: (!description.is_empty()).then_some(description),
+        data: IoRef::from(picture.to_vec()),
+        ..Default::default()
+    })
+}
+
+/// Probe score of the first bytes of an input, the `fLaC` marker after optional ID3v2 tags.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    if skip_id3v2(data).starts_with(b"fLaC") { PROBE_SCORE_MAX } else { 0 }
+}
+
+/// Demuxer for native FLAC streams, as in `.flac` files. Each frame is a packet, split at the next
+/// frame header whose CRC-8 matches and which either continues the frame numbering or ends a frame
+/// with a matching CRC-16. VORBIS_COMMENT blocks and leading ID3v2 tags become tags, PICTURE blocks
+/// become attachments. Seeking uses the SEEKTABLE, or a binary search over the frame headers.
+pub struct DemuxerFlac<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    tags: Vec<Tag>,
+    attachments: Vec<Attachment>,
+    info: StreamInfo,
+    /// Sample number and offset from the first frame of the seek points, without the placeholders.
+    seek_points: Vec<(u64, u64)>,
+    first_frame_pos: usize,
+    /// Data read from the input, scanned from `buf_start`.
+    buf: Vec<u8>,
+    buf_start: usize,
+    /// Input position of `buf[0]`.
+    buf_pos: usize,
+    eof: bool,
+}
+
+impl<S: MediaIoBufRead> DemuxerFlac<S> {
+    /// Creates the demuxer, reading the metadata blocks.
+    pub fn new(mut iobuf_reader: S) -> Result<Self> {
+        let mut simple_tags = read_id3v2(&mut iobuf_reader)?;
+        let mut marker = [0u8; 4];
+        iobuf_reader.get_bytes(&mut marker)?;
+        if marker != *b"fLaC" {
+            return decode_error("flac: no stream marker");
+        }
+
+        let mut stream = Stream { codec_id: CodecId::Flac, media_type: MediaType::Audio, ..Default::default() };
+        let mut info = None;
+        let mut seek_points = Vec::new();
+        let mut attachments = Vec::new();
+        loop {
+            let mut block_header = [0u8; 4];
+            iobuf_reader.get_bytes(&mut block_header)?;
+            let block_type = block_header[0] & 0x7F;
+            let size = u32::from_be_bytes([0, block_header[1], block_header[2], block_header[3]]) as usize;
+            if info.is_none() && block_type != STREAMINFO {
+                return decode_error("flac: STREAMINFO is not the first block");
+            }
+            if !matches!(block_type, STREAMINFO | SEEKTABLE | VORBIS_COMMENT | PICTURE) {
+                iobuf_reader.skip(size)?;
+            } else {
+                let mut data = vec![0u8; size];
+                iobuf_reader.get_bytes(&mut data)?;
+                match block_type {
+                    STREAMINFO => {
+                        let Some(parsed) = StreamInfo::parse(&data) else {
+                            return decode_error("flac: invalid STREAMINFO");
+                        };
+                        stream.time_base = TimeBase::new(1, parsed.sample_rate);
+                        stream.duration = parsed.total_samples as i64;
+                        stream.sample_rate = parsed.sample_rate;
+                        stream.channels = parsed.channels;
+                        stream.bits_per_sample = parsed.bits_per_sample;
+                        // the stream marker and the STREAMINFO block, flagged as the last one
+                        let block_header = [0x80, 0, 0, STREAMINFO_LEN as u8];
+                        stream.codec_params = [b"fLaC", &block_header, &data[..STREAMINFO_LEN]].concat();
+                        info = Some(parsed);
+                    }
+                    SEEKTABLE => {
+                        seek_points.extend(data.chunks_exact(SEEK_POINT_LEN)
+                            .map(|point| {
+                                let sample = u64::from_be_bytes(point[..8].try_into().unwrap());
+                                (sample, u64::from_be_bytes(point[8..16].try_into().unwrap()))
+                            })
+                            .filter(|&(sample, _)| sample != PLACEHOLDER_POINT));
+                    }
+                    VORBIS_COMMENT => simple_tags.extend(parse_vorbis_comment(&data).unwrap_or_default()),
+                    _ => attachments.extend(parse_picture(&data, attachments.len() as u64 + 1)),
+                }
+            }
+            if block_header[0] & 0x80 != 0 {
+                break;
+            }
+        }
+        seek_points.sort_unstable();
+
+        let first_frame_pos = iobuf_reader.pos();
+        Ok(Self {
+            iobuf_reader,
+            streams: vec![stream],
+            tags: if simple_tags.is_empty() { Vec::new() } else { vec![Tag { simple_tags, ..Default::default() }] },
+            attachments,
+            info: info.unwrap_or_default(),
+            seek_points,
+            first_frame_pos,
+            buf: Vec::new(),
+            buf_start: 0,
+            buf_pos: first_frame_pos,
+            eof: false,
+        })
+    }
+
+    /// Reads more data into the scan buffer, sets `eof` at the end of the input.
+    fn fill(&mut self) -> Result<()> {
+        self.buf.drain(..self.buf_start);
+        self.buf_pos += self.buf_start;
+        self.buf_start = 0;
+        if self.buf.len() > MAX_FRAME_SIZE {
+            return limit_error("flac: frame too large");
+        }
+
+        let mut len = READ_SIZE;
+        if let Some(stream_len) = self.iobuf_reader.stream_len() {
+            len = len.min(stream_len.saturating_sub(self.iobuf_reader.pos()));
+        }
+        // the length is unknown when streaming, the last read is shortened to the available data
+        while len > 0 {
+            let start = self.buf.len();
+            self.buf.resize(start + len, 0);
+            match self.iobuf_reader.get_bytes(&mut self.buf[start..]) {
+                Ok(()) => return Ok(()),
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => {
+                    self.buf.truncate(start);
+                    len /= 2;
+                }
+                Err(err) => {
+                    self.buf.truncate(start);
+                    return Err(err);
+                }
+            }
+        }
+        self.eof = true;
+        Ok(())
+    }
+
+    /// Scans the buffer from `from` for a frame header accepted by `accept`. Returns its index and
+    /// the header, or the index to resume from once more data is read.
+    fn scan(
+        &self,
+        from: usize,
+        accept: impl Fn(usize, &FrameHeader) -> bool,
+    ) -> std::result::Result<(usize, FrameHeader), usize> {
+        for i in from..self.buf.len().saturating_sub(1) {
+            if self.buf[i] != 0xFF || self.buf[i + 1] & 0xFE != 0xF8 {
+                continue;
+            }
+            if self.buf.len() - i < MAX_HEADER_LEN && !self.eof {
+                return Err(i);
+            }
+            if let Some(header) = FrameHeader::parse(&self.buf[i..], &self.info) {
+                if accept(i, &header) {
+                    return Ok((i, header));
+                }
+            }
+        }
+        // the last byte may begin a sync code
+        Err(self.buf.len().saturating_sub(1).max(from))
+    }
+
+    /// Moves `buf_start` to the next frame header, dropping the data before it. Gives up at the input
+    /// position `limit`.
+    fn next_header(&mut self, limit: usize) -> Result<Option<FrameHeader>> {
+        loop {
+            match self.scan(self.buf_start, |_, _| true) {
+                Ok((start, header)) => {
+                    self.buf_start = start;
+                    return Ok(Some(header));
+                }
+                Err(resume) => {
+                    self.buf_start = resume;
+                    if self.eof || self.buf_pos + resume >= limit {
+                        return Ok(None);
+                    }
+                    // the end of the input is scanned again once known, for a short last frame
+                    self.fill()?;
+                }
+            }
+        }
+    }
+
+    /// Finds the end of the frame at `buf_start`, where the next frame starts or the input ends.
+    fn frame_end(&mut self, header: &FrameHeader) -> Result<usize> {
+        let next_sample = header.sample + header.block_size as u64;
+        let mut from = self.buf_start + 2;
+        loop {
+            let start = self.buf_start;
+            let found = self.scan(from, |end, next| {
+                next.variable == header.variable && (next.sample == next_sample || crc16(&self.buf[start..end]) == 0)
+            });
+            match found {
+                Ok((end, _)) => return Ok(end),
+                Err(resume) => {
+                    if self.eof {
+                        return Ok(self.buf.len());
+                    }
+                    let scanned = resume - self.buf_start;
+                    self.fill()?;
+                    from = self.buf_start + scanned;
+                }
+            }
+        }
+    }
+
+    /// Restarts reading at the input position `pos`.
+    fn reset(&mut self, pos: usize) -> Result<()> {
+        self.iobuf_reader.seek(pos)?;
+        self.buf.clear();
+        self.buf_start = 0;
+        self.buf_pos = pos;
+        self.eof = false;
+        Ok(())
+    }
+
+    /// Binary search of the last frame starting at or before `target`, returns its position and first
+    /// sample.
+    fn bisect(&mut self, target: u64, end: usize) -> Result<(usize, u64)> {
+        let mut best = (self.first_frame_pos, 0);
+        let (mut low, mut high) = (self.first_frame_pos + 1, end);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            self.reset(mid)?;
+            match self.next_header(high)? {
+                Some(header) if header.sample <= target && self.buf_pos + self.buf_start < high => {
+                    best = (self.buf_pos + self.buf_start, header.sample);
+                    low = best.0 + 1;
+                }
+                _ => high = mid,
+            }
+        }
+        Ok(best)
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerFlac<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        let Some(header) = self.next_header(usize::MAX)? else {
+            return end_of_stream_error();
+        };
+        let end = self.frame_end(&header)?;
+        packet.pos = (self.buf_pos + self.buf_start) as i64;
+        packet.ioref = IoRef::from(self.buf[self.buf_start..end].to_vec());
+        self.buf_start = end;
+        packet.stream_id = 0;
+        packet.pts = header.sample as i64;
+        packet.dts = packet.pts;
+        packet.duration = header.block_size as i64;
+        packet.keyframe = true;
+        Ok(())
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn seek(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        if stream_id != 0 {
+            return invalid_input_error();
+        }
+        let Some(stream_len) = self.iobuf_reader.stream_len() else {
+            return unsupported_error("flac: seek without stream length");
+        };
+        let target = timestamp.max(0) as u64;
+        let (pos, sample) = if self.seek_points.is_empty() {
+            self.bisect(target, stream_len)?
+        } else {
+            match self.seek_points.iter().rev().find(|&&(sample, _)| sample <= target) {
+                Some(&(sample, offset)) => (self.first_frame_pos + offset as usize, sample),
+                None => (self.first_frame_pos, 0),
+            }
+        };
+        self.reset(pos)?;
+        Ok(sample as i64)
+    }
+
+    fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+    use header::crc8;
+
+    const BLOCK_SIZE: i64 = 4096;
+
+    fn block(block_type: u8, last: bool, data: &[u8]) -> Vec<u8> {
+        let len = (data.len() as u32).to_be_bytes();
+        [&[block_type | if last { 0x80 } else { 0 }, len[1], len[2], len[3]], data].concat()
+    }
+
+    /// 44.1 kHz stereo 16 bit, with a fixed block size of 4096 samples.
+    fn streaminfo(total_samples: u64) -> Vec<u8> {
+        let mut data = vec![0u8; STREAMINFO_LEN];
+        data[..4].copy_from_slice(&[0x10, 0x00, 0x10, 0x00]);
+        data[10..14].copy_from_slice(&[0x0A, 0xC4, 0x42, 0xF0 | (total_samples >> 32) as u8]);
+        data[14..18].copy_from_slice(&(total_samples as u32).to_be_bytes());
+        data
+    }
+
+    /// Frame `number` of 4096 samples, or of `last_block` samples coded after the header.
+    fn frame(number: u8, last_block: Option<u16>, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0xFF, 0xF8, if last_block.is_some() { 0x79 } else { 0xC9 }, 0x18, number];
+        if let Some(size) = last_block {
+            frame.extend_from_slice(&(size - 1).to_be_bytes());
+        }
+        frame.push(crc8(&frame));
+        frame.extend_from_slice(payload);
+        let crc = crc16(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes());
+        frame
+    }
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerFlac<S>) -> Vec<(i64, i64, i64, Vec<u8>)> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.pts, packet.duration, packet.pos, packet.data().to_vec()));
+        }
+        packets
+    }
+
+    #[test]
+    fn metadata_and_seektable() {
+        // the payload of the second frame contains a header of the fourth one
+        let false_header = frame(3, None, &[]);
+        let payloads = [vec![0x11; 40], [&[0x22; 10], &false_header[..6], &[0x22; 10]].concat(), vec![0x33; 50], vec![0x44; 30]];
+        let mut frames: Vec<Vec<u8>> = payloads.iter().enumerate().map(|(i, payload)| frame(i as u8, None, payload)).collect();
+        frames.push(frame(4, Some(1000), &[0x55; 20]));
+
+        let comment = [&3u32.to_le_bytes()[..], b"rav", &1u32.to_le_bytes(), &10u32.to_le_bytes(), b"title=Song"].concat();
+        let picture = [&3u32.to_be_bytes()[..], &9u32.to_be_bytes(), b"image/png", &5u32.to_be_bytes(), b"Cover", &[0; 16],
+            &4u32.to_be_bytes(), b"\x89PNG"].concat();
+        let offset = (frames[0].len() + frames[1].len()) as u64;
+        let seektable = [&0u64.to_be_bytes()[..], &0u64.to_be_bytes(), &4096u16.to_be_bytes(),
+            &8192u64.to_be_bytes(), &offset.to_be_bytes(), &4096u16.to_be_bytes(),
+            &u64::MAX.to_be_bytes(), &0u64.to_be_bytes(), &0u16.to_be_bytes()].concat();
+        let header = [&b"fLaC"[..], &block(STREAMINFO, false, &streaminfo(4 * 4096 + 1000)), &block(SEEKTABLE, false, &seektable),
+            &block(1, false, &[0; 8]), &block(VORBIS_COMMENT, false, &comment), &block(PICTURE, true, &picture)].concat();
+        let data = [header.clone(), frames.concat()].concat();
+
+        let mut demuxer = DemuxerFlac::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 4096))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.sample_rate, stream.channels, stream.bits_per_sample), (CodecId::Flac, 44100, 2, 16));
+        assert_eq!((stream.duration, stream.time_base), (4 * 4096 + 1000, TimeBase::new(1, 44100)));
+        assert_eq!(stream.codec_params, [&b"fLaC\x80\x00\x00\x22"[..], &streaminfo(4 * 4096 + 1000)].concat());
+        let tag = &demuxer.tags()[0].simple_tags[0];
+        assert_eq!((tag.name.as_str(), tag.value.as_deref()), ("TITLE", Some("Song")));
+        let attachment = &demuxer.attachments()[0];
+        assert_eq!((attachment.mime_type.as_str(), attachment.description.as_deref()), ("image/png", Some("Cover")));
+        assert_eq!(attachment.data.data(), b"\x89PNG");
+
+        let mut pos = header.len() as i64;
+        let mut expected = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            let duration = if i == 4 { 1000 } else { BLOCK_SIZE };
+            expected.push((i as i64 * BLOCK_SIZE, duration, pos, frame.clone()));
+            pos += frame.len() as i64;
+        }
+        assert_eq!(read_all(&mut demuxer), expected);
+
+        assert_eq!(demuxer.seek(0, 3 * BLOCK_SIZE), Ok(2 * BLOCK_SIZE));
+        assert_eq!(read_all(&mut demuxer), expected[2..]);
+        assert_eq!(demuxer.seek(0, 100), Ok(0));
+        assert_eq!(demuxer.seek(1, 0), Err(Error::InvalidInput));
+        assert_eq!(read_all(&mut demuxer).len(), 5);
+    }
+
+    #[test]
+    fn binary_search_seek() {
+        let frames: Vec<Vec<u8>> = (0..30u8).map(|i| frame(i, None, &vec![i; 100 + i as usize * 7])).collect();
+        let data = [&b"fLaC"[..], &block(STREAMINFO, true, &streaminfo(30 * 4096)), &frames.concat()].concat();
+        let mut demuxer = DemuxerFlac::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 4096))).unwrap();
+
+        let mut packet = Packet2::default();
+        assert_eq!(demuxer.seek(0, 13 * BLOCK_SIZE + 5), Ok(13 * BLOCK_SIZE));
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!((packet.pts, packet.data()), (13 * BLOCK_SIZE, &frames[13][..]));
+        assert_eq!(demuxer.seek(0, 100 * BLOCK_SIZE), Ok(29 * BLOCK_SIZE));
+        assert_eq!(read_all(&mut demuxer).len(), 1);
+        assert_eq!(demuxer.seek(0, 0), Ok(0));
+        assert_eq!(read_all(&mut demuxer).len(), 30);
+    }
+}
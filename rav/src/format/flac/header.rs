@@ -0,0 +1,121 @@
+/// Length of the STREAMINFO metadata block.
+pub(super) const STREAMINFO_LEN: usize = 34;
+
+/// Longest frame header, with a 7 byte coded number, 16 bit block size and sample rate.
+pub(super) const MAX_HEADER_LEN: usize = 16;
+
+const SAMPLE_RATES: [u32; 12] = [0, 88200, 176400, 192000, 8000, 16000, 22050, 24000, 32000, 44100, 48000, 96000];
+const SAMPLE_SIZES: [u16; 8] = [0, 8, 12, 0, 16, 20, 24, 32];
+
+/// CRC-8 with polynomial 0x07, protecting the frame header.
+pub(super) fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { crc << 1 ^ 0x07 } else { crc << 1 })
+    })
+}
+
+/// CRC-16 with polynomial 0x8005, protecting the whole frame. It is 0 over a frame including its CRC.
+pub(super) fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| if crc & 0x8000 != 0 { crc << 1 ^ 0x8005 } else { crc << 1 })
+    })
+}
+
+/// Fields of the STREAMINFO metadata block used by the demuxer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct StreamInfo {
+    pub(super) max_block_size: u32,
+    pub(super) sample_rate: u32,
+    pub(super) channels: u16,
+    pub(super) bits_per_sample: u16,
+    /// Total samples per channel, 0 if unknown.
+    pub(super) total_samples: u64,
+}
+
+impl StreamInfo {
+    pub(super) fn parse(data: &[u8]) -> Option<Self> {
+        let data = data.get(..STREAMINFO_LEN)?;
+        let total_low = u32::from_be_bytes(data[14..18].try_into().unwrap()) as u64;
+        let info = StreamInfo {
+            max_block_size: u16::from_be_bytes([data[2], data[3]]) as u32,
+            sample_rate: (data[10] as u32) << 12 | (data[11] as u32) << 4 | (data[12] >> 4) as u32,
+            channels: ((data[12] >> 1) & 0x07) as u16 + 1,
+            bits_per_sample: (((data[12] & 0x01) << 4) | data[13] >> 4) as u16 + 1,
+            total_samples: ((data[13] & 0x0F) as u64) << 32 | total_low,
+        };
+        (info.sample_rate != 0 && info.max_block_size >= 16).then_some(info)
+    }
+}
+
+/// Fields of a frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct FrameHeader {
+    /// The frames have a variable block size and code their first sample instead of their number.
+    pub(super) variable: bool,
+    /// First sample of the frame.
+    pub(super) sample: u64,
+    pub(super) block_size: u32,
+}
+
+impl FrameHeader {
+    /// Parses a frame header, `None` unless it is complete, its CRC-8 matches, and its parameters match
+    /// the stream.
+    pub(super) fn parse(data: &[u8], info: &StreamInfo) -> Option<Self> {
+        if data.len() < 6 || data[0] != 0xFF || data[1] & 0xFE != 0xF8 {
+            return None;
+        }
+        let size_code = data[2] >> 4;
+        let rate_code = data[2] & 0x0F;
+        let channel_code = data[3] >> 4;
+        let size_bits_code = (data[3] >> 1) & 0x07;
+        if size_code == 0 || rate_code == 15 || channel_code > 10 || size_bits_code == 3 || data[3] & 0x01 != 0 {
+            return None;
+        }
+
+        // the coded frame or sample number is UTF-8 like
+        let number_len = match data[4].leading_ones() {
+            0 => 1,
+            len @ 2..=7 => len as usize,
+            _ => return None,
+        };
+        let mut number = (data[4] & (0x7F >> number_len)) as u64;
+        for &byte in data.get(5..4 + number_len)? {
+            if byte & 0xC0 != 0x80 {
+                return None;
+            }
+            number = number << 6 | (byte & 0x3F) as u64;
+        }
+
+        let mut pos = 4 + number_len;
+        let mut read = |len: usize| {
+            let value = data.get(pos..pos + len)?.iter().fold(0u32, |value, &byte| value << 8 | byte as u32);
+            pos += len;
+            Some(value)
+        };
+        let block_size = match size_code {
+            1 => 192,
+            2..=5 => 576 << (size_code - 2),
+            6 => read(1)? + 1,
+            7 => read(2)? + 1,
+            _ => 256 << (size_code - 8),
+        };
+        let sample_rate = match rate_code {
+            0 => info.sample_rate,
+            12 => read(1)? * 1000,
+            13 => read(2)?,
+            14 => read(2)? * 10,
+            _ => SAMPLE_RATES[rate_code as usize],
+        };
+        if *data.get(pos)? != crc8(&data[..pos]) {
+            return None;
+        }
+
+        let sample_size = SAMPLE_SIZES[size_bits_code as usize];
+        let channels = if channel_code < 8 { channel_code as u16 + 1 } else { 2 };
+        let matches_stream = sample_rate == info.sample_rate && channels == info.channels
+            && (sample_size == 0 || sample_size == info.bits_per_sample);
+        let variable = data[1] & 0x01 != 0;
+        let sample = if variable { number } else { number * info.max_block_size as u64 };
+        matches_stream.then_some(FrameHeader { variable, sample, block_size })
+    }
+}
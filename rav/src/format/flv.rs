@@ -0,0 +1,598 @@
+use std::collections::VecDeque;
+
+use crate::codec::{parse_aac_config, CodecId, MediaType};
+use crate::data::{IoRef, Packet2};
+use crate::error::{
+    decode_error, end_of_stream_error, invalid_input_error, limit_error, unsupported_error, Error, Result,
+};
+use crate::metadata::{SimpleTag, Tag};
+
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+mod amf;
+
+use amf::parse_amf_values;
+
+pub use amf::AmfValue;
+
+// tag types
+const TAG_AUDIO: u8 = 8;
+const TAG_VIDEO: u8 = 9;
+const TAG_SCRIPT: u8 = 18;
+
+// audio formats
+const AUDIO_PCM: u8 = 0;
+const AUDIO_MP3: u8 = 2;
+const AUDIO_PCM_LE: u8 = 3;
+const AUDIO_AAC: u8 = 10;
+const AUDIO_MP3_8K: u8 = 14;
+
+// legacy video codecs
+const VIDEO_AVC: u8 = 7;
+const VIDEO_HEVC: u8 = 12;
+
+// AVC and AAC packet types
+const SEQUENCE_HEADER: u8 = 0;
+const CODED_FRAMES: u8 = 1;
+
+// Enhanced RTMP video packet types
+const PACKET_SEQUENCE_START: u8 = 0;
+const PACKET_CODED_FRAMES: u8 = 1;
+const PACKET_CODED_FRAMES_X: u8 = 3;
+
+const FRAME_KEY: u8 = 1;
+const FRAME_COMMAND: u8 = 5;
+
+/// Number of tags read by `new` to find the codec configuration of the streams.
+const PROBE_TAGS: usize = 100;
+
+/// Largest script data tag loaded in memory.
+const MAX_SCRIPT_SIZE: usize = 1024 * 1024;
+
+fn video_codec_from_fourcc(fourcc: &[u8; 4]) -> CodecId {
+    match fourcc {
+        b"avc1" => CodecId::H264,
+        b"hvc1" => CodecId::Hevc,
+        b"av01" => CodecId::Av1,
+        b"vp09" => CodecId::Vp9,
+        b"vp08" => CodecId::Vp8,
+        _ => CodecId::Unknown,
+    }
+}
+
+/// The codec has a configuration record sent before the frames.
+fn needs_config(codec_id: CodecId) -> bool {
+    matches!(codec_id, CodecId::H264 | CodecId::Hevc | CodecId::Av1 | CodecId::Vp9 | CodecId::Aac)
+}
+
+fn u24(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32
+}
+
+/// Header of an FLV tag.
+#[derive(Debug, Clone, Copy)]
+struct TagHeader {
+    tag_type: u8,
+    /// Position of the tag.
+    pos: usize,
+    /// Position following the tag data.
+    end: usize,
+    /// Decoding timestamp in milliseconds.
+    timestamp: i64,
+}
+
+/// Probe score of the first bytes of an input, the FLV signature and version 1.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    if data.starts_with(b"FLV\x01") { PROBE_SCORE_MAX } else { 0 }
+}
+
+/// FLV demuxer, including the Enhanced RTMP video FourCC tags. All streams use a 1/1000 time base.
+pub struct DemuxerFlv<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    audio_stream: Option<usize>,
+    video_stream: Option<usize>,
+    /// Properties of the `onMetaData` script tag.
+    metadata: Vec<(String, AmfValue)>,
+    tags: Vec<Tag>,
+    /// Packets read while probing the streams.
+    pending: VecDeque<Packet2>,
+    /// After a seek, packets are dropped until a keyframe of this stream.
+    seek_target: Option<usize>,
+}
+
+impl<S: MediaIoBufRead> DemuxerFlv<S> {
+    /// Creates the demuxer, reading the first tags until the streams announced by the header are configured.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            audio_stream: None,
+            video_stream: None,
+            metadata: Vec::new(),
+            tags: Vec::new(),
+            pending: VecDeque::new(),
+            seek_target: None,
+        };
+        demuxer.read_headers()?;
+        Ok(demuxer)
+    }
+
+    /// Properties of the `onMetaData` script tag, e.g. `duration`, `width` or `framerate`.
+    pub fn metadata(&self) -> &[(String, AmfValue)] {
+        &self.metadata
+    }
+
+    fn metadata_value(&self, key: &str) -> Option<&AmfValue> {
+        self.metadata.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+    }
+
+    fn read_headers(&mut self) -> Result<()> {
+        let mut header = [0u8; 9];
+        self.iobuf_reader.get_bytes(&mut header)?;
+        if &header[..3] != b"FLV" {
+            return decode_error("flv: missing signature");
+        }
+        let (has_audio, has_video) = (header[4] & 0x04 != 0, header[4] & 0x01 != 0);
+        let data_offset = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+        if data_offset < 9 {
+            return decode_error("flv: invalid header size");
+        }
+        // PreviousTagSize0 follows the header
+        self.iobuf_reader.skip(data_offset - 9 + 4)?;
+
+        let configured = |stream: Option<usize>, streams: &[Stream]| {
+            stream.is_some_and(|id| !needs_config(streams[id].codec_id) || !streams[id].codec_params.is_empty())
+        };
+        for _ in 0..PROBE_TAGS {
+            if (!has_audio || configured(self.audio_stream, &self.streams))
+                && (!has_video || configured(self.video_stream, &self.streams))
+            {
+                break;
+            }
+            let mut packet = Packet2::default();
+            match self.read_tag(&mut packet) {
+                Ok(true) => self.pending.push_back(packet),
+                Ok(false) => {}
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_tag_header(&mut self) -> Result<TagHeader> {
+        let pos = self.iobuf_reader.pos();
+        let mut header = [0u8; 11];
+        self.iobuf_reader.get_bytes(&mut header)?;
+        let size = u24(&header[1..4]) as usize;
+        let timestamp = (u24(&header[4..7]) | (header[7] as u32) << 24) as i32 as i64;
+        Ok(TagHeader { tag_type: header[0], pos, end: pos + 11 + size, timestamp })
+    }
+
+    /// Reads a tag, returns true if it holds a frame, which is stored in `packet`.
+    fn read_tag(&mut self, packet: &mut Packet2) -> Result<bool> {
+        let header = self.read_tag_header()?;
+        let is_frame = match header.tag_type {
+            TAG_AUDIO => self.read_audio(&header, packet)?,
+            TAG_VIDEO => self.read_video(&header, packet)?,
+            TAG_SCRIPT => {
+                self.read_script(&header)?;
+                false
+            }
+            // encrypted tags have the filter bit set
+            _ => false,
+        };
+        // skip what's left of the tag and the PreviousTagSize
+        let pos = self.iobuf_reader.pos();
+        if pos > header.end {
+            return decode_error("flv: tag header exceeds the tag");
+        }
+        self.iobuf_reader.skip(header.end - pos + 4)?;
+        Ok(is_frame)
+    }
+
+    /// Reads the data left in the tag, up to `limit` bytes.
+    fn read_rest(&mut self, header: &TagHeader, limit: usize) -> Result<Vec<u8>> {
+        let len = header.end.saturating_sub(self.iobuf_reader.pos());
+        if len > limit {
+            return limit_error("flv: tag too large");
+        }
+        let mut data = vec![0u8; len];
+        self.iobuf_reader.get_bytes(&mut data)?;
+        Ok(data)
+    }
+
+    /// Returns the stream of the audio or video tags, creating it on the first tag.
+    fn stream(&mut self, video: bool, codec_id: CodecId) -> usize {
+        let stream_id = if video { self.video_stream } else { self.audio_stream };
+        if let Some(stream_id) = stream_id {
+            return stream_id;
+        }
+        let stream_id = self.streams.len();
+        let duration = self.metadata_value("duration").and_then(AmfValue::as_f64).unwrap_or(0.0);
+        let mut stream = Stream {
+            id: stream_id,
+            codec_id,
+            media_type: if video { MediaType::Video } else { MediaType::Audio },
+            time_base: TimeBase::new(1, 1000),
+            duration: (duration * 1000.0) as i64,
+            ..Default::default()
+        };
+        if video {
+            stream.width = self.metadata_value("width").and_then(AmfValue::as_f64).unwrap_or(0.0) as u32;
+            stream.height = self.metadata_value("height").and_then(AmfValue::as_f64).unwrap_or(0.0) as u32;
+            self.video_stream = Some(stream_id);
+        } else {
+            self.audio_stream = Some(stream_id);
+        }
+        self.streams.push(stream);
+        stream_id
+    }
+
+    /// Stores the frame left in the tag, returns false if the tag is empty.
+    fn read_frame(&mut self, header: &TagHeader, stream_id: usize, pts: i64, keyframe: bool, packet: &mut Packet2) -> Result<bool> {
+        let len = header.end.saturating_sub(self.iobuf_reader.pos());
+        if len == 0 {
+            return Ok(false);
+        }
+        self.iobuf_reader.get_ioref(&mut packet.ioref, len)?;
+        packet.stream_id = stream_id;
+        packet.dts = header.timestamp;
+        packet.pts = pts;
+        packet.duration = 0;
+        packet.keyframe = keyframe;
+        packet.pos = header.pos as i64;
+        Ok(true)
+    }
+
+    fn read_audio(&mut self, header: &TagHeader, packet: &mut Packet2) -> Result<bool> {
+        if header.end <= header.pos + 11 {
+            return Ok(false);
+        }
+        let flags = self.iobuf_reader.get_u8()?;
+        let format = flags >> 4;
+        let sample_rate = [5512, 11025, 22050, 44100][(flags >> 2 & 3) as usize];
+        let bits = if flags & 0x02 != 0 { 16 } else { 8 };
+        let channels = if flags & 0x01 != 0 { 2 } else { 1 };
+        let codec_id = match format {
+            AUDIO_AAC => CodecId::Aac,
+            AUDIO_MP3 | AUDIO_MP3_8K => CodecId::Mp3,
+            AUDIO_PCM | AUDIO_PCM_LE if bits == 8 => CodecId::PcmU8,
+            AUDIO_PCM | AUDIO_PCM_LE => CodecId::PcmS16Le,
+            _ => CodecId::Unknown,
+        };
+
+        let new_stream = self.audio_stream.is_none();
+        let stream_id = self.stream(false, codec_id);
+        let stream = &mut self.streams[stream_id];
+        if new_stream {
+            stream.sample_rate = if format == AUDIO_MP3_8K { 8000 } else { sample_rate };
+            stream.channels = channels;
+            stream.bits_per_sample = if codec_id == CodecId::Aac || codec_id == CodecId::Mp3 { 0 } else { bits };
+        }
+
+        if format == AUDIO_AAC {
+            match self.iobuf_reader.get_u8()? {
+                SEQUENCE_HEADER => {
+                    let config = self.read_rest(header, MAX_SCRIPT_SIZE)?;
+                    let stream = &mut self.streams[stream_id];
+                    // the tag flags are always 44.1 kHz stereo for AAC
+                    if let Some((sample_rate, channels)) = parse_aac_config(&config) {
+                        stream.sample_rate = sample_rate;
+                        stream.channels = channels;
+                    }
+                    stream.codec_params = config;
+                    return Ok(false);
+                }
+                CODED_FRAMES => {}
+                _ => return Ok(false),
+            }
+        }
+        self.read_frame(header, stream_id, header.timestamp, true, packet)
+    }
+
+    fn read_video(&mut self, header: &TagHeader, packet: &mut Packet2) -> Result<bool> {
+        if header.end <= header.pos + 11 {
+            return Ok(false);
+        }
+        let flags = self.iobuf_reader.get_u8()?;
+        let (frame_type, codec_id, packet_type, has_cts) = if flags & 0x80 != 0 {
+            // Enhanced RTMP: the low bits are the packet type and a FourCC follows
+            let mut fourcc = [0u8; 4];
+            self.iobuf_reader.get_bytes(&mut fourcc)?;
+            let codec_id = video_codec_from_fourcc(&fourcc);
+            let (packet_type, has_cts) = match flags & 0x0F {
+                PACKET_SEQUENCE_START => (SEQUENCE_HEADER, false),
+                PACKET_CODED_FRAMES => (CODED_FRAMES, matches!(codec_id, CodecId::H264 | CodecId::Hevc)),
+                PACKET_CODED_FRAMES_X => (CODED_FRAMES, false),
+                // sequence end, metadata, multitrack and other packet types carry no frame
+                _ => return Ok(false),
+            };
+            (flags >> 4 & 0x07, codec_id, packet_type, has_cts)
+        } else {
+            match flags & 0x0F {
+                // AVCPacketType followed by the composition time
+                VIDEO_AVC => (flags >> 4, CodecId::H264, self.iobuf_reader.get_u8()?, true),
+                VIDEO_HEVC => (flags >> 4, CodecId::Hevc, self.iobuf_reader.get_u8()?, true),
+                _ => (flags >> 4, CodecId::Unknown, CODED_FRAMES, false),
+            }
+        };
+        if frame_type == FRAME_COMMAND {
+            return Ok(false);
+        }
+        let mut cts = 0;
+        if has_cts {
+            let mut bytes = [0u8; 3];
+            self.iobuf_reader.get_bytes(&mut bytes)?;
+            // signed 24-bit
+            cts = ((u24(&bytes) << 8) as i32 >> 8) as i64;
+        }
+
+        let stream_id = self.stream(true, codec_id);
+        match packet_type {
+            SEQUENCE_HEADER => {
+                self.streams[stream_id].codec_params = self.read_rest(header, MAX_SCRIPT_SIZE)?;
+                Ok(false)
+            }
+            CODED_FRAMES => self.read_frame(header, stream_id, header.timestamp + cts, frame_type == FRAME_KEY, packet),
+            // end of sequence
+            _ => Ok(false),
+        }
+    }
+
+    fn read_script(&mut self, header: &TagHeader) -> Result<()> {
+        let data = self.read_rest(header, MAX_SCRIPT_SIZE)?;
+        let values = parse_amf_values(&data)?;
+        let mut values = values.into_iter().skip_while(|value| *value == AmfValue::String("@setDataFrame".into()));
+        if values.next() != Some(AmfValue::String("onMetaData".into())) {
+            return Ok(());
+        }
+        let Some(AmfValue::Object(metadata)) = values.next() else {
+            return Ok(());
+        };
+
+        let simple_tags = metadata.iter()
+            .filter_map(|(name, value)| {
+                let value = match value {
+                    AmfValue::Number(value) => value.to_string(),
+                    AmfValue::Boolean(value) => value.to_string(),
+                    AmfValue::String(value) => value.clone(),
+                    _ => return None,
+                };
+                Some(SimpleTag { name: name.clone(), value: Some(value), default: true, ..Default::default() })
+            })
+            .collect();
+        self.tags = vec![Tag { simple_tags, ..Default::default() }];
+        self.metadata = metadata;
+        Ok(())
+    }
+
+    /// Returns the keyframe times in milliseconds and tag positions of the `keyframes` metadata property.
+    fn keyframe_index(&self) -> Vec<(i64, usize)> {
+        let Some(keyframes) = self.metadata_value("keyframes") else {
+            return Vec::new();
+        };
+        let (Some(AmfValue::Array(times)), Some(AmfValue::Array(positions))) =
+            (keyframes.get("times"), keyframes.get("filepositions"))
+        else {
+            return Vec::new();
+        };
+        times.iter()
+            .zip(positions)
+            .filter_map(|(time, pos)| Some(((time.as_f64()? * 1000.0).round() as i64, pos.as_f64()? as usize)))
+            .collect()
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerFlv<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        loop {
+            if let Some(next) = self.pending.pop_front() {
+                *packet = next;
+            } else {
+                match self.read_tag(packet) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return end_of_stream_error(),
+                    Err(err) => return Err(err),
+                }
+            }
+            if let Some(stream_id) = self.seek_target {
+                if packet.stream_id != stream_id || !packet.keyframe {
+                    continue;
+                }
+                self.seek_target = None;
+            }
+            return Ok(());
+        }
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    /// Seeks using the `keyframes` index of the metadata, which some encoders add.
+    fn seek(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        if stream_id >= self.streams.len() {
+            return invalid_input_error();
+        }
+        let index = self.keyframe_index();
+        let Some(&(time, pos)) = index.iter().rev().find(|(time, _)| *time <= timestamp).or(index.first()) else {
+            return unsupported_error("flv: no keyframe index");
+        };
+        self.iobuf_reader.seek(pos)?;
+        self.pending.clear();
+        self.seek_target = Some(stream_id);
+        Ok(time)
+    }
+
+    fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+
+    fn amf_string(value: &str) -> Vec<u8> {
+        [&[0x02][..], &(value.len() as u16).to_be_bytes(), value.as_bytes()].concat()
+    }
+
+    fn amf_number(value: f64) -> Vec<u8> {
+        [&[0x00][..], &value.to_be_bytes()].concat()
+    }
+
+    /// Properties of an object or ECMA array, followed by the end marker.
+    fn amf_properties(properties: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (name, value) in properties {
+            data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(value);
+        }
+        data.extend_from_slice(&[0, 0, 0x09]);
+        data
+    }
+
+    fn amf_array(values: &[f64]) -> Vec<u8> {
+        let values: Vec<u8> = values.iter().flat_map(|&value| amf_number(value)).collect();
+        [&[0x0A][..], &(values.len() as u32 / 9).to_be_bytes(), &values].concat()
+    }
+
+    fn flv_tag(tag_type: u8, timestamp: u32, data: &[u8]) -> Vec<u8> {
+        let size = (data.len() as u32).to_be_bytes();
+        let ts = timestamp.to_be_bytes();
+        let header = [tag_type, size[1], size[2], size[3], ts[1], ts[2], ts[3], ts[0], 0, 0, 0];
+        [&header[..], data, &(11 + data.len() as u32).to_be_bytes()].concat()
+    }
+
+    fn flv_header(flags: u8) -> Vec<u8> {
+        [&b"FLV\x01"[..], &[flags, 0, 0, 0, 9], &[0; 4]].concat()
+    }
+
+    type PacketInfo = (usize, i64, i64, bool, Vec<u8>, i64);
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerFlv<S>) -> Vec<PacketInfo> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.stream_id, packet.pts, packet.dts, packet.keyframe, packet.data().to_vec(), packet.pos));
+        }
+        packets
+    }
+
+    #[test]
+    fn avc_and_aac() {
+        // the keyframe index points at the last video tag
+        let script = |keyframe_pos: usize| {
+            flv_tag(TAG_SCRIPT, 0, &[
+                amf_string("onMetaData"),
+                [&[0x08][..], &3u32.to_be_bytes(), &amf_properties(&[
+                    ("duration", amf_number(0.1)),
+                    ("width", amf_number(320.0)),
+                    ("height", amf_number(240.0)),
+                    ("encoder", amf_string("test")),
+                    ("stereo", vec![0x01, 1]),
+                    ("keyframes", [&[0x03][..], &amf_properties(&[
+                        ("times", amf_array(&[0.0, 0.08])),
+                        ("filepositions", amf_array(&[0.0, keyframe_pos as f64])),
+                    ])].concat()),
+                ])].concat(),
+            ].concat())
+        };
+        let tags = [
+            flv_tag(TAG_VIDEO, 0, &[0x17, 0, 0, 0, 0, 1, 0x64, 0, 0x1F]),
+            flv_tag(TAG_AUDIO, 0, &[0xAF, 0, 0x11, 0x90]),
+            flv_tag(TAG_VIDEO, 0, &[0x17, 1, 0, 0, 40, 0xA1, 0xA2]),
+            flv_tag(TAG_AUDIO, 10, &[0xAF, 1, 0xB1]),
+            flv_tag(TAG_VIDEO, 40, &[0x27, 1, 0xFF, 0xFF, 0xEC, 0xA3]),
+        ].concat();
+        let last_pos = flv_header(0x05).len() + script(0).len() + tags.len();
+        let data = [flv_header(0x05), script(last_pos), tags, flv_tag(TAG_VIDEO, 80, &[0x17, 1, 0, 0, 0, 0xA4])].concat();
+
+        let mut demuxer = DemuxerFlv::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 16))).unwrap();
+        let streams = demuxer.streams();
+        assert_eq!(streams.len(), 2);
+        assert_eq!((streams[0].codec_id, streams[0].media_type), (CodecId::H264, MediaType::Video));
+        assert_eq!(streams[0].codec_params, vec![1, 0x64, 0, 0x1F]);
+        assert_eq!((streams[0].width, streams[0].height, streams[0].duration), (320, 240, 100));
+        assert_eq!((streams[1].codec_id, streams[1].media_type), (CodecId::Aac, MediaType::Audio));
+        assert_eq!(streams[1].codec_params, vec![0x11, 0x90]);
+        assert_eq!((streams[1].sample_rate, streams[1].channels), (48000, 2));
+
+        assert_eq!(demuxer.metadata()[3], ("encoder".to_string(), AmfValue::String("test".into())));
+        let simple_tags = &demuxer.tags()[0].simple_tags;
+        assert_eq!(simple_tags.iter().map(|tag| tag.name.as_str()).collect::<Vec<_>>(), ["duration", "width", "height", "encoder", "stereo"]);
+        assert_eq!(simple_tags[0].value.as_deref(), Some("0.1"));
+        assert_eq!(simple_tags[4].value.as_deref(), Some("true"));
+
+        let packets = read_all(&mut demuxer);
+        assert_eq!(packets.last().map(|packet| packet.5), Some(last_pos as i64));
+        let packets: Vec<_> = packets.into_iter().map(|(id, pts, dts, key, data, _)| (id, pts, dts, key, data)).collect();
+        assert_eq!(packets, vec![
+            (0, 40, 0, true, vec![0xA1, 0xA2]),
+            (1, 10, 10, true, vec![0xB1]),
+            (0, 20, 40, false, vec![0xA3]),
+            (0, 80, 80, true, vec![0xA4]),
+        ]);
+
+        assert_eq!(demuxer.seek(0, 90), Ok(80));
+        assert_eq!(read_all(&mut demuxer).iter().map(|packet| packet.1).collect::<Vec<_>>(), vec![80]);
+        assert_eq!(demuxer.seek(2, 0), Err(Error::InvalidInput));
+    }
+
+    #[test]
+    fn enhanced_hevc_and_mp3() {
+        let hvcc = [1, 0x01, 0x60, 0, 0, 0];
+        let data = [
+            flv_header(0x05),
+            flv_tag(TAG_VIDEO, 0, &[&[0x90][..], b"hvc1", &hvcc].concat()),
+            flv_tag(TAG_AUDIO, 0, &[0x2E, 0xFF, 0xFB]),
+            flv_tag(TAG_VIDEO, 0, &[&[0x91][..], b"hvc1", &[0, 0, 33, 0xC1]].concat()),
+            flv_tag(TAG_VIDEO, 33, &[&[0xA3][..], b"hvc1", &[0xC2]].concat()),
+            // metadata packets carry no frame
+            flv_tag(TAG_VIDEO, 33, &[&[0x94][..], b"hvc1", &[0x02]].concat()),
+            flv_tag(TAG_VIDEO, 66, &[&[0x92][..], b"hvc1"].concat()),
+        ].concat();
+
+        let mut demuxer = DemuxerFlv::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 7))).unwrap();
+        let streams = demuxer.streams();
+        assert_eq!((streams[0].codec_id, streams[0].codec_params.as_slice()), (CodecId::Hevc, &hvcc[..]));
+        assert_eq!((streams[1].codec_id, streams[1].sample_rate, streams[1].channels), (CodecId::Mp3, 44100, 1));
+        assert!(demuxer.tags().is_empty());
+
+        let packets: Vec<_> = read_all(&mut demuxer).into_iter().map(|(id, pts, dts, key, data, _)| (id, pts, dts, key, data)).collect();
+        assert_eq!(packets, vec![
+            (1, 0, 0, true, vec![0xFF, 0xFB]),
+            (0, 33, 0, true, vec![0xC1]),
+            (0, 33, 33, false, vec![0xC2]),
+        ]);
+        assert!(matches!(demuxer.seek(0, 0), Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn amf_values() {
+        let data = [
+            amf_string("onCuePoint"),
+            [&[0x03][..], &amf_properties(&[("name", amf_string("cue")), ("time", amf_number(1.5))])].concat(),
+            vec![0x05, 0x06],
+            [&[0x0B][..], &2.0f64.to_be_bytes(), &[0, 0]].concat(),
+            [&[0x0C][..], &3u32.to_be_bytes(), b"abc"].concat(),
+        ].concat();
+        let values = parse_amf_values(&data).unwrap();
+        assert_eq!(values, vec![
+            AmfValue::String("onCuePoint".into()),
+            AmfValue::Object(vec![("name".into(), AmfValue::String("cue".into())), ("time".into(), AmfValue::Number(1.5))]),
+            AmfValue::Null,
+            AmfValue::Undefined,
+            AmfValue::Date(2.0),
+            AmfValue::String("abc".into()),
+        ]);
+        assert_eq!(values[1].get("time").and_then(AmfValue::as_f64), Some(1.5));
+        assert!(parse_amf_values(&[0x02, 0, 5, b'a']).is_err());
+    }
+}
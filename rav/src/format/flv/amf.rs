@@ -0,0 +1,135 @@
+use crate::error::{decode_error, Result};
+
+/// Nesting depth limit of AMF0 objects and arrays.
+const MAX_DEPTH: usize = 64;
+
+/// An AMF0 value of a script data tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmfValue {
+    Number(f64),
+    Boolean(bool),
+    String(String),
+    /// Properties of an object or an ECMA array, in order.
+    Object(Vec<(String, AmfValue)>),
+    Array(Vec<AmfValue>),
+    /// Milliseconds since the Unix epoch.
+    Date(f64),
+    Null,
+    /// Undefined values and references to other objects, which are not resolved.
+    Undefined,
+}
+
+impl AmfValue {
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            AmfValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the property `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&AmfValue> {
+        match self {
+            AmfValue::Object(properties) => properties.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+struct AmfReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> AmfReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len() - self.pos {
+            return decode_error("flv: truncated AMF value");
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn string(&mut self, len: usize) -> Result<String> {
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn short_string(&mut self) -> Result<String> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        self.string(len)
+    }
+
+    /// Reads object properties up to the empty name followed by the object end marker.
+    fn properties(&mut self, depth: usize) -> Result<Vec<(String, AmfValue)>> {
+        let mut properties = Vec::new();
+        loop {
+            // some encoders omit the end marker of the top level ECMA array
+            if self.pos == self.data.len() {
+                return Ok(properties);
+            }
+            let name = self.short_string()?;
+            if name.is_empty() && self.data.get(self.pos) == Some(&0x09) {
+                self.pos += 1;
+                return Ok(properties);
+            }
+            properties.push((name, self.value(depth + 1)?));
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<AmfValue> {
+        if depth > MAX_DEPTH {
+            return decode_error("flv: AMF values nested too deeply");
+        }
+        let value = match self.array::<1>()?[0] {
+            0x00 => AmfValue::Number(f64::from_be_bytes(self.array()?)),
+            0x01 => AmfValue::Boolean(self.array::<1>()?[0] != 0),
+            0x02 => AmfValue::String(self.short_string()?),
+            0x03 => AmfValue::Object(self.properties(depth)?),
+            0x05 => AmfValue::Null,
+            0x06 => AmfValue::Undefined,
+            0x07 => {
+                self.bytes(2)?;
+                AmfValue::Undefined
+            }
+            0x08 => {
+                // the count is only a hint, the array ends like an object
+                self.bytes(4)?;
+                AmfValue::Object(self.properties(depth)?)
+            }
+            0x0A => {
+                let count = u32::from_be_bytes(self.array()?) as usize;
+                if count > self.data.len() - self.pos {
+                    return decode_error("flv: truncated AMF array");
+                }
+                AmfValue::Array((0..count).map(|_| self.value(depth + 1)).collect::<Result<_>>()?)
+            }
+            0x0B => {
+                let date = f64::from_be_bytes(self.array()?);
+                // time zone, unused
+                self.bytes(2)?;
+                AmfValue::Date(date)
+            }
+            0x0C => {
+                let len = u32::from_be_bytes(self.array()?) as usize;
+                AmfValue::String(self.string(len)?)
+            }
+            _ => return decode_error("flv: unsupported AMF type"),
+        };
+        Ok(value)
+    }
+}
+
+/// Parses the AMF0 values of a script data tag.
+pub(super) fn parse_amf_values(data: &[u8]) -> Result<Vec<AmfValue>> {
+    let mut reader = AmfReader { data, pos: 0 };
+    let mut values = Vec::new();
+    while reader.pos < data.len() {
+        values.push(reader.value(0)?);
+    }
+    Ok(values)
+}
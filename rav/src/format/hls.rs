@@ -0,0 +1,192 @@
+use crate::data::IoBuf;
+use crate::error::{decode_error, end_of_stream_error, invalid_input_error, retry_later_error, Result};
+
+use super::segmented::{fetch, supply_segments, SegmentConcat, SegmentTimeline};
+use super::IoBufSupply;
+
+mod playlist;
+
+pub use playlist::{MasterPlaylist, MediaPlaylist, MediaSegment, Playlist, Variant};
+
+/// Supplies the segments of an HLS media playlist in order, from local files or an `http://` server.
+/// The initialization section of fragmented MP4 segments precedes the first segment and every segment using
+/// another one, so the supplied bytes can be read by the demuxer of the segment format. Wrap that demuxer in a
+/// [`DemuxerSegmented`](super::DemuxerSegmented) with the [`timeline`](Self::timeline) to keep the timestamps
+/// continuous across discontinuities.
+#[derive(Debug, Default)]
+pub struct IoBufSupplierHls {
+    /// Highest variant bandwidth to select from a master playlist, the highest bandwidth variant if `None`.
+    max_bandwidth: Option<u64>,
+    master: Option<MasterPlaylist>,
+    media_uri: String,
+    media: MediaPlaylist,
+    /// Index in `media.segments` of the next segment to supply.
+    next_segment: usize,
+    /// Playlist time of the next segment, the sum of the previous `EXTINF` durations.
+    time: f64,
+    concat: SegmentConcat,
+}
+
+impl IoBufSupplierHls {
+    /// Selects the highest bandwidth variant not above `max_bandwidth` bits per second, or the lowest one.
+    pub fn with_max_bandwidth(mut self, max_bandwidth: u64) -> Self {
+        self.max_bandwidth = Some(max_bandwidth);
+        self
+    }
+
+    /// The master playlist, if the input was one.
+    pub fn master_playlist(&self) -> Option<&MasterPlaylist> {
+        self.master.as_ref()
+    }
+
+    /// The media playlist being supplied, as last loaded.
+    pub fn media_playlist(&self) -> &MediaPlaylist {
+        &self.media
+    }
+
+    /// Segment positions to pass to [`DemuxerSegmented`](super::DemuxerSegmented).
+    pub fn timeline(&self) -> SegmentTimeline {
+        self.concat.timeline()
+    }
+
+    fn load_playlist(uri: &str) -> Result<Playlist> {
+        let text = fetch(uri, None)?;
+        match String::from_utf8(text) {
+            Ok(text) => Playlist::parse(&text, uri),
+            Err(_) => decode_error("hls: playlist is not utf-8"),
+        }
+    }
+
+    /// Loads a live media playlist again, keeping the segments after the last supplied one.
+    fn reload(&mut self) -> Result<()> {
+        let Playlist::Media(media) = Self::load_playlist(&self.media_uri)? else {
+            return decode_error("hls: master playlist in place of a media playlist");
+        };
+        let next_sequence = self.media.media_sequence + self.media.segments.len() as u64;
+        self.next_segment = media.segments.iter().position(|segment| segment.sequence >= next_sequence)
+            .unwrap_or(media.segments.len());
+        self.media = media;
+        Ok(())
+    }
+
+    /// Reads the next segment with its initialization section if it changed.
+    fn next_segment_data(&mut self) -> Result<Vec<u8>> {
+        if self.next_segment >= self.media.segments.len() {
+            if self.media.end_list {
+                return end_of_stream_error();
+            }
+            self.reload()?;
+            if self.next_segment >= self.media.segments.len() {
+                return if self.media.end_list { end_of_stream_error() } else { retry_later_error() };
+            }
+        }
+
+        let segment = &self.media.segments[self.next_segment];
+        let data = self.concat.read(segment.map.as_ref(), &segment.uri, segment.byte_range,
+            segment.discontinuity_sequence, self.time)?;
+        self.time += segment.duration;
+        self.next_segment += 1;
+        Ok(data)
+    }
+}
+
+impl IoBufSupply for IoBufSupplierHls {
+    fn open_input(&mut self, uri: &str) -> Result<()> {
+        let mut uri = uri.to_string();
+        let mut playlist = Self::load_playlist(&uri)?;
+        if let Playlist::Master(master) = &playlist {
+            let fits = |variant: &&Variant| self.max_bandwidth.is_none_or(|max| variant.bandwidth <= max);
+            let variant = master.variants.iter().filter(fits).max_by_key(|variant| variant.bandwidth)
+                .or_else(|| master.variants.iter().min_by_key(|variant| variant.bandwidth));
+            let Some(variant) = variant else {
+                return decode_error("hls: master playlist without variants");
+            };
+            uri = variant.uri.clone();
+            self.master = Some(master.clone());
+            playlist = Self::load_playlist(&uri)?;
+        }
+        let Playlist::Media(media) = playlist else {
+            return decode_error("hls: variant is a master playlist");
+        };
+
+        self.media_uri = uri;
+        self.media = media;
+        self.next_segment = 0;
+        self.time = 0.0;
+        self.concat = SegmentConcat::default();
+        Ok(())
+    }
+
+    fn supply_iobufs(&mut self, len_bytes: usize, _parsed_iobufs: &[IoBuf], new_iobufs: &mut [IoBuf]) -> Result<usize> {
+        if self.media_uri.is_empty() {
+            return invalid_input_error();
+        }
+        if new_iobufs.is_empty() {
+            return retry_later_error();
+        }
+        supply_segments(len_bytes, new_iobufs, || self.next_segment_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use crate::data::IoRef;
+    use crate::format::{MediaIoBufRead, MediaSourceStream};
+
+    /// Serves `files` over HTTP, ignoring range requests.
+    fn serve(files: Vec<(&'static str, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut byte = [0u8];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or("");
+                let response = match files.iter().find(|(name, _)| path == format!("/{name}")) {
+                    Some((_, body)) => [format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).as_bytes(), body].concat(),
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        format!("http://{address}")
+    }
+
+    #[test]
+    fn segments_from_http_server() {
+        let master = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=500000\nlow.m3u8\n#EXT-X-STREAM-INF:BANDWIDTH=900000\nhigh.m3u8\n";
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXT-X-MAP:URI=\"init.mp4\"\n\
+            #EXTINF:2.0,\n#EXT-X-BYTERANGE:4@0\nmain.m4s\n#EXTINF:1.5,\n#EXT-X-BYTERANGE:4\nmain.m4s\n\
+            #EXT-X-DISCONTINUITY\n#EXT-X-MAP:URI=\"ad/init.mp4\"\n#EXTINF:2.0,\nad/0.m4s\n#EXT-X-ENDLIST\n";
+        let base = serve(vec![
+            ("master.m3u8", master.into()),
+            ("low.m3u8", media.into()),
+            ("init.mp4", b"INIT".to_vec()),
+            ("main.m4s", b"aaaabbbb".to_vec()),
+            ("ad/init.mp4", b"init".to_vec()),
+            ("ad/0.m4s", b"cccc".to_vec()),
+        ]);
+
+        let mut supplier = IoBufSupplierHls::default().with_max_bandwidth(800_000);
+        supplier.open_input(&format!("{base}/master.m3u8")).unwrap();
+        assert_eq!(supplier.master_playlist().unwrap().variants.len(), 2);
+        assert_eq!(supplier.media_playlist().segments.len(), 3);
+        let timeline = supplier.timeline();
+        let mut stream = MediaSourceStream::new(supplier);
+        let mut ioref = IoRef::default();
+        stream.get_ioref(&mut ioref, 20).unwrap();
+        assert_eq!(ioref.data(), b"INITaaaabbbbinitcccc");
+        assert!(stream.get_u8().is_err());
+        let segments: Vec<_> = [0, 11, 12, 19].iter().map(|&pos| timeline.segment_at(pos).unwrap()).collect();
+        assert_eq!(segments.iter().map(|segment| (segment.pos, segment.discontinuity_sequence)).collect::<Vec<_>>(),
+            vec![(0, 0), (8, 0), (12, 1), (12, 1)]);
+        assert_eq!(segments.iter().map(|segment| segment.time).collect::<Vec<_>>(), vec![0.0, 2.0, 3.5, 3.5]);
+    }
+}
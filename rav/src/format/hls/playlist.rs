@@ -0,0 +1,332 @@
+use crate::error::{decode_error, unsupported_error, Result};
+
+use crate::format::segmented::{resolve_uri, ByteRange, InitSection};
+
+/// A variant stream of a master playlist (`EXT-X-STREAM-INF`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variant {
+    /// URI of the media playlist.
+    pub uri: String,
+    /// Peak bit rate in bits per second.
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    /// RFC 6381 codec strings, e.g. `avc1.64001f,mp4a.40.2`.
+    pub codecs: Option<String>,
+    pub resolution: Option<(u32, u32)>,
+    pub frame_rate: Option<f64>,
+}
+
+/// A media segment of a media playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaSegment {
+    pub uri: String,
+    /// Media sequence number.
+    pub sequence: u64,
+    /// Duration in seconds from `EXTINF`.
+    pub duration: f64,
+    pub byte_range: Option<ByteRange>,
+    pub map: Option<InitSection>,
+    /// Discontinuity sequence number, incremented by every `EXT-X-DISCONTINUITY`.
+    pub discontinuity_sequence: u64,
+    /// The segment follows an `EXT-X-DISCONTINUITY`.
+    pub discontinuity: bool,
+    /// Date and time of the first sample in milliseconds since the Unix epoch, from `EXT-X-PROGRAM-DATE-TIME` or
+    /// extrapolated from the previous segment.
+    pub program_date_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaPlaylist {
+    /// Maximum segment duration in seconds.
+    pub target_duration: u64,
+    pub media_sequence: u64,
+    pub discontinuity_sequence: u64,
+    /// No more segments will be added (`EXT-X-ENDLIST`).
+    pub end_list: bool,
+    pub segments: Vec<MediaSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Playlist {
+    Master(MasterPlaylist),
+    Media(MediaPlaylist),
+}
+
+/// Splits an attribute list into names and values, quotes are removed from quoted string values.
+fn attributes(list: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = list.trim();
+    while let Some((name, value)) = rest.split_once('=') {
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        attributes.push((name.trim(), value.trim()));
+        rest = next.trim_start().strip_prefix(',').unwrap_or(next).trim_start();
+    }
+    attributes
+}
+
+/// Parses `<len>[@<offset>]`, the offset defaults to `next_offset`.
+fn parse_byte_range(value: &str, next_offset: u64) -> Result<ByteRange> {
+    let (len, offset) = match value.split_once('@') {
+        Some((len, offset)) => (len, offset.parse().ok()),
+        None => (value, Some(next_offset)),
+    };
+    match (len.parse(), offset) {
+        (Ok(len), Some(offset)) => Ok(ByteRange { offset, len }),
+        _ => decode_error("hls: invalid byte range"),
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses an ISO 8601 date and time such as `2010-02-19T14:54:23.031+08:00`, returns milliseconds since the
+/// Unix epoch.
+fn parse_date_time(value: &str) -> Option<i64> {
+    let number = |s: &str| s.parse::<i64>().ok();
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-');
+    let (year, month, day) = (number(date.next()?)?, number(date.next()?)?, number(date.next()?)?);
+
+    // the time zone is Z or a +hh:mm, +hhmm or +hh offset
+    let (time, offset_minutes) = match time.find(['Z', 'z', '+', '-']) {
+        Some(pos) if time[pos..].eq_ignore_ascii_case("z") => (&time[..pos], 0),
+        Some(pos) => {
+            let zone = time[pos + 1..].replace(':', "");
+            let zone_minutes = zone.get(2..).filter(|minutes| !minutes.is_empty()).map_or(Some(0), number)?;
+            let minutes = number(zone.get(..2)?)? * 60 + zone_minutes;
+            (&time[..pos], if &time[pos..pos + 1] == "-" { -minutes } else { minutes })
+        }
+        None => (time, 0),
+    };
+    let mut time = time.splitn(3, ':');
+    let (hour, minute) = (number(time.next()?)?, number(time.next()?)?);
+    let seconds: f64 = time.next().map_or(Some(0.0), |s| s.parse().ok())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59
+        || !(0.0..61.0).contains(&seconds) {
+        return None;
+    }
+
+    let minutes = days_from_civil(year, month, day) * 1440 + hour * 60 + minute - offset_minutes;
+    Some(minutes * 60_000 + (seconds * 1000.0).round() as i64)
+}
+
+impl Playlist {
+    /// Parses a master or media playlist, URIs are resolved relative to `uri`, the location of the playlist.
+    pub fn parse(text: &str, uri: &str) -> Result<Playlist> {
+        let mut lines = text.trim_start_matches('\u{feff}').lines().map(str::trim).filter(|line| !line.is_empty());
+        if lines.next() != Some("#EXTM3U") {
+            return decode_error("hls: no #EXTM3U header");
+        }
+
+        let mut master = MasterPlaylist::default();
+        let mut media = MediaPlaylist::default();
+        let mut is_master = false;
+        // tags applying to the next URI
+        let mut variant: Option<Variant> = None;
+        let mut duration = None;
+        let mut byte_range = None;
+        let mut discontinuity = false;
+        let mut program_date_time = None;
+        // tags applying to all following segments
+        let mut map = None;
+        let mut discontinuity_sequence = None;
+        let mut next_offset = 0;
+
+        for line in lines {
+            let Some(tag) = line.strip_prefix('#') else {
+                if let Some(mut variant) = variant.take() {
+                    variant.uri = resolve_uri(uri, line);
+                    master.variants.push(variant);
+                    continue;
+                }
+                let discontinuity_sequence =
+                    discontinuity_sequence.get_or_insert(media.discontinuity_sequence);
+                *discontinuity_sequence += discontinuity as u64;
+                let duration: f64 = duration.take().unwrap_or(0.0);
+                let program_date_time = program_date_time.take().or_else(|| {
+                    let previous = media.segments.last().filter(|_| !discontinuity)?;
+                    Some(previous.program_date_time? + (previous.duration * 1000.0).round() as i64)
+                });
+                media.segments.push(MediaSegment {
+                    uri: resolve_uri(uri, line),
+                    sequence: media.media_sequence + media.segments.len() as u64,
+                    duration,
+                    byte_range: byte_range.take(),
+                    map: map.clone(),
+                    discontinuity_sequence: *discontinuity_sequence,
+                    discontinuity,
+                    program_date_time,
+                });
+                discontinuity = false;
+                continue;
+            };
+            let (name, value) = tag.split_once(':').unwrap_or((tag, ""));
+            match name {
+                "EXT-X-STREAM-INF" => {
+                    is_master = true;
+                    let mut stream_inf = Variant::default();
+                    for (name, value) in attributes(value) {
+                        match name {
+                            "BANDWIDTH" => stream_inf.bandwidth = value.parse().unwrap_or(0),
+                            "AVERAGE-BANDWIDTH" => stream_inf.average_bandwidth = value.parse().ok(),
+                            "CODECS" => stream_inf.codecs = Some(value.to_string()),
+                            "RESOLUTION" => {
+                                stream_inf.resolution = value.split_once(['x', 'X'])
+                                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+                            }
+                            "FRAME-RATE" => stream_inf.frame_rate = value.parse().ok(),
+                            _ => {}
+                        }
+                    }
+                    variant = Some(stream_inf);
+                }
+                "EXTINF" => {
+                    let value = value.split_once(',').map_or(value, |(duration, _title)| duration);
+                    match value.trim().parse() {
+                        Ok(value) => duration = Some(value),
+                        Err(_) => return decode_error("hls: invalid EXTINF"),
+                    }
+                }
+                "EXT-X-BYTERANGE" => {
+                    let range = parse_byte_range(value, next_offset)?;
+                    next_offset = range.offset + range.len;
+                    byte_range = Some(range);
+                }
+                "EXT-X-MAP" => {
+                    let mut section = InitSection { uri: String::new(), byte_range: None };
+                    for (name, value) in attributes(value) {
+                        match name {
+                            "URI" => section.uri = resolve_uri(uri, value),
+                            "BYTERANGE" => section.byte_range = Some(parse_byte_range(value, 0)?),
+                            _ => {}
+                        }
+                    }
+                    if section.uri.is_empty() {
+                        return decode_error("hls: EXT-X-MAP without URI");
+                    }
+                    map = Some(section);
+                }
+                "EXT-X-DISCONTINUITY" => discontinuity = true,
+                "EXT-X-PROGRAM-DATE-TIME" => match parse_date_time(value) {
+                    Some(date_time) => program_date_time = Some(date_time),
+                    None => return decode_error("hls: invalid EXT-X-PROGRAM-DATE-TIME"),
+                },
+                "EXT-X-KEY" if attributes(value).iter().any(|&(name, value)| name == "METHOD" && value != "NONE") => {
+                    return unsupported_error("hls: encrypted segments");
+                }
+                "EXT-X-TARGETDURATION" => media.target_duration = value.parse().unwrap_or(0),
+                "EXT-X-MEDIA-SEQUENCE" => media.media_sequence = value.parse().unwrap_or(0),
+                "EXT-X-DISCONTINUITY-SEQUENCE" => media.discontinuity_sequence = value.parse().unwrap_or(0),
+                "EXT-X-ENDLIST" => media.end_list = true,
+                _ => {}
+            }
+        }
+
+        if is_master {
+            Ok(Playlist::Master(master))
+        } else {
+            Ok(Playlist::Media(media))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn master_and_media_playlists() {
+        let master = "#EXTM3U\n#EXT-X-VERSION:6\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=1280000,AVERAGE-BANDWIDTH=1000000,CODECS=\"avc1.4d401e,mp4a.40.2\",RESOLUTION=640x360\n\
+            low/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=2560000,RESOLUTION=1280x720,FRAME-RATE=29.970\n\
+            /live/high.m3u8\n";
+        let Playlist::Master(master) = Playlist::parse(master, "http://127.0.0.1:8000/hls/master.m3u8").unwrap() else {
+            panic!("not a master playlist");
+        };
+        assert_eq!(master.variants, vec![
+            Variant {
+                uri: "http://127.0.0.1:8000/hls/low/index.m3u8".into(),
+                bandwidth: 1_280_000,
+                average_bandwidth: Some(1_000_000),
+                codecs: Some("avc1.4d401e,mp4a.40.2".into()),
+                resolution: Some((640, 360)),
+                frame_rate: None,
+            },
+            Variant {
+                uri: "http://127.0.0.1:8000/live/high.m3u8".into(),
+                bandwidth: 2_560_000,
+                resolution: Some((1280, 720)),
+                frame_rate: Some(29.97),
+                ..Default::default()
+            },
+        ]);
+
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:10\n\
+            #EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"\n\
+            #EXT-X-PROGRAM-DATE-TIME:2024-03-01T12:00:00.500+01:00\n\
+            #EXTINF:4.0,\n#EXT-X-BYTERANGE:1000@720\nmedia.mp4\n\
+            #EXTINF:3.5,title\n#EXT-X-BYTERANGE:800\nmedia.mp4\n\
+            #EXT-X-DISCONTINUITY\n#EXT-X-MAP:URI=\"ad/init.mp4\"\n\
+            #EXTINF:2,\nad/0.m4s\n#EXT-X-ENDLIST\n";
+        let Playlist::Media(media) = Playlist::parse(media, "/srv/hls/index.m3u8").unwrap() else {
+            panic!("not a media playlist");
+        };
+        assert_eq!((media.target_duration, media.media_sequence, media.end_list), (4, 10, true));
+        let map = InitSection { uri: "/srv/hls/init.mp4".into(), byte_range: Some(ByteRange { offset: 0, len: 720 }) };
+        let ad_map = InitSection { uri: "/srv/hls/ad/init.mp4".into(), byte_range: None };
+        let start = 1_709_290_800_500;
+        assert_eq!(media.segments, vec![
+            MediaSegment {
+                uri: "/srv/hls/media.mp4".into(),
+                sequence: 10,
+                duration: 4.0,
+                byte_range: Some(ByteRange { offset: 720, len: 1000 }),
+                map: Some(map.clone()),
+                discontinuity_sequence: 0,
+                discontinuity: false,
+                program_date_time: Some(start),
+            },
+            MediaSegment {
+                uri: "/srv/hls/media.mp4".into(),
+                sequence: 11,
+                duration: 3.5,
+                byte_range: Some(ByteRange { offset: 1720, len: 800 }),
+                map: Some(map),
+                discontinuity_sequence: 0,
+                discontinuity: false,
+                program_date_time: Some(start + 4000),
+            },
+            MediaSegment {
+                uri: "/srv/hls/ad/0.m4s".into(),
+                sequence: 12,
+                duration: 2.0,
+                byte_range: None,
+                map: Some(ad_map),
+                discontinuity_sequence: 1,
+                discontinuity: true,
+                program_date_time: None,
+            },
+        ]);
+        assert_eq!(parse_date_time("1970-01-01T00:00:01Z"), Some(1000));
+        assert_eq!(parse_date_time("1970-01-01T00:00:00-0130"), Some(5_400_000));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error::{Error, Result};
+use crate::metadata::SimpleTag;
+
+use super::MediaIoBufRead;
+
+/// Largest ID3v2 tag loaded in memory, larger tags are skipped.
+const MAX_ID3V2_SIZE: usize = 16 * 1024 * 1024;
+
+const ID3V1_SIZE: usize = 128;
+const APE_FOOTER_SIZE: usize = 32;
+
+fn syncsafe(data: &[u8]) -> Option<usize> {
+    data.iter().try_fold(0usize, |size, &byte| (byte < 0x80).then_some(size << 7 | byte as usize))
+}
+
+fn text_tag(name: &str, value: String) -> SimpleTag {
+    SimpleTag { name: name.to_string(), value: Some(value), default: true, ..Default::default() }
+}
+
+/// The data following the ID3v2 tags at the start of `data`, empty if they extend beyond it.
+pub(super) fn skip_id3v2(mut data: &[u8]) -> &[u8] {
+    while let (Some(b"ID3"), Some(2..=4), Some(size)) = (data.get(..3), data.get(3), data.get(6..10).and_then(syncsafe))
+    {
+        let footer = if data[3] == 4 && data[5] & 0x10 != 0 { 10 } else { 0 };
+        data = data.get(10 + size + footer..).unwrap_or(&[]);
+    }
+    data
+}
+
+/// Reads the ID3v2 tags at the current position, the position is left after them.
+pub(super) fn read_id3v2<S: MediaIoBufRead + ?Sized>(reader: &mut S) -> Result<Vec<SimpleTag>> {
+    let mut tags = Vec::new();
+    loop {
+        let mut header = [0u8; 10];
+        match reader.peek_bytes(&mut header) {
+            Ok(()) => {}
+            Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return Ok(tags),
+            Err(err) => return Err(err),
+        }
+        let size = syncsafe(&header[6..]);
+        let (true, Some(size), 2..=4) = (header.starts_with(b"ID3"), size, header[3]) else {
+            return Ok(tags);
+        };
+        reader.skip(10)?;
+        // a footer repeats the header
+        let footer = if header[3] == 4 && header[5] & 0x10 != 0 { 10 } else { 0 };
+        if size > MAX_ID3V2_SIZE {
+            reader.skip(size + footer)?;
+            continue;
+        }
+        let mut body = vec![0u8; size];
+        reader.get_bytes(&mut body)?;
+        reader.skip(footer)?;
+        tags.extend(parse_id3v2(header[3], header[5], &body));
+    }
+}
+
+/// Removes the zero bytes inserted after 0xFF by the unsynchronisation scheme.
+fn remove_unsync(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        if byte != 0 || i == 0 || data[i - 1] != 0xFF {
+            out.push(byte);
+        }
+    }
+    out
+}
+
+/// Parses the frames of an ID3v2 tag body into tags named after the frame IDs, e.g. `TIT2` or `TPE1`.
+/// `TXXX` frames are named after their description, `COMM` frames keep their language.
+fn parse_id3v2(major: u8, flags: u8, body: &[u8]) -> Vec<SimpleTag> {
+    let body = if flags & 0x80 != 0 && major < 4 { remove_unsync(body) } else { body.to_vec() };
+    let mut pos = 0;
+    if flags & 0x40 != 0 {
+        match major {
+            // compressed tags of version 2.2 can't be read
+            2 => return Vec::new(),
+            3 => {
+                pos = body.get(..4).map_or(body.len(), |size| 4 + u32::from_be_bytes(size.try_into().unwrap()) as usize);
+            }
+            _ => pos = body.get(..4).and_then(syncsafe).unwrap_or(body.len()),
+        }
+    }
+
+    let (id_len, header_len) = if major == 2 { (3, 6) } else { (4, 10) };
+    let mut tags = Vec::new();
+    while pos + header_len <= body.len() && body[pos] != 0 {
+        let header = &body[pos..pos + header_len];
+        let id = String::from_utf8_lossy(&header[..id_len]).into_owned();
+        let size = match major {
+            2 => (header[3] as usize) << 16 | (header[4] as usize) << 8 | header[5] as usize,
+            3 => u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize,
+            _ => syncsafe(&header[4..8]).unwrap_or(usize::MAX),
+        };
+        pos += header_len;
+        let Some(data) = body.get(pos..pos.saturating_add(size)) else {
+            break;
+        };
+        pos += size;
+
+        let frame_flags = if major == 2 { 0 } else { u16::from_be_bytes([header[8], header[9]]) };
+        let (compressed, grouped) = match major {
+            3 => (frame_flags & 0x00C0 != 0, frame_flags & 0x0020 != 0),
+            _ => (frame_flags & 0x000C != 0, frame_flags & 0x0040 != 0),
+        };
+        if compressed {
+            continue;
+        }
+        let mut data = &data[(grouped as usize).min(data.len())..];
+        if major == 4 && frame_flags & 0x0001 != 0 {
+            // data length indicator
+            data = &data[4.min(data.len())..];
+        }
+        let unsync;
+        if major == 4 && frame_flags & 0x0002 != 0 {
+            unsync = remove_unsync(data);
+            data = &unsync;
+        }
+        tags.extend(parse_frame(&id, data));
+    }
+    tags
+}
+
+fn parse_frame(id: &str, data: &[u8]) -> Option<SimpleTag> {
+    let (&encoding, data) = data.split_first()?;
+    match id {
+        "TXXX" | "TXX" => {
+            let (description, value) = split_terminated(encoding, data);
+            Some(text_tag(&decode_text(encoding, description), decode_text(encoding, value)))
+        }
+        "COMM" | "COM" if data.len() >= 3 => {
+            let (_, text) = split_terminated(encoding, &data[3..]);
+            let mut tag = text_tag(id, decode_text(encoding, text));
+            tag.language = Some(String::from_utf8_lossy(&data[..3]).into_owned());
+            Some(tag)
+        }
+        _ if id.starts_with('T') => {
+            // version 2.4 separates multiple values with a terminator
+            let mut values = Vec::new();
+            let mut rest = data;
+            while !rest.is_empty() {
+                let (value, next) = split_terminated(encoding, rest);
+                values.push(decode_text(encoding, value));
+                rest = next;
+            }
+            values.retain(|value| !value.is_empty());
+            Some(text_tag(id, values.join("; ")))
+        }
+        _ => None,
+    }
+}
+
+/// Splits a string terminated by a zero byte, or two zero bytes with UTF-16, from the following data.
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    let end = if matches!(encoding, 1 | 2) {
+        data.chunks_exact(2).position(|pair| pair == [0, 0]).map(|i| (i * 2, i * 2 + 2))
+    } else {
+        data.iter().position(|&byte| byte == 0).map(|i| (i, i + 1))
+    };
+    match end {
+        Some((end, next)) => (&data[..end], &data[next..]),
+        None => (data, &[]),
+    }
+}
+
+fn decode_text(encoding: u8, data: &[u8]) -> String {
+    match encoding {
+        0 => data.iter().map(|&byte| byte as char).collect(),
+        1 | 2 => {
+            let (big_endian, data) = match data {
+                [0xFF, 0xFE, rest @ ..] => (false, rest),
+                [0xFE, 0xFF, rest @ ..] => (true, rest),
+                _ => (encoding == 2, data),
+            };
+            let units = data.chunks_exact(2).map(|pair| {
+                if big_endian { u16::from_be_bytes([pair[0], pair[1]]) } else { u16::from_le_bytes([pair[0], pair[1]]) }
+            });
+            char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+        }
+        _ => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+/// Parses an ID3v1 or ID3v1.1 tag into tags named after the matching ID3v2 frames.
+fn parse_id3v1(data: &[u8]) -> Vec<SimpleTag> {
+    let field = |range: std::ops::Range<usize>| {
+        let value = data[range].split(|&byte| byte == 0).next().unwrap_or_default();
+        decode_text(0, value).trim_end().to_string()
+    };
+    let fields = [("TIT2", 3..33), ("TPE1", 33..63), ("TALB", 63..93), ("TYER", 93..97), ("COMM", 97..127)];
+    let mut tags: Vec<SimpleTag> = fields.into_iter()
+        .map(|(name, range)| text_tag(name, field(range)))
+        .filter(|tag| tag.value.as_ref().is_some_and(|value| !value.is_empty()))
+        .collect();
+    // ID3v1.1 stores the track number at the end of the comment
+    if data[125] == 0 && data[126] != 0 {
+        tags.push(text_tag("TRCK", data[126].to_string()));
+    }
+    if data[127] != 0xFF {
+        tags.push(text_tag("TCON", format!("({})", data[127])));
+    }
+    tags
+}
+
+/// Parses the items of an APEv2 tag, text items become tags named after their keys.
+fn parse_ape_items(data: &[u8], count: usize) -> Vec<SimpleTag> {
+    let mut tags = Vec::new();
+    let mut pos = 0;
+    for _ in 0..count {
+        let Some(header) = data.get(pos..pos + 8) else {
+            break;
+        };
+        let size = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let flags = u32::from_le_bytes(header[4..].try_into().unwrap());
+        pos += 8;
+        let Some(key_len) = data[pos..].iter().position(|&byte| byte == 0) else {
+            break;
+        };
+        let key = String::from_utf8_lossy(&data[pos..pos + key_len]).into_owned();
+        pos += key_len + 1;
+        let Some(value) = data.get(pos..pos.saturating_add(size)) else {
+            break;
+        };
+        pos += size;
+        // the item type is in bits 1-2, 0 is UTF-8 text
+        if flags & 0x06 == 0 {
+            tags.push(text_tag(&key, String::from_utf8_lossy(value).replace('\0', "; ")));
+        }
+    }
+    tags
+}
+
+/// Reads the ID3v1 and APEv2 tags ending at `end`, returns the position where they start and the tags.
+/// The reader position is changed.
+pub(super) fn read_trailing_tags<S: MediaIoBufRead + ?Sized>(
+    reader: &mut S,
+    mut end: usize,
+) -> Result<(usize, Vec<SimpleTag>)> {
+    let mut id3v1 = Vec::new();
+    if end >= ID3V1_SIZE {
+        let mut data = [0u8; ID3V1_SIZE];
+        reader.seek(end - ID3V1_SIZE)?;
+        reader.get_bytes(&mut data)?;
+        if data.starts_with(b"TAG") {
+            id3v1 = parse_id3v1(&data);
+            end -= ID3V1_SIZE;
+        }
+    }
+
+    let mut tags = Vec::new();
+    if end >= APE_FOOTER_SIZE {
+        let mut footer = [0u8; APE_FOOTER_SIZE];
+        reader.seek(end - APE_FOOTER_SIZE)?;
+        reader.get_bytes(&mut footer)?;
+        let size = u32::from_le_bytes(footer[12..16].try_into().unwrap()) as usize;
+        if footer.starts_with(b"APETAGEX") && (APE_FOOTER_SIZE..=end.min(MAX_ID3V2_SIZE)).contains(&size) {
+            let count = u32::from_le_bytes(footer[16..20].try_into().unwrap()) as usize;
+            let has_header = u32::from_le_bytes(footer[20..24].try_into().unwrap()) & 0x8000_0000 != 0;
+            let mut items = vec![0u8; size - APE_FOOTER_SIZE];
+            reader.seek(end - size)?;
+            reader.get_bytes(&mut items)?;
+            tags = parse_ape_items(&items, count);
+            end = (end - size).saturating_sub(if has_header { APE_FOOTER_SIZE } else { 0 });
+        }
+    }
+    tags.extend(id3v1);
+    Ok((end, tags))
+}
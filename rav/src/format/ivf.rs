@@ -0,0 +1,175 @@
+use crate::codec::{CodecId, MediaType};
+use crate::data::{IoRef, Packet2};
+use crate::error::{decode_error, end_of_stream_error, limit_error, unsupported_error, Error, Result};
+
+use super::av1::SequenceHeader;
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+const HEADER_LEN: usize = 32;
+const FRAME_HEADER_LEN: usize = 12;
+
+/// Largest frame.
+const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// True if a VP9 frame is a key frame, from the start of its uncompressed header.
+fn vp9_keyframe(frame: &[u8]) -> bool {
+    let Some(&first) = frame.first() else {
+        return false;
+    };
+    // the frame marker and profile bits are followed by a reserved bit in profile 3, then
+    // show_existing_frame and frame_type
+    let profile = (first >> 5) & 0x01 | (first >> 3) & 0x02;
+    let frame_type_bit = if profile == 3 { 1 } else { 2 };
+    first >> 6 == 2 && (first >> frame_type_bit) & 0x03 == 0
+}
+
+/// Probe score of the first bytes of an input, the IVF signature and version 0.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    if data.starts_with(b"DKIF\x00\x00") { PROBE_SCORE_MAX } else { 0 }
+}
+
+/// Demuxer for IVF files, holding VP8, VP9 or AV1 frames with 64 bit timestamps, as used for codec
+/// conformance streams. AV1 frames are temporal units in the low overhead format, the `av1C` record
+/// of their first sequence header is the codec parameters.
+pub struct DemuxerIvf<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    sequence_header: Option<SequenceHeader>,
+}
+
+impl<S: MediaIoBufRead> DemuxerIvf<S> {
+    /// Creates the demuxer, reading the file header.
+    pub fn new(mut iobuf_reader: S) -> Result<Self> {
+        let mut header = [0u8; HEADER_LEN];
+        iobuf_reader.get_bytes(&mut header)?;
+        let u16_le = |pos: usize| u16::from_le_bytes([header[pos], header[pos + 1]]);
+        let u32_le = |pos: usize| u32::from_le_bytes(header[pos..pos + 4].try_into().unwrap());
+        if !header.starts_with(b"DKIF") {
+            return decode_error("ivf: no signature");
+        }
+        iobuf_reader.skip((u16_le(6) as usize).saturating_sub(HEADER_LEN))?;
+        let codec_id = match &header[8..12] {
+            b"VP80" => CodecId::Vp8,
+            b"VP90" => CodecId::Vp9,
+            b"AV01" => CodecId::Av1,
+            _ => return unsupported_error("ivf: codec"),
+        };
+        // the time base is stored as a rate and a scale
+        let (rate, scale) = (u32_le(16), u32_le(20));
+        if rate == 0 || scale == 0 {
+            return decode_error("ivf: invalid time base");
+        }
+
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: vec![Stream {
+                codec_id,
+                media_type: MediaType::Video,
+                time_base: TimeBase::new(scale, rate),
+                width: u16_le(12) as u32,
+                height: u16_le(14) as u32,
+                ..Default::default()
+            }],
+            sequence_header: None,
+        };
+        if codec_id == CodecId::Av1 {
+            demuxer.find_sequence_header()?;
+        }
+        Ok(demuxer)
+    }
+
+    /// Looks for the sequence header in the first frame, without consuming it.
+    fn find_sequence_header(&mut self) -> Result<()> {
+        let mut frame_header = [0u8; FRAME_HEADER_LEN];
+        let mut frame = Vec::new();
+        match self.iobuf_reader.peek_bytes(&mut frame_header) {
+            Ok(()) => {
+                let size = u32::from_le_bytes(frame_header[..4].try_into().unwrap()) as usize;
+                if size > MAX_FRAME_SIZE {
+                    return limit_error("ivf: frame too large");
+                }
+                frame.resize(FRAME_HEADER_LEN + size, 0);
+                self.iobuf_reader.peek_bytes(&mut frame)?;
+            }
+            Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        self.sequence_header = SequenceHeader::find(&frame[FRAME_HEADER_LEN..]);
+        if let Some(sequence_header) = &self.sequence_header {
+            self.streams[0].codec_params = sequence_header.av1c.clone();
+        }
+        Ok(())
+    }
+
+    fn is_keyframe(&self, frame: &[u8]) -> bool {
+        match self.streams[0].codec_id {
+            // the first bit of the frame tag is clear for key frames
+            CodecId::Vp8 => frame.first().is_some_and(|&first| first & 0x01 == 0),
+            CodecId::Vp9 => vp9_keyframe(frame),
+            _ => self.sequence_header.as_ref().is_some_and(|header| header.is_keyframe(frame)),
+        }
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerIvf<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        packet.pos = self.iobuf_reader.pos() as i64;
+        let mut frame_header = [0u8; FRAME_HEADER_LEN];
+        match self.iobuf_reader.get_bytes(&mut frame_header) {
+            Ok(()) => {}
+            Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return end_of_stream_error(),
+            Err(err) => return Err(err),
+        }
+        let size = u32::from_le_bytes(frame_header[..4].try_into().unwrap()) as usize;
+        if size > MAX_FRAME_SIZE {
+            return limit_error("ivf: frame too large");
+        }
+        self.iobuf_reader.get_ioref(&mut packet.ioref, size)?;
+        packet.stream_id = 0;
+        packet.pts = i64::from_le_bytes(frame_header[4..].try_into().unwrap());
+        packet.dts = packet.pts;
+        packet.duration = 0;
+        packet.keyframe = self.is_keyframe(packet.data());
+        Ok(())
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+
+    #[test]
+    fn vp9_frames() {
+        let mut header = [&b"DKIF\x00\x00\x20\x00VP90"[..], &320u16.to_le_bytes(), &240u16.to_le_bytes()].concat();
+        header.extend_from_slice(&[&30000u32.to_le_bytes()[..], &1001u32.to_le_bytes(), &3u32.to_le_bytes(), &[0; 4]].concat());
+        // a key frame, an inter frame, and a profile 3 key frame
+        let frames: [&[u8]; 3] = [&[0x82, 0x49, 0x83, 0x42], &[0x86, 0x00], &[0xB1, 0x00, 0x00]];
+        let mut data = header;
+        for (i, frame) in frames.iter().enumerate() {
+            data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(i as u64 * 2).to_le_bytes());
+            data.extend_from_slice(frame);
+        }
+
+        let mut demuxer = DemuxerIvf::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 4096))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.width, stream.height), (CodecId::Vp9, 320, 240));
+        assert_eq!(stream.time_base, TimeBase::new(1001, 30000));
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.pts, packet.keyframe, packet.pos, packet.data().to_vec()));
+        }
+        assert_eq!(packets, vec![
+            (0, true, 32, frames[0].to_vec()),
+            (2, false, 48, frames[1].to_vec()),
+            (4, true, 62, frames[2].to_vec()),
+        ]);
+    }
+}
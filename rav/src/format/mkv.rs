@@ -0,0 +1,1225 @@
+use std::collections::VecDeque;
+
+use crate::codec::{CodecId, MediaType};
+use crate::data::{IoRef, Packet2, NOPTS_VALUE};
+use crate::ebml::{parse_vint, EbmlRead};
+use crate::error::{decode_error, end_of_stream_error, invalid_input_error, unsupported_error, Error, Result};
+use crate::metadata::{Attachment, Chapter, ChapterDisplay, Edition, SimpleTag, Tag};
+
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+// EBML header
+const EBML: u32 = 0x1A45DFA3;
+const DOC_TYPE: u32 = 0x4282;
+
+// top level elements
+const SEGMENT: u32 = 0x18538067;
+const SEEK_HEAD: u32 = 0x114D9B74;
+const INFO: u32 = 0x1549A966;
+const TRACKS: u32 = 0x1654AE6B;
+const CLUSTER: u32 = 0x1F43B675;
+const CUES: u32 = 0x1C53BB6B;
+const CHAPTERS: u32 = 0x1043A770;
+const TAGS: u32 = 0x1254C367;
+const ATTACHMENTS: u32 = 0x1941A469;
+
+// SeekHead
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+
+// Info
+const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const DURATION: u32 = 0x4489;
+
+// Tracks
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const LANGUAGE: u32 = 0x22B59C;
+const DEFAULT_DURATION: u32 = 0x23E383;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const BIT_DEPTH: u32 = 0x6264;
+
+// Cluster
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const BLOCK_DURATION: u32 = 0x9B;
+const REFERENCE_BLOCK: u32 = 0xFB;
+
+// Cues
+const CUE_POINT: u32 = 0xBB;
+const CUE_TIME: u32 = 0xB3;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_TRACK: u32 = 0xF7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+
+// Chapters
+const EDITION_ENTRY: u32 = 0x45B9;
+const EDITION_UID: u32 = 0x45BC;
+const EDITION_FLAG_HIDDEN: u32 = 0x45BD;
+const EDITION_FLAG_DEFAULT: u32 = 0x45DB;
+const EDITION_FLAG_ORDERED: u32 = 0x45DD;
+const CHAPTER_ATOM: u32 = 0xB6;
+const CHAPTER_UID: u32 = 0x73C4;
+const CHAPTER_STRING_UID: u32 = 0x5654;
+const CHAPTER_TIME_START: u32 = 0x91;
+const CHAPTER_TIME_END: u32 = 0x92;
+const CHAPTER_FLAG_HIDDEN: u32 = 0x98;
+const CHAPTER_FLAG_ENABLED: u32 = 0x4598;
+const CHAPTER_DISPLAY: u32 = 0x80;
+const CHAP_STRING: u32 = 0x85;
+const CHAP_LANGUAGE: u32 = 0x437C;
+const CHAP_COUNTRY: u32 = 0x437E;
+
+// Tags
+const TAG: u32 = 0x7373;
+const TARGETS: u32 = 0x63C0;
+const TARGET_TYPE_VALUE: u32 = 0x68CA;
+const TARGET_TYPE: u32 = 0x63CA;
+const TAG_TRACK_UID: u32 = 0x63C5;
+const TAG_EDITION_UID: u32 = 0x63C9;
+const TAG_CHAPTER_UID: u32 = 0x63C4;
+const TAG_ATTACHMENT_UID: u32 = 0x63C6;
+const SIMPLE_TAG: u32 = 0x67C8;
+const TAG_NAME: u32 = 0x45A3;
+const TAG_LANGUAGE: u32 = 0x447A;
+const TAG_DEFAULT: u32 = 0x4484;
+const TAG_STRING: u32 = 0x4487;
+const TAG_BINARY: u32 = 0x4485;
+
+// Attachments
+const ATTACHED_FILE: u32 = 0x61A7;
+const FILE_DESCRIPTION: u32 = 0x467E;
+const FILE_NAME: u32 = 0x466E;
+const FILE_MEDIA_TYPE: u32 = 0x4660;
+const FILE_DATA: u32 = 0x465C;
+const FILE_UID: u32 = 0x46AE;
+
+const TRACK_TYPE_VIDEO: u64 = 1;
+const TRACK_TYPE_AUDIO: u64 = 2;
+const TRACK_TYPE_SUBTITLE: u64 = 17;
+
+fn is_top_level(id: u32) -> bool {
+    matches!(id, SEEK_HEAD | INFO | TRACKS | CLUSTER | CUES | CHAPTERS | TAGS | ATTACHMENTS)
+}
+
+fn codec_from_mkv(codec: &str, bit_depth: u16) -> CodecId {
+    match codec {
+        "V_MPEG4/ISO/AVC" => CodecId::H264,
+        "V_MPEGH/ISO/HEVC" => CodecId::Hevc,
+        "V_VP8" => CodecId::Vp8,
+        "V_VP9" => CodecId::Vp9,
+        "V_AV1" => CodecId::Av1,
+        "V_MPEG1" => CodecId::Mpeg1Video,
+        "V_MPEG2" => CodecId::Mpeg2Video,
+        "V_MPEG4/ISO/SP" | "V_MPEG4/ISO/ASP" | "V_MPEG4/ISO/AP" => CodecId::Mpeg4,
+        "V_THEORA" => CodecId::Theora,
+        "A_MPEG/L1" => CodecId::Mp1,
+        "A_MPEG/L2" => CodecId::Mp2,
+        "A_MPEG/L3" => CodecId::Mp3,
+        "A_AC3" => CodecId::Ac3,
+        "A_EAC3" => CodecId::Eac3,
+        "A_DTS" => CodecId::Dts,
+        "A_TRUEHD" => CodecId::TrueHd,
+        "A_OPUS" => CodecId::Opus,
+        "A_VORBIS" => CodecId::Vorbis,
+        "A_FLAC" => CodecId::Flac,
+        "A_PCM/INT/LIT" => match bit_depth {
+            8 => CodecId::PcmU8,
+            24 => CodecId::PcmS24Le,
+            32 => CodecId::PcmS32Le,
+            _ => CodecId::PcmS16Le,
+        },
+        "A_PCM/INT/BIG" => match bit_depth {
+            8 => CodecId::PcmS8,
+            24 => CodecId::PcmS24Be,
+            32 => CodecId::PcmS32Be,
+            _ => CodecId::PcmS16Be,
+        },
+        "A_PCM/FLOAT/IEEE" => match bit_depth {
+            64 => CodecId::PcmF64Le,
+            _ => CodecId::PcmF32Le,
+        },
+        "S_TEXT/UTF8" => CodecId::SubRip,
+        "S_TEXT/SSA" | "S_TEXT/ASS" => CodecId::Ass,
+        "S_TEXT/WEBVTT" => CodecId::WebVtt,
+        "S_HDMV/PGS" => CodecId::Pgs,
+        "S_VOBSUB" => CodecId::DvdSubtitle,
+        "S_DVBSUB" => CodecId::DvbSubtitle,
+        _ if codec.starts_with("A_AAC") => CodecId::Aac,
+        _ => CodecId::Unknown,
+    }
+}
+
+/// Entry of the seek index, either from Cues or built by scanning the clusters.
+#[derive(Debug, Clone, Copy)]
+struct CuePoint {
+    /// Timestamp in TimestampScale units.
+    time: i64,
+    track: u64,
+    /// Absolute position of the cluster.
+    cluster_pos: usize,
+}
+
+/// A Block or SimpleBlock with the properties collected from its BlockGroup.
+struct Block {
+    data: IoRef,
+    /// Keyframe flag from the BlockGroup, `None` for SimpleBlocks which carry the flag in the block header.
+    keyframe: Option<bool>,
+    duration: Option<u64>,
+}
+
+/// Probe score of the first bytes of an input, the EBML magic with a Matroska or WebM doctype.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    if !data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return 0;
+    }
+    // the doctype is in the EBML header, a few dozen bytes long
+    let header = &data[..data.len().min(64)];
+    if header.windows(8).any(|w| w == b"matroska") || header.windows(4).any(|w| w == b"webm") {
+        PROBE_SCORE_MAX
+    } else {
+        PROBE_SCORE_MAX / 2
+    }
+}
+
+pub struct DemuxerMkv<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    /// Matroska track number of each stream.
+    track_numbers: Vec<u64>,
+    /// Matroska track UID of each stream.
+    track_uids: Vec<u64>,
+    /// Default frame duration of each stream in TimestampScale units, 0 if unknown.
+    default_durations: Vec<u64>,
+    timestamp_scale: u64,
+    /// Segment duration in TimestampScale units, 0 if unknown.
+    duration: i64,
+    /// Absolute position of the Segment data, positions in SeekHead and Cues are relative to it.
+    segment_start: usize,
+    segment_end: Option<usize>,
+    /// Top level elements referenced by SeekHead, with their absolute positions.
+    seek_head: Vec<(u32, usize)>,
+    first_cluster_pos: usize,
+    /// Seek index, loaded on the first seek.
+    cues: Option<Vec<CuePoint>>,
+    /// The seek index was built by scanning the clusters, it has every keyframe track.
+    clusters_indexed: bool,
+    chapters: Vec<Edition>,
+    tags: Vec<Tag>,
+    attachments: Vec<Attachment>,
+    /// Positions of the Chapters, Tags and Attachments elements already parsed.
+    metadata_positions: Vec<usize>,
+    in_cluster: bool,
+    cluster_pos: usize,
+    cluster_end: Option<usize>,
+    cluster_ts: i64,
+    /// Frames of a laced block waiting to be returned.
+    pending: VecDeque<Packet2>,
+    /// After a seek, packets are dropped until a keyframe of this stream at or after this timestamp.
+    seek_target: Option<(usize, i64)>,
+}
+
+impl<S: MediaIoBufRead> DemuxerMkv<S> {
+    /// Creates the demuxer, reading the headers up to the first Cluster.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            track_numbers: Vec::new(),
+            track_uids: Vec::new(),
+            default_durations: Vec::new(),
+            timestamp_scale: 1_000_000,
+            duration: 0,
+            segment_start: 0,
+            segment_end: None,
+            seek_head: Vec::new(),
+            first_cluster_pos: 0,
+            cues: None,
+            clusters_indexed: false,
+            chapters: Vec::new(),
+            tags: Vec::new(),
+            attachments: Vec::new(),
+            metadata_positions: Vec::new(),
+            in_cluster: false,
+            cluster_pos: 0,
+            cluster_end: None,
+            cluster_ts: 0,
+            pending: VecDeque::new(),
+            seek_target: None,
+        };
+        demuxer.read_headers()?;
+        Ok(demuxer)
+    }
+
+    /// Reads the header of a child element which must have a known size.
+    fn read_child(&mut self) -> Result<(u32, usize)> {
+        let header = self.iobuf_reader.read_element_header()?;
+        Ok((header.id, header.data_size()?))
+    }
+
+    fn read_headers(&mut self) -> Result<()> {
+        let header = self.iobuf_reader.read_element_header()?;
+        if header.id != EBML {
+            return decode_error("mkv: missing EBML header");
+        }
+        let end = self.iobuf_reader.pos() + header.data_size()?;
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                DOC_TYPE => {
+                    let doc_type = self.iobuf_reader.read_string(size)?;
+                    if doc_type != "matroska" && doc_type != "webm" {
+                        return unsupported_error("mkv: unknown DocType");
+                    }
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+
+        let header = self.iobuf_reader.read_element_header()?;
+        if header.id != SEGMENT {
+            return decode_error("mkv: missing Segment");
+        }
+        self.segment_start = self.iobuf_reader.pos();
+        self.segment_end = header.size.map(|size| self.segment_start + size as usize);
+
+        loop {
+            let pos = self.iobuf_reader.pos();
+            if Some(pos) == self.segment_end {
+                break;
+            }
+            let header = match self.iobuf_reader.read_element_header() {
+                Ok(header) => header,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            };
+            if header.id == CLUSTER {
+                self.first_cluster_pos = pos;
+                self.enter_cluster(pos, header.size);
+                break;
+            }
+
+            let id = header.id;
+            let size = header.data_size()?;
+            let end = self.iobuf_reader.pos() + size;
+            match id {
+                SEEK_HEAD => self.parse_seek_head(end)?,
+                INFO => self.parse_info(end)?,
+                TRACKS => self.parse_tracks(end)?,
+                CUES => self.cues = Some(self.parse_cues(end)?),
+                CHAPTERS | TAGS | ATTACHMENTS => self.parse_metadata(id, pos, end)?,
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+
+        if self.streams.is_empty() {
+            return decode_error("mkv: no tracks");
+        }
+
+        self.load_metadata()?;
+        for tag in self.tags.iter_mut() {
+            tag.target.stream_ids = tag.target.track_uids.iter()
+                .filter_map(|uid| self.track_uids.iter().position(|track_uid| track_uid == uid))
+                .collect();
+        }
+
+        let time_base = match u32::try_from(self.timestamp_scale) {
+            Ok(scale) if scale > 0 => TimeBase::new(scale, 1_000_000_000),
+            _ => return unsupported_error("mkv: TimestampScale"),
+        };
+        for (stream, default_duration) in self.streams.iter_mut().zip(self.default_durations.iter_mut()) {
+            stream.time_base = time_base;
+            stream.duration = self.duration;
+            *default_duration /= self.timestamp_scale;
+        }
+        Ok(())
+    }
+
+    fn parse_seek_head(&mut self, end: usize) -> Result<()> {
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            if id != SEEK {
+                self.iobuf_reader.skip(size)?;
+                continue;
+            }
+
+            let seek_end = self.iobuf_reader.pos() + size;
+            let mut seek_id = 0;
+            let mut seek_pos = None;
+            while self.iobuf_reader.pos() < seek_end {
+                let (id, size) = self.read_child()?;
+                match id {
+                    SEEK_ID => seek_id = self.iobuf_reader.read_unsigned(size)? as u32,
+                    SEEK_POSITION => seek_pos = Some(self.iobuf_reader.read_unsigned(size)? as usize),
+                    _ => self.iobuf_reader.skip(size)?,
+                }
+            }
+            if let Some(seek_pos) = seek_pos {
+                self.seek_head.push((seek_id, self.segment_start + seek_pos));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_info(&mut self, end: usize) -> Result<()> {
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                TIMESTAMP_SCALE => self.timestamp_scale = self.iobuf_reader.read_unsigned(size)?,
+                DURATION => self.duration = self.iobuf_reader.read_float(size)? as i64,
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_tracks(&mut self, end: usize) -> Result<()> {
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                TRACK_ENTRY => {
+                    let entry_end = self.iobuf_reader.pos() + size;
+                    self.parse_track_entry(entry_end)?;
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_track_entry(&mut self, end: usize) -> Result<()> {
+        let mut stream = Stream { id: self.streams.len(), language: Some("eng".into()), ..Default::default() };
+        let mut track_number = 0;
+        let mut track_uid = 0;
+        let mut track_type = 0;
+        let mut codec = String::new();
+        let mut default_duration = 0;
+
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                TRACK_NUMBER => track_number = self.iobuf_reader.read_unsigned(size)?,
+                TRACK_UID => track_uid = self.iobuf_reader.read_unsigned(size)?,
+                TRACK_TYPE => track_type = self.iobuf_reader.read_unsigned(size)?,
+                CODEC_ID => codec = self.iobuf_reader.read_string(size)?,
+                CODEC_PRIVATE => stream.codec_params = self.iobuf_reader.read_binary(size)?,
+                LANGUAGE => stream.language = Some(self.iobuf_reader.read_string(size)?),
+                DEFAULT_DURATION => default_duration = self.iobuf_reader.read_unsigned(size)?,
+                VIDEO | AUDIO => {
+                    let sub_end = self.iobuf_reader.pos() + size;
+                    while self.iobuf_reader.pos() < sub_end {
+                        let (id, size) = self.read_child()?;
+                        match id {
+                            PIXEL_WIDTH => stream.width = self.iobuf_reader.read_unsigned(size)? as u32,
+                            PIXEL_HEIGHT => stream.height = self.iobuf_reader.read_unsigned(size)? as u32,
+                            SAMPLING_FREQUENCY => stream.sample_rate = self.iobuf_reader.read_float(size)? as u32,
+                            CHANNELS => stream.channels = self.iobuf_reader.read_unsigned(size)? as u16,
+                            BIT_DEPTH => stream.bits_per_sample = self.iobuf_reader.read_unsigned(size)? as u16,
+                            _ => self.iobuf_reader.skip(size)?,
+                        }
+                    }
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+
+        if track_number == 0 {
+            return decode_error("mkv: missing TrackNumber");
+        }
+        stream.codec_id = codec_from_mkv(&codec, stream.bits_per_sample);
+        stream.media_type = match track_type {
+            TRACK_TYPE_VIDEO => MediaType::Video,
+            TRACK_TYPE_AUDIO => MediaType::Audio,
+            TRACK_TYPE_SUBTITLE => MediaType::Subtitle,
+            _ => stream.codec_id.media_type(),
+        };
+        self.streams.push(stream);
+        self.track_numbers.push(track_number);
+        self.track_uids.push(track_uid);
+        self.default_durations.push(default_duration);
+        Ok(())
+    }
+
+    fn parse_cues(&mut self, end: usize) -> Result<Vec<CuePoint>> {
+        let mut cues = Vec::new();
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            if id != CUE_POINT {
+                self.iobuf_reader.skip(size)?;
+                continue;
+            }
+
+            let point_end = self.iobuf_reader.pos() + size;
+            let mut time = 0;
+            let mut positions = Vec::new();
+            while self.iobuf_reader.pos() < point_end {
+                let (id, size) = self.read_child()?;
+                match id {
+                    CUE_TIME => time = self.iobuf_reader.read_unsigned(size)? as i64,
+                    CUE_TRACK_POSITIONS => {
+                        let positions_end = self.iobuf_reader.pos() + size;
+                        let mut track = 0;
+                        let mut cluster_pos = None;
+                        while self.iobuf_reader.pos() < positions_end {
+                            let (id, size) = self.read_child()?;
+                            match id {
+                                CUE_TRACK => track = self.iobuf_reader.read_unsigned(size)?,
+                                CUE_CLUSTER_POSITION => cluster_pos = Some(self.iobuf_reader.read_unsigned(size)? as usize),
+                                _ => self.iobuf_reader.skip(size)?,
+                            }
+                        }
+                        if let Some(cluster_pos) = cluster_pos {
+                            positions.push((track, self.segment_start + cluster_pos));
+                        }
+                    }
+                    _ => self.iobuf_reader.skip(size)?,
+                }
+            }
+            cues.extend(positions.into_iter().map(|(track, cluster_pos)| CuePoint { time, track, cluster_pos }));
+        }
+        cues.sort_by_key(|cue| cue.time);
+        Ok(cues)
+    }
+
+    /// Parses a Chapters, Tags or Attachments element starting at `pos`, with data ending at `end`.
+    fn parse_metadata(&mut self, id: u32, pos: usize, end: usize) -> Result<()> {
+        while self.iobuf_reader.pos() < end {
+            let (child_id, size) = self.read_child()?;
+            let child_end = self.iobuf_reader.pos() + size;
+            match (id, child_id) {
+                (CHAPTERS, EDITION_ENTRY) => {
+                    let edition = self.parse_edition(child_end)?;
+                    self.chapters.push(edition);
+                }
+                (TAGS, TAG) => {
+                    let tag = self.parse_tag(child_end)?;
+                    self.tags.push(tag);
+                }
+                (ATTACHMENTS, ATTACHED_FILE) => {
+                    let attachment = self.parse_attached_file(child_end)?;
+                    self.attachments.push(attachment);
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        self.metadata_positions.push(pos);
+        Ok(())
+    }
+
+    /// Loads the Chapters, Tags and Attachments referenced by SeekHead which were not found before
+    /// the first Cluster, the stream position is restored afterwards.
+    fn load_metadata(&mut self) -> Result<()> {
+        let positions: Vec<(u32, usize)> = self.seek_head.iter()
+            .filter(|(id, pos)| matches!(*id, CHAPTERS | TAGS | ATTACHMENTS) && !self.metadata_positions.contains(pos))
+            .copied()
+            .collect();
+        if positions.is_empty() {
+            return Ok(());
+        }
+
+        let resume_pos = self.iobuf_reader.pos();
+        for (seek_id, pos) in positions {
+            match self.iobuf_reader.seek(pos) {
+                Ok(()) => {}
+                // not seekable input, metadata after the clusters is not available
+                Err(Error::Unsupported(_)) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+            let header = self.iobuf_reader.read_element_header()?;
+            if header.id != seek_id {
+                return decode_error("mkv: invalid SeekHead position");
+            }
+            let end = self.iobuf_reader.pos() + header.data_size()?;
+            self.parse_metadata(seek_id, pos, end)?;
+        }
+        self.iobuf_reader.seek(resume_pos)
+    }
+
+    fn parse_edition(&mut self, end: usize) -> Result<Edition> {
+        let mut edition = Edition::default();
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                EDITION_UID => edition.uid = self.iobuf_reader.read_unsigned(size)?,
+                EDITION_FLAG_HIDDEN => edition.hidden = self.iobuf_reader.read_unsigned(size)? != 0,
+                EDITION_FLAG_DEFAULT => edition.default = self.iobuf_reader.read_unsigned(size)? != 0,
+                EDITION_FLAG_ORDERED => edition.ordered = self.iobuf_reader.read_unsigned(size)? != 0,
+                CHAPTER_ATOM => {
+                    let chapter_end = self.iobuf_reader.pos() + size;
+                    let chapter = self.parse_chapter_atom(chapter_end)?;
+                    edition.chapters.push(chapter);
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        Ok(edition)
+    }
+
+    fn parse_chapter_atom(&mut self, end: usize) -> Result<Chapter> {
+        let mut chapter = Chapter::default();
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                CHAPTER_UID => chapter.uid = self.iobuf_reader.read_unsigned(size)?,
+                CHAPTER_STRING_UID => chapter.string_uid = Some(self.iobuf_reader.read_string(size)?),
+                CHAPTER_TIME_START => chapter.start = self.iobuf_reader.read_unsigned(size)?,
+                CHAPTER_TIME_END => chapter.end = Some(self.iobuf_reader.read_unsigned(size)?),
+                CHAPTER_FLAG_HIDDEN => chapter.hidden = self.iobuf_reader.read_unsigned(size)? != 0,
+                CHAPTER_FLAG_ENABLED => chapter.enabled = self.iobuf_reader.read_unsigned(size)? != 0,
+                CHAPTER_DISPLAY => {
+                    let display_end = self.iobuf_reader.pos() + size;
+                    let mut display = ChapterDisplay::default();
+                    while self.iobuf_reader.pos() < display_end {
+                        let (id, size) = self.read_child()?;
+                        match id {
+                            CHAP_STRING => display.string = self.iobuf_reader.read_string(size)?,
+                            CHAP_LANGUAGE => display.language = Some(self.iobuf_reader.read_string(size)?),
+                            CHAP_COUNTRY => display.country = Some(self.iobuf_reader.read_string(size)?),
+                            _ => self.iobuf_reader.skip(size)?,
+                        }
+                    }
+                    chapter.displays.push(display);
+                }
+                CHAPTER_ATOM => {
+                    let child_end = self.iobuf_reader.pos() + size;
+                    let child = self.parse_chapter_atom(child_end)?;
+                    chapter.children.push(child);
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        Ok(chapter)
+    }
+
+    fn parse_tag(&mut self, end: usize) -> Result<Tag> {
+        let mut tag = Tag::default();
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                TARGETS => {
+                    let targets_end = self.iobuf_reader.pos() + size;
+                    while self.iobuf_reader.pos() < targets_end {
+                        let (id, size) = self.read_child()?;
+                        let target = &mut tag.target;
+                        match id {
+                            TARGET_TYPE_VALUE => target.type_value = self.iobuf_reader.read_unsigned(size)?,
+                            TARGET_TYPE => target.target_type = Some(self.iobuf_reader.read_string(size)?),
+                            TAG_TRACK_UID => target.track_uids.push(self.iobuf_reader.read_unsigned(size)?),
+                            TAG_EDITION_UID => target.edition_uids.push(self.iobuf_reader.read_unsigned(size)?),
+                            TAG_CHAPTER_UID => target.chapter_uids.push(self.iobuf_reader.read_unsigned(size)?),
+                            TAG_ATTACHMENT_UID => target.attachment_uids.push(self.iobuf_reader.read_unsigned(size)?),
+                            _ => self.iobuf_reader.skip(size)?,
+                        }
+                    }
+                }
+                SIMPLE_TAG => {
+                    let simple_tag_end = self.iobuf_reader.pos() + size;
+                    let simple_tag = self.parse_simple_tag(simple_tag_end)?;
+                    tag.simple_tags.push(simple_tag);
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        Ok(tag)
+    }
+
+    fn parse_simple_tag(&mut self, end: usize) -> Result<SimpleTag> {
+        let mut simple_tag = SimpleTag { default: true, ..Default::default() };
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                TAG_NAME => simple_tag.name = self.iobuf_reader.read_string(size)?,
+                TAG_LANGUAGE => simple_tag.language = Some(self.iobuf_reader.read_string(size)?),
+                TAG_DEFAULT => simple_tag.default = self.iobuf_reader.read_unsigned(size)? != 0,
+                TAG_STRING => simple_tag.value = Some(self.iobuf_reader.read_string(size)?),
+                TAG_BINARY => simple_tag.binary = Some(self.iobuf_reader.read_binary(size)?),
+                SIMPLE_TAG => {
+                    let child_end = self.iobuf_reader.pos() + size;
+                    let child = self.parse_simple_tag(child_end)?;
+                    simple_tag.children.push(child);
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        Ok(simple_tag)
+    }
+
+    fn parse_attached_file(&mut self, end: usize) -> Result<Attachment> {
+        let mut attachment = Attachment::default();
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                FILE_UID => attachment.uid = self.iobuf_reader.read_unsigned(size)?,
+                FILE_NAME => attachment.filename = self.iobuf_reader.read_string(size)?,
+                FILE_MEDIA_TYPE => attachment.mime_type = self.iobuf_reader.read_string(size)?,
+                FILE_DESCRIPTION => attachment.description = Some(self.iobuf_reader.read_string(size)?),
+                // copied once into a buffer of the file size: attachments live as long as the demuxer, an IoRef
+                // into the ring would keep its IoBufs from being recycled
+                FILE_DATA => attachment.data = IoRef::from(self.iobuf_reader.read_binary(size)?),
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        Ok(attachment)
+    }
+
+    fn enter_cluster(&mut self, pos: usize, size: Option<u64>) {
+        self.in_cluster = true;
+        self.cluster_pos = pos;
+        self.cluster_end = size.map(|size| self.iobuf_reader.pos() + size as usize);
+        self.cluster_ts = 0;
+    }
+
+    /// Reads the next Block or SimpleBlock, crossing cluster boundaries and skipping other elements.
+    fn read_block(&mut self) -> Result<Block> {
+        loop {
+            let pos = self.iobuf_reader.pos();
+            if self.in_cluster && Some(pos) == self.cluster_end {
+                self.in_cluster = false;
+            }
+            if Some(pos) == self.segment_end {
+                return end_of_stream_error();
+            }
+
+            let header = self.iobuf_reader.read_element_header()?;
+            if header.id == CLUSTER {
+                // also ends a previous cluster of unknown size
+                self.enter_cluster(pos, header.size);
+                continue;
+            }
+            if !self.in_cluster || is_top_level(header.id) {
+                self.in_cluster = false;
+                self.iobuf_reader.skip(header.data_size()?)?;
+                continue;
+            }
+
+            let size = header.data_size()?;
+            match header.id {
+                TIMESTAMP => self.cluster_ts = self.iobuf_reader.read_unsigned(size)? as i64,
+                SIMPLE_BLOCK => {
+                    let mut data = IoRef::default();
+                    self.iobuf_reader.get_ioref(&mut data, size)?;
+                    return Ok(Block { data, keyframe: None, duration: None });
+                }
+                BLOCK_GROUP => {
+                    if let Some(block) = self.read_block_group(size)? {
+                        return Ok(block);
+                    }
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+    }
+
+    fn read_block_group(&mut self, size: usize) -> Result<Option<Block>> {
+        let end = self.iobuf_reader.pos() + size;
+        let mut data = None;
+        let mut keyframe = true;
+        let mut duration = None;
+        while self.iobuf_reader.pos() < end {
+            let (id, size) = self.read_child()?;
+            match id {
+                BLOCK => {
+                    let mut ioref = IoRef::default();
+                    self.iobuf_reader.get_ioref(&mut ioref, size)?;
+                    data = Some(ioref);
+                }
+                BLOCK_DURATION => duration = Some(self.iobuf_reader.read_unsigned(size)?),
+                REFERENCE_BLOCK => {
+                    self.iobuf_reader.skip(size)?;
+                    keyframe = false;
+                }
+                _ => self.iobuf_reader.skip(size)?,
+            }
+        }
+        Ok(data.map(|data| Block { data, keyframe: Some(keyframe), duration }))
+    }
+
+    /// Splits a block into its frames and queues them as packets.
+    fn queue_block(&mut self, mut block: Block) -> Result<()> {
+        let data = block.data.data();
+        let Some((track, width)) = parse_vint(data) else {
+            return decode_error("mkv: invalid block track number");
+        };
+        if data.len() < width + 3 {
+            return decode_error("mkv: block too short");
+        }
+        let Some(stream_id) = self.track_numbers.iter().position(|&number| number == track) else {
+            // block of an unknown track
+            return Ok(());
+        };
+        let timestamp = self.cluster_ts + i16::from_be_bytes([data[width], data[width + 1]]) as i64;
+        let flags = data[width + 2];
+        let keyframe = block.keyframe.unwrap_or(flags & 0x80 != 0);
+        let mut pos = width + 3;
+
+        let mut frame_sizes = Vec::new();
+        let lacing = (flags >> 1) & 0x03;
+        if lacing != 0 {
+            let Some(&count) = data.get(pos) else {
+                return decode_error("mkv: invalid lacing");
+            };
+            let count = count as usize + 1;
+            pos += 1;
+            match lacing {
+                // Xiph lacing
+                1 => {
+                    for _ in 0..count - 1 {
+                        let mut size = 0;
+                        loop {
+                            let Some(&b) = data.get(pos) else {
+                                return decode_error("mkv: invalid xiph lacing");
+                            };
+                            pos += 1;
+                            size += b as usize;
+                            if b != 255 {
+                                break;
+                            }
+                        }
+                        frame_sizes.push(size);
+                    }
+                }
+                // EBML lacing, sizes after the first one are coded as signed differences, each size is
+                // bounded by the block so the sum of the sizes cannot overflow
+                3 => {
+                    let Some((first, width)) = parse_vint(&data[pos..]) else {
+                        return decode_error("mkv: invalid ebml lacing");
+                    };
+                    if first > data.len() as u64 {
+                        return decode_error("mkv: invalid ebml lacing");
+                    }
+                    pos += width;
+                    let mut size = first as i64;
+                    frame_sizes.push(first as usize);
+                    for _ in 1..count - 1 {
+                        let Some((diff, width)) = parse_vint(&data[pos..]) else {
+                            return decode_error("mkv: invalid ebml lacing");
+                        };
+                        pos += width;
+                        size += diff as i64 - ((1i64 << (7 * width - 1)) - 1);
+                        if size < 0 || size > data.len() as i64 {
+                            return decode_error("mkv: invalid ebml lacing");
+                        }
+                        frame_sizes.push(size as usize);
+                    }
+                }
+                // fixed size lacing
+                _ => {
+                    let len = data.len() - pos;
+                    if !len.is_multiple_of(count) {
+                        return decode_error("mkv: invalid fixed lacing");
+                    }
+                    frame_sizes.resize(count - 1, len / count);
+                }
+            }
+            let laced: usize = frame_sizes.iter().sum();
+            if pos + laced > data.len() {
+                return decode_error("mkv: invalid lacing");
+            }
+            frame_sizes.push(data.len() - pos - laced);
+        } else {
+            frame_sizes.push(data.len() - pos);
+        }
+
+        let default_duration = self.default_durations[stream_id] as i64;
+        let duration = match block.duration {
+            Some(duration) => duration as i64 / frame_sizes.len() as i64,
+            None => default_duration,
+        };
+        block.data.make_shared();
+        for (i, size) in frame_sizes.into_iter().enumerate() {
+            let pts = if i == 0 {
+                timestamp
+            } else if duration > 0 {
+                timestamp + duration * i as i64
+            } else {
+                NOPTS_VALUE
+            };
+            self.pending.push_back(Packet2 {
+                ioref: block.data.slice(pos, size),
+                stream_id,
+                pts,
+                dts: NOPTS_VALUE,
+                duration,
+                keyframe: keyframe && i == 0,
+                pos: -1,
+            });
+            pos += size;
+        }
+        Ok(())
+    }
+
+    /// Loads the seek index from Cues, or builds it by scanning the clusters when Cues are missing.
+    fn load_cues(&mut self) -> Result<()> {
+        if self.clusters_indexed || self.cues.as_ref().is_some_and(|cues| !cues.is_empty()) {
+            return Ok(());
+        }
+
+        if let Some(&(_, pos)) = self.seek_head.iter().find(|(id, _)| *id == CUES) {
+            self.iobuf_reader.seek(pos)?;
+            let header = self.iobuf_reader.read_element_header()?;
+            if header.id == CUES {
+                let end = self.iobuf_reader.pos() + header.data_size()?;
+                let cues = self.parse_cues(end)?;
+                if !cues.is_empty() {
+                    self.cues = Some(cues);
+                    return Ok(());
+                }
+            }
+        }
+
+        self.cues = Some(self.build_index()?);
+        self.clusters_indexed = true;
+        Ok(())
+    }
+
+    /// Scans all clusters and indexes the first keyframe of every track in each cluster.
+    fn build_index(&mut self) -> Result<Vec<CuePoint>> {
+        self.iobuf_reader.seek(self.first_cluster_pos)?;
+        self.in_cluster = false;
+
+        let mut cues: Vec<CuePoint> = Vec::new();
+        loop {
+            let block = match self.read_block() {
+                Ok(block) => block,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            };
+            let data = block.data.data();
+            let Some((track, width)) = parse_vint(data) else {
+                continue;
+            };
+            if data.len() < width + 3 {
+                continue;
+            }
+            let keyframe = block.keyframe.unwrap_or(data[width + 2] & 0x80 != 0);
+            let indexed = cues.iter().rev()
+                .take_while(|cue| cue.cluster_pos == self.cluster_pos)
+                .any(|cue| cue.track == track);
+            if keyframe && !indexed {
+                let time = self.cluster_ts + i16::from_be_bytes([data[width], data[width + 1]]) as i64;
+                cues.push(CuePoint { time, track, cluster_pos: self.cluster_pos });
+            }
+        }
+        cues.sort_by_key(|cue| cue.time);
+        Ok(cues)
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerMkv<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        loop {
+            while let Some(next) = self.pending.pop_front() {
+                if let Some((stream_id, timestamp)) = self.seek_target {
+                    if next.stream_id != stream_id || !next.keyframe || next.pts < timestamp {
+                        continue;
+                    }
+                    self.seek_target = None;
+                }
+                *packet = next;
+                return Ok(());
+            }
+
+            let block = self.read_block()?;
+            self.queue_block(block)?;
+        }
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn seek(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        let Some(&track) = self.track_numbers.get(stream_id) else {
+            return invalid_input_error();
+        };
+        self.load_cues()?;
+        let has_track = |cues: &Option<Vec<CuePoint>>| cues.iter().flatten().any(|cue| cue.track == track);
+        if !has_track(&self.cues) && !self.clusters_indexed {
+            // the Cues of the file don't reference this track
+            self.cues = Some(self.build_index()?);
+            self.clusters_indexed = true;
+        }
+
+        let cues = self.cues.as_deref().unwrap_or_default();
+        let track_cues = || cues.iter().filter(|cue| cue.track == track);
+        let Some(cue) = track_cues().take_while(|cue| cue.time <= timestamp).last().or(track_cues().next()) else {
+            return unsupported_error("mkv: seek in a track without keyframes");
+        };
+        let (time, cluster_pos) = (cue.time, cue.cluster_pos);
+
+        self.iobuf_reader.seek(cluster_pos)?;
+        self.in_cluster = false;
+        self.pending.clear();
+        self.seek_target = Some((stream_id, time));
+        Ok(time)
+    }
+
+    fn chapters(&self) -> &[Edition] {
+        &self.chapters
+    }
+
+    fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+
+    const UNKNOWN_SIZE: u64 = u64::MAX;
+
+    fn el_sized(id: u32, size: u64, data: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = id.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        if size == UNKNOWN_SIZE {
+            out.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        } else {
+            out.push(0x01);
+            out.extend_from_slice(&size.to_be_bytes()[1..]);
+        }
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn el(id: u32, data: &[u8]) -> Vec<u8> {
+        el_sized(id, data.len() as u64, data)
+    }
+
+    fn uint(id: u32, value: u64) -> Vec<u8> {
+        el(id, &value.to_be_bytes())
+    }
+
+    fn simple_block(track: u8, timestamp: i16, keyframe: bool, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x80 | track];
+        data.extend_from_slice(&timestamp.to_be_bytes());
+        data.push(if keyframe { 0x80 } else { 0x00 });
+        data.extend_from_slice(payload);
+        el(SIMPLE_BLOCK, &data)
+    }
+
+    fn track_entry(number: u64, track_type: u64, codec: &str) -> Vec<u8> {
+        el(TRACK_ENTRY, &[uint(TRACK_NUMBER, number), uint(TRACK_TYPE, track_type), el(CODEC_ID, codec.as_bytes())].concat())
+    }
+
+    fn cluster(timestamp: u64, blocks: &[Vec<u8>], unknown_size: bool) -> Vec<u8> {
+        let data = [uint(TIMESTAMP, timestamp), blocks.concat()].concat();
+        el_sized(CLUSTER, if unknown_size { UNKNOWN_SIZE } else { data.len() as u64 }, &data)
+    }
+
+    /// Three clusters, one second each, with a video and an audio track.
+    fn build_file(with_cues: bool, unknown_size_clusters: bool) -> Vec<u8> {
+        let header = el(EBML, &el(DOC_TYPE, b"matroska"));
+        let info = el(INFO, &uint(TIMESTAMP_SCALE, 1_000_000));
+        let tracks = el(TRACKS, &[track_entry(1, TRACK_TYPE_VIDEO, "V_MPEG4/ISO/AVC"), track_entry(2, TRACK_TYPE_AUDIO, "A_OPUS")].concat());
+
+        let mut clusters = Vec::new();
+        let mut cluster_offsets = Vec::new();
+        for c in 0..3u8 {
+            cluster_offsets.push(clusters.len());
+            clusters.extend(cluster(c as u64 * 1000, &[
+                simple_block(2, 0, true, &[c, 2]),
+                simple_block(1, 0, true, &[c, 0]),
+                simple_block(1, 500, false, &[c, 1]),
+                simple_block(2, 500, true, &[c, 3]),
+            ], unknown_size_clusters));
+        }
+
+        let seek_head_len = el(SEEK_HEAD, &el(SEEK, &[el(SEEK_ID, &CUES.to_be_bytes()), uint(SEEK_POSITION, 0)].concat())).len();
+        let before_clusters = if with_cues { seek_head_len } else { 0 } + info.len() + tracks.len();
+
+        let mut segment = Vec::new();
+        if with_cues {
+            let cues_pos = before_clusters + clusters.len();
+            segment.extend(el(SEEK_HEAD, &el(SEEK, &[el(SEEK_ID, &CUES.to_be_bytes()), uint(SEEK_POSITION, cues_pos as u64)].concat())));
+        }
+        segment.extend(info);
+        segment.extend(tracks);
+        segment.extend(clusters);
+        if with_cues {
+            let points: Vec<u8> = cluster_offsets.iter().enumerate().flat_map(|(c, offset)| {
+                el(CUE_POINT, &[
+                    uint(CUE_TIME, c as u64 * 1000),
+                    el(CUE_TRACK_POSITIONS, &[uint(CUE_TRACK, 1), uint(CUE_CLUSTER_POSITION, (before_clusters + offset) as u64)].concat()),
+                ].concat())
+            }).collect();
+            segment.extend(el(CUES, &points));
+        }
+
+        [header, el(SEGMENT, &segment)].concat()
+    }
+
+    fn open(data: Vec<u8>) -> DemuxerMkv<MediaSourceStream<IoBufSupplierMem>> {
+        DemuxerMkv::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 16))).unwrap()
+    }
+
+    #[test]
+    fn read_headers_and_packets() {
+        let mut demuxer = open(build_file(true, false));
+        assert_eq!(demuxer.streams().len(), 2);
+        assert_eq!(demuxer.streams()[0].codec_id, CodecId::H264);
+        assert_eq!(demuxer.streams()[1].codec_id, CodecId::Opus);
+        assert_eq!(demuxer.streams()[1].media_type, MediaType::Audio);
+        assert_eq!(demuxer.streams()[0].time_base, TimeBase::new(1_000_000, 1_000_000_000));
+
+        let mut packet = Packet2::default();
+        let mut count = 0;
+        while demuxer.read_packet(&mut packet).is_ok() {
+            count += 1;
+            if packet.stream_id == 0 {
+                assert_eq!(packet.data()[1] == 0, packet.keyframe);
+                assert_eq!(packet.pts, packet.data()[0] as i64 * 1000 + packet.data()[1] as i64 * 500);
+            }
+        }
+        assert_eq!(count, 12);
+    }
+
+    #[test]
+    fn seek_with_cues() {
+        let mut demuxer = open(build_file(true, false));
+        assert_eq!(demuxer.seek(0, 1600), Ok(1000));
+
+        let mut packet = Packet2::default();
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!((packet.stream_id, packet.pts, packet.keyframe), (0, 1000, true));
+        assert_eq!(packet.data(), &[1, 0]);
+
+        assert_eq!(demuxer.seek(0, 99999), Ok(2000));
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!(packet.pts, 2000);
+
+        // the cues only reference the video track, the audio track is indexed from the clusters
+        assert_eq!(demuxer.seek(1, 2200), Ok(2000));
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!((packet.stream_id, packet.pts), (1, 2000));
+        assert_eq!(demuxer.seek(2, 0), Err(Error::InvalidInput));
+    }
+
+    #[test]
+    fn seek_builds_index_without_cues() {
+        let mut demuxer = open(build_file(false, true));
+        assert_eq!(demuxer.seek(1, 2200), Ok(2000));
+
+        let mut packet = Packet2::default();
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!((packet.stream_id, packet.pts), (1, 2000));
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!((packet.stream_id, packet.pts), (0, 2000));
+        assert_eq!(demuxer.cues.as_ref().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn ebml_laced_block() {
+        let header = el(EBML, &el(DOC_TYPE, b"webm"));
+        let tracks = el(TRACKS, &el(TRACK_ENTRY, &[
+            uint(TRACK_NUMBER, 1), uint(TRACK_TYPE, TRACK_TYPE_AUDIO), el(CODEC_ID, b"A_VORBIS"), uint(DEFAULT_DURATION, 20_000_000),
+        ].concat()));
+        // 3 frames of 2, 4 and 1 bytes, the second size is coded as +2 difference
+        let mut block = vec![0x81, 0x00, 0x10, 0x80 | 0x06, 2, 0x82, 0xC1];
+        block.extend_from_slice(&[1, 1, 2, 2, 2, 2, 3]);
+        let segment = [tracks.clone(), cluster(100, &[el(SIMPLE_BLOCK, &block)], false)].concat();
+        let mut demuxer = open([header.clone(), el(SEGMENT, &segment)].concat());
+
+        let mut packet = Packet2::default();
+        for (pts, data) in [(116, &[1u8, 1][..]), (136, &[2, 2, 2, 2]), (156, &[3])] {
+            demuxer.read_packet(&mut packet).unwrap();
+            assert_eq!(packet.pts, pts);
+            assert_eq!(packet.duration, 20);
+            assert_eq!(packet.data(), data);
+        }
+        assert!(demuxer.read_packet(&mut packet).is_err());
+
+        // 255 frames with the largest size differences, the sizes would overflow without the bound
+        let mut block = vec![0x81, 0x00, 0x10, 0x80 | 0x06, 0xFE, 0x81];
+        for _ in 0..253 {
+            block.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        }
+        let segment = [tracks, cluster(100, &[el(SIMPLE_BLOCK, &block)], false)].concat();
+        let mut demuxer = open([header, el(SEGMENT, &segment)].concat());
+        assert_eq!(demuxer.read_packet(&mut packet), Err(Error::DecodeError("mkv: invalid ebml lacing")));
+    }
+
+    #[test]
+    fn chapters_tags_and_attachments() {
+        let header = el(EBML, &el(DOC_TYPE, b"matroska"));
+        let tracks = el(TRACKS, &el(TRACK_ENTRY, &[
+            uint(TRACK_NUMBER, 1), uint(TRACK_UID, 0xABCD), uint(TRACK_TYPE, TRACK_TYPE_SUBTITLE), el(CODEC_ID, b"S_TEXT/ASS"),
+        ].concat()));
+        let display = |string: &str, language: &str| el(CHAPTER_DISPLAY, &[el(CHAP_STRING, string.as_bytes()), el(CHAP_LANGUAGE, language.as_bytes())].concat());
+        let chapters = el(CHAPTERS, &el(EDITION_ENTRY, &[
+            uint(EDITION_UID, 7),
+            uint(EDITION_FLAG_DEFAULT, 1),
+            el(CHAPTER_ATOM, &[
+                uint(CHAPTER_UID, 1),
+                uint(CHAPTER_TIME_START, 0),
+                uint(CHAPTER_TIME_END, 60_000_000_000),
+                display("Intro", "eng"),
+                display("Einleitung", "ger"),
+                el(CHAPTER_ATOM, &[uint(CHAPTER_UID, 2), uint(CHAPTER_TIME_START, 30_000_000_000), display("Credits", "eng")].concat()),
+            ].concat()),
+        ].concat()));
+        let clusters = cluster(0, &[simple_block(1, 0, true, b"text")], false);
+        let tags = el(TAGS, &el(TAG, &[
+            el(TARGETS, &[uint(TARGET_TYPE_VALUE, 30), uint(TAG_TRACK_UID, 0xABCD)].concat()),
+            el(SIMPLE_TAG, &[
+                el(TAG_NAME, b"TITLE"),
+                el(TAG_STRING, b"Signs"),
+                el(SIMPLE_TAG, &[el(TAG_NAME, b"SORT_WITH"), el(TAG_STRING, b"signs")].concat()),
+            ].concat()),
+        ].concat()));
+        let attachments = el(ATTACHMENTS, &el(ATTACHED_FILE, &[
+            el(FILE_NAME, b"font.ttf"), el(FILE_MEDIA_TYPE, b"font/ttf"), uint(FILE_UID, 99), el(FILE_DATA, b"\x00\x01\x00\x00"),
+        ].concat()));
+
+        let seek = |id: u32, pos: usize| el(SEEK, &[el(SEEK_ID, &id.to_be_bytes()), uint(SEEK_POSITION, pos as u64)].concat());
+        let seek_head_len = el(SEEK_HEAD, &[seek(TAGS, 0), seek(ATTACHMENTS, 0)].concat()).len();
+        let tags_pos = seek_head_len + tracks.len() + chapters.len() + clusters.len();
+        let attachments_pos = tags_pos + tags.len();
+        let seek_head = el(SEEK_HEAD, &[seek(TAGS, tags_pos), seek(ATTACHMENTS, attachments_pos)].concat());
+        let segment = [seek_head, tracks, chapters, clusters, tags, attachments].concat();
+        let mut demuxer = open([header, el(SEGMENT, &segment)].concat());
+
+        let edition = &demuxer.chapters()[0];
+        assert_eq!((edition.uid, edition.default), (7, true));
+        let intro = &edition.chapters[0];
+        assert_eq!(intro.end, Some(60_000_000_000));
+        assert_eq!(intro.displays.len(), 2);
+        assert_eq!(intro.displays[1].string, "Einleitung");
+        assert_eq!(intro.displays[1].language.as_deref(), Some("ger"));
+        assert_eq!(intro.children[0].start, 30_000_000_000);
+        assert_eq!(intro.children[0].displays[0].string, "Credits");
+
+        let tag = &demuxer.tags()[0];
+        assert_eq!(tag.target.type_value, 30);
+        assert_eq!(tag.target.stream_ids, vec![0]);
+        assert_eq!(tag.simple_tags[0].name, "TITLE");
+        assert_eq!(tag.simple_tags[0].value.as_deref(), Some("Signs"));
+        assert_eq!(tag.simple_tags[0].children[0].name, "SORT_WITH");
+
+        let attachment = &demuxer.attachments()[0];
+        assert_eq!((attachment.filename.as_str(), attachment.mime_type.as_str(), attachment.uid), ("font.ttf", "font/ttf", 99));
+        assert_eq!(attachment.data.data(), b"\x00\x01\x00\x00");
+
+        // reading resumes at the first cluster
+        let mut packet = Packet2::default();
+        demuxer.read_packet(&mut packet).unwrap();
+        assert_eq!(packet.data(), b"text");
+    }
+}
@@ -0,0 +1,387 @@
+use crate::codec::MediaType;
+use crate::data::{IoRef, Packet2};
+use crate::error::{decode_error, end_of_stream_error, invalid_input_error, unsupported_error, Error, Result};
+use crate::metadata::Tag;
+
+use super::id3::{read_id3v2, read_trailing_tags, skip_id3v2};
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+mod header;
+mod xing;
+
+use header::FrameHeader;
+use xing::{Toc, VbrInfo};
+
+const HEADER_LEN: usize = 4;
+
+/// Probe score of the first bytes of an input, from the number of consecutive frames of one stream.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    let tagged = skip_id3v2(data);
+    let id3 = tagged.len() < data.len();
+    let data = tagged;
+    let Some(first) = FrameHeader::parse(data) else {
+        // ID3v2 tags also precede other formats
+        return if id3 { PROBE_SCORE_MAX / 4 } else { 0 };
+    };
+    let mut pos = 0;
+    let mut frames = 0;
+    while let Some(header) = data.get(pos..).and_then(FrameHeader::parse).filter(|header| header.same_stream(&first)) {
+        frames += 1;
+        pos += header.frame_len;
+    }
+    // a truncated last frame is expected at the end of the probe data
+    if pos >= data.len() || frames >= 4 {
+        PROBE_SCORE_MAX * 9 / 10
+    } else if id3 {
+        PROBE_SCORE_MAX / 2
+    } else {
+        PROBE_SCORE_MAX / 4
+    }
+}
+
+/// Demuxer for MPEG audio layer I, II and III elementary streams, as in `.mp3` files. ID3v2 tags are
+/// read at the start, ID3v1 and APEv2 tags at the end when the input is seekable. The Xing, Info or
+/// VBRI header gives the duration, the encoder delay and padding, and the seek table. Timestamps
+/// include the encoder delay, which `encoder_delay` returns to trim it.
+pub struct DemuxerMp3<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    tags: Vec<Tag>,
+    /// Header of the first frame, following frames must have the same version, layer and sample rate.
+    first_header: FrameHeader,
+    /// Position of the first frame, which may be the VBR header frame.
+    first_frame_pos: usize,
+    /// Position of the first audio frame.
+    audio_start: usize,
+    /// Position of the trailing tags, `None` if unknown.
+    data_end: Option<usize>,
+    vbr_info: Option<VbrInfo>,
+    /// False after data was skipped, until a frame is confirmed by the next header.
+    synced: bool,
+    next_pts: i64,
+    /// Copy of the frame header and the following one.
+    frame: Vec<u8>,
+}
+
+impl<S: MediaIoBufRead> DemuxerMp3<S> {
+    /// Creates the demuxer, reading the tags and the first frame.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            tags: Vec::new(),
+            first_header: FrameHeader::default(),
+            first_frame_pos: 0,
+            audio_start: 0,
+            data_end: None,
+            vbr_info: None,
+            synced: false,
+            next_pts: 0,
+            frame: Vec::new(),
+        };
+        let mut simple_tags = read_id3v2(&mut demuxer.iobuf_reader)?;
+        if let Some(len) = demuxer.iobuf_reader.stream_len() {
+            let start = demuxer.iobuf_reader.pos();
+            let (end, trailing_tags) = read_trailing_tags(&mut demuxer.iobuf_reader, len)?;
+            simple_tags.extend(trailing_tags);
+            demuxer.data_end = Some(end);
+            demuxer.iobuf_reader.seek(start)?;
+        }
+        if !simple_tags.is_empty() {
+            demuxer.tags = vec![Tag { simple_tags, ..Default::default() }];
+        }
+
+        let header = match demuxer.sync(None) {
+            Ok(header) => header,
+            Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return decode_error("mp3: no frames"),
+            Err(err) => return Err(err),
+        };
+        demuxer.first_header = header;
+        demuxer.first_frame_pos = demuxer.iobuf_reader.pos();
+        demuxer.frame.truncate(header.frame_len);
+        demuxer.vbr_info = VbrInfo::parse(&demuxer.frame, &header);
+        if demuxer.vbr_info.is_some() {
+            demuxer.iobuf_reader.skip(header.frame_len)?;
+        }
+        demuxer.audio_start = demuxer.iobuf_reader.pos();
+
+        let duration = match (&demuxer.vbr_info, demuxer.data_end) {
+            (Some(VbrInfo { frames: Some(frames), .. }), _) => *frames as i64 * header.samples as i64,
+            // constant bitrate
+            (None, Some(end)) => {
+                let bits = end.saturating_sub(demuxer.audio_start) as u64 * 8;
+                (bits * header.sample_rate as u64 / header.bitrate as u64) as i64
+            }
+            _ => 0,
+        };
+        demuxer.streams.push(Stream {
+            codec_id: header.codec_id(),
+            media_type: MediaType::Audio,
+            time_base: TimeBase::new(1, header.sample_rate),
+            duration,
+            sample_rate: header.sample_rate,
+            channels: header.channels,
+            ..Default::default()
+        });
+        Ok(demuxer)
+    }
+
+    /// Samples added by the encoder at the start of the stream, from the LAME or VBRI header.
+    pub fn encoder_delay(&self) -> u32 {
+        self.vbr_info.as_ref().map_or(0, |info| info.delay)
+    }
+
+    /// Samples added by the encoder at the end of the stream, from the LAME header.
+    pub fn encoder_padding(&self) -> u32 {
+        self.vbr_info.as_ref().map_or(0, |info| info.padding)
+    }
+
+    /// Moves to the next valid frame header and copies the frame header followed by the next one when
+    /// the sync has to be confirmed. `first` is the header that following frames must match.
+    fn sync(&mut self, first: Option<FrameHeader>) -> Result<FrameHeader> {
+        loop {
+            let pos = self.iobuf_reader.pos();
+            if self.data_end.is_some_and(|end| pos + HEADER_LEN > end) {
+                return Err(Error::IoError(std::io::ErrorKind::UnexpectedEof));
+            }
+            let mut data = [0u8; HEADER_LEN];
+            self.iobuf_reader.peek_bytes(&mut data)?;
+            let header = FrameHeader::parse(&data).filter(|header| first.is_none_or(|first| first.same_stream(header)));
+            let Some(header) = header else {
+                self.synced = false;
+                self.iobuf_reader.skip(1)?;
+                continue;
+            };
+            if self.data_end.is_some_and(|end| pos + header.frame_len > end) {
+                // a frame truncated by the trailing tags
+                return Err(Error::IoError(std::io::ErrorKind::UnexpectedEof));
+            }
+
+            // the last frame before the trailing tags isn't followed by a header
+            let at_end = self.data_end == Some(pos + header.frame_len);
+            self.frame.resize(header.frame_len + HEADER_LEN, 0);
+            let confirmed = match self.iobuf_reader.peek_bytes(&mut self.frame) {
+                Ok(()) => {
+                    let next = FrameHeader::parse(&self.frame[header.frame_len..]);
+                    next.is_some_and(|next| next.same_stream(&header))
+                }
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => {
+                    self.frame.truncate(header.frame_len);
+                    match self.iobuf_reader.peek_bytes(&mut self.frame) {
+                        // the last frame
+                        Ok(()) => true,
+                        // a false sync word near the end
+                        Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) if !self.synced => false,
+                        Err(err) => return Err(err),
+                    }
+                }
+                Err(err) => return Err(err),
+            };
+            if self.synced || confirmed || at_end {
+                self.synced = true;
+                return Ok(header);
+            }
+            self.iobuf_reader.skip(1)?;
+        }
+    }
+
+    /// Returns the position and timestamp to resume reading from for `timestamp`, using the seek
+    /// table of the VBR header, or the bitrate of the first frame.
+    fn seek_position(&self, timestamp: i64) -> (usize, i64) {
+        let samples = self.first_header.samples as i64;
+        let duration = self.streams[0].duration;
+        let timestamp = if duration > 0 { timestamp.clamp(0, duration) } else { timestamp.max(0) };
+        let info = self.vbr_info.as_ref();
+        match (info.and_then(|info| info.toc.as_ref()), info.and_then(|info| info.bytes)) {
+            (Some(Toc::Xing(toc)), Some(bytes)) if duration > 0 => {
+                // interpolates between the positions of the percents of the duration
+                let percent = (timestamp as f64 * 100.0 / duration as f64).min(99.999);
+                let index = percent as usize;
+                let start = toc[index] as f64;
+                let end = toc.get(index + 1).map_or(256.0, |&end| end as f64);
+                let offset = (start + (end - start) * (percent - index as f64)) / 256.0 * bytes as f64;
+                ((self.first_frame_pos + offset as usize).max(self.audio_start), timestamp)
+            }
+            (Some(Toc::Vbri { sizes, frames_per_entry }), _) => {
+                let entry = ((timestamp / samples) as usize / *frames_per_entry as usize).min(sizes.len());
+                let offset: u64 = sizes[..entry].iter().sum();
+                (self.audio_start + offset as usize, entry as i64 * *frames_per_entry as i64 * samples)
+            }
+            _ => {
+                let frame = timestamp / samples;
+                let header = &self.first_header;
+                let frame_len = header.bitrate as f64 * header.samples as f64 / 8.0 / header.sample_rate as f64;
+                (self.audio_start + (frame as f64 * frame_len) as usize, frame * samples)
+            }
+        }
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerMp3<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        let header = match self.sync(Some(self.first_header)) {
+            Ok(header) => header,
+            Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return end_of_stream_error(),
+            Err(err) => return Err(err),
+        };
+        packet.pos = self.iobuf_reader.pos() as i64;
+        self.iobuf_reader.get_ioref(&mut packet.ioref, header.frame_len)?;
+        packet.stream_id = 0;
+        packet.pts = self.next_pts;
+        packet.dts = self.next_pts;
+        packet.duration = header.samples as i64;
+        packet.keyframe = true;
+        self.next_pts += packet.duration;
+        Ok(())
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn seek(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        if stream_id != 0 {
+            return invalid_input_error();
+        }
+        if self.iobuf_reader.stream_len().is_none() {
+            return unsupported_error("mp3: seek without stream length");
+        }
+        let (pos, timestamp) = self.seek_position(timestamp);
+        self.iobuf_reader.seek(pos)?;
+        // the position may be inside a frame
+        self.synced = false;
+        self.next_pts = timestamp;
+        Ok(timestamp)
+    }
+
+    fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::CodecId;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+
+    /// MPEG-1 layer III, 128 kbit/s, 44.1 kHz stereo frames of 417 bytes.
+    const MPEG1_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+    /// MPEG-2 layer III, 64 kbit/s, 24 kHz mono frames of 192 bytes.
+    const MPEG2_HEADER: [u8; 4] = [0xFF, 0xF3, 0x84, 0xC0];
+
+    fn frame(header: [u8; 4], fill: u8) -> Vec<u8> {
+        let len = FrameHeader::parse(&header).unwrap().frame_len;
+        [&header[..], &vec![fill; len - 4]].concat()
+    }
+
+    fn xing_frame(frames: u32, bytes: u32) -> Vec<u8> {
+        let mut data = frame(MPEG1_HEADER, 0);
+        let toc: Vec<u8> = (0..100).map(|i| (i * 256 / 100) as u8).collect();
+        let mut lame = b"LAME3.100".to_vec();
+        lame.resize(21, 0);
+        lame.extend_from_slice(&(576u32 << 12 | 1000).to_be_bytes()[1..]);
+        let xing = [&b"Xing\0\0\0\x0F"[..], &frames.to_be_bytes(), &bytes.to_be_bytes(), &toc, &[0, 0, 0, 50], &lame].concat();
+        data[36..36 + xing.len()].copy_from_slice(&xing);
+        data
+    }
+
+    #[test]
+    fn vbri_entry_sizes() {
+        let mut data = frame(MPEG1_HEADER, 0);
+        let header = [&b"VBRI\0\x01\x02\x40\0\x50"[..], &1000u32.to_be_bytes(), &20u32.to_be_bytes()].concat();
+        let entries = [&[0, 2, 0xFF, 0xFF, 0, 4, 0, 10][..], &u32::MAX.to_be_bytes(), &1u32.to_be_bytes()].concat();
+        let vbri = [header, entries].concat();
+        data[36..36 + vbri.len()].copy_from_slice(&vbri);
+        let info = VbrInfo::parse(&data, &FrameHeader::parse(&MPEG1_HEADER).unwrap()).unwrap();
+        assert_eq!((info.frames, info.bytes, info.delay), (Some(20), Some(1000), 576));
+        // the scaled sizes exceed 32 bits
+        let sizes = vec![u32::MAX as u64 * 0xFFFF, 0xFFFF];
+        assert_eq!(info.toc, Some(Toc::Vbri { sizes, frames_per_entry: 10 }));
+    }
+
+    fn id3v2_frame(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        [&id[..], &(data.len() as u32).to_be_bytes(), &[0, 0], data].concat()
+    }
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerMp3<S>) -> Vec<(i64, i64, u8)> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.pts, packet.pos, packet.data()[4]));
+        }
+        packets
+    }
+
+    #[test]
+    fn xing_toc_and_tags() {
+        let frames = [
+            id3v2_frame(b"TIT2", b"\x00Song"),
+            id3v2_frame(b"TXXX", b"\x01\xFF\xFEM\0O\0O\0D\0\0\0\xFF\xFEc\0a\0l\0m\0"),
+            id3v2_frame(b"COMM", b"\x00eng\x00Nice"),
+        ].concat();
+        let id3v2 = [&b"ID3\x03\x00\x00\x00\x00"[..], &[(frames.len() >> 7) as u8, (frames.len() & 0x7F) as u8], &frames].concat();
+        let ape_items = [&4u32.to_le_bytes()[..], &0u32.to_le_bytes(), b"Artist\0Band"].concat();
+        let ape_size = (ape_items.len() + 32) as u32;
+        let ape = [&ape_items[..], b"APETAGEX", &2000u32.to_le_bytes(), &ape_size.to_le_bytes(), &1u32.to_le_bytes(), &[0; 12]].concat();
+        let mut id3v1 = vec![0u8; 128];
+        id3v1[..3].copy_from_slice(b"TAG");
+        id3v1[63..68].copy_from_slice(b"Album");
+        id3v1[126] = 3;
+        id3v1[127] = 17;
+
+        let audio: Vec<Vec<u8>> = (1..=20).map(|i| frame(MPEG1_HEADER, i)).collect();
+        let data = [
+            &id3v2[..],
+            &xing_frame(20, 21 * 417),
+            &audio[..5].concat(),
+            b"\xFF\xE0junk",
+            &audio[5..].concat(),
+            &ape,
+            &id3v1,
+        ].concat();
+        let first_frame_pos = id3v2.len();
+        let frame_pos = |i: usize| (first_frame_pos + 417 * (i + 1) + if i >= 5 { 6 } else { 0 }) as i64;
+
+        let mut demuxer = DemuxerMp3::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 4096))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.sample_rate, stream.channels), (CodecId::Mp3, 44100, 2));
+        assert_eq!((stream.duration, stream.time_base), (20 * 1152, TimeBase::new(1, 44100)));
+        assert_eq!((demuxer.encoder_delay(), demuxer.encoder_padding()), (576, 1000));
+        let tags: Vec<_> = demuxer.tags()[0].simple_tags.iter()
+            .map(|tag| (tag.name.as_str(), tag.value.as_deref().unwrap(), tag.language.as_deref()))
+            .collect();
+        assert_eq!(tags, vec![
+            ("TIT2", "Song", None),
+            ("MOOD", "calm", None),
+            ("COMM", "Nice", Some("eng")),
+            ("Artist", "Band", None),
+            ("TALB", "Album", None),
+            ("TRCK", "3", None),
+            ("TCON", "(17)", None),
+        ]);
+
+        let packets = read_all(&mut demuxer);
+        let expected: Vec<_> = (0..20).map(|i| (i as i64 * 1152, frame_pos(i), i as u8 + 1)).collect();
+        assert_eq!(packets, expected);
+
+        // half of the duration is in the middle of the 10th audio frame, reading resumes with the next one
+        assert_eq!(demuxer.seek(0, 10 * 1152), Ok(10 * 1152));
+        assert_eq!(demuxer.seek(1, 0), Err(Error::InvalidInput));
+        assert_eq!(read_all(&mut demuxer)[0], (10 * 1152, frame_pos(10), 11));
+    }
+
+    #[test]
+    fn constant_bitrate_seek() {
+        let data: Vec<u8> = (0..10).flat_map(|i| frame(MPEG2_HEADER, i)).collect();
+        let mut demuxer = DemuxerMp3::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 4096))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.sample_rate, stream.channels, stream.duration), (24000, 1, 10 * 576));
+        assert!(demuxer.tags().is_empty());
+        assert_eq!(read_all(&mut demuxer).len(), 10);
+
+        assert_eq!(demuxer.seek(0, 5 * 576 + 100), Ok(5 * 576));
+        assert_eq!(read_all(&mut demuxer), (5..10).map(|i| (i * 576, i * 192, i as u8)).collect::<Vec<_>>());
+    }
+}
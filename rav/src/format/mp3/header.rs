@@ -0,0 +1,105 @@
+use crate::codec::CodecId;
+
+/// Bitrates in kbit/s, indexed by MPEG-1 / MPEG-2 and the layer.
+const BITRATES: [[[u16; 15]; 3]; 2] = [
+    [
+        [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+        [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    ],
+    [
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ],
+];
+
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// Fields of an MPEG audio frame header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct FrameHeader {
+    /// 1 for MPEG-1, 2 for MPEG-2 and MPEG-2.5.
+    pub version: u8,
+    /// MPEG-2.5 extension of MPEG-2 to lower sample rates.
+    pub mpeg25: bool,
+    pub layer: u8,
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Frame length including the header.
+    pub frame_len: usize,
+    /// Samples per channel in the frame.
+    pub samples: u32,
+}
+
+impl FrameHeader {
+    /// Parses and validates a header, free format frames are not supported.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let header = u32::from_be_bytes(data.get(..4)?.try_into().unwrap());
+        if header >> 21 != 0x7FF {
+            return None;
+        }
+        let (version, mpeg25) = match (header >> 19) & 0x03 {
+            0 => (2, true),
+            2 => (2, false),
+            3 => (1, false),
+            _ => return None,
+        };
+        let layer = match (header >> 17) & 0x03 {
+            0 => return None,
+            bits => 4 - bits as u8,
+        };
+        let bitrate_index = ((header >> 12) & 0x0F) as usize;
+        let sample_rate_index = ((header >> 10) & 0x03) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 || header & 0x03 == 2 {
+            return None;
+        }
+
+        let bitrate = BITRATES[version as usize - 1][layer as usize - 1][bitrate_index] as u32 * 1000;
+        let sample_rate = SAMPLE_RATES[sample_rate_index] >> (version - 1 + mpeg25 as u8);
+        let padding = (header >> 9) & 0x01;
+        let samples = match (layer, version) {
+            (1, _) => 384,
+            (2, _) | (3, 1) => 1152,
+            _ => 576,
+        };
+        let frame_len = match layer {
+            1 => (12 * bitrate / sample_rate + padding) * 4,
+            _ => samples / 8 * bitrate / sample_rate + padding,
+        };
+        Some(FrameHeader {
+            version,
+            mpeg25,
+            layer,
+            bitrate,
+            sample_rate,
+            channels: if (header >> 6) & 0x03 == 3 { 1 } else { 2 },
+            frame_len: frame_len as usize,
+            samples,
+        })
+    }
+
+    /// True if `other` is a header of the same stream, the bitrate may change between frames.
+    pub fn same_stream(&self, other: &FrameHeader) -> bool {
+        self.version == other.version && self.mpeg25 == other.mpeg25 && self.layer == other.layer
+            && self.sample_rate == other.sample_rate
+    }
+
+    pub fn codec_id(&self) -> CodecId {
+        match self.layer {
+            1 => CodecId::Mp1,
+            2 => CodecId::Mp2,
+            _ => CodecId::Mp3,
+        }
+    }
+
+    /// Offset of the data following the layer III side information, where the Xing header is.
+    pub fn side_info_end(&self) -> usize {
+        4 + match (self.version, self.channels) {
+            (1, 1) | (2, 2) => 17,
+            (1, _) => 32,
+            _ => 9,
+        }
+    }
+}
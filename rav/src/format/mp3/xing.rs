@@ -0,0 +1,103 @@
+use super::header::FrameHeader;
+
+fn be_u16(data: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([data[pos], data[pos + 1]])
+}
+
+fn be_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+const XING_FRAMES: u32 = 0x01;
+const XING_BYTES: u32 = 0x02;
+const XING_TOC: u32 = 0x04;
+const XING_QUALITY: u32 = 0x08;
+
+/// Position of the VBRI header from the frame start.
+const VBRI_OFFSET: usize = 36;
+
+/// Seek table of a VBR header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Toc {
+    /// Xing: the byte position of each percent of the duration, in 1/256 of the stream size.
+    Xing([u8; 100]),
+    /// VBRI: the byte size of each group of `frames_per_entry` frames.
+    Vbri { sizes: Vec<u64>, frames_per_entry: u32 },
+}
+
+/// Contents of a Xing, Info or VBRI header, stored in the first frame instead of audio.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct VbrInfo {
+    /// Number of audio frames, excluding the header frame.
+    pub frames: Option<u32>,
+    /// Size of the stream in bytes, including the header frame.
+    pub bytes: Option<u32>,
+    pub toc: Option<Toc>,
+    /// Samples added by the encoder at the start, from the LAME or VBRI header.
+    pub delay: u32,
+    /// Samples added by the encoder at the end, from the LAME header.
+    pub padding: u32,
+}
+
+impl VbrInfo {
+    /// Parses the VBR header of the first frame, `None` if the frame holds audio.
+    pub fn parse(frame: &[u8], header: &FrameHeader) -> Option<Self> {
+        if header.layer != 3 {
+            return None;
+        }
+        let xing = header.side_info_end();
+        let tag = frame.get(xing..xing + 8);
+        if tag.is_some_and(|tag| tag.starts_with(b"Xing") || tag.starts_with(b"Info")) {
+            return Self::parse_xing(&frame[xing..]);
+        }
+        match frame.get(VBRI_OFFSET..VBRI_OFFSET + 26) {
+            Some(vbri) if vbri.starts_with(b"VBRI") => Self::parse_vbri(&frame[VBRI_OFFSET..]),
+            _ => None,
+        }
+    }
+
+    fn parse_xing(data: &[u8]) -> Option<Self> {
+        let flags = be_u32(data, 4);
+        let mut info = VbrInfo::default();
+        let mut pos = 8;
+        let mut field = |flag: u32, len: usize| {
+            let value = (flags & flag != 0).then(|| data.get(pos..pos + len)).flatten();
+            pos += if value.is_some() { len } else { 0 };
+            value
+        };
+        info.frames = field(XING_FRAMES, 4).map(|value| be_u32(value, 0));
+        info.bytes = field(XING_BYTES, 4).map(|value| be_u32(value, 0));
+        info.toc = field(XING_TOC, 100).map(|toc| Toc::Xing(toc.try_into().unwrap()));
+        field(XING_QUALITY, 4);
+
+        // the LAME extension holds the encoder delay and padding, 21 bytes after the encoder version
+        let lame = data.get(pos..pos + 24).filter(|lame| lame.starts_with(b"LAME") || lame.starts_with(b"Lavc"));
+        if let Some(lame) = lame {
+            let value = (lame[21] as u32) << 16 | (lame[22] as u32) << 8 | lame[23] as u32;
+            info.delay = value >> 12;
+            info.padding = value & 0xFFF;
+        }
+        Some(info)
+    }
+
+    fn parse_vbri(data: &[u8]) -> Option<Self> {
+        let entry_count = be_u16(data, 18) as usize;
+        let scale = be_u16(data, 20) as u64;
+        let entry_size = be_u16(data, 22) as usize;
+        let frames_per_entry = be_u16(data, 24) as u32;
+        let sizes = data.get(26..26 + entry_count * entry_size)
+            .filter(|_| (1..=4).contains(&entry_size) && frames_per_entry > 0)
+            .map(|entries| {
+                entries.chunks_exact(entry_size)
+                    .map(|entry| entry.iter().fold(0u64, |value, &byte| value << 8 | byte as u64) * scale)
+                    .collect()
+            });
+        Some(VbrInfo {
+            frames: Some(be_u32(data, 14)),
+            bytes: Some(be_u32(data, 10)),
+            toc: sizes.map(|sizes| Toc::Vbri { sizes, frames_per_entry }),
+            delay: be_u16(data, 6) as u32,
+            padding: 0,
+        })
+    }
+}
@@ -0,0 +1,1100 @@
+use std::collections::VecDeque;
+
+use crate::codec::CodecId;
+use crate::data::{IoRef, Packet2};
+use crate::error::{
+    decode_error, end_of_stream_error, invalid_input_error, limit_error, unsupported_error, Error, Result,
+};
+
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+mod boxes;
+mod edit_list;
+mod fragment;
+mod sample_entry;
+mod sample_table;
+
+use boxes::{
+    read_box_header, BoxReader, CMOV, EDTS, ELST, FTYP, HDLR, MDAT, MDHD, MDIA, MINF, MOOF, MOOV, MVEX, MVHD, SBTL,
+    SIDX, SOUN, STBL, STSD, SUBT, TEXT, TKHD, TRAF, TRAK, TREX, VIDE,
+};
+use edit_list::{parse_elst, timestamp_offset};
+use fragment::{fragment_times, parse_sidx, parse_traf, parse_trex, traf_track_id, TrackDefaults};
+use sample_entry::parse_stsd;
+use sample_table::{Sample, SampleTable};
+
+pub use edit_list::EditListEntry;
+
+/// Largest `moov` or `moof` box loaded in memory.
+const MAX_MOOV_SIZE: u64 = 512 * 1024 * 1024;
+
+fn is_pcm(codec_id: CodecId) -> bool {
+    use CodecId::*;
+    matches!(
+        codec_id,
+        PcmS8 | PcmU8 | PcmS16Le | PcmS16Be | PcmS24Le | PcmS24Be | PcmS32Le | PcmS32Be | PcmF32Le | PcmF32Be
+            | PcmF64Le | PcmF64Be
+    )
+}
+
+/// Decodes the ISO 639-2/T language of an `mdhd` box, packed as three 5-bit letters.
+fn parse_language(code: u16) -> Option<String> {
+    // QuickTime language codes are below 0x400
+    if code < 0x400 || code == 0x7FFF {
+        return None;
+    }
+    let language: String = [code >> 10, code >> 5, code].iter().map(|&c| ((c & 0x1F) as u8 + 0x60) as char).collect();
+    (language != "und").then_some(language)
+}
+
+/// A track with its samples and the index of the next one to read.
+#[derive(Debug)]
+struct Track {
+    track_id: u32,
+    /// Samples of the `moov` sample tables.
+    samples: Vec<Sample>,
+    next: usize,
+    /// Sample defaults of the track fragments.
+    defaults: TrackDefaults,
+    /// Decoding time following the last fragment sample, used when `tfdt` is missing.
+    fragment_dts: i64,
+    edits: Vec<EditListEntry>,
+    /// Offset from the media timestamps to the emitted ones, from the edit list.
+    ts_offset: i64,
+}
+
+/// True if the packets of both streams can be passed to the same decoder.
+fn same_codec(a: &Stream, b: &Stream) -> bool {
+    (a.codec_id, &a.codec_params, a.time_base) == (b.codec_id, &b.codec_params, b.time_base)
+}
+
+/// Start of a fragment or subsegment, used for seeking fragmented input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FragmentEntry {
+    stream_id: usize,
+    /// Time in stream time base units.
+    time: i64,
+    pos: usize,
+}
+
+/// Probe score of the first bytes of an input, from the type of the first box.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    let Some(size) = data.get(..4).map(|size| u32::from_be_bytes(size.try_into().unwrap())) else {
+        return 0;
+    };
+    // a size of 1 is followed by a 64 bit size, 0 extends to the end of the file
+    let valid_size = size == 0 || size == 1 || size >= 8;
+    match data.get(4..8) {
+        Some(b"ftyp" | b"styp" | b"moov") if valid_size => PROBE_SCORE_MAX,
+        Some(b"moof" | b"sidx") if valid_size => PROBE_SCORE_MAX * 3 / 4,
+        Some(b"mdat" | b"free" | b"skip" | b"wide" | b"pnot") if valid_size => PROBE_SCORE_MAX / 2,
+        _ => 0,
+    }
+}
+
+/// MP4/ISOBMFF and QuickTime demuxer, including fragmented MP4 and series of CMAF segments.
+/// Samples are read in file order, so input where `moov` precedes `mdat` is read front to back,
+/// otherwise the input must be seekable.
+pub struct DemuxerMp4<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    tracks: Vec<Track>,
+    major_brand: Option<[u8; 4]>,
+    compatible_brands: Vec<[u8; 4]>,
+    /// `moov` has an `mvex` box, samples are also described by `moof` boxes.
+    fragmented: bool,
+    /// Samples of the current fragment in file order, with their stream index.
+    fragment_samples: VecDeque<(usize, Sample)>,
+    /// Position of the top level box following the current `mdat`, `None` if it extends to the end.
+    next_box_pos: Option<usize>,
+    /// Position of the first `moof`.
+    first_fragment_pos: Option<usize>,
+    /// Fragments from `sidx` boxes and from the `moof` boxes read so far, sorted by position.
+    fragment_index: Vec<FragmentEntry>,
+    /// All the `moof` boxes of the input were indexed.
+    fragment_index_complete: bool,
+    /// After a seek, packets are dropped until a keyframe of this stream.
+    seek_target: Option<usize>,
+}
+
+impl<S: MediaIoBufRead> DemuxerMp4<S> {
+    /// Creates the demuxer, reading the top level boxes up to the samples of the first `mdat`
+    /// following `moov`.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            tracks: Vec::new(),
+            major_brand: None,
+            compatible_brands: Vec::new(),
+            fragmented: false,
+            fragment_samples: VecDeque::new(),
+            next_box_pos: None,
+            first_fragment_pos: None,
+            fragment_index: Vec::new(),
+            fragment_index_complete: false,
+            seek_target: None,
+        };
+        demuxer.read_headers()?;
+        Ok(demuxer)
+    }
+
+    /// Major brand of the `ftyp` box, `None` for QuickTime files without one.
+    pub fn major_brand(&self) -> Option<[u8; 4]> {
+        self.major_brand
+    }
+
+    pub fn compatible_brands(&self) -> &[[u8; 4]] {
+        &self.compatible_brands
+    }
+
+    /// ISOBMFF track ID of a stream.
+    pub fn track_id(&self, stream_id: usize) -> Option<u32> {
+        self.tracks.get(stream_id).map(|track| track.track_id)
+    }
+
+    /// Edit list of a stream as stored in the file.
+    pub fn edit_list(&self, stream_id: usize) -> &[EditListEntry] {
+        self.tracks.get(stream_id).map_or(&[], |track| &track.edits)
+    }
+
+    /// Offset added by the edit list to the media timestamps of a stream, the raw timestamps
+    /// of a packet are its `pts` and `dts` minus this offset.
+    pub fn timestamp_offset(&self, stream_id: usize) -> i64 {
+        self.tracks.get(stream_id).map_or(0, |track| track.ts_offset)
+    }
+
+    /// True for fragmented MP4, where samples are described by `moof` boxes.
+    pub fn is_fragmented(&self) -> bool {
+        self.fragmented
+    }
+
+    /// Reads a box payload into memory.
+    fn read_payload(&mut self, size: u64) -> Result<Vec<u8>> {
+        if size > MAX_MOOV_SIZE {
+            return limit_error("mp4: box too large");
+        }
+        let mut data = vec![0u8; size as usize];
+        self.iobuf_reader.get_bytes(&mut data)?;
+        Ok(data)
+    }
+
+    /// Moves the reader to `pos`, skipping forward without seeking when possible.
+    fn move_to(&mut self, pos: usize) -> Result<()> {
+        let current = self.iobuf_reader.pos();
+        if pos >= current {
+            self.iobuf_reader.skip(pos - current)
+        } else {
+            self.iobuf_reader.seek(pos)
+        }
+    }
+
+    fn read_headers(&mut self) -> Result<()> {
+        let mut moov_found = false;
+        loop {
+            let pos = self.iobuf_reader.pos();
+            let header = match read_box_header(&mut self.iobuf_reader) {
+                Ok(header) => header,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            };
+            match (header.kind, header.size) {
+                // samples follow
+                (MDAT, size) if moov_found => {
+                    self.next_box_pos = size.map(|size| self.iobuf_reader.pos() + size as usize);
+                    break;
+                }
+                (MOOV, Some(size)) => {
+                    let moov = self.read_payload(size)?;
+                    self.parse_moov(BoxReader::new(&moov))?;
+                    moov_found = true;
+                }
+                (MOOF, Some(size)) if moov_found && self.fragmented => self.read_moof(pos, size)?,
+                (SIDX, Some(size)) if moov_found => self.read_sidx(size)?,
+                (FTYP, Some(size)) => {
+                    let ftyp = self.read_payload(size)?;
+                    let mut ftyp = BoxReader::new(&ftyp);
+                    self.major_brand = Some(ftyp.u32()?.to_be_bytes());
+                    ftyp.skip(4)?;
+                    self.compatible_brands = ftyp.rest().chunks_exact(4).map(|brand| brand.try_into().unwrap()).collect();
+                }
+                (_, Some(size)) => self.iobuf_reader.skip(size as usize)?,
+                // the box extends to the end of the file
+                (_, None) => break,
+            }
+        }
+
+        if !moov_found {
+            return decode_error("mp4: missing moov");
+        }
+        if self.streams.is_empty() {
+            return decode_error("mp4: no tracks");
+        }
+        Ok(())
+    }
+
+    fn parse_moov(&mut self, mut moov: BoxReader) -> Result<()> {
+        let movie_timescale = match moov.find_box(MVHD)? {
+            Some(mut mvhd) => {
+                let (version, _) = mvhd.full_box()?;
+                mvhd.skip(if version == 0 { 8 } else { 16 })?;
+                mvhd.u32()?
+            }
+            None => 0,
+        };
+        let first_track = self.tracks.len();
+        let mut defaults = Vec::new();
+        while let Some((kind, mut child)) = moov.next_box()? {
+            match kind {
+                TRAK => self.parse_trak(child, movie_timescale)?,
+                MVEX => {
+                    self.fragmented = true;
+                    while let Some((kind, trex)) = child.next_box()? {
+                        if kind == TREX {
+                            defaults.push(parse_trex(trex)?);
+                        }
+                    }
+                }
+                CMOV => return unsupported_error("mp4: compressed moov"),
+                _ => {}
+            }
+        }
+        for (track_id, defaults) in defaults {
+            if let Some(track) = self.tracks[first_track..].iter_mut().find(|track| track.track_id == track_id) {
+                track.defaults = defaults;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_trak(&mut self, trak: BoxReader, movie_timescale: u32) -> Result<()> {
+        let Some(mut tkhd) = trak.find_box(TKHD)? else {
+            return decode_error("mp4: missing tkhd");
+        };
+        let (version, _) = tkhd.full_box()?;
+        // creation and modification times
+        tkhd.skip(if version == 0 { 8 } else { 16 })?;
+        let track_id = tkhd.u32()?;
+
+        let Some(mdia) = trak.find_box(MDIA)? else {
+            return decode_error("mp4: missing mdia");
+        };
+        let Some(mut mdhd) = mdia.find_box(MDHD)? else {
+            return decode_error("mp4: missing mdhd");
+        };
+        let (version, _) = mdhd.full_box()?;
+        mdhd.skip(if version == 0 { 8 } else { 16 })?;
+        let timescale = mdhd.u32()?;
+        let duration = mdhd.versioned_u64(version)?;
+        let language = parse_language(mdhd.u16()?);
+        if timescale == 0 {
+            return decode_error("mp4: invalid timescale");
+        }
+
+        let Some(mut hdlr) = mdia.find_box(HDLR)? else {
+            return decode_error("mp4: missing hdlr");
+        };
+        hdlr.full_box()?;
+        hdlr.skip(4)?;
+        let handler = hdlr.u32()?;
+        // hint, timecode and metadata tracks are not exposed
+        if !matches!(handler, VIDE | SOUN | SUBT | SBTL | TEXT) {
+            return Ok(());
+        }
+
+        let Some(stbl) = mdia.find_box(MINF)?.map(|minf| minf.find_box(STBL)).transpose()?.flatten() else {
+            return decode_error("mp4: missing stbl");
+        };
+        let Some(stsd) = stbl.find_box(STSD)? else {
+            return decode_error("mp4: missing stsd");
+        };
+        let mut stream = Stream {
+            id: self.streams.len(),
+            time_base: TimeBase::new(1, timescale),
+            duration: duration as i64,
+            language,
+            ..Default::default()
+        };
+        parse_stsd(stsd, handler, &mut stream)?;
+        let input_len = self.iobuf_reader.stream_len().map(|len| len as u64);
+        let samples = SampleTable::parse(&stbl)?.samples(is_pcm(stream.codec_id), input_len)?;
+        let fragment_dts = samples.last().map_or(0, |sample| sample.dts + sample.duration as i64);
+        let edits = match trak.find_box(EDTS)?.map(|edts| edts.find_box(ELST)).transpose()?.flatten() {
+            Some(elst) => parse_elst(elst)?,
+            None => Vec::new(),
+        };
+        let ts_offset = timestamp_offset(&edits, movie_timescale, stream.time_base)?;
+
+        self.streams.push(stream);
+        self.tracks.push(Track {
+            track_id,
+            samples,
+            next: 0,
+            defaults: TrackDefaults::default(),
+            fragment_dts,
+            edits,
+            ts_offset,
+        });
+        Ok(())
+    }
+
+    /// Adds a fragment to the seek index, unless it's already there.
+    fn index_fragment(&mut self, entry: FragmentEntry) {
+        let idx = self.fragment_index.partition_point(|other| (other.pos, other.stream_id) < (entry.pos, entry.stream_id));
+        match self.fragment_index.get(idx) {
+            Some(other) if (other.pos, other.stream_id) == (entry.pos, entry.stream_id) => {}
+            _ => self.fragment_index.insert(idx, entry),
+        }
+    }
+
+    /// Reads a `sidx` box into the seek index, the box header was read.
+    fn read_sidx(&mut self, size: u64) -> Result<()> {
+        let sidx = self.read_payload(size)?;
+        let index = parse_sidx(BoxReader::new(&sidx), self.iobuf_reader.pos() as u64)?;
+        let stream_id = match self.tracks.iter().position(|track| track.track_id == index.reference_id) {
+            Some(stream_id) => stream_id,
+            None if self.tracks.len() == 1 => 0,
+            None => return Ok(()),
+        };
+        let time_base = TimeBase::new(1, index.timescale);
+        for (time, pos) in index.subsegments {
+            let time = time_base.rescale(time, self.streams[stream_id].time_base);
+            self.index_fragment(FragmentEntry { stream_id, time, pos: pos as usize });
+        }
+        Ok(())
+    }
+
+    /// Reads a `moof` box starting at `pos` and queues its samples, the box header was read.
+    fn read_moof(&mut self, pos: usize, size: u64) -> Result<()> {
+        let moof = self.read_payload(size)?;
+        let mut moof = BoxReader::new(&moof);
+        self.first_fragment_pos.get_or_insert(pos);
+
+        let mut samples = Vec::new();
+        let mut data_end = pos as u64;
+        while let Some((kind, traf)) = moof.next_box()? {
+            if kind != TRAF {
+                continue;
+            }
+            let track_id = traf_track_id(&traf)?;
+            let Some(stream_id) = self.tracks.iter().position(|track| track.track_id == track_id) else {
+                continue;
+            };
+            let track = &mut self.tracks[stream_id];
+            let mut traf_samples = Vec::new();
+            parse_traf(&traf, pos as u64, &mut data_end, track.defaults, &mut track.fragment_dts, &mut traf_samples)?;
+            if let Some(first) = traf_samples.first() {
+                let time = first.dts;
+                self.index_fragment(FragmentEntry { stream_id, time, pos });
+            }
+            samples.extend(traf_samples.into_iter().map(|sample| (stream_id, sample)));
+        }
+        samples.sort_by_key(|(_, sample)| sample.pos);
+        self.fragment_samples = samples.into();
+        Ok(())
+    }
+
+    /// Reads a `moov` box following fragments, such as the initialization segment of a new DASH period, the box
+    /// header was read. A track with the codec of the current track of the same ID keeps its stream, other tracks
+    /// are added as new streams.
+    fn read_new_moov(&mut self, size: u64) -> Result<()> {
+        let moov = self.read_payload(size)?;
+        let first_track = self.tracks.len();
+        self.parse_moov(BoxReader::new(&moov))?;
+
+        let tracks: Vec<Track> = self.tracks.drain(first_track..).collect();
+        let streams: Vec<Stream> = self.streams.drain(first_track..).collect();
+        for (track, mut stream) in tracks.into_iter().zip(streams) {
+            let current = self.tracks.iter().position(|current| current.track_id == track.track_id);
+            match current {
+                Some(stream_id) if same_codec(&self.streams[stream_id], &stream) => {
+                    let current = &mut self.tracks[stream_id];
+                    current.defaults = track.defaults;
+                    (current.edits, current.ts_offset) = (track.edits, track.ts_offset);
+                }
+                _ => {
+                    // track ID 0 is invalid, the replaced track no longer matches track fragments
+                    if let Some(stream_id) = current {
+                        self.tracks[stream_id].track_id = 0;
+                    }
+                    stream.id = self.streams.len();
+                    self.streams.push(stream);
+                    self.tracks.push(Track { next: track.samples.len(), ..track });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the top level boxes up to the `mdat` of the next fragment, returns false at the end of the input.
+    fn read_fragment(&mut self) -> Result<bool> {
+        let Some(pos) = self.next_box_pos.take() else {
+            return Ok(false);
+        };
+        self.move_to(pos)?;
+
+        let mut moof_found = false;
+        loop {
+            let pos = self.iobuf_reader.pos();
+            let header = match read_box_header(&mut self.iobuf_reader) {
+                Ok(header) => header,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => {
+                    self.fragment_index_complete = true;
+                    return Ok(false);
+                }
+                Err(err) => return Err(err),
+            };
+            match (header.kind, header.size) {
+                (MOOF, Some(size)) => {
+                    self.read_moof(pos, size)?;
+                    moof_found = true;
+                }
+                (MOOV, Some(size)) => self.read_new_moov(size)?,
+                (MDAT, size) if moof_found => {
+                    self.next_box_pos = size.map(|size| self.iobuf_reader.pos() + size as usize);
+                    return Ok(true);
+                }
+                (SIDX, Some(size)) => self.read_sidx(size)?,
+                (_, Some(size)) => self.iobuf_reader.skip(size as usize)?,
+                (_, None) => return Ok(false),
+            }
+        }
+    }
+
+    /// Indexes the fragments following the last indexed one by reading their `moof` boxes,
+    /// the reading position must be restored by the caller.
+    fn build_fragment_index(&mut self) -> Result<()> {
+        let Some(mut pos) = self.fragment_index.last().map(|entry| entry.pos).or(self.first_fragment_pos) else {
+            self.fragment_index_complete = true;
+            return Ok(());
+        };
+        self.iobuf_reader.seek(pos)?;
+        loop {
+            let header = match read_box_header(&mut self.iobuf_reader) {
+                Ok(header) => header,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            };
+            let Some(size) = header.size else {
+                break;
+            };
+            match header.kind {
+                MOOF => {
+                    let moof = self.read_payload(size)?;
+                    for (track_id, time) in fragment_times(BoxReader::new(&moof))? {
+                        if let Some(stream_id) = self.tracks.iter().position(|track| track.track_id == track_id) {
+                            self.index_fragment(FragmentEntry { stream_id, time, pos });
+                        }
+                    }
+                }
+                SIDX => self.read_sidx(size)?,
+                _ => self.iobuf_reader.skip(size as usize)?,
+            }
+            pos = self.iobuf_reader.pos();
+        }
+        self.fragment_index_complete = true;
+        Ok(())
+    }
+
+    /// Returns the next sample in file order with its stream index.
+    fn next_sample(&mut self) -> Result<Option<(usize, Sample)>> {
+        // samples of the moov sample tables are interleaved by file position
+        let next = self.tracks.iter_mut()
+            .enumerate()
+            .filter(|(_, track)| track.next < track.samples.len())
+            .min_by_key(|(_, track)| track.samples[track.next].pos);
+        if let Some((stream_id, track)) = next {
+            track.next += 1;
+            return Ok(Some((stream_id, track.samples[track.next - 1])));
+        }
+
+        loop {
+            if let Some(next) = self.fragment_samples.pop_front() {
+                return Ok(Some(next));
+            }
+            if !self.fragmented || !self.read_fragment()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn seek_fragment(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        let timestamp = timestamp.saturating_sub(self.tracks[stream_id].ts_offset);
+        let after = |index: &[FragmentEntry]| index.iter().any(|entry| entry.stream_id == stream_id && entry.time > timestamp);
+        if !self.fragment_index_complete && !after(&self.fragment_index) {
+            self.build_fragment_index()?;
+        }
+
+        let entries = || self.fragment_index.iter().filter(|entry| entry.stream_id == stream_id);
+        let Some(entry) = entries().filter(|entry| entry.time <= timestamp).max_by_key(|entry| entry.time)
+            .or_else(|| entries().min_by_key(|entry| entry.time))
+            .copied()
+        else {
+            return decode_error("mp4: no fragment to seek to");
+        };
+
+        for track in self.tracks.iter_mut() {
+            track.next = track.samples.len();
+        }
+        // used if the fragment has no tfdt
+        self.tracks[stream_id].fragment_dts = entry.time;
+        self.fragment_samples.clear();
+        self.next_box_pos = Some(entry.pos);
+        self.seek_target = Some(stream_id);
+        Ok(entry.time.saturating_add(self.tracks[stream_id].ts_offset))
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerMp4<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        loop {
+            let Some((stream_id, sample)) = self.next_sample()? else {
+                return end_of_stream_error();
+            };
+            if let Some(target) = self.seek_target {
+                if stream_id != target || !sample.keyframe {
+                    continue;
+                }
+                self.seek_target = None;
+            }
+
+            let pos = sample.pos as usize;
+            self.move_to(pos)?;
+            if sample.size > 0 {
+                self.iobuf_reader.get_ioref(&mut packet.ioref, sample.size as usize)?;
+            }
+
+            packet.stream_id = stream_id;
+            let ts_offset = self.tracks[stream_id].ts_offset;
+            packet.pts = sample.pts().saturating_add(ts_offset);
+            packet.dts = sample.dts.saturating_add(ts_offset);
+            packet.duration = sample.duration as i64;
+            packet.keyframe = sample.keyframe;
+            packet.pos = pos as i64;
+            return Ok(());
+        }
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn seek(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        if stream_id >= self.tracks.len() {
+            return invalid_input_error();
+        }
+        if self.fragmented {
+            return self.seek_fragment(stream_id, timestamp);
+        }
+
+        let ts_offset = self.tracks[stream_id].ts_offset;
+        let timestamp = timestamp.saturating_sub(ts_offset);
+        let samples = &self.tracks[stream_id].samples;
+        let Some(index) = samples.iter()
+            .rposition(|sample| sample.keyframe && sample.pts() <= timestamp)
+            .or_else(|| samples.iter().position(|sample| sample.keyframe))
+        else {
+            return decode_error("mp4: no keyframe to seek to");
+        };
+        let time = samples[index].pts().saturating_add(ts_offset);
+        let dts = samples[index].dts.saturating_add(ts_offset);
+
+        // the other tracks resume at the first sample decoded at or after the keyframe
+        let time_base = self.streams[stream_id].time_base;
+        for (i, track) in self.tracks.iter_mut().enumerate() {
+            track.next = if i == stream_id {
+                index
+            } else {
+                let dts = time_base.rescale(dts, self.streams[i].time_base).saturating_sub(track.ts_offset);
+                track.samples.partition_point(|sample| sample.dts < dts)
+            };
+        }
+        Ok(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::MediaType;
+    use crate::format::{
+        DemuxerSegmented, IoBufSupplierDash, IoBufSupplierMem, IoBufSupplierMmap, IoBufSupply, MediaSourceStream,
+        StreamingSupplier,
+    };
+
+    const ENG: u16 = (5 << 10) | (14 << 5) | 7;
+    const UND: u16 = (21 << 10) | (14 << 5) | 4;
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        [&(8 + payload.len() as u32).to_be_bytes()[..], kind, payload].concat()
+    }
+
+    fn full_box(kind: &[u8; 4], version: u8, payload: &[u8]) -> Vec<u8> {
+        mp4_box(kind, &[&[version, 0, 0, 0][..], payload].concat())
+    }
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_be_bytes()).collect()
+    }
+
+    /// A full box holding an entry count followed by the entries.
+    fn table(kind: &[u8; 4], entries: &[&[u32]]) -> Vec<u8> {
+        let values: Vec<u32> = entries.iter().flat_map(|entry| entry.iter().copied()).collect();
+        full_box(kind, 0, &[u32s(&[entries.len() as u32]), u32s(&values)].concat())
+    }
+
+    fn trak(track_id: u32, handler: &[u8; 4], timescale: u32, duration: u32, language: u16, entry: Vec<u8>, tables: Vec<u8>) -> Vec<u8> {
+        let tkhd = full_box(b"tkhd", 0, &[u32s(&[0, 0, track_id, 0, duration]), vec![0; 60]].concat());
+        let mdhd = full_box(b"mdhd", 0, &[u32s(&[0, 0, timescale, duration]), language.to_be_bytes().to_vec(), vec![0; 2]].concat());
+        let hdlr = full_box(b"hdlr", 0, &[u32s(&[0]), handler.to_vec(), vec![0; 13]].concat());
+        let stsd = full_box(b"stsd", 0, &[u32s(&[1]), entry].concat());
+        let minf = mp4_box(b"minf", &mp4_box(b"stbl", &[stsd, tables].concat()));
+        mp4_box(b"trak", &[tkhd, mp4_box(b"mdia", &[mdhd, hdlr, minf].concat())].concat())
+    }
+
+    fn avc1_entry() -> Vec<u8> {
+        let avcc = mp4_box(b"avcC", &[1, 0x64, 0, 0x1F]);
+        mp4_box(b"avc1", &[vec![0; 6], vec![0, 1], vec![0; 16], u32s(&[640 << 16 | 360]), vec![0; 50], avcc].concat())
+    }
+
+    fn audio_entry(format: &[u8; 4], channels: u16, bits: u16, sample_rate: u32, config: Vec<u8>) -> Vec<u8> {
+        mp4_box(format, &[
+            vec![0; 6],
+            vec![0, 1],
+            vec![0; 8],
+            channels.to_be_bytes().to_vec(),
+            bits.to_be_bytes().to_vec(),
+            vec![0; 4],
+            u32s(&[sample_rate << 16]),
+            config,
+        ].concat())
+    }
+
+    fn esds(object_type: u8, info: &[u8]) -> Vec<u8> {
+        let mut config = vec![object_type, 0x15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, info.len() as u8];
+        config.extend_from_slice(info);
+        let mut es = vec![0, 1, 0, 4, config.len() as u8];
+        es.extend(config);
+        full_box(b"esds", 0, &[&[3, es.len() as u8][..], &es].concat())
+    }
+
+    /// Inserts an `edts` box holding `elst` after the `tkhd` box of a track.
+    fn with_edits(trak: Vec<u8>, elst: Option<&Vec<u8>>) -> Vec<u8> {
+        let Some(elst) = elst else {
+            return trak;
+        };
+        let tkhd_end = 8 + u32::from_be_bytes(trak[8..12].try_into().unwrap()) as usize;
+        mp4_box(b"trak", &[&trak[8..tkhd_end], &mp4_box(b"edts", elst), &trak[tkhd_end..]].concat())
+    }
+
+    fn build_file(moov_first: bool) -> Vec<u8> {
+        build_edited_file(moov_first, [None, None])
+    }
+
+    /// A video track with 3 samples in 2 chunks and an audio track with 4 samples in 2 chunks,
+    /// the chunks of both tracks alternate in `mdat`. The movie timescale is 1000.
+    fn build_edited_file(moov_first: bool, elsts: [Option<Vec<u8>>; 2]) -> Vec<u8> {
+        let ftyp = mp4_box(b"ftyp", b"isom\0\0\x02\0isomavc1");
+        let mdat = mp4_box(b"mdat", &[
+            vec![0x10; 5], vec![0x11; 3],
+            vec![0x20; 2], vec![0x21; 2],
+            vec![0x12; 4],
+            vec![0x22; 6], vec![0x23; 1],
+        ].concat());
+        let moov = |start: u32| {
+            let video = trak(1, b"vide", 1000, 60, ENG, avc1_entry(), [
+                table(b"stts", &[&[3, 20]]),
+                table(b"ctts", &[&[1, 20], &[1, 40], &[1, 0]]),
+                table(b"stsc", &[&[1, 2, 1], &[2, 1, 1]]),
+                full_box(b"stsz", 0, &u32s(&[0, 3, 5, 3, 4])),
+                table(b"stco", &[&[start], &[start + 12]]),
+                table(b"stss", &[&[1], &[3]]),
+            ].concat());
+            let audio = trak(2, b"soun", 8000, 640, UND, audio_entry(b"mp4a", 2, 16, 8000, esds(0x40, &[0x15, 0x90])), [
+                table(b"stts", &[&[4, 160]]),
+                table(b"stsc", &[&[1, 2, 1]]),
+                full_box(b"stsz", 0, &u32s(&[0, 4, 2, 2, 6, 1])),
+                table(b"stco", &[&[start + 8], &[start + 16]]),
+            ].concat());
+            let mvhd = full_box(b"mvhd", 0, &[u32s(&[0, 0, 1000, 60]), vec![0; 80]].concat());
+            mp4_box(b"moov", &[mvhd, with_edits(video, elsts[0].as_ref()), with_edits(audio, elsts[1].as_ref())].concat())
+        };
+
+        let moov_len = moov(0).len() as u32;
+        if moov_first {
+            let start = ftyp.len() as u32 + moov_len + 8;
+            [ftyp, moov(start), mdat].concat()
+        } else {
+            let start = ftyp.len() as u32 + 8;
+            [ftyp, mdat, moov(start)].concat()
+        }
+    }
+
+    type PacketInfo = (usize, i64, i64, i64, bool, Vec<u8>, i64);
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerMp4<S>) -> Vec<PacketInfo> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.stream_id, packet.pts, packet.dts, packet.duration, packet.keyframe, packet.data().to_vec(), packet.pos));
+        }
+        packets
+    }
+
+    fn expected_packets(start: i64) -> Vec<PacketInfo> {
+        vec![
+            (0, 20, 0, 20, true, vec![0x10; 5], start),
+            (0, 60, 20, 20, false, vec![0x11; 3], start + 5),
+            (1, 0, 0, 160, true, vec![0x20; 2], start + 8),
+            (1, 160, 160, 160, true, vec![0x21; 2], start + 10),
+            (0, 40, 40, 20, true, vec![0x12; 4], start + 12),
+            (1, 320, 320, 160, true, vec![0x22; 6], start + 16),
+            (1, 480, 480, 160, true, vec![0x23; 1], start + 22),
+        ]
+    }
+
+    #[test]
+    fn moov_before_mdat() {
+        let data = build_file(true);
+        let start = data.len() as i64 - 23;
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 5))).unwrap();
+        assert_eq!(demuxer.major_brand(), Some(*b"isom"));
+        assert_eq!(demuxer.compatible_brands(), &[*b"isom", *b"avc1"]);
+        assert_eq!(demuxer.track_id(1), Some(2));
+
+        let streams = demuxer.streams();
+        assert_eq!(streams.len(), 2);
+        assert_eq!((streams[0].codec_id, streams[0].media_type), (CodecId::H264, MediaType::Video));
+        assert_eq!((streams[0].width, streams[0].height), (640, 360));
+        assert_eq!(streams[0].codec_params, vec![1, 0x64, 0, 0x1F]);
+        assert_eq!(streams[0].time_base, TimeBase::new(1, 1000));
+        assert_eq!(streams[0].duration, 60);
+        assert_eq!(streams[0].language.as_deref(), Some("eng"));
+        assert_eq!((streams[1].codec_id, streams[1].media_type), (CodecId::Aac, MediaType::Audio));
+        assert_eq!((streams[1].sample_rate, streams[1].channels), (8000, 2));
+        assert_eq!(streams[1].codec_params, vec![0x15, 0x90]);
+        assert_eq!(streams[1].language, None);
+        assert_eq!(read_all(&mut demuxer), expected_packets(start));
+
+        // read front to back without seeking
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(StreamingSupplier(IoBufSupplierMem::new(data.clone(), 7)))).unwrap();
+        assert_eq!(read_all(&mut demuxer), expected_packets(start));
+
+        let path = std::env::temp_dir().join(format!("rav-mp4-test-{}.mp4", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let mut supplier = IoBufSupplierMmap::default();
+        supplier.open_input(path.to_str().unwrap()).unwrap();
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(supplier)).unwrap();
+        assert_eq!(read_all(&mut demuxer), expected_packets(start));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn moov_after_mdat() {
+        let data = build_file(false);
+        // samples start after ftyp and the mdat header
+        let start = 24 + 8;
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 5))).unwrap();
+        assert_eq!(read_all(&mut demuxer), expected_packets(start));
+
+        // the samples were read through to reach moov
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(StreamingSupplier(IoBufSupplierMem::new(data, 5)))).unwrap();
+        assert!(matches!(demuxer.read_packet(&mut Packet2::default()), Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn seek_to_keyframe() {
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(build_file(true), 5))).unwrap();
+        let expected = expected_packets(demuxer.tracks[0].samples[0].pos as i64);
+
+        // the audio track resumes at the keyframe decoding time
+        assert_eq!(demuxer.seek(0, 50), Ok(40));
+        assert_eq!(read_all(&mut demuxer), vec![expected[4].clone(), expected[5].clone(), expected[6].clone()]);
+
+        assert_eq!(demuxer.seek(0, 0), Ok(20));
+        assert_eq!(read_all(&mut demuxer), expected);
+        assert_eq!(demuxer.seek(2, 0), Err(Error::InvalidInput));
+    }
+
+    #[test]
+    fn sample_count_bounds() {
+        let file = |samples_per_chunk: u32| {
+            let moov = |start: u32| {
+                let entry = audio_entry(b"mp4a", 2, 16, 8000, esds(0x40, &[0x15, 0x90]));
+                mp4_box(b"moov", &trak(1, b"soun", 8000, 0, UND, entry, [
+                    table(b"stts", &[&[1, 160]]),
+                    table(b"stsc", &[&[1, samples_per_chunk, 1]]),
+                    full_box(b"stsz", 0, &u32s(&[2, u32::MAX])),
+                    table(b"stco", &[&[start]]),
+                ].concat()))
+            };
+            let start = moov(0).len() as u32 + 8;
+            [moov(start), mp4_box(b"mdat", &[1, 1, 2, 2, 3, 3, 4, 4])].concat()
+        };
+        // the chunk holds fewer samples than stsz declares
+        let demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(file(3), 64))).unwrap();
+        assert_eq!(demuxer.tracks[0].samples.len(), 3);
+        // neither holds, the samples of 2 bytes can't exceed the input
+        let data = file(u32::MAX);
+        let input_len = data.len();
+        let demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 64))).unwrap();
+        assert_eq!(demuxer.tracks[0].samples.len(), input_len / 2);
+    }
+
+    #[test]
+    fn pcm_chunks_and_co64() {
+        let entry = audio_entry(b"sowt", 2, 16, 44100, Vec::new());
+        let moov_with = |start: u64, duration: u32| {
+            mp4_box(b"moov", &trak(1, b"soun", 44100, 6, UND, entry.clone(), [
+                table(b"stts", &[&[6, duration]]),
+                table(b"stsc", &[&[1, 3, 1]]),
+                full_box(b"stsz", 0, &u32s(&[4, 6])),
+                full_box(b"co64", 0, &[u32s(&[2]), start.to_be_bytes().to_vec(), (start + 12).to_be_bytes().to_vec()].concat()),
+            ].concat()))
+        };
+        let moov = |start: u64| moov_with(start, 1);
+        let start = moov(0).len() as u64 + 8;
+        let data = [moov(start), mp4_box(b"mdat", &[[1u8; 12], [2u8; 12]].concat())].concat();
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 16))).unwrap();
+        assert_eq!(demuxer.major_brand(), None);
+        assert_eq!(demuxer.streams()[0].codec_id, CodecId::PcmS16Le);
+        assert_eq!(demuxer.streams()[0].sample_rate, 44100);
+
+        let start = start as i64;
+        assert_eq!(read_all(&mut demuxer), vec![
+            (0, 0, 0, 3, true, vec![1; 12], start),
+            (0, 3, 3, 3, true, vec![2; 12], start + 12),
+        ]);
+
+        // the durations of a chunk overflow 32 bits
+        let data = [moov_with(start as u64, 0x8000_0000), mp4_box(b"mdat", &[0; 24])].concat();
+        let result = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 16)));
+        assert!(matches!(result, Err(Error::DecodeError("mp4: chunk duration too large"))));
+    }
+
+    /// Sample of a track fragment: size, duration, flags and composition offset.
+    type FragmentSample = (u32, u32, u32, u32);
+
+    const SYNC: u32 = 0x0200_0000;
+    const NON_SYNC: u32 = 0x0101_0000;
+
+    /// Initialization segment with a video track at 1/1000 and an audio track at 1/8000.
+    fn init_segment() -> Vec<u8> {
+        let empty = || [table(b"stts", &[]), table(b"stsc", &[]), full_box(b"stsz", 0, &u32s(&[0, 0])), table(b"stco", &[])].concat();
+        let video = trak(1, b"vide", 1000, 0, ENG, avc1_entry(), empty());
+        let audio = trak(2, b"soun", 8000, 0, UND, audio_entry(b"mp4a", 2, 16, 8000, esds(0x40, &[0x15, 0x90])), empty());
+        let mvex = mp4_box(b"mvex", &[
+            full_box(b"trex", 0, &u32s(&[1, 1, 20, 0, NON_SYNC])),
+            full_box(b"trex", 0, &u32s(&[2, 1, 160, 0, SYNC])),
+        ].concat());
+        [mp4_box(b"ftyp", b"iso6\0\0\0\0iso6cmfc"), mp4_box(b"moov", &[video, audio, mvex].concat())].concat()
+    }
+
+    /// A `moof` and `mdat` pair, the sample data of each track fragment is filled with `fill`.
+    fn fragment(sequence: u32, tracks: &[(u32, u32, &[FragmentSample], u8)]) -> Vec<u8> {
+        let moof = |data_offset: u32| {
+            let mut offset = data_offset;
+            let trafs: Vec<u8> = tracks.iter().flat_map(|&(track_id, tfdt, samples, _)| {
+                let entries: Vec<u32> = samples.iter().flat_map(|&(size, duration, flags, cts)| [duration, size, flags, cts]).collect();
+                let trun = mp4_box(b"trun", &[u32s(&[0x000F01, samples.len() as u32, offset]), u32s(&entries)].concat());
+                offset += samples.iter().map(|sample| sample.0).sum::<u32>();
+                let tfhd = mp4_box(b"tfhd", &u32s(&[0x02_0000, track_id]));
+                mp4_box(b"traf", &[tfhd, full_box(b"tfdt", 0, &u32s(&[tfdt])), trun].concat())
+            }).collect();
+            mp4_box(b"moof", &[full_box(b"mfhd", 0, &u32s(&[sequence])), trafs].concat())
+        };
+        let data: Vec<u8> = tracks.iter()
+            .flat_map(|&(_, _, samples, fill)| vec![fill; samples.iter().map(|sample| sample.0 as usize).sum()])
+            .collect();
+        let moof_len = moof(0).len() as u32;
+        [moof(moof_len + 8), mp4_box(b"mdat", &data)].concat()
+    }
+
+    fn fragments() -> [Vec<u8>; 2] {
+        [
+            fragment(1, &[
+                (1, 0, &[(4, 20, SYNC, 0), (2, 20, NON_SYNC, 0), (3, 20, NON_SYNC, 0)], 0x10),
+                (2, 0, &[(5, 160, SYNC, 0), (6, 160, SYNC, 0)], 0x20),
+            ]),
+            fragment(2, &[
+                (1, 60, &[(7, 20, SYNC, 0), (1, 20, NON_SYNC, 0)], 0x11),
+                (2, 320, &[(2, 160, SYNC, 0), (3, 160, SYNC, 0)], 0x21),
+            ]),
+        ]
+    }
+
+    /// Packets of the fragments following an initialization segment of `start` bytes.
+    fn expected_fragment_packets(start: i64) -> Vec<PacketInfo> {
+        let [first, second] = fragments();
+        let (data1, data2) = (start + first.len() as i64 - 20, start + first.len() as i64 + second.len() as i64 - 13);
+        vec![
+            (0, 0, 0, 20, true, vec![0x10; 4], data1),
+            (0, 20, 20, 20, false, vec![0x10; 2], data1 + 4),
+            (0, 40, 40, 20, false, vec![0x10; 3], data1 + 6),
+            (1, 0, 0, 160, true, vec![0x20; 5], data1 + 9),
+            (1, 160, 160, 160, true, vec![0x20; 6], data1 + 14),
+            (0, 60, 60, 20, true, vec![0x11; 7], data2),
+            (0, 80, 80, 20, false, vec![0x11; 1], data2 + 7),
+            (1, 320, 320, 160, true, vec![0x21; 2], data2 + 8),
+            (1, 480, 480, 160, true, vec![0x21; 3], data2 + 10),
+        ]
+    }
+
+    #[test]
+    fn fragmented_with_sidx() {
+        let [first, second] = fragments();
+        let references = [(first.len() as u32, 60), (second.len() as u32, 40)];
+        let entries: Vec<u32> = references.iter().flat_map(|&(size, duration)| [size, duration, 0x9000_0000]).collect();
+        let sidx = full_box(b"sidx", 0, &[u32s(&[1, 1000, 0, 0, 2]), u32s(&entries)].concat());
+        let start = (init_segment().len() + sidx.len()) as i64;
+        let data = [init_segment(), sidx, first, second].concat();
+
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(StreamingSupplier(IoBufSupplierMem::new(data.clone(), 7)))).unwrap();
+        assert!(demuxer.is_fragmented());
+        assert_eq!(demuxer.streams()[1].codec_id, CodecId::Aac);
+        assert_eq!(read_all(&mut demuxer), expected_fragment_packets(start));
+
+        let expected = expected_fragment_packets(start);
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 5))).unwrap();
+        assert_eq!(demuxer.seek(0, 70), Ok(60));
+        assert_eq!(read_all(&mut demuxer), expected[5..].to_vec());
+        assert_eq!(demuxer.seek(0, 0), Ok(0));
+        assert_eq!(read_all(&mut demuxer), expected);
+    }
+
+    #[test]
+    fn segment_series() {
+        let styp = mp4_box(b"styp", b"msdh\0\0\0\0msdhmsix");
+        let [first, second] = fragments();
+        let start = (init_segment().len() + styp.len()) as i64;
+        let data = [init_segment(), styp.clone(), first.clone(), styp.clone(), second].concat();
+        let expected: Vec<PacketInfo> = expected_fragment_packets(start).into_iter()
+            .map(|mut packet| {
+                if packet.6 >= start + first.len() as i64 {
+                    packet.6 += styp.len() as i64;
+                }
+                packet
+            })
+            .collect();
+
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 6))).unwrap();
+        assert_eq!(read_all(&mut demuxer), expected);
+
+        // the second fragment is found by scanning the moof boxes
+        assert_eq!(demuxer.seek(1, 400), Ok(320));
+        assert_eq!(read_all(&mut demuxer), expected[7..].to_vec());
+        assert_eq!(demuxer.seek(1, 100), Ok(0));
+        assert_eq!(read_all(&mut demuxer)[0], expected[3]);
+    }
+
+    #[test]
+    fn dash_periods() {
+        // the second period changes the video codec configuration and keeps the audio track, its timestamps restart
+        let mut init2 = init_segment();
+        let avcc = init2.windows(8).position(|w| w == b"avcC\x01\x64\0\x1F").unwrap();
+        init2[avcc + 5] = 0x4D;
+        let [first, second] = fragments();
+        let third = fragment(3, &[(1, 0, &[(4, 20, SYNC, 0)], 0x12), (2, 0, &[(5, 160, SYNC, 0)], 0x22)]);
+        let dir = std::env::temp_dir().join(format!("rav-mp4-dash-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, data) in [("init1.mp4", init_segment()), ("p1-1.m4s", first), ("p1-2.m4s", second),
+            ("init2.mp4", init2), ("p2-1.m4s", third)] {
+            std::fs::write(dir.join(name), data).unwrap();
+        }
+        let period = |number: u32| format!(r#"<Period duration="PT0.1S"><AdaptationSet contentType="video">
+            <SegmentTemplate timescale="1000" duration="{}" initialization="init{number}.mp4"
+                media="p{number}-$Number$.m4s"/>
+            <Representation id="av" bandwidth="1"/></AdaptationSet></Period>"#, 100 / (3 - number));
+        let mpd = format!(r#"<MPD type="static">{}{}</MPD>"#, period(1), period(2));
+        std::fs::write(dir.join("manifest.mpd"), mpd).unwrap();
+
+        let mut supplier = IoBufSupplierDash::default();
+        supplier.open_input(dir.join("manifest.mpd").to_str().unwrap()).unwrap();
+        let timeline = supplier.timeline();
+        let demuxer = DemuxerMp4::new(MediaSourceStream::new(supplier)).unwrap();
+        let mut demuxer = DemuxerSegmented::new(demuxer, timeline);
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.stream_id, packet.pts, packet.data()[0]));
+        }
+        let mut expected: Vec<_> = expected_fragment_packets(0).into_iter()
+            .map(|(stream_id, pts, _, _, _, data, _)| (stream_id, pts, data[0]))
+            .collect();
+        expected.extend([(2, 100, 0x12), (1, 800, 0x22)]);
+        assert_eq!(packets, expected);
+        let streams = demuxer.streams();
+        assert_eq!(streams.len(), 3);
+        assert_eq!((streams[2].id, streams[2].codec_id, streams[2].codec_params[1]), (2, CodecId::H264, 0x4D));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn edit_lists() {
+        // the video skips the composition delay, the audio starts after an empty edit of 10 ms
+        let video_elst = full_box(b"elst", 0, &u32s(&[1, 60, 20, 1 << 16]));
+        let audio_elst = full_box(b"elst", 1, &[
+            u32s(&[2]),
+            10u64.to_be_bytes().to_vec(), (-1i64).to_be_bytes().to_vec(), u32s(&[1 << 16]),
+            80u64.to_be_bytes().to_vec(), 0u64.to_be_bytes().to_vec(), u32s(&[1 << 16]),
+        ].concat());
+        let data = build_edited_file(true, [Some(video_elst), Some(audio_elst)]);
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 5))).unwrap();
+        assert_eq!(demuxer.edit_list(0), &[EditListEntry { segment_duration: 60, media_time: 20, media_rate: 1 << 16 }]);
+        assert_eq!(demuxer.edit_list(1)[0], EditListEntry { segment_duration: 10, media_time: -1, media_rate: 1 << 16 });
+        assert!(demuxer.edit_list(1)[0].is_empty());
+        assert_eq!((demuxer.timestamp_offset(0), demuxer.timestamp_offset(1)), (-20, 80));
+
+        let expected: Vec<PacketInfo> = expected_packets(demuxer.tracks[0].samples[0].pos as i64).into_iter()
+            .map(|(stream_id, pts, dts, duration, keyframe, data, pos)| {
+                let offset = demuxer.timestamp_offset(stream_id);
+                (stream_id, pts + offset, dts + offset, duration, keyframe, data, pos)
+            })
+            .collect();
+        assert_eq!((expected[0].1, expected[0].2), (0, -20));
+        assert_eq!(read_all(&mut demuxer), expected);
+
+        // seeking uses the edited timestamps, the audio resumes at 20 ms
+        assert_eq!(demuxer.seek(0, 30), Ok(20));
+        assert_eq!(read_all(&mut demuxer), expected[3..].to_vec());
+    }
+
+    #[test]
+    fn negative_composition_offsets() {
+        let moov = |start: u32| {
+            let entries = [&[1, 0][..], &[1, (-20i32) as u32], &[1, 0]];
+            mp4_box(b"moov", &trak(1, b"vide", 1000, 60, UND, avc1_entry(), [
+                table(b"stts", &[&[3, 20]]),
+                full_box(b"ctts", 1, &[u32s(&[3]), u32s(&entries.concat())].concat()),
+                table(b"stsc", &[&[1, 3, 1]]),
+                full_box(b"stsz", 0, &u32s(&[1, 3])),
+                table(b"stco", &[&[start]]),
+            ].concat()))
+        };
+        let start = moov(0).len() as u32 + 8;
+        let data = [moov(start), mp4_box(b"mdat", &[1, 2, 3])].concat();
+        let mut demuxer = DemuxerMp4::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 16))).unwrap();
+        let timestamps: Vec<(i64, i64)> = read_all(&mut demuxer).iter().map(|packet| (packet.1, packet.2)).collect();
+        assert_eq!(timestamps, vec![(0, 0), (0, 20), (40, 40)]);
+    }
+
+    #[test]
+    fn index_and_edit_overflow() {
+        let sidx = |time: u64, offset: u64| {
+            let times = [time.to_be_bytes(), offset.to_be_bytes()].concat();
+            [&[1, 0, 0, 0][..], &u32s(&[1, 1000]), &times, &[0, 0, 0, 2], &u32s(&[16, 10, 0, 16, 10, 0])].concat()
+        };
+        assert_eq!(parse_sidx(BoxReader::new(&sidx(0, 100)), 50).unwrap().subsegments, vec![(0, 150), (10, 166)]);
+        assert!(parse_sidx(BoxReader::new(&sidx(0, u64::MAX)), 50).is_err());
+        assert!(parse_sidx(BoxReader::new(&sidx(i64::MAX as u64 - 5, 0)), 50).is_err());
+        assert!(parse_sidx(BoxReader::new(&sidx(u64::MAX, 0)), 50).is_err());
+
+        let time_base = TimeBase::new(1, 1000);
+        let edit = |segment_duration, media_time| EditListEntry { segment_duration, media_time, media_rate: 1 << 16 };
+        assert_eq!(timestamp_offset(&[edit(0, 20)], 1000, time_base), Ok(-20));
+        assert!(timestamp_offset(&[edit(0, i64::MIN)], 1000, time_base).is_err());
+        assert!(timestamp_offset(&[edit(u64::MAX, -1), edit(0, 0)], 1000, time_base).is_err());
+        assert!(timestamp_offset(&[edit(i64::MAX as u64, -1), edit(1, -1)], 1000, time_base).is_err());
+    }
+}
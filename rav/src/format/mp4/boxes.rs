@@ -0,0 +1,193 @@
+use crate::error::{decode_error, Result};
+use crate::format::MediaIoBufRead;
+
+/// Builds a box type from its four characters.
+pub(super) const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*code)
+}
+
+// file level
+pub(super) const FTYP: u32 = fourcc(b"ftyp");
+pub(super) const MOOV: u32 = fourcc(b"moov");
+pub(super) const MDAT: u32 = fourcc(b"mdat");
+pub(super) const MOOF: u32 = fourcc(b"moof");
+pub(super) const SIDX: u32 = fourcc(b"sidx");
+
+// moov
+pub(super) const MVHD: u32 = fourcc(b"mvhd");
+pub(super) const TRAK: u32 = fourcc(b"trak");
+pub(super) const TKHD: u32 = fourcc(b"tkhd");
+pub(super) const EDTS: u32 = fourcc(b"edts");
+pub(super) const ELST: u32 = fourcc(b"elst");
+pub(super) const MDIA: u32 = fourcc(b"mdia");
+pub(super) const MDHD: u32 = fourcc(b"mdhd");
+pub(super) const HDLR: u32 = fourcc(b"hdlr");
+pub(super) const MINF: u32 = fourcc(b"minf");
+pub(super) const STBL: u32 = fourcc(b"stbl");
+pub(super) const CMOV: u32 = fourcc(b"cmov");
+pub(super) const MVEX: u32 = fourcc(b"mvex");
+pub(super) const TREX: u32 = fourcc(b"trex");
+
+// moof
+pub(super) const TRAF: u32 = fourcc(b"traf");
+pub(super) const TFHD: u32 = fourcc(b"tfhd");
+pub(super) const TFDT: u32 = fourcc(b"tfdt");
+pub(super) const TRUN: u32 = fourcc(b"trun");
+
+// stbl
+pub(super) const STSD: u32 = fourcc(b"stsd");
+pub(super) const STTS: u32 = fourcc(b"stts");
+pub(super) const CTTS: u32 = fourcc(b"ctts");
+pub(super) const STSC: u32 = fourcc(b"stsc");
+pub(super) const STSZ: u32 = fourcc(b"stsz");
+pub(super) const STZ2: u32 = fourcc(b"stz2");
+pub(super) const STCO: u32 = fourcc(b"stco");
+pub(super) const CO64: u32 = fourcc(b"co64");
+pub(super) const STSS: u32 = fourcc(b"stss");
+
+// handler types
+pub(super) const VIDE: u32 = fourcc(b"vide");
+pub(super) const SOUN: u32 = fourcc(b"soun");
+pub(super) const SUBT: u32 = fourcc(b"subt");
+pub(super) const SBTL: u32 = fourcc(b"sbtl");
+pub(super) const TEXT: u32 = fourcc(b"text");
+
+/// Header of a box read from the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct BoxHeader {
+    pub kind: u32,
+    /// Size of the box payload, `None` if the box extends to the end of the file.
+    pub size: Option<u64>,
+}
+
+/// Reads a box header, including the 64-bit largesize.
+pub(super) fn read_box_header<R: MediaIoBufRead + ?Sized>(reader: &mut R) -> Result<BoxHeader> {
+    let mut header = [0u8; 8];
+    reader.get_bytes(&mut header)?;
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let kind = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let size = match size {
+        0 => None,
+        1 => {
+            let mut largesize = [0u8; 8];
+            reader.get_bytes(&mut largesize)?;
+            match u64::from_be_bytes(largesize).checked_sub(16) {
+                Some(size) => Some(size),
+                None => return decode_error("mp4: invalid box size"),
+            }
+        }
+        2..=7 => return decode_error("mp4: invalid box size"),
+        size => Some(size - 8),
+    };
+    Ok(BoxHeader { kind, size })
+}
+
+/// Reads big-endian fields from a box payload held in memory.
+#[derive(Debug, Clone)]
+pub(super) struct BoxReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BoxReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Number of bytes left.
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.remaining() {
+            return decode_error("mp4: box too short");
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Returns the rest of the payload.
+    pub fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.pos..];
+        self.pos = self.data.len();
+        rest
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        self.array().map(u32::from_be_bytes)
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        self.array().map(i32::from_be_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        self.array().map(u64::from_be_bytes)
+    }
+
+    /// Reads the version and flags of a full box.
+    pub fn full_box(&mut self) -> Result<(u8, u32)> {
+        let version_flags = self.u32()?;
+        Ok(((version_flags >> 24) as u8, version_flags & 0x00FF_FFFF))
+    }
+
+    /// Reads a 32-bit value for version 0 boxes and a 64-bit value otherwise.
+    pub fn versioned_u64(&mut self, version: u8) -> Result<u64> {
+        if version == 0 {
+            self.u32().map(u64::from)
+        } else {
+            self.u64()
+        }
+    }
+
+    /// Reads the next child box, returns its type and a reader over its payload.
+    pub fn next_box(&mut self) -> Result<Option<(u32, BoxReader<'a>)>> {
+        if self.remaining() < 8 {
+            return Ok(None);
+        }
+        let size = self.u32()? as u64;
+        let kind = self.u32()?;
+        let size = match size {
+            0 => self.remaining() as u64,
+            1 => match self.u64()?.checked_sub(16) {
+                Some(size) => size,
+                None => return decode_error("mp4: invalid box size"),
+            },
+            2..=7 => return decode_error("mp4: invalid box size"),
+            size => size - 8,
+        };
+        if size > self.remaining() as u64 {
+            return decode_error("mp4: box exceeds its parent");
+        }
+        Ok(Some((kind, BoxReader::new(self.bytes(size as usize)?))))
+    }
+
+    /// Finds the first child box of a given type.
+    pub fn find_box(&self, kind: u32) -> Result<Option<BoxReader<'a>>> {
+        let mut children = self.clone();
+        while let Some((child_kind, child)) = children.next_box()? {
+            if child_kind == kind {
+                return Ok(Some(child));
+            }
+        }
+        Ok(None)
+    }
+}
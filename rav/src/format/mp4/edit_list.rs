@@ -0,0 +1,66 @@
+use crate::error::{decode_error, Result};
+use crate::format::TimeBase;
+
+use super::boxes::BoxReader;
+
+/// An entry of an `elst` edit list, mapping a part of the media timeline to the presentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditListEntry {
+    /// Duration of the edit in movie timescale units.
+    pub segment_duration: u64,
+    /// Start of the edit in media timescale units, -1 for an empty edit.
+    pub media_time: i64,
+    /// Playback rate as 16.16 fixed point, 0 for a dwell holding the frame at `media_time`.
+    pub media_rate: i32,
+}
+
+impl EditListEntry {
+    pub fn is_empty(&self) -> bool {
+        self.media_time == -1
+    }
+}
+
+/// Parses an `elst` box.
+pub(super) fn parse_elst(mut elst: BoxReader) -> Result<Vec<EditListEntry>> {
+    let (version, _) = elst.full_box()?;
+    let count = elst.u32()? as usize;
+    let entry_size = if version == 0 { 12 } else { 20 };
+    if count.saturating_mul(entry_size) > elst.remaining() {
+        return decode_error("mp4: elst entries exceed the box");
+    }
+    (0..count)
+        .map(|_| {
+            let segment_duration = elst.versioned_u64(version)?;
+            let media_time = if version == 0 { elst.i32()? as i64 } else { elst.u64()? as i64 };
+            Ok(EditListEntry { segment_duration, media_time, media_rate: elst.i32()? })
+        })
+        .collect()
+}
+
+/// Returns the offset added to the media timestamps to place them on the presentation timeline.
+/// Leading empty edits and dwells delay the track, the first regular edit skips the media before
+/// its `media_time`. Later edits are not applied, as the samples are not repeated or dropped.
+pub(super) fn timestamp_offset(edits: &[EditListEntry], movie_timescale: u32, time_base: TimeBase) -> Result<i64> {
+    let mut delay = 0i64;
+    let mut media_time = 0i64;
+    for edit in edits {
+        if edit.is_empty() || edit.media_rate == 0 {
+            let Some(sum) = i64::try_from(edit.segment_duration).ok().and_then(|duration| delay.checked_add(duration))
+            else {
+                return decode_error("mp4: edit list duration overflow");
+            };
+            delay = sum;
+        } else {
+            media_time = edit.media_time;
+            break;
+        }
+    }
+    let delay = match movie_timescale {
+        0 => 0,
+        _ => TimeBase::new(1, movie_timescale).rescale(delay, time_base),
+    };
+    match delay.checked_sub(media_time) {
+        Some(offset) => Ok(offset),
+        None => decode_error("mp4: edit list media time overflow"),
+    }
+}
@@ -0,0 +1,208 @@
+use crate::error::{decode_error, Result};
+
+use super::boxes::{BoxReader, TFDT, TFHD, TRAF, TRUN};
+use super::sample_table::Sample;
+
+// tfhd flags
+const TFHD_BASE_DATA_OFFSET: u32 = 0x01;
+const TFHD_SAMPLE_DESCRIPTION_INDEX: u32 = 0x02;
+const TFHD_DEFAULT_DURATION: u32 = 0x08;
+const TFHD_DEFAULT_SIZE: u32 = 0x10;
+const TFHD_DEFAULT_FLAGS: u32 = 0x20;
+const TFHD_DEFAULT_BASE_IS_MOOF: u32 = 0x02_0000;
+
+// trun flags
+const TRUN_DATA_OFFSET: u32 = 0x01;
+const TRUN_FIRST_SAMPLE_FLAGS: u32 = 0x04;
+const TRUN_DURATION: u32 = 0x100;
+const TRUN_SIZE: u32 = 0x200;
+const TRUN_FLAGS: u32 = 0x400;
+const TRUN_CTS_OFFSET: u32 = 0x800;
+
+/// sample_is_non_sync_sample bit of the sample flags.
+const SAMPLE_NON_SYNC: u32 = 0x1_0000;
+
+/// Sample defaults of a track, from its `trex` box.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct TrackDefaults {
+    pub duration: u32,
+    pub size: u32,
+    pub flags: u32,
+}
+
+/// Parses a `trex` box, returns the track ID and its sample defaults.
+pub(super) fn parse_trex(mut trex: BoxReader) -> Result<(u32, TrackDefaults)> {
+    trex.full_box()?;
+    let track_id = trex.u32()?;
+    // default_sample_description_index
+    trex.skip(4)?;
+    Ok((track_id, TrackDefaults { duration: trex.u32()?, size: trex.u32()?, flags: trex.u32()? }))
+}
+
+/// Returns the track ID of a `traf` box.
+pub(super) fn traf_track_id(traf: &BoxReader) -> Result<u32> {
+    let Some(mut tfhd) = traf.find_box(TFHD)? else {
+        return decode_error("mp4: missing tfhd");
+    };
+    tfhd.full_box()?;
+    tfhd.u32()
+}
+
+/// Returns the `tfdt` decoding time of a `traf` box.
+fn base_media_decode_time(traf: &BoxReader) -> Result<Option<i64>> {
+    let Some(mut tfdt) = traf.find_box(TFDT)? else {
+        return Ok(None);
+    };
+    let (version, _) = tfdt.full_box()?;
+    match i64::try_from(tfdt.versioned_u64(version)?) {
+        Ok(time) => Ok(Some(time)),
+        Err(_) => decode_error("mp4: invalid tfdt time"),
+    }
+}
+
+/// Returns the track IDs and decoding times of the track fragments of a `moof` box.
+pub(super) fn fragment_times(mut moof: BoxReader) -> Result<Vec<(u32, i64)>> {
+    let mut times = Vec::new();
+    while let Some((kind, traf)) = moof.next_box()? {
+        if kind != TRAF {
+            continue;
+        }
+        if let Some(time) = base_media_decode_time(&traf)? {
+            times.push((traf_track_id(&traf)?, time));
+        }
+    }
+    Ok(times)
+}
+
+/// Appends the samples of a `traf` box. `data_end` is the end of the data of the previous track
+/// fragment of the `moof`, or the `moof` position for the first one, and is moved past this one.
+/// `next_dts` is the decoding time of the first sample when `tfdt` is missing.
+pub(super) fn parse_traf(
+    traf: &BoxReader,
+    moof_pos: u64,
+    data_end: &mut u64,
+    defaults: TrackDefaults,
+    next_dts: &mut i64,
+    samples: &mut Vec<Sample>,
+) -> Result<()> {
+    let Some(mut tfhd) = traf.find_box(TFHD)? else {
+        return decode_error("mp4: missing tfhd");
+    };
+    let (_, flags) = tfhd.full_box()?;
+    tfhd.skip(4)?;
+    let base = if flags & TFHD_BASE_DATA_OFFSET != 0 {
+        tfhd.u64()?
+    } else if flags & TFHD_DEFAULT_BASE_IS_MOOF != 0 {
+        moof_pos
+    } else {
+        *data_end
+    };
+    if flags & TFHD_SAMPLE_DESCRIPTION_INDEX != 0 {
+        tfhd.skip(4)?;
+    }
+    let mut defaults = defaults;
+    if flags & TFHD_DEFAULT_DURATION != 0 {
+        defaults.duration = tfhd.u32()?;
+    }
+    if flags & TFHD_DEFAULT_SIZE != 0 {
+        defaults.size = tfhd.u32()?;
+    }
+    if flags & TFHD_DEFAULT_FLAGS != 0 {
+        defaults.flags = tfhd.u32()?;
+    }
+    if let Some(time) = base_media_decode_time(traf)? {
+        *next_dts = time;
+    }
+
+    let mut pos = base;
+    let mut children = traf.clone();
+    while let Some((kind, mut trun)) = children.next_box()? {
+        if kind != TRUN {
+            continue;
+        }
+        let (_, flags) = trun.full_box()?;
+        let count = trun.u32()? as usize;
+        if flags & TRUN_DATA_OFFSET != 0 {
+            pos = base.wrapping_add_signed(trun.i32()? as i64);
+        }
+        let first_flags = if flags & TRUN_FIRST_SAMPLE_FLAGS != 0 { Some(trun.u32()?) } else { None };
+        let entry_size = 4 * (flags & (TRUN_DURATION | TRUN_SIZE | TRUN_FLAGS | TRUN_CTS_OFFSET)).count_ones() as usize;
+        if count.saturating_mul(entry_size) > trun.remaining() {
+            return decode_error("mp4: trun entries exceed the box");
+        }
+
+        samples.reserve(count);
+        for i in 0..count {
+            let duration = if flags & TRUN_DURATION != 0 { trun.u32()? } else { defaults.duration };
+            let size = if flags & TRUN_SIZE != 0 { trun.u32()? } else { defaults.size };
+            let sample_flags = match (flags & TRUN_FLAGS != 0, first_flags) {
+                (true, _) => trun.u32()?,
+                (false, Some(first_flags)) if i == 0 => first_flags,
+                _ => defaults.flags,
+            };
+            // version 0 offsets are unsigned, but negative values stored there are common
+            let cts_offset = if flags & TRUN_CTS_OFFSET != 0 { trun.i32()? } else { 0 };
+            samples.push(Sample {
+                pos,
+                size,
+                dts: *next_dts,
+                cts_offset,
+                duration,
+                keyframe: sample_flags & SAMPLE_NON_SYNC == 0,
+            });
+            let (Some(next_pos), Some(dts)) = (pos.checked_add(size as u64), next_dts.checked_add(duration as i64))
+            else {
+                return decode_error("mp4: trun overflow");
+            };
+            (pos, *next_dts) = (next_pos, dts);
+        }
+        *data_end = pos;
+    }
+    Ok(())
+}
+
+/// A `sidx` box, the times are in `timescale` units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SegmentIndex {
+    pub reference_id: u32,
+    pub timescale: u32,
+    /// Earliest presentation time and absolute position of the indexed subsegments.
+    pub subsegments: Vec<(i64, u64)>,
+}
+
+/// Parses a `sidx` box, `anchor` is the position following the box.
+pub(super) fn parse_sidx(mut sidx: BoxReader, anchor: u64) -> Result<SegmentIndex> {
+    let (version, _) = sidx.full_box()?;
+    let reference_id = sidx.u32()?;
+    let timescale = sidx.u32()?;
+    let Ok(mut time) = i64::try_from(sidx.versioned_u64(version)?) else {
+        return decode_error("mp4: invalid sidx time");
+    };
+    let Some(mut pos) = anchor.checked_add(sidx.versioned_u64(version)?) else {
+        return decode_error("mp4: invalid sidx offset");
+    };
+    sidx.skip(2)?;
+    let count = sidx.u16()? as usize;
+    if timescale == 0 {
+        return decode_error("mp4: invalid sidx timescale");
+    }
+
+    let mut subsegments = Vec::with_capacity(count);
+    for _ in 0..count {
+        let reference = sidx.u32()?;
+        let duration = sidx.u32()?;
+        // starts_with_SAP, SAP_type and SAP_delta_time
+        sidx.skip(4)?;
+        // references to other sidx boxes are indexed when those are read
+        if reference & 0x8000_0000 == 0 {
+            subsegments.push((time, pos));
+        }
+        let (Some(next_time), Some(next_pos)) =
+            (time.checked_add(duration as i64), pos.checked_add((reference & 0x7FFF_FFFF) as u64))
+        else {
+            return decode_error("mp4: sidx overflow");
+        };
+        (time, pos) = (next_time, next_pos);
+    }
+    Ok(SegmentIndex { reference_id, timescale, subsegments })
+}
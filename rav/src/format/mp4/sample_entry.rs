@@ -0,0 +1,341 @@
+use crate::codec::{CodecId, MediaType};
+use crate::error::{decode_error, Result};
+use crate::format::Stream;
+
+use super::boxes::{fourcc, BoxReader, SOUN, VIDE};
+
+// video sample entries
+const AVC1: u32 = fourcc(b"avc1");
+const AVC3: u32 = fourcc(b"avc3");
+const HVC1: u32 = fourcc(b"hvc1");
+const HEV1: u32 = fourcc(b"hev1");
+const AV01: u32 = fourcc(b"av01");
+const VP08: u32 = fourcc(b"vp08");
+const VP09: u32 = fourcc(b"vp09");
+const MP4V: u32 = fourcc(b"mp4v");
+const M1V: u32 = fourcc(b"m1v ");
+const M2V1: u32 = fourcc(b"m2v1");
+
+// audio sample entries
+const MP4A: u32 = fourcc(b"mp4a");
+const MP3: u32 = fourcc(b".mp3");
+const AC_3: u32 = fourcc(b"ac-3");
+const EC_3: u32 = fourcc(b"ec-3");
+const OPUS: u32 = fourcc(b"Opus");
+const FLAC: u32 = fourcc(b"fLaC");
+const DTSC: u32 = fourcc(b"dtsc");
+const DTSH: u32 = fourcc(b"dtsh");
+const DTSL: u32 = fourcc(b"dtsl");
+const DTSE: u32 = fourcc(b"dtse");
+const MLPA: u32 = fourcc(b"mlpa");
+const SOWT: u32 = fourcc(b"sowt");
+const TWOS: u32 = fourcc(b"twos");
+const RAW: u32 = fourcc(b"raw ");
+const IN24: u32 = fourcc(b"in24");
+const IN32: u32 = fourcc(b"in32");
+const FL32: u32 = fourcc(b"fl32");
+const FL64: u32 = fourcc(b"fl64");
+const LPCM: u32 = fourcc(b"lpcm");
+const IPCM: u32 = fourcc(b"ipcm");
+const FPCM: u32 = fourcc(b"fpcm");
+
+// subtitle sample entries
+const WVTT: u32 = fourcc(b"wvtt");
+
+// codec configuration boxes
+const AVCC: u32 = fourcc(b"avcC");
+const HVCC: u32 = fourcc(b"hvcC");
+const AV1C: u32 = fourcc(b"av1C");
+const VPCC: u32 = fourcc(b"vpcC");
+const ESDS: u32 = fourcc(b"esds");
+const DAC3: u32 = fourcc(b"dac3");
+const DEC3: u32 = fourcc(b"dec3");
+const DOPS: u32 = fourcc(b"dOps");
+const DFLA: u32 = fourcc(b"dfLa");
+const PCMC: u32 = fourcc(b"pcmC");
+const ENDA: u32 = fourcc(b"enda");
+/// QuickTime sound description extension, holding the codec configuration boxes.
+const WAVE: u32 = fourcc(b"wave");
+
+// MPEG-4 descriptor tags
+const ES_DESCR_TAG: u8 = 0x03;
+const DECODER_CONFIG_DESCR_TAG: u8 = 0x04;
+const DEC_SPECIFIC_INFO_TAG: u8 = 0x05;
+
+/// Flags of the QuickTime version 2 sound description for `lpcm`.
+const LPCM_FLAG_FLOAT: u32 = 0x01;
+const LPCM_FLAG_BIG_ENDIAN: u32 = 0x02;
+const LPCM_FLAG_SIGNED: u32 = 0x04;
+
+/// Maps an MPEG-4 objectTypeIndication to a codec.
+fn codec_from_object_type(object_type: u8) -> CodecId {
+    match object_type {
+        0x20 => CodecId::Mpeg4,
+        0x21 => CodecId::H264,
+        0x23 => CodecId::Hevc,
+        0x40 | 0x66..=0x68 => CodecId::Aac,
+        0x60..=0x65 => CodecId::Mpeg2Video,
+        0x69 | 0x6B => CodecId::Mp3,
+        0x6A => CodecId::Mpeg1Video,
+        0xA5 => CodecId::Ac3,
+        0xA6 => CodecId::Eac3,
+        0xA9..=0xAC => CodecId::Dts,
+        0xAD => CodecId::Opus,
+        0xDD => CodecId::Vorbis,
+        _ => CodecId::Unknown,
+    }
+}
+
+fn pcm_codec(float: bool, big_endian: bool, signed: bool, bits: u16) -> CodecId {
+    match (float, bits, big_endian) {
+        (true, 32, false) => CodecId::PcmF32Le,
+        (true, 32, true) => CodecId::PcmF32Be,
+        (true, 64, false) => CodecId::PcmF64Le,
+        (true, 64, true) => CodecId::PcmF64Be,
+        (true, _, _) => CodecId::Unknown,
+        (false, 8, _) if signed => CodecId::PcmS8,
+        (false, 8, _) => CodecId::PcmU8,
+        (false, 16, false) => CodecId::PcmS16Le,
+        (false, 16, true) => CodecId::PcmS16Be,
+        (false, 24, false) => CodecId::PcmS24Le,
+        (false, 24, true) => CodecId::PcmS24Be,
+        (false, 32, false) => CodecId::PcmS32Le,
+        (false, 32, true) => CodecId::PcmS32Be,
+        _ => CodecId::Unknown,
+    }
+}
+
+/// Reads an MPEG-4 descriptor, returns its tag and payload.
+fn read_descriptor<'a>(reader: &mut BoxReader<'a>) -> Result<(u8, BoxReader<'a>)> {
+    let tag = reader.u8()?;
+    let mut size = 0usize;
+    for _ in 0..4 {
+        let b = reader.u8()?;
+        size = size << 7 | (b & 0x7F) as usize;
+        if b & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok((tag, BoxReader::new(reader.bytes(size)?)))
+}
+
+/// Parses an `esds` box, returns the objectTypeIndication and the DecoderSpecificInfo.
+fn parse_esds(mut esds: BoxReader) -> Result<(u8, Vec<u8>)> {
+    esds.full_box()?;
+    let (tag, mut es) = read_descriptor(&mut esds)?;
+    if tag != ES_DESCR_TAG {
+        return decode_error("mp4: missing ES_Descriptor");
+    }
+    es.skip(2)?;
+    let flags = es.u8()?;
+    if flags & 0x80 != 0 {
+        es.skip(2)?;
+    }
+    if flags & 0x40 != 0 {
+        let url_len = es.u8()?;
+        es.skip(url_len as usize)?;
+    }
+    if flags & 0x20 != 0 {
+        es.skip(2)?;
+    }
+
+    while es.remaining() > 0 {
+        let (tag, mut config) = read_descriptor(&mut es)?;
+        if tag != DECODER_CONFIG_DESCR_TAG {
+            continue;
+        }
+        let object_type = config.u8()?;
+        // streamType, bufferSizeDB, maxBitrate and avgBitrate
+        config.skip(12)?;
+        while config.remaining() > 0 {
+            let (tag, mut info) = read_descriptor(&mut config)?;
+            if tag == DEC_SPECIFIC_INFO_TAG {
+                return Ok((object_type, info.rest().to_vec()));
+            }
+        }
+        return Ok((object_type, Vec::new()));
+    }
+    decode_error("mp4: missing DecoderConfigDescriptor")
+}
+
+/// Converts an Opus `dOps` box into the OpusHead header used by Ogg and Matroska.
+fn opus_head(mut dops: BoxReader) -> Result<Vec<u8>> {
+    dops.skip(1)?;
+    let channels = dops.u8()?;
+    let pre_skip = dops.u16()?;
+    let sample_rate = dops.u32()?;
+    let gain = dops.u16()?;
+    let mut head = b"OpusHead".to_vec();
+    head.extend_from_slice(&[1, channels]);
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&sample_rate.to_le_bytes());
+    head.extend_from_slice(&gain.to_le_bytes());
+    // mapping family and table are single bytes
+    head.extend_from_slice(dops.rest());
+    Ok(head)
+}
+
+/// Child boxes of a sample entry, the boxes of a QuickTime `wave` box are included.
+fn config_boxes<'a>(mut entry: BoxReader<'a>) -> Result<Vec<(u32, BoxReader<'a>)>> {
+    let mut boxes = Vec::new();
+    while let Some((kind, child)) = entry.next_box()? {
+        if kind == WAVE {
+            boxes.extend(config_boxes(child)?);
+        } else {
+            boxes.push((kind, child));
+        }
+    }
+    Ok(boxes)
+}
+
+fn parse_visual_entry(format: u32, mut entry: BoxReader, stream: &mut Stream) -> Result<()> {
+    entry.skip(16)?;
+    stream.width = entry.u16()? as u32;
+    stream.height = entry.u16()? as u32;
+    // resolution, frame count, compressor name and depth
+    entry.skip(50)?;
+
+    stream.codec_id = match format {
+        AVC1 | AVC3 => CodecId::H264,
+        HVC1 | HEV1 => CodecId::Hevc,
+        AV01 => CodecId::Av1,
+        VP08 => CodecId::Vp8,
+        VP09 => CodecId::Vp9,
+        MP4V => CodecId::Mpeg4,
+        M1V => CodecId::Mpeg1Video,
+        M2V1 => CodecId::Mpeg2Video,
+        _ => CodecId::Unknown,
+    };
+    for (kind, mut child) in config_boxes(entry)? {
+        match kind {
+            AVCC | HVCC | AV1C | VPCC => stream.codec_params = child.rest().to_vec(),
+            ESDS => {
+                let (object_type, info) = parse_esds(child)?;
+                stream.codec_id = codec_from_object_type(object_type);
+                stream.codec_params = info;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_audio_entry(format: u32, mut entry: BoxReader, stream: &mut Stream) -> Result<()> {
+    let version = entry.u16()?;
+    // revision level and vendor
+    entry.skip(6)?;
+    stream.channels = entry.u16()?;
+    stream.bits_per_sample = entry.u16()?;
+    // compression id and packet size
+    entry.skip(4)?;
+    stream.sample_rate = entry.u32()? >> 16;
+
+    let mut lpcm_flags = 0;
+    match version {
+        // QuickTime sound description version 1, sizes of packets and frames
+        1 => entry.skip(16)?,
+        // QuickTime sound description version 2
+        2 => {
+            entry.skip(4)?;
+            stream.sample_rate = f64::from_bits(entry.u64()?) as u32;
+            stream.channels = entry.u32()? as u16;
+            entry.skip(4)?;
+            stream.bits_per_sample = entry.u32()? as u16;
+            lpcm_flags = entry.u32()?;
+            entry.skip(8)?;
+        }
+        _ => {}
+    }
+
+    let bits = stream.bits_per_sample;
+    stream.codec_id = match format {
+        MP4A => CodecId::Aac,
+        MP3 => CodecId::Mp3,
+        AC_3 => CodecId::Ac3,
+        EC_3 => CodecId::Eac3,
+        OPUS => CodecId::Opus,
+        FLAC => CodecId::Flac,
+        DTSC | DTSH | DTSL | DTSE => CodecId::Dts,
+        MLPA => CodecId::TrueHd,
+        SOWT => pcm_codec(false, false, true, bits),
+        TWOS => pcm_codec(false, true, true, bits),
+        RAW => CodecId::PcmU8,
+        IN24 => CodecId::PcmS24Be,
+        IN32 => CodecId::PcmS32Be,
+        FL32 => CodecId::PcmF32Be,
+        FL64 => CodecId::PcmF64Be,
+        LPCM => pcm_codec(
+            lpcm_flags & LPCM_FLAG_FLOAT != 0,
+            lpcm_flags & LPCM_FLAG_BIG_ENDIAN != 0,
+            lpcm_flags & LPCM_FLAG_SIGNED != 0,
+            bits,
+        ),
+        _ => CodecId::Unknown,
+    };
+
+    for (kind, mut child) in config_boxes(entry)? {
+        match kind {
+            ESDS => {
+                let (object_type, info) = parse_esds(child)?;
+                let codec_id = codec_from_object_type(object_type);
+                if codec_id != CodecId::Unknown {
+                    stream.codec_id = codec_id;
+                }
+                stream.codec_params = info;
+            }
+            DAC3 | DEC3 => stream.codec_params = child.rest().to_vec(),
+            DOPS => stream.codec_params = opus_head(child)?,
+            DFLA => {
+                child.full_box()?;
+                stream.codec_params = [b"fLaC", child.rest()].concat();
+            }
+            PCMC if matches!(format, IPCM | FPCM) => {
+                child.full_box()?;
+                let little_endian = child.u8()? & 0x01 != 0;
+                stream.bits_per_sample = child.u8()? as u16;
+                stream.codec_id = pcm_codec(format == FPCM, !little_endian, true, stream.bits_per_sample);
+            }
+            // little endian variants of the QuickTime PCM formats
+            ENDA if child.u16()? == 1 => {
+                stream.codec_id = match stream.codec_id {
+                    CodecId::PcmS24Be => CodecId::PcmS24Le,
+                    CodecId::PcmS32Be => CodecId::PcmS32Le,
+                    CodecId::PcmF32Be => CodecId::PcmF32Le,
+                    CodecId::PcmF64Be => CodecId::PcmF64Le,
+                    codec_id => codec_id,
+                };
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Sets the codec properties of a stream from a `stsd` box.
+pub(super) fn parse_stsd(mut stsd: BoxReader, handler: u32, stream: &mut Stream) -> Result<()> {
+    stsd.full_box()?;
+    let _entry_count = stsd.u32()?;
+    let Some((format, mut entry)) = stsd.next_box()? else {
+        return decode_error("mp4: empty stsd");
+    };
+    // reserved and data_reference_index
+    entry.skip(8)?;
+    match handler {
+        VIDE => {
+            stream.media_type = MediaType::Video;
+            parse_visual_entry(format, entry, stream)
+        }
+        SOUN => {
+            stream.media_type = MediaType::Audio;
+            parse_audio_entry(format, entry, stream)
+        }
+        _ => {
+            stream.media_type = MediaType::Subtitle;
+            stream.codec_id = match format {
+                WVTT => CodecId::WebVtt,
+                _ => CodecId::Unknown,
+            };
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use crate::error::{decode_error, Result};
+
+use super::boxes::{BoxReader, CO64, CTTS, STCO, STSC, STSS, STSZ, STTS, STZ2};
+
+/// A sample of a track with its location and timing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Sample {
+    /// Absolute position of the sample data.
+    pub pos: u64,
+    pub size: u32,
+    /// Decoding timestamp in media timescale units.
+    pub dts: i64,
+    /// Composition offset, the presentation timestamp is `dts + cts_offset`.
+    pub cts_offset: i32,
+    pub duration: u32,
+    pub keyframe: bool,
+}
+
+impl Sample {
+    pub fn pts(&self) -> i64 {
+        self.dts.saturating_add(self.cts_offset as i64)
+    }
+}
+
+/// Reads the entry count of a table and checks that the entries fit in the box.
+fn entry_count(reader: &mut BoxReader, entry_size: usize) -> Result<usize> {
+    let count = reader.u32()? as usize;
+    if count.saturating_mul(entry_size) > reader.remaining() {
+        return decode_error("mp4: sample table entries exceed the box");
+    }
+    Ok(count)
+}
+
+/// Tables of an `stbl` box describing the samples of a track.
+#[derive(Debug, Default)]
+pub(super) struct SampleTable {
+    /// `stts` runs of sample count and duration.
+    time_to_sample: Vec<(u32, u32)>,
+    /// `ctts` runs of sample count and composition offset.
+    composition_offsets: Vec<(u32, i32)>,
+    /// `stsc` entries of first chunk, 1-based, and samples per chunk.
+    sample_to_chunk: Vec<(u32, u32)>,
+    /// Size of all the samples, 0 if the sizes are in `sample_sizes`.
+    sample_size: u32,
+    sample_sizes: Vec<u32>,
+    sample_count: u32,
+    chunk_offsets: Vec<u64>,
+    /// 1-based numbers of the sync samples, `None` if all samples are sync samples.
+    sync_samples: Option<Vec<u32>>,
+}
+
+impl SampleTable {
+    /// Parses the sample tables of an `stbl` box, the sample descriptions are left to the caller.
+    pub fn parse(stbl: &BoxReader) -> Result<Self> {
+        let mut table = SampleTable::default();
+        let mut children = stbl.clone();
+        while let Some((kind, mut child)) = children.next_box()? {
+            match kind {
+                STTS => {
+                    child.full_box()?;
+                    let count = entry_count(&mut child, 8)?;
+                    table.time_to_sample = (0..count).map(|_| Ok((child.u32()?, child.u32()?))).collect::<Result<_>>()?;
+                }
+                CTTS => {
+                    // version 0 offsets are unsigned, but negative values stored there are common
+                    child.full_box()?;
+                    let count = entry_count(&mut child, 8)?;
+                    table.composition_offsets = (0..count).map(|_| Ok((child.u32()?, child.i32()?))).collect::<Result<_>>()?;
+                }
+                STSC => {
+                    child.full_box()?;
+                    let count = entry_count(&mut child, 12)?;
+                    table.sample_to_chunk = (0..count)
+                        .map(|_| {
+                            let entry = (child.u32()?, child.u32()?);
+                            child.skip(4)?;
+                            Ok(entry)
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                STSZ => {
+                    child.full_box()?;
+                    table.sample_size = child.u32()?;
+                    if table.sample_size == 0 {
+                        let count = entry_count(&mut child, 4)?;
+                        table.sample_sizes = (0..count).map(|_| child.u32()).collect::<Result<_>>()?;
+                        table.sample_count = count as u32;
+                    } else {
+                        table.sample_count = child.u32()?;
+                    }
+                }
+                STZ2 => {
+                    child.full_box()?;
+                    child.skip(3)?;
+                    let field_size = child.u8()?;
+                    let count = child.u32()? as usize;
+                    if count.saturating_mul(field_size as usize).div_ceil(8) > child.remaining() {
+                        return decode_error("mp4: sample table entries exceed the box");
+                    }
+                    table.sample_sizes = match field_size {
+                        4 => {
+                            let bytes = child.bytes(count.div_ceil(2))?;
+                            (0..count).map(|i| (bytes[i / 2] >> (4 - 4 * (i % 2)) & 0x0F) as u32).collect()
+                        }
+                        8 => (0..count).map(|_| child.u8().map(u32::from)).collect::<Result<_>>()?,
+                        16 => (0..count).map(|_| child.u16().map(u32::from)).collect::<Result<_>>()?,
+                        _ => return decode_error("mp4: invalid stz2 field size"),
+                    };
+                    table.sample_count = count as u32;
+                }
+                STCO => {
+                    child.full_box()?;
+                    let count = entry_count(&mut child, 4)?;
+                    table.chunk_offsets = (0..count).map(|_| child.u32().map(u64::from)).collect::<Result<_>>()?;
+                }
+                CO64 => {
+                    child.full_box()?;
+                    let count = entry_count(&mut child, 8)?;
+                    table.chunk_offsets = (0..count).map(|_| child.u64()).collect::<Result<_>>()?;
+                }
+                STSS => {
+                    child.full_box()?;
+                    let count = entry_count(&mut child, 4)?;
+                    let sync_samples: Vec<u32> = (0..count).map(|_| child.u32()).collect::<Result<_>>()?;
+                    // an empty table is treated as missing
+                    if !sync_samples.is_empty() {
+                        table.sync_samples = Some(sync_samples);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(table)
+    }
+
+    /// Number of samples the chunk offsets and `stsc` runs can hold.
+    fn addressable_samples(&self) -> u64 {
+        let chunk_count = self.chunk_offsets.len() as u64;
+        self.sample_to_chunk.iter().enumerate().fold(0u64, |total, (i, &(first_chunk, count))| {
+            let next_chunk = self.sample_to_chunk.get(i + 1).map_or(chunk_count + 1, |&(next, _)| next as u64);
+            let chunks = next_chunk.min(chunk_count + 1).saturating_sub(first_chunk as u64);
+            total.saturating_add(chunks.saturating_mul(count as u64))
+        })
+    }
+
+    /// Builds the list of samples. When `group_chunks` is set, the samples of each chunk are merged
+    /// into one, which is used for PCM tracks where every sample is a single audio frame. `input_len`
+    /// bounds the number of samples of a fixed size, the `stsz` sample count is not trusted.
+    pub fn samples(&self, group_chunks: bool, input_len: Option<u64>) -> Result<Vec<Sample>> {
+        let group_chunks = group_chunks && self.sample_size != 0;
+        let mut sample_count = (self.sample_count as u64).min(self.addressable_samples());
+        if let (Some(input_len), 1..) = (input_len, self.sample_size) {
+            sample_count = sample_count.min(input_len / self.sample_size as u64);
+        }
+        let sample_count = sample_count as u32;
+        let mut durations = self.time_to_sample.iter()
+            .flat_map(|&(count, duration)| std::iter::repeat_n(duration, count as usize));
+        let mut cts_offsets = self.composition_offsets.iter()
+            .flat_map(|&(count, offset)| std::iter::repeat_n(offset, count as usize));
+        let mut sync_samples = self.sync_samples.as_deref().map(|samples| samples.iter().peekable());
+
+        let capacity = if group_chunks { self.chunk_offsets.len() } else { sample_count as usize };
+        let mut samples = Vec::with_capacity(capacity);
+        let mut sample_number = 0u32;
+        let mut dts = 0i64;
+        let mut stsc_idx = 0;
+        for (chunk_idx, &chunk_offset) in self.chunk_offsets.iter().enumerate() {
+            let chunk = chunk_idx as u32 + 1;
+            while stsc_idx + 1 < self.sample_to_chunk.len() && self.sample_to_chunk[stsc_idx + 1].0 <= chunk {
+                stsc_idx += 1;
+            }
+            let samples_per_chunk = match self.sample_to_chunk.get(stsc_idx) {
+                Some(&(first_chunk, count)) if first_chunk <= chunk => count,
+                _ => 0,
+            };
+            let count = samples_per_chunk.min(sample_count - sample_number);
+            if count == 0 {
+                continue;
+            }
+
+            if group_chunks {
+                let duration = durations.by_ref().take(count as usize).try_fold(0u32, u32::checked_add);
+                let Some(duration) = duration else {
+                    return decode_error("mp4: chunk duration too large");
+                };
+                let cts_offset = cts_offsets.next().unwrap_or(0);
+                cts_offsets.by_ref().take(count as usize - 1).for_each(drop);
+                let Some(size) = self.sample_size.checked_mul(count) else {
+                    return decode_error("mp4: chunk too large");
+                };
+                samples.push(Sample { pos: chunk_offset, size, dts, cts_offset, duration, keyframe: true });
+                sample_number += count;
+                dts += duration as i64;
+                continue;
+            }
+
+            let mut pos = chunk_offset;
+            for _ in 0..count {
+                sample_number += 1;
+                let size = match self.sample_size {
+                    0 => self.sample_sizes[sample_number as usize - 1],
+                    size => size,
+                };
+                let duration = durations.next().unwrap_or(0);
+                let keyframe = match sync_samples.as_mut() {
+                    None => true,
+                    Some(sync_samples) => {
+                        while sync_samples.next_if(|&&number| number < sample_number).is_some() {}
+                        sync_samples.peek().is_some_and(|&&number| number == sample_number)
+                    }
+                };
+                samples.push(Sample {
+                    pos,
+                    size,
+                    dts,
+                    cts_offset: cts_offsets.next().unwrap_or(0),
+                    duration,
+                    keyframe,
+                });
+                pos += size as u64;
+                dts += duration as i64;
+            }
+        }
+        Ok(samples)
+    }
+}
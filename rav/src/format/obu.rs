@@ -0,0 +1,351 @@
+use crate::codec::{CodecId, MediaType};
+use crate::data::{IoRef, Packet2};
+use crate::error::{decode_error, end_of_stream_error, limit_error, Error, Result};
+
+use super::av1::{leb128, write_obu, ObuHeader, SequenceHeader, OBU_SEQUENCE_HEADER, OBU_TEMPORAL_DELIMITER};
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+/// Largest OBU or temporal unit.
+const MAX_UNIT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Frame rate used when the sequence header has no timing info, until `with_frame_rate` is called.
+const DEFAULT_FRAME_RATE: u32 = 25;
+
+/// Bitstream format of a raw AV1 stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObuFormat {
+    /// Low overhead bitstream format of section 5, OBUs with size fields, as in `.obu` files.
+    LowOverhead,
+    /// Length delimited format of annex B, temporal units, frame units and OBUs prefixed with their sizes.
+    AnnexB,
+}
+
+/// Reads a leb128 value from the input.
+fn read_leb128<S: MediaIoBufRead + ?Sized>(reader: &mut S) -> Result<u64> {
+    let mut value = 0u64;
+    for i in 0..8 {
+        let byte = reader.get_u8()?;
+        value |= ((byte & 0x7F) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    decode_error("obu: invalid leb128 value")
+}
+
+/// Converts a size read from the input, checking it against `MAX_UNIT_SIZE`.
+fn unit_size(size: u64) -> Result<usize> {
+    match usize::try_from(size) {
+        Ok(size) if size <= MAX_UNIT_SIZE => Ok(size),
+        _ => limit_error("obu: unit too large"),
+    }
+}
+
+/// Probe score of the first bytes of an input, a temporal delimiter OBU followed by a sequence header
+/// in `format`.
+pub(super) fn probe(data: &[u8], format: ObuFormat) -> u32 {
+    let obus = match format {
+        ObuFormat::LowOverhead => data,
+        ObuFormat::AnnexB => {
+            // temporal unit and frame unit sizes, then the size of each OBU
+            let mut pos = 0;
+            for _ in 0..2 {
+                let Some((_, len)) = leb128(&data[pos..]) else {
+                    return 0;
+                };
+                pos += len;
+            }
+            match leb128(&data[pos..]) {
+                Some((1, len)) => &data[pos + len..],
+                _ => return 0,
+            }
+        }
+    };
+    let Some((header, header_len)) = ObuHeader::parse(obus) else {
+        return 0;
+    };
+    let size_len = match (format, header.has_size) {
+        (ObuFormat::LowOverhead, true) if obus.get(header_len) == Some(&0) => 1,
+        (ObuFormat::AnnexB, false) => 0,
+        _ => return 0,
+    };
+    if header.obu_type != OBU_TEMPORAL_DELIMITER {
+        return 0;
+    }
+    let next = obus.get(header_len + size_len..).unwrap_or(&[]);
+    let next = match format {
+        // skip the size of the next OBU
+        ObuFormat::AnnexB => leb128(next).map_or(&[][..], |(_, len)| &next[len..]),
+        ObuFormat::LowOverhead => next,
+    };
+    match ObuHeader::parse(next) {
+        Some((header, _)) if header.obu_type == OBU_SEQUENCE_HEADER => PROBE_SCORE_MAX * 3 / 4,
+        _ => PROBE_SCORE_MAX / 4,
+    }
+}
+
+/// Demuxer for raw AV1 streams in the low overhead or the annex B format. Each temporal unit is a
+/// packet in the low overhead format, with size fields and without its temporal delimiter, as stored
+/// in MP4 and Matroska. The codec parameters are the `av1C` record of the first sequence header.
+/// Timestamps count the temporal units, in the time base of the sequence header timing info or of
+/// the frame rate.
+pub struct DemuxerObu<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    format: ObuFormat,
+    sequence_header: Option<SequenceHeader>,
+    /// Temporal unit read ahead by `new`, with its position.
+    pending: Option<(usize, Vec<u8>)>,
+    /// Position of the temporal delimiter which ended the previous temporal unit.
+    next_unit_pos: Option<usize>,
+    frame_count: i64,
+}
+
+impl<S: MediaIoBufRead> DemuxerObu<S> {
+    /// Creates the demuxer for a stream in `format`, reading the first temporal unit which must hold
+    /// a sequence header.
+    pub fn new(iobuf_reader: S, format: ObuFormat) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            format,
+            sequence_header: None,
+            pending: None,
+            next_unit_pos: None,
+            frame_count: 0,
+        };
+        demuxer.pending = demuxer.read_temporal_unit()?;
+        let Some(sequence_header) = demuxer.sequence_header.as_ref() else {
+            return decode_error("obu: no sequence header");
+        };
+        demuxer.streams.push(Stream {
+            codec_id: CodecId::Av1,
+            media_type: MediaType::Video,
+            time_base: sequence_header.frame_duration.unwrap_or(TimeBase::new(1, DEFAULT_FRAME_RATE)),
+            width: sequence_header.width,
+            height: sequence_header.height,
+            codec_params: sequence_header.av1c.clone(),
+            ..Default::default()
+        });
+        Ok(demuxer)
+    }
+
+    /// Sets the frame rate `num / den` used to generate the timestamps, instead of the timing info of
+    /// the sequence header or 25 fps.
+    pub fn with_frame_rate(mut self, num: u32, den: u32) -> Self {
+        if num != 0 && den != 0 {
+            self.streams[0].time_base = TimeBase::new(den, num);
+        }
+        self
+    }
+
+    /// Appends an OBU to a temporal unit, keeping the first sequence header.
+    fn push_obu(&mut self, unit: &mut Vec<u8>, header: &ObuHeader, payload: &[u8]) {
+        if header.obu_type == OBU_SEQUENCE_HEADER && self.sequence_header.is_none() {
+            self.sequence_header = SequenceHeader::parse(header, payload);
+        }
+        write_obu(unit, header, payload);
+    }
+
+    /// Reads the next temporal unit and its position, `None` at the end of the input.
+    fn read_temporal_unit(&mut self) -> Result<Option<(usize, Vec<u8>)>> {
+        match self.format {
+            ObuFormat::LowOverhead => self.read_low_overhead(),
+            ObuFormat::AnnexB => self.read_annexb(),
+        }
+    }
+
+    /// Reads OBUs up to the next temporal delimiter.
+    fn read_low_overhead(&mut self) -> Result<Option<(usize, Vec<u8>)>> {
+        let mut unit = Vec::new();
+        let mut unit_pos = self.next_unit_pos.take();
+        loop {
+            let obu_pos = self.iobuf_reader.pos();
+            let first = match self.iobuf_reader.get_u8() {
+                Ok(first) => first,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            };
+            let extension = if first & 0x04 != 0 { Some(self.iobuf_reader.get_u8()?) } else { None };
+            let Some((header, _)) = ObuHeader::parse(&[first, extension.unwrap_or_default()]) else {
+                return decode_error("obu: forbidden bit set");
+            };
+            if !header.has_size {
+                return decode_error("obu: OBU without size field");
+            }
+            let size = unit_size(read_leb128(&mut self.iobuf_reader)?)?;
+
+            if header.obu_type == OBU_TEMPORAL_DELIMITER {
+                self.iobuf_reader.skip(size)?;
+                if !unit.is_empty() {
+                    self.next_unit_pos = Some(obu_pos);
+                    return Ok(Some((unit_pos.unwrap_or(obu_pos), unit)));
+                }
+                unit_pos = Some(obu_pos);
+                continue;
+            }
+            if unit.len() + size > MAX_UNIT_SIZE {
+                return limit_error("obu: temporal unit too large");
+            }
+            let mut payload = vec![0u8; size];
+            self.iobuf_reader.get_bytes(&mut payload)?;
+            unit_pos.get_or_insert(obu_pos);
+            self.push_obu(&mut unit, &header, &payload);
+        }
+        Ok(unit_pos.filter(|_| !unit.is_empty()).map(|pos| (pos, unit)))
+    }
+
+    /// Reads a temporal unit and converts its OBUs to the low overhead format.
+    fn read_annexb(&mut self) -> Result<Option<(usize, Vec<u8>)>> {
+        let pos = self.iobuf_reader.pos();
+        let size = match self.iobuf_reader.get_u8() {
+            // the first byte is read apart to tell the end of the input from a truncated unit
+            Ok(first) if first & 0x80 == 0 => first as u64,
+            Ok(first) => (first & 0x7F) as u64 | read_leb128(&mut self.iobuf_reader)? << 7,
+            Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut data = vec![0u8; unit_size(size)?];
+        self.iobuf_reader.get_bytes(&mut data)?;
+
+        let mut unit = Vec::new();
+        let mut frame_units = &data[..];
+        while !frame_units.is_empty() {
+            let Some((frame_unit, rest)) = split_sized(frame_units) else {
+                return decode_error("obu: invalid frame unit size");
+            };
+            frame_units = rest;
+            let mut obus = frame_unit;
+            while !obus.is_empty() {
+                let Some((obu, rest)) = split_sized(obus) else {
+                    return decode_error("obu: invalid OBU length");
+                };
+                obus = rest;
+                let Some((header, header_len)) = ObuHeader::parse(obu) else {
+                    return decode_error("obu: invalid OBU header");
+                };
+                // the size field is optional, the OBU length gives the payload size
+                let payload = match header.has_size {
+                    true => leb128(&obu[header_len..]).and_then(|(size, len)| {
+                        obu.get(header_len + len..(header_len + len).checked_add(usize::try_from(size).ok()?)?)
+                    }),
+                    false => Some(&obu[header_len..]),
+                };
+                let Some(payload) = payload else {
+                    return decode_error("obu: invalid OBU size");
+                };
+                if header.obu_type != OBU_TEMPORAL_DELIMITER {
+                    self.push_obu(&mut unit, &header, payload);
+                }
+            }
+        }
+        Ok(Some((pos, unit)))
+    }
+}
+
+/// Splits a leb128 size prefixed element from the following data.
+fn split_sized(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (size, len) = leb128(data)?;
+    let end = len.checked_add(usize::try_from(size).ok()?)?;
+    Some((data.get(len..end)?, &data[end..]))
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerObu<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        let unit = match self.pending.take() {
+            Some(unit) => Some(unit),
+            None => match self.read_temporal_unit() {
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => None,
+                result => result?,
+            },
+        };
+        let Some((pos, data)) = unit else {
+            return end_of_stream_error();
+        };
+        packet.keyframe = self.sequence_header.as_ref().is_some_and(|header| header.is_keyframe(&data));
+        packet.ioref = IoRef::from(data);
+        packet.stream_id = 0;
+        packet.pts = self.frame_count;
+        packet.dts = self.frame_count;
+        packet.duration = 1;
+        packet.pos = pos as i64;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+
+    /// Packs `(value, bits)` fields MSB first, padded with the trailing one bit.
+    fn pack_bits(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut bits: Vec<bool> = fields.iter().flat_map(|&(value, count)| (0..count).rev().map(move |i| value >> i & 1 != 0)).collect();
+        bits.push(true);
+        bits.chunks(8).map(|byte| byte.iter().enumerate().fold(0, |acc, (i, &bit)| acc | (bit as u8) << (7 - i))).collect()
+    }
+
+    /// Main profile, level 4.0, 640x360, 8 bit 4:2:0.
+    fn sequence_header() -> Vec<u8> {
+        pack_bits(&[
+            (0, 3), (0, 1), (0, 1), (0, 1), (0, 1), (0, 5), (0, 12), (8, 5), (0, 1),
+            (15, 4), (15, 4), (639, 16), (359, 16), (0, 1), (7, 3), (0, 4), (1, 1), (0, 2),
+            (1, 1), (1, 1), (6, 3), (2, 3), (0, 1), (0, 1), (0, 1), (0, 1), (0, 2), (0, 1),
+        ])
+    }
+
+    /// OBU without size field.
+    fn obu(obu_type: u8, payload: &[u8]) -> Vec<u8> {
+        [&[obu_type << 3][..], payload].concat()
+    }
+
+    fn sized(data: &[u8]) -> Vec<u8> {
+        [&[data.len() as u8][..], data].concat()
+    }
+
+    #[test]
+    fn low_overhead_and_annexb() {
+        let seq = sequence_header();
+        // a key frame, an inter frame, and a frame header with a tile group
+        let units = [
+            vec![(OBU_SEQUENCE_HEADER, seq.clone()), (6, vec![0x10, 0xAA, 0xBB])],
+            vec![(6, vec![0x30, 0xCC])],
+            vec![(3, vec![0x30]), (4, vec![0xDD, 0xEE])],
+        ];
+        let mut expected = Vec::new();
+        let mut low_overhead = Vec::new();
+        let mut annexb = Vec::new();
+        for obus in &units {
+            let mut packet = Vec::new();
+            low_overhead.extend_from_slice(&[0x12, 0]);
+            let mut frame_unit = sized(&obu(OBU_TEMPORAL_DELIMITER, &[]));
+            for (obu_type, payload) in obus {
+                let header = ObuHeader { obu_type: *obu_type, extension: None, has_size: true };
+                write_obu(&mut packet, &header, payload);
+                frame_unit.extend(sized(&obu(*obu_type, payload)));
+            }
+            low_overhead.extend_from_slice(&packet);
+            annexb.extend(sized(&sized(&frame_unit)));
+            expected.push(packet);
+        }
+
+        for (format, data) in [(ObuFormat::LowOverhead, low_overhead), (ObuFormat::AnnexB, annexb)] {
+            let mut demuxer = DemuxerObu::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 4096)), format).unwrap();
+            let stream = &demuxer.streams()[0];
+            assert_eq!((stream.codec_id, stream.width, stream.height), (CodecId::Av1, 640, 360));
+            assert_eq!(stream.codec_params, [&[0x81, 0x08, 0x0C, 0x00, 0x0A, seq.len() as u8][..], &seq].concat());
+            let mut packets = Vec::new();
+            let mut packet = Packet2::default();
+            while demuxer.read_packet(&mut packet).is_ok() {
+                packets.push((packet.pts, packet.keyframe, packet.data().to_vec()));
+            }
+            assert_eq!(packets, vec![(0, true, expected[0].clone()), (1, false, expected[1].clone()), (2, false, expected[2].clone())]);
+        }
+    }
+}
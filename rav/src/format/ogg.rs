@@ -0,0 +1,512 @@
+use std::collections::VecDeque;
+
+use crate::data::{IoRef, Packet2, NOPTS_VALUE};
+use crate::error::{decode_error, end_of_stream_error, Error, Result};
+use crate::metadata::{Tag, TagTarget};
+
+use super::{Demux, MediaIoBufRead, Stream, PROBE_SCORE_MAX};
+
+mod codecs;
+
+pub(super) use codecs::parse_vorbis_comment;
+use codecs::{add_header, codec_params, granule_to_time, identify, is_keyframe, packet_duration, OggCodec};
+
+// page header flags
+const CONTINUED: u8 = 0x01;
+const BOS: u8 = 0x02;
+const EOS: u8 = 0x04;
+
+const PAGE_HEADER_LEN: usize = 27;
+
+/// Number of pages read by `new` to find the header packets of the streams.
+const PROBE_PAGES: usize = 256;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// CRC-32 with polynomial 0x04C11DB7, no reflection and a zero initial value.
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { crc << 1 ^ 0x04C1_1DB7 } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn page_crc(page: &[u8]) -> u32 {
+    page.iter().enumerate().fold(0u32, |crc, (i, &byte)| {
+        // the checksum field is computed as zeros
+        let byte = if (22..26).contains(&i) { 0 } else { byte };
+        crc << 8 ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+    })
+}
+
+/// A page whose checksum was verified.
+#[derive(Debug)]
+struct Page {
+    pos: usize,
+    flags: u8,
+    granule: i64,
+    serial: u32,
+    lacing: Vec<u8>,
+    /// The whole page, including the header.
+    data: IoRef,
+}
+
+/// State of a logical bitstream.
+#[derive(Debug)]
+struct LogicalStream {
+    serial: u32,
+    /// `None` until the first packet was identified, and for unsupported codecs.
+    stream_id: Option<usize>,
+    codec: Option<OggCodec>,
+    header_count: usize,
+    headers: Vec<Vec<u8>>,
+    /// Start of a packet continued on the next page.
+    partial: Option<Vec<u8>>,
+    /// Time of the next packet, known once a granule position was read.
+    next_pts: Option<i64>,
+}
+
+impl LogicalStream {
+    fn headers_done(&self) -> bool {
+        self.codec.is_none() || self.headers.len() >= self.header_count
+    }
+}
+
+/// Probe score of the first bytes of an input, the capture pattern and version 0 of the first page.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    if data.starts_with(b"OggS\x00") { PROBE_SCORE_MAX } else { 0 }
+}
+
+/// Ogg demuxer for Opus, Vorbis, FLAC and Theora. Pages are checked with their CRC and the demuxer
+/// resyncs on the next capture pattern after damaged data. Packet timestamps are derived from the
+/// granule positions and the packet durations, the Opus pre-skip is subtracted so that the first
+/// audible sample is at 0. Chained streams starting after the first data pages are ignored.
+pub struct DemuxerOgg<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    logical_streams: Vec<LogicalStream>,
+    tags: Vec<Tag>,
+    pending: VecDeque<Packet2>,
+    /// Page copy used to verify the checksum.
+    page_buf: Vec<u8>,
+    /// Number of pages dropped because of a checksum mismatch.
+    crc_errors: u64,
+}
+
+impl<S: MediaIoBufRead> DemuxerOgg<S> {
+    /// Creates the demuxer, reading the pages up to the end of the header packets of all the streams.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            logical_streams: Vec::new(),
+            tags: Vec::new(),
+            pending: VecDeque::new(),
+            page_buf: Vec::new(),
+            crc_errors: 0,
+        };
+        demuxer.read_headers()?;
+        Ok(demuxer)
+    }
+
+    /// Serial number of the logical bitstream of a stream.
+    pub fn serial(&self, stream_id: usize) -> Option<u32> {
+        self.logical_streams.iter().find(|logical| logical.stream_id == Some(stream_id)).map(|logical| logical.serial)
+    }
+
+    /// Number of pages dropped because their checksum didn't match.
+    pub fn crc_errors(&self) -> u64 {
+        self.crc_errors
+    }
+
+    fn read_headers(&mut self) -> Result<()> {
+        for _ in 0..PROBE_PAGES {
+            let page = match self.read_page() {
+                Ok(page) => page,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            };
+            let bos = page.flags & BOS != 0;
+            self.process_page(page)?;
+            // all the BOS pages precede the data pages
+            if !bos && !self.streams.is_empty() && self.logical_streams.iter().all(LogicalStream::headers_done) {
+                return Ok(());
+            }
+        }
+        if self.streams.is_empty() {
+            return decode_error("ogg: no supported streams");
+        }
+        Ok(())
+    }
+
+    /// Reads the next page with a valid checksum.
+    fn read_page(&mut self) -> Result<Page> {
+        loop {
+            let mut header = [0u8; PAGE_HEADER_LEN];
+            self.iobuf_reader.peek_bytes(&mut header)?;
+            if &header[..4] != b"OggS" || header[4] != 0 {
+                self.iobuf_reader.skip(1)?;
+                continue;
+            }
+            let header_len = PAGE_HEADER_LEN + header[26] as usize;
+            self.page_buf.resize(header_len, 0);
+            self.iobuf_reader.peek_bytes(&mut self.page_buf)?;
+            let page_len = header_len + self.page_buf[PAGE_HEADER_LEN..].iter().map(|&len| len as usize).sum::<usize>();
+            self.page_buf.resize(page_len, 0);
+            self.iobuf_reader.peek_bytes(&mut self.page_buf)?;
+            if page_crc(&self.page_buf) != u32::from_le_bytes(header[22..26].try_into().unwrap()) {
+                self.crc_errors += 1;
+                self.iobuf_reader.skip(1)?;
+                continue;
+            }
+
+            let pos = self.iobuf_reader.pos();
+            let mut data = IoRef::default();
+            self.iobuf_reader.get_ioref(&mut data, page_len)?;
+            data.make_shared();
+            return Ok(Page {
+                pos,
+                flags: header[5],
+                granule: i64::from_le_bytes(header[6..14].try_into().unwrap()),
+                serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+                lacing: self.page_buf[PAGE_HEADER_LEN..header_len].to_vec(),
+                data,
+            });
+        }
+    }
+
+    /// Reassembles the packets of a page and queues the data packets.
+    fn process_page(&mut self, page: Page) -> Result<()> {
+        let index = match self.logical_streams.iter().position(|logical| logical.serial == page.serial) {
+            Some(index) => index,
+            // new logical bitstreams are only accepted before the data pages
+            None if page.flags & BOS != 0 && self.pending.is_empty() => {
+                self.logical_streams.push(LogicalStream {
+                    serial: page.serial,
+                    stream_id: None,
+                    codec: None,
+                    header_count: 0,
+                    headers: Vec::new(),
+                    partial: None,
+                    next_pts: None,
+                });
+                self.logical_streams.len() - 1
+            }
+            None => return Ok(()),
+        };
+
+        // packets completed on this page
+        let mut packets = Vec::new();
+        let logical = &mut self.logical_streams[index];
+        let mut skip_first = false;
+        if page.flags & CONTINUED == 0 {
+            logical.partial = None;
+        } else if logical.partial.is_none() {
+            // the start of the packet was lost
+            skip_first = true;
+        }
+        let mut offset = PAGE_HEADER_LEN + page.lacing.len();
+        let mut len = 0;
+        for &lacing in &page.lacing {
+            len += lacing as usize;
+            if lacing == 255 {
+                continue;
+            }
+            let data = page.data.slice(offset, len);
+            offset += len;
+            len = 0;
+            if std::mem::take(&mut skip_first) {
+                continue;
+            }
+            packets.push(match logical.partial.take() {
+                Some(mut partial) => {
+                    partial.extend_from_slice(data.data());
+                    IoRef::from(partial)
+                }
+                None => data,
+            });
+        }
+        if len > 0 && !skip_first {
+            logical.partial.get_or_insert_with(Vec::new).extend_from_slice(page.data.slice(offset, len).data());
+        }
+
+        let mut data_packets = Vec::new();
+        for packet in packets {
+            if self.logical_streams[index].headers_done() && self.logical_streams[index].codec.is_some() {
+                data_packets.push(packet);
+            } else {
+                self.add_header_packet(index, packet.data())?;
+            }
+        }
+        self.queue_packets(index, &page, data_packets);
+        Ok(())
+    }
+
+    fn add_header_packet(&mut self, index: usize, packet: &[u8]) -> Result<()> {
+        let logical = &mut self.logical_streams[index];
+        if logical.headers.is_empty() {
+            let mut stream = Stream { id: self.streams.len(), ..Default::default() };
+            let Some((codec, header_count)) = identify(packet, &mut stream) else {
+                // unsupported codecs are skipped, the first packet is kept so it isn't identified again
+                logical.headers.push(Vec::new());
+                return Ok(());
+            };
+            logical.stream_id = Some(stream.id);
+            logical.codec = Some(codec);
+            logical.header_count = header_count;
+            logical.headers.push(packet.to_vec());
+            self.streams.push(stream);
+        } else if let (Some(codec), Some(stream_id)) = (logical.codec.as_mut(), logical.stream_id) {
+            let comments = add_header(codec, packet, logical.headers.len(), &mut logical.headers)?;
+            if let Some(simple_tags) = comments {
+                let target = TagTarget { stream_ids: vec![stream_id], ..Default::default() };
+                self.tags.push(Tag { target, simple_tags });
+            }
+        }
+
+        let logical = &self.logical_streams[index];
+        if let (Some(codec), Some(stream_id), true) = (&logical.codec, logical.stream_id, logical.headers_done()) {
+            codec_params(codec, &logical.headers, &mut self.streams[stream_id].codec_params);
+        }
+        Ok(())
+    }
+
+    /// Times the data packets completed on a page and queues them.
+    fn queue_packets(&mut self, index: usize, page: &Page, packets: Vec<IoRef>) {
+        let logical = &mut self.logical_streams[index];
+        let (Some(codec), Some(stream_id)) = (logical.codec.as_mut(), logical.stream_id) else {
+            return;
+        };
+        let durations: Vec<Option<i64>> = packets.iter().map(|packet| packet_duration(codec, packet.data())).collect();
+        let end = (page.granule != -1).then(|| granule_to_time(codec, page.granule));
+
+        // the granule position gives the end of the last packet, the first ones are timed from it
+        if logical.next_pts.is_none() {
+            if let (Some(end), Some(total)) = (end, durations.iter().copied().sum::<Option<i64>>()) {
+                logical.next_pts = Some(end - total);
+            }
+        }
+
+        let first = self.pending.len();
+        for (packet, duration) in packets.into_iter().zip(durations) {
+            let pts = logical.next_pts.unwrap_or(NOPTS_VALUE);
+            logical.next_pts = logical.next_pts.zip(duration).map(|(pts, duration)| pts + duration);
+            self.pending.push_back(Packet2 {
+                keyframe: is_keyframe(codec, packet.data()),
+                ioref: packet,
+                stream_id,
+                pts,
+                dts: pts,
+                duration: duration.unwrap_or(0),
+                pos: page.pos as i64,
+            });
+        }
+
+        if let Some(end) = end {
+            // the last page can end before its last packet, to trim the padding
+            let queued = self.pending.len() > first;
+            let last = self.pending.back_mut().filter(|last| queued && last.pts != NOPTS_VALUE);
+            if let Some(last) = last.filter(|_| page.flags & EOS != 0) {
+                last.duration = last.duration.min(end - last.pts).max(0);
+            }
+            // resync after packets of unknown duration
+            logical.next_pts.get_or_insert(end);
+        }
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerOgg<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        loop {
+            if let Some(next) = self.pending.pop_front() {
+                *packet = next;
+                return Ok(());
+            }
+            let page = match self.read_page() {
+                Ok(page) => page,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return end_of_stream_error(),
+                Err(err) => return Err(err),
+            };
+            self.process_page(page)?;
+        }
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{CodecId, MediaType};
+    use crate::format::{IoBufSupplierMem, MediaSourceStream, TimeBase};
+
+    /// Builds a page, the last packet continues on the next page if `complete` is false.
+    fn page(flags: u8, granule: i64, serial: u32, packets: &[&[u8]], complete: bool) -> Vec<u8> {
+        let mut lacing = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            if complete || i + 1 < packets.len() {
+                lacing.push((packet.len() % 255) as u8);
+            }
+        }
+        let mut data = [&b"OggS\0"[..], &[flags], &granule.to_le_bytes(), &serial.to_le_bytes(), &[0; 8]].concat();
+        data.push(lacing.len() as u8);
+        data.extend_from_slice(&lacing);
+        data.extend(packets.iter().copied().flatten());
+        let crc = page_crc(&data);
+        data[22..26].copy_from_slice(&crc.to_le_bytes());
+        data
+    }
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut data = [&4u32.to_le_bytes()[..], b"test", &(comments.len() as u32).to_le_bytes()].concat();
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+    type PacketInfo = (usize, i64, i64, bool, Vec<u8>);
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerOgg<S>) -> Vec<PacketInfo> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.stream_id, packet.pts, packet.duration, packet.keyframe, packet.data().to_vec()));
+        }
+        packets
+    }
+
+    #[test]
+    fn opus_pre_skip_and_resync() {
+        let head = [&b"OpusHead\x01\x02"[..], &312u16.to_le_bytes(), &48000u32.to_le_bytes(), &[0, 0, 0]].concat();
+        let tags = [&b"OpusTags"[..], &vorbis_comment(&["title=Test"])].concat();
+        // 20 ms CELT packets of 960 samples, the second one spans two pages
+        let long: Vec<u8> = std::iter::once(0xF8).chain((0..599).map(|i| i as u8)).collect();
+        let (first, rest) = long.split_at(510);
+        let last_page = page(CONTINUED | EOS, 3 * 960 - 100, 7, &[rest, &[0xF8, 3]], true);
+        let mut corrupted = last_page.clone();
+        corrupted[40] ^= 0xFF;
+        let data = [
+            page(BOS, 0, 7, &[&head], true),
+            page(0, 0, 7, &[&tags], true),
+            page(0, 960, 7, &[&[0xF8, 1], first], false),
+            b"garbage OggS".to_vec(),
+            corrupted,
+            last_page,
+        ].concat();
+
+        let mut demuxer = DemuxerOgg::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 16))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.media_type), (CodecId::Opus, MediaType::Audio));
+        assert_eq!((stream.sample_rate, stream.channels, stream.time_base), (48000, 2, TimeBase::new(1, 48000)));
+        assert_eq!(stream.codec_params, head);
+        assert_eq!(demuxer.serial(0), Some(7));
+        assert_eq!(demuxer.tags()[0].target.stream_ids, vec![0]);
+        assert_eq!(demuxer.tags()[0].simple_tags[0].name, "TITLE");
+        assert_eq!(demuxer.tags()[0].simple_tags[0].value.as_deref(), Some("Test"));
+
+        assert_eq!(read_all(&mut demuxer), vec![
+            (0, -312, 960, true, vec![0xF8, 1]),
+            (0, 648, 960, true, long),
+            (0, 1608, 860, true, vec![0xF8, 3]),
+        ]);
+        assert_eq!(demuxer.crc_errors(), 1);
+    }
+
+    #[test]
+    fn vorbis_block_sizes() {
+        let id = [&b"\x01vorbis\0\0\0\0\x02"[..], &44100u32.to_le_bytes(), &[0; 12], &[0xB8, 0x01]].concat();
+        let mut stream = Stream::default();
+        let (mut codec, header_count) = identify(&id, &mut stream).unwrap();
+        assert_eq!((stream.codec_id, stream.sample_rate, stream.channels, header_count), (CodecId::Vorbis, 44100, 2, 3));
+
+        // some codebook bits, two modes with a short and a long block, the framing bit
+        let mut bits = vec![true, false, true, true, false, true, false, false, true];
+        bits.extend([true, false, false, false, false, false]);
+        for (flag, mapping) in [(false, 0u8), (true, 1u8)] {
+            bits.push(flag);
+            bits.extend(std::iter::repeat_n(false, 32));
+            bits.extend((0..8).map(|i| mapping >> i & 1 == 1));
+        }
+        bits.push(true);
+        let mut setup = b"\x05vorbis".to_vec();
+        setup.extend(bits.chunks(8).map(|byte| byte.iter().enumerate().fold(0u8, |acc, (i, &bit)| acc | (bit as u8) << i)));
+
+        let mut headers = vec![id];
+        let comment = [&b"\x03vorbis"[..], &vorbis_comment(&["ALBUM=Album"])].concat();
+        let tags = add_header(&mut codec, &comment, 1, &mut headers).unwrap().unwrap();
+        assert_eq!((tags[0].name.as_str(), tags[0].value.as_deref()), ("ALBUM", Some("Album")));
+        assert_eq!(add_header(&mut codec, &setup, 2, &mut headers), Ok(None));
+        let durations: Vec<_> = [0x02, 0x00, 0x02, 0x02].iter().map(|&mode| packet_duration(&mut codec, &[mode])).collect();
+        assert_eq!(durations, vec![Some(0), Some(576), Some(576), Some(1024)]);
+    }
+
+    #[test]
+    fn multiplexed_theora_and_flac() {
+        let mut theora = [&b"\x80theora\x03\x02\x01\x00\x14\x00\x0F\x00\x01\x40\x00\x00\xF0\x00\x00"[..], &25u32.to_be_bytes(), &1u32.to_be_bytes()].concat();
+        theora.extend_from_slice(&[0; 10]);
+        theora.extend_from_slice(&[0x00, 0xC0]);
+        let theora_comment = [&b"\x81theora"[..], &vorbis_comment(&["ENCODER=test"])].concat();
+        let theora_setup = b"\x82theora\x00".to_vec();
+        // 44.1 kHz, 2 channels, 16 bits
+        let mut streaminfo = vec![0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0, 0x0A, 0xC4, 0x42, 0xF0];
+        streaminfo.resize(34, 0);
+        let flac = [&b"\x7FFLAC\x01\x00\x00\x01fLaC\x00\x00\x00\x22"[..], &streaminfo].concat();
+        let flac_comment = [&[0x84, 0, 0, 20][..], &vorbis_comment(&["ARTIST=Someone"])].concat();
+        let frame = |number: u8| vec![0xFF, 0xF8, 0xC9, 0x18, number, 0xAA];
+
+        let data = [
+            page(BOS, 0, 1, &[&theora], true),
+            page(BOS, 0, 2, &[b"\x01unknown"], true),
+            page(BOS, 0, 3, &[&flac], true),
+            page(0, 0, 1, &[&theora_comment, &theora_setup], true),
+            page(0, 0, 3, &[&flac_comment], true),
+            page(0, 1 << 6 | 2, 1, &[&[0x00, 1], &[0x40, 2], &[0x40, 3]], true),
+            page(0, 0, 2, &[b"skipped"], true),
+            page(EOS, 8192, 3, &[&frame(0), &frame(1)], true),
+        ].concat();
+
+        let mut demuxer = DemuxerOgg::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 64))).unwrap();
+        let streams = demuxer.streams();
+        assert_eq!(streams.len(), 2);
+        assert_eq!((streams[0].codec_id, streams[0].media_type), (CodecId::Theora, MediaType::Video));
+        assert_eq!((streams[0].width, streams[0].height, streams[0].time_base), (320, 240, TimeBase::new(1, 25)));
+        assert_eq!(&streams[0].codec_params[..3], &[2, 42, theora_comment.len() as u8]);
+        assert_eq!(streams[0].codec_params.len(), 3 + theora.len() + theora_comment.len() + theora_setup.len());
+        assert_eq!((streams[1].codec_id, streams[1].sample_rate, streams[1].channels), (CodecId::Flac, 44100, 2));
+        assert_eq!(streams[1].bits_per_sample, 16);
+        assert_eq!(streams[1].codec_params, [&flac[9..], &flac_comment].concat());
+        assert_eq!((demuxer.serial(0), demuxer.serial(1)), (Some(1), Some(3)));
+        assert_eq!(demuxer.tags().len(), 2);
+        assert_eq!(demuxer.tags()[1].target.stream_ids, vec![1]);
+        assert_eq!(demuxer.tags()[1].simple_tags[0].value.as_deref(), Some("Someone"));
+
+        let packets = read_all(&mut demuxer);
+        assert_eq!(packets.iter().map(|packet| (packet.0, packet.1, packet.2, packet.3)).collect::<Vec<_>>(), vec![
+            (0, 0, 1, true),
+            (0, 1, 1, false),
+            (0, 2, 1, false),
+            (1, 0, 4096, true),
+            (1, 4096, 4096, true),
+        ]);
+        assert_eq!(packets[4].4, frame(1));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::codec::{CodecId, MediaType};
+use crate::error::{decode_error, Result};
+use crate::metadata::SimpleTag;
+
+use crate::format::{Stream, TimeBase};
+
+/// Codec of a logical bitstream with the state needed to time its packets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum OggCodec {
+    Opus {
+        /// Samples at 48 kHz to drop at the start of the stream.
+        pre_skip: u16,
+    },
+    Vorbis {
+        /// Short and long block sizes.
+        block_sizes: [u32; 2],
+        /// Block flag of each mode, from the setup header.
+        mode_block_flags: Vec<bool>,
+        /// Block size of the previous audio packet, 0 at the start of the stream.
+        previous_block_size: u32,
+    },
+    Theora {
+        /// Bits of the granule position holding the frames since the last keyframe.
+        keyframe_shift: u8,
+        /// The granule position refers to the end of the frame, from version 3.2.1.
+        granule_is_end: bool,
+    },
+    Flac,
+}
+
+/// Identifies the codec of a logical bitstream from its first packet, returns the codec, the number of
+/// header packets including the first one, and the stream parameters.
+pub(super) fn identify(packet: &[u8], stream: &mut Stream) -> Option<(OggCodec, usize)> {
+    if packet.starts_with(b"OpusHead") && packet.len() >= 19 {
+        stream.media_type = MediaType::Audio;
+        stream.codec_id = CodecId::Opus;
+        stream.channels = packet[9] as u16;
+        stream.sample_rate = 48000;
+        stream.time_base = TimeBase::new(1, 48000);
+        stream.codec_params = packet.to_vec();
+        let pre_skip = u16::from_le_bytes([packet[10], packet[11]]);
+        return Some((OggCodec::Opus { pre_skip }, 2));
+    }
+    if packet.starts_with(b"\x01vorbis") && packet.len() >= 30 {
+        let sample_rate = u32::from_le_bytes(packet[12..16].try_into().unwrap());
+        if sample_rate == 0 {
+            return None;
+        }
+        stream.media_type = MediaType::Audio;
+        stream.codec_id = CodecId::Vorbis;
+        stream.channels = packet[11] as u16;
+        stream.sample_rate = sample_rate;
+        stream.time_base = TimeBase::new(1, sample_rate);
+        let block_sizes = [1 << (packet[28] & 0x0F), 1 << (packet[28] >> 4)];
+        return Some((OggCodec::Vorbis { block_sizes, mode_block_flags: Vec::new(), previous_block_size: 0 }, 3));
+    }
+    if packet.starts_with(b"\x80theora") && packet.len() >= 42 {
+        let u24 = |pos: usize| (packet[pos] as u32) << 16 | (packet[pos + 1] as u32) << 8 | packet[pos + 2] as u32;
+        let u32_be = |pos: usize| u32::from_be_bytes(packet[pos..pos + 4].try_into().unwrap());
+        let (numerator, denominator) = (u32_be(22), u32_be(26));
+        if numerator == 0 || denominator == 0 {
+            return None;
+        }
+        stream.media_type = MediaType::Video;
+        stream.codec_id = CodecId::Theora;
+        stream.width = u24(14);
+        stream.height = u24(17);
+        stream.time_base = TimeBase::new(denominator, numerator);
+        let keyframe_shift = (packet[40] & 0x03) << 3 | packet[41] >> 5;
+        let granule_is_end = (packet[7], packet[8], packet[9]) >= (3, 2, 1);
+        return Some((OggCodec::Theora { keyframe_shift, granule_is_end }, 3));
+    }
+    // FLAC mapping header, followed by the fLaC signature and STREAMINFO
+    if packet.starts_with(b"\x7FFLAC") && packet.len() >= 51 && &packet[9..13] == b"fLaC" {
+        let streaminfo = &packet[17..];
+        let sample_rate = (streaminfo[10] as u32) << 12 | (streaminfo[11] as u32) << 4 | (streaminfo[12] as u32) >> 4;
+        if sample_rate == 0 {
+            return None;
+        }
+        stream.media_type = MediaType::Audio;
+        stream.codec_id = CodecId::Flac;
+        stream.sample_rate = sample_rate;
+        stream.channels = ((streaminfo[12] >> 1) & 0x07) as u16 + 1;
+        stream.bits_per_sample = (((streaminfo[12] & 0x01) << 4) | streaminfo[13] >> 4) as u16 + 1;
+        stream.time_base = TimeBase::new(1, sample_rate);
+        stream.codec_params = packet[9..].to_vec();
+        let header_packets = u16::from_be_bytes([packet[7], packet[8]]) as usize;
+        return Some((OggCodec::Flac, 1 + header_packets));
+    }
+    None
+}
+
+/// Appends a header packet following the first one to the codec parameters. Returns the Vorbis comments
+/// of the packet, if it is a comment header.
+pub(super) fn add_header(
+    codec: &mut OggCodec,
+    packet: &[u8],
+    index: usize,
+    headers: &mut Vec<Vec<u8>>,
+) -> Result<Option<Vec<SimpleTag>>> {
+    let comments = match codec {
+        OggCodec::Opus { .. } if packet.starts_with(b"OpusTags") => Some(parse_vorbis_comment(&packet[8..])),
+        OggCodec::Vorbis { .. } if index == 1 && packet.starts_with(b"\x03vorbis") => {
+            Some(parse_vorbis_comment(&packet[7..]))
+        }
+        OggCodec::Vorbis { mode_block_flags, .. } if index == 2 => {
+            if !packet.starts_with(b"\x05vorbis") {
+                return decode_error("ogg: missing Vorbis setup header");
+            }
+            *mode_block_flags = vorbis_mode_block_flags(packet)?;
+            None
+        }
+        OggCodec::Theora { .. } if packet.starts_with(b"\x81theora") => Some(parse_vorbis_comment(&packet[7..])),
+        // metadata blocks, VORBIS_COMMENT has type 4
+        OggCodec::Flac if packet.len() >= 4 && packet[0] & 0x7F == 4 => Some(parse_vorbis_comment(&packet[4..])),
+        _ => None,
+    };
+    headers.push(packet.to_vec());
+    Ok(comments.flatten())
+}
+
+/// Builds the codec parameters once all the header packets were received.
+pub(super) fn codec_params(codec: &OggCodec, headers: &[Vec<u8>], params: &mut Vec<u8>) {
+    match codec {
+        // Xiph lacing of the three headers, as in Matroska
+        OggCodec::Vorbis { .. } | OggCodec::Theora { .. } => {
+            params.clear();
+            params.push(headers.len() as u8 - 1);
+            for header in &headers[..headers.len() - 1] {
+                params.extend(std::iter::repeat_n(255, header.len() / 255));
+                params.push((header.len() % 255) as u8);
+            }
+            params.extend(headers.iter().flatten());
+        }
+        OggCodec::Flac => {
+            for header in &headers[1..] {
+                params.extend_from_slice(header);
+            }
+        }
+        OggCodec::Opus { .. } => {}
+    }
+}
+
+/// Returns the duration of a data packet in stream time base units, `None` if unknown.
+pub(super) fn packet_duration(codec: &mut OggCodec, packet: &[u8]) -> Option<i64> {
+    match codec {
+        OggCodec::Opus { .. } => opus_duration(packet),
+        OggCodec::Vorbis { block_sizes, mode_block_flags, previous_block_size } => {
+            // audio packets start with a 0 bit, followed by the mode number
+            let &first = packet.first()?;
+            if first & 0x01 != 0 {
+                return None;
+            }
+            let mode_bits = usize::BITS - (mode_block_flags.len().saturating_sub(1)).leading_zeros();
+            let mode = (first as usize >> 1) & ((1 << mode_bits) - 1);
+            let block_size = block_sizes[*mode_block_flags.get(mode)? as usize];
+            // the first packet only primes the overlap
+            let duration = match *previous_block_size {
+                0 => 0,
+                previous => (previous / 4 + block_size / 4) as i64,
+            };
+            *previous_block_size = block_size;
+            Some(duration)
+        }
+        OggCodec::Theora { .. } => Some(1),
+        OggCodec::Flac => flac_block_size(packet).map(i64::from),
+    }
+}
+
+/// Returns true if a data packet can be decoded on its own.
+pub(super) fn is_keyframe(codec: &OggCodec, packet: &[u8]) -> bool {
+    match codec {
+        // intra frames have the frame type bit cleared
+        OggCodec::Theora { .. } => packet.first().is_some_and(|&first| first & 0x40 == 0),
+        _ => true,
+    }
+}
+
+/// Converts a granule position to the end time of the last packet completed on its page.
+pub(super) fn granule_to_time(codec: &OggCodec, granule: i64) -> i64 {
+    match *codec {
+        OggCodec::Opus { pre_skip } => granule - pre_skip as i64,
+        OggCodec::Theora { keyframe_shift, granule_is_end } => {
+            let frames = (granule >> keyframe_shift) + (granule & ((1 << keyframe_shift) - 1));
+            if granule_is_end { frames } else { frames + 1 }
+        }
+        _ => granule,
+    }
+}
+
+/// Returns the duration of an Opus packet in 48 kHz samples, from its TOC byte.
+fn opus_duration(packet: &[u8]) -> Option<i64> {
+    let &toc = packet.first()?;
+    let config = toc >> 3;
+    let frame_size = match config {
+        0..=11 => [480, 960, 1920, 2880][(config & 3) as usize],
+        12..=15 => [480, 960][(config & 1) as usize],
+        _ => [120, 240, 480, 960][(config & 3) as usize],
+    };
+    let frames = match toc & 3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => (*packet.get(1)? & 0x3F) as i64,
+    };
+    Some(frame_size * frames)
+}
+
+/// Returns the block size of a FLAC frame from its header.
+pub(super) fn flac_block_size(frame: &[u8]) -> Option<u32> {
+    if frame.len() < 5 || frame[0] != 0xFF || frame[1] & 0xFE != 0xF8 {
+        return None;
+    }
+    let code = frame[2] >> 4;
+    // the coded frame or sample number is UTF-8 like
+    let number_len = match frame[4].leading_ones() {
+        0 => 1,
+        len @ 2..=7 => len as usize,
+        _ => return None,
+    };
+    let extra = &frame[4 + number_len..];
+    match code {
+        1 => Some(192),
+        2..=5 => Some(576 << (code - 2)),
+        6 => extra.first().map(|&size| size as u32 + 1),
+        7 => extra.get(..2).map(|size| u16::from_be_bytes([size[0], size[1]]) as u32 + 1),
+        8..=15 => Some(256 << (code - 8)),
+        _ => None,
+    }
+}
+
+/// Reads the block flags of the Vorbis modes, which are at the end of the setup header. The codebooks,
+/// floors and residues preceding them would have to be decoded to find them from the start, so the
+/// header is read backwards, like the bits of the mode configurations are laid out.
+fn vorbis_mode_block_flags(setup: &[u8]) -> Result<Vec<bool>> {
+    let total = setup.len() * 8;
+    // bit `pos` counted from the end of the packet, Vorbis packs the bits from the LSB
+    let bit = |pos: usize| (setup[(total - 1 - pos) / 8] >> ((total - 1 - pos) % 8)) & 1;
+    let bits = |pos: &mut usize, count: usize| {
+        let value = (0..count).fold(0u32, |value, i| value << 1 | bit(*pos + i) as u32);
+        *pos += count;
+        value
+    };
+
+    // skip the padding up to the framing bit
+    let mut pos = 0;
+    while total - pos > 97 && bit(pos) == 0 {
+        pos += 1;
+    }
+    if total - pos <= 97 {
+        return decode_error("ogg: invalid Vorbis setup header");
+    }
+    pos += 1;
+    let modes_end = pos;
+
+    // each mode has a mapping, transform type, window type and block flag, the mode count precedes them
+    let mut mode_count = 0;
+    let mut found = 0;
+    while total - pos >= 97 && mode_count < 64 {
+        if bits(&mut pos, 8) > 63 || bits(&mut pos, 16) != 0 || bits(&mut pos, 16) != 0 {
+            break;
+        }
+        pos += 1;
+        mode_count += 1;
+        if bits(&mut pos.clone(), 6) as usize + 1 == mode_count {
+            found = mode_count;
+        }
+    }
+    if found == 0 {
+        return decode_error("ogg: Vorbis modes not found");
+    }
+    Ok((0..found).rev().map(|i| bit(modes_end + i * 41 + 40) == 1).collect())
+}
+
+/// Parses a Vorbis comment header without its packet type prefix, into `KEY=value` tags.
+pub(in crate::format) fn parse_vorbis_comment(data: &[u8]) -> Option<Vec<SimpleTag>> {
+    let mut pos = 0;
+    let read_u32 = |pos: &mut usize| {
+        let value = u32::from_le_bytes(data.get(*pos..*pos + 4)?.try_into().unwrap()) as usize;
+        *pos += 4;
+        Some(value)
+    };
+    let vendor_len = read_u32(&mut pos)?;
+    pos += vendor_len;
+    let count = read_u32(&mut pos)?;
+    let mut tags = Vec::new();
+    for _ in 0..count.min(data.len() / 4) {
+        let len = read_u32(&mut pos)?;
+        let comment = String::from_utf8_lossy(data.get(pos..pos + len)?);
+        pos += len;
+        if let Some((name, value)) = comment.split_once('=') {
+            tags.push(SimpleTag {
+                name: name.to_ascii_uppercase(),
+                value: Some(value.to_string()),
+                default: true,
+                ..Default::default()
+            });
+        }
+    }
+    Some(tags)
+}
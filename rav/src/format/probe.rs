@@ -0,0 +1,289 @@
+use std::sync::Mutex;
+
+use crate::codec::CodecId;
+use crate::error::{invalid_input_error, Result};
+
+use super::{
+    adts, annexb, avi, flac, flv, ivf, mkv, mp3, mp4, obu, ogg, ps, wav, Demux, DemuxerAdts, DemuxerAnnexB,
+    DemuxerAvi, DemuxerFlac, DemuxerFlv, DemuxerIvf, DemuxerMkv, DemuxerMp3, DemuxerMp4, DemuxerObu, DemuxerOgg,
+    DemuxerPs, DemuxerWav, IoBufSupplierFile, IoBufSupply, MediaIoBufRead, MediaSourceStream, ObuFormat,
+};
+
+/// Highest probe score, the input is certainly of the format.
+pub const PROBE_SCORE_MAX: u32 = 100;
+
+/// Probe score of a format whose file extensions include the extension of the input.
+pub const PROBE_SCORE_EXTENSION: u32 = 50;
+
+/// Bytes at the start of the input given to the probe functions.
+const PROBE_SIZE: usize = 64 * 1024;
+
+/// The input of the demuxers opened by `FormatContext::open_input`.
+pub type ProbeStream = MediaSourceStream<IoBufSupplierFile>;
+
+/// A demuxer `FormatContext::open_input` can select.
+#[derive(Debug, Clone, Copy)]
+pub struct InputFormat {
+    /// Short name, used to force the format.
+    pub name: &'static str,
+    /// File extensions in lower case, without the dot.
+    pub extensions: &'static [&'static str],
+    /// Scores the first bytes of the input from 0, not this format, to `PROBE_SCORE_MAX`.
+    pub probe: fn(&[u8]) -> u32,
+    pub open: fn(ProbeStream) -> Result<Box<dyn Demux>>,
+}
+
+/// Score of a format for an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeScore {
+    pub name: &'static str,
+    pub score: u32,
+}
+
+/// The demuxers of this crate, in the order ties are broken.
+pub fn input_formats() -> Vec<InputFormat> {
+    vec![
+        InputFormat {
+            name: "mkv",
+            extensions: &["mkv", "mka", "mks", "mk3d", "webm"],
+            probe: mkv::probe,
+            open: |stream| Ok(Box::new(DemuxerMkv::new(stream)?)),
+        },
+        InputFormat {
+            name: "mp4",
+            extensions: &["mp4", "m4a", "m4v", "m4s", "mov", "3gp", "cmfv", "cmfa"],
+            probe: mp4::probe,
+            open: |stream| Ok(Box::new(DemuxerMp4::new(stream)?)),
+        },
+        InputFormat {
+            name: "avi",
+            extensions: &["avi"],
+            probe: avi::probe,
+            open: |stream| Ok(Box::new(DemuxerAvi::new(stream)?)),
+        },
+        InputFormat {
+            name: "wav",
+            extensions: &["wav", "rf64", "bw64", "aif", "aiff", "aifc"],
+            probe: wav::probe,
+            open: |stream| Ok(Box::new(DemuxerWav::new(stream)?)),
+        },
+        InputFormat {
+            name: "ogg",
+            extensions: &["ogg", "oga", "ogv", "opus", "spx"],
+            probe: ogg::probe,
+            open: |stream| Ok(Box::new(DemuxerOgg::new(stream)?)),
+        },
+        InputFormat {
+            name: "flac",
+            extensions: &["flac"],
+            probe: flac::probe,
+            open: |stream| Ok(Box::new(DemuxerFlac::new(stream)?)),
+        },
+        InputFormat {
+            name: "flv",
+            extensions: &["flv"],
+            probe: flv::probe,
+            open: |stream| Ok(Box::new(DemuxerFlv::new(stream)?)),
+        },
+        InputFormat {
+            name: "ivf",
+            extensions: &["ivf"],
+            probe: ivf::probe,
+            open: |stream| Ok(Box::new(DemuxerIvf::new(stream)?)),
+        },
+        InputFormat {
+            name: "ps",
+            extensions: &["mpg", "mpeg", "vob", "ps"],
+            probe: ps::probe,
+            open: |stream| Ok(Box::new(DemuxerPs::new(stream)?)),
+        },
+        InputFormat {
+            name: "mp3",
+            extensions: &["mp3", "mp2", "m2a"],
+            probe: mp3::probe,
+            open: |stream| Ok(Box::new(DemuxerMp3::new(stream)?)),
+        },
+        InputFormat {
+            name: "adts",
+            extensions: &["aac"],
+            probe: adts::probe,
+            open: |stream| Ok(Box::new(DemuxerAdts::new(stream)?)),
+        },
+        InputFormat {
+            name: "h264",
+            extensions: &["h264", "264", "avc"],
+            probe: |data| annexb::probe(data, CodecId::H264),
+            open: |stream| Ok(Box::new(DemuxerAnnexB::new(stream, CodecId::H264)?)),
+        },
+        InputFormat {
+            name: "hevc",
+            extensions: &["h265", "265", "hevc"],
+            probe: |data| annexb::probe(data, CodecId::Hevc),
+            open: |stream| Ok(Box::new(DemuxerAnnexB::new(stream, CodecId::Hevc)?)),
+        },
+        InputFormat {
+            name: "obu",
+            extensions: &["obu", "av1"],
+            probe: |data| obu::probe(data, ObuFormat::LowOverhead),
+            open: |stream| Ok(Box::new(DemuxerObu::new(stream, ObuFormat::LowOverhead)?)),
+        },
+        InputFormat {
+            name: "obu_annexb",
+            extensions: &[],
+            probe: |data| obu::probe(data, ObuFormat::AnnexB),
+            open: |stream| Ok(Box::new(DemuxerObu::new(stream, ObuFormat::AnnexB)?)),
+        },
+    ]
+}
+
+/// Formats of other crates added by `register_input_format`.
+static REGISTERED_FORMATS: Mutex<Vec<InputFormat>> = Mutex::new(Vec::new());
+
+/// Adds a format to `FormatOptions::default()`, so `FormatContext::open_input` probes it after the demuxers of
+/// this crate. A format with the name of a registered one is ignored.
+pub fn register_input_format(format: InputFormat) {
+    let mut formats = REGISTERED_FORMATS.lock().unwrap_or_else(|err| err.into_inner());
+    if !formats.iter().any(|registered| registered.name == format.name) {
+        formats.push(format);
+    }
+}
+
+/// Lower case extension of a path or URL.
+fn extension(uri: &str) -> Option<String> {
+    let path = uri.split(['?', '#']).next().unwrap_or(uri);
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    name.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase())
+}
+
+/// Options of `FormatContext::open_input_with`: the formats to probe and the forced format.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    formats: Vec<InputFormat>,
+    forced_format: Option<String>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        let mut formats = input_formats();
+        formats.extend(REGISTERED_FORMATS.lock().unwrap_or_else(|err| err.into_inner()).iter().copied());
+        Self { formats, forced_format: None }
+    }
+}
+
+impl FormatOptions {
+    /// Adds a format, such as a demuxer of another crate.
+    pub fn with_format(mut self, format: InputFormat) -> Self {
+        self.formats.push(format);
+        self
+    }
+
+    /// Opens the input with the format named `name`, without probing.
+    pub fn with_forced_format(mut self, name: &str) -> Self {
+        self.forced_format = Some(name.to_string());
+        self
+    }
+
+    pub fn formats(&self) -> &[InputFormat] {
+        &self.formats
+    }
+
+    /// Scores every format for `data`, the first bytes of an input, and the extension of its `uri`. The
+    /// scores are sorted from the highest, formats with equal scores keep their order.
+    pub fn probe_data(&self, data: &[u8], uri: &str) -> Vec<ProbeScore> {
+        let extension = extension(uri);
+        let mut scores: Vec<ProbeScore> = self.formats.iter().map(|format| {
+            let matches_extension = extension.as_deref().is_some_and(|ext| format.extensions.contains(&ext));
+            let extension_score = if matches_extension { PROBE_SCORE_EXTENSION } else { 0 };
+            ProbeScore { name: format.name, score: (format.probe)(data).min(PROBE_SCORE_MAX).max(extension_score) }
+        }).collect();
+        scores.sort_by_key(|score| std::cmp::Reverse(score.score));
+        scores
+    }
+
+    /// Scores every format for the file at `uri`.
+    pub fn probe(&self, uri: &str) -> Result<Vec<ProbeScore>> {
+        let mut stream = open_file(uri)?;
+        Ok(self.probe_data(&peek_probe_data(&mut stream)?, uri))
+    }
+
+    /// The formats to try in order, the forced one or the ones with a non-zero score.
+    pub(super) fn candidates(&self, stream: &mut ProbeStream, uri: &str) -> Result<Vec<InputFormat>> {
+        let format = |name: &str| self.formats.iter().find(|format| format.name == name).copied();
+        if let Some(name) = &self.forced_format {
+            return match format(name) {
+                Some(format) => Ok(vec![format]),
+                None => invalid_input_error(),
+            };
+        }
+        let scores = self.probe_data(&peek_probe_data(stream)?, uri);
+        Ok(scores.iter().filter(|score| score.score > 0).filter_map(|score| format(score.name)).collect())
+    }
+}
+
+pub(super) fn open_file(uri: &str) -> Result<ProbeStream> {
+    let mut iobuf_supplier = IoBufSupplierFile::default();
+    iobuf_supplier.open_input(uri)?;
+    Ok(MediaSourceStream::new(iobuf_supplier))
+}
+
+/// The first bytes of the input, without consuming them.
+fn peek_probe_data(stream: &mut ProbeStream) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; stream.stream_len().unwrap_or(PROBE_SIZE).min(PROBE_SIZE)];
+    stream.peek_bytes(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::FormatContext;
+
+    #[test]
+    fn scores_and_extensions() {
+        let options = FormatOptions::default();
+        let best = |data: &[u8], uri: &str| options.probe_data(data, uri)[0];
+        let ebml = [0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x82, 0x88, b'm', b'a', b't', b'r', b'o', b's', b'k', b'a'];
+        assert_eq!(best(&ebml, "movie.bin"), ProbeScore { name: "mkv", score: PROBE_SCORE_MAX });
+        assert_eq!(best(b"\0\0\0\x18ftypisom", "a"), ProbeScore { name: "mp4", score: PROBE_SCORE_MAX });
+        assert_eq!(best(b"RIFF\0\0\0\0AVI LIST", "a").name, "avi");
+        assert_eq!(best(b"RIFF\0\0\0\0WAVEfmt ", "a").name, "wav");
+        assert_eq!(best(b"OggS\0\x02", "a").name, "ogg");
+        assert_eq!(best(b"ID3\x04\0\0\0\0\0\x02\0\0fLaC", "a").name, "flac");
+        assert_eq!(best(&[0, 0, 1, 0xBA, 0x44, 0, 4, 0, 4, 1, 1, 0x89, 0xC3, 0xF8, 0, 0, 1, 0xE0], "a").name, "ps");
+        assert_eq!(best(&[0, 0, 0, 1, 0x67, 0x64], "a").name, "h264");
+        assert_eq!(best(&[0, 0, 0, 1, 0x40, 0x01], "a").name, "hevc");
+        assert_eq!(best(&[0x12, 0x00, 0x0A, 0x0B], "a").name, "obu");
+        assert_eq!(best(&[0x10, 0x0F, 0x01, 0x10, 0x0C, 0x08], "a").name, "obu_annexb");
+
+        // four ADTS frames of 16 bytes, the next one truncated
+        let frame = [&[0xFF, 0xF1, 0x50, 0x80, 0x02, 0x1F, 0xFC][..], &[0x21; 9]].concat();
+        let adts = frame.repeat(4);
+        assert_eq!(best(&adts, "a"), ProbeScore { name: "adts", score: PROBE_SCORE_MAX * 9 / 10 });
+        // unknown content, the extension decides
+        assert_eq!(best(b"junk", "http://host/audio.MP3?x=1"), ProbeScore { name: "mp3", score: PROBE_SCORE_EXTENSION });
+        assert_eq!(best(b"junk", "a").score, 0);
+    }
+
+    #[test]
+    fn open_probed_and_forced() {
+        let path = std::env::temp_dir().join(format!("rav-probe-test-{}.bin", std::process::id()));
+        let format = [1u16.to_le_bytes(), 1u16.to_le_bytes()].concat();
+        let format = [&format[..], &8000u32.to_le_bytes(), &16000u32.to_le_bytes(), &2u16.to_le_bytes(), &16u16.to_le_bytes()].concat();
+        let data = [&b"RIFF\x2C\0\0\0WAVEfmt \x10\0\0\0"[..], &format, b"data\x08\0\0\0", &[1, 0, 2, 0, 3, 0, 4, 0]].concat();
+        std::fs::write(&path, &data).unwrap();
+        let uri = path.to_str().unwrap();
+
+        let context = FormatContext::open_input(uri).unwrap();
+        assert_eq!(context.format_name(), "wav");
+        assert_eq!(context.streams()[0].sample_rate, 8000);
+        let scores = FormatOptions::default().probe(uri).unwrap();
+        assert_eq!(scores[0], ProbeScore { name: "wav", score: PROBE_SCORE_MAX });
+        assert!(scores[1..].iter().all(|score| score.score == 0));
+
+        let options = FormatOptions::default().with_forced_format("mkv");
+        assert!(FormatContext::open_input_with(uri, &options).is_err());
+        let options = FormatOptions::default().with_forced_format("nope");
+        assert!(matches!(FormatContext::open_input_with(uri, &options), Err(crate::error::Error::InvalidInput)));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::codec::CodecId;
+use crate::data::{IoRef, Packet2, NOPTS_VALUE};
+use crate::error::{decode_error, end_of_stream_error, Error, Result};
+
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+const PROGRAM_END: u8 = 0xB9;
+const PACK_HEADER: u8 = 0xBA;
+const PROGRAM_STREAM_MAP: u8 = 0xBC;
+const PRIVATE_STREAM_1: u8 = 0xBD;
+const EXTENDED_STREAM: u8 = 0xFD;
+
+const MPEG1_PACK_LEN: usize = 12;
+const MPEG2_PACK_LEN: usize = 14;
+
+/// Longest PES header with the private_stream_1 substream header: the MPEG-2 fixed fields,
+/// 255 bytes of optional fields and the 7 bytes of the LPCM header.
+const MAX_PES_HEADER_LEN: usize = 9 + 255 + 7;
+
+/// Bytes read by `new` to find the streams.
+const PROBE_SIZE: usize = 256 * 1024;
+
+/// Decodes a 33-bit timestamp coded in 5 bytes with marker bits.
+fn parse_timestamp(data: &[u8]) -> i64 {
+    (((data[0] >> 1) & 0x07) as i64) << 30
+        | (data[1] as i64) << 22
+        | ((data[2] >> 1) as i64) << 15
+        | (data[3] as i64) << 7
+        | (data[4] >> 1) as i64
+}
+
+/// Parses the header of a PES packet, in the MPEG-1 or MPEG-2 syntax. Returns the header length and
+/// the timestamps, `None` if the header is invalid or truncated.
+fn parse_pes_header(data: &[u8]) -> Option<(usize, i64, i64)> {
+    let (mut pts, mut dts) = (NOPTS_VALUE, NOPTS_VALUE);
+    if *data.get(6)? & 0xC0 == 0x80 {
+        let header_len = 9 + *data.get(8)? as usize;
+        let flags = data[7] >> 6;
+        if flags & 0x02 != 0 {
+            pts = parse_timestamp(data.get(9..14)?);
+        }
+        if flags == 0x03 {
+            dts = parse_timestamp(data.get(14..19)?);
+        }
+        return (header_len <= data.len()).then_some((header_len, pts, dts));
+    }
+
+    // MPEG-1: stuffing, the optional STD buffer size, then the timestamps
+    let mut pos = 6;
+    while *data.get(pos)? == 0xFF && pos < 6 + 16 {
+        pos += 1;
+    }
+    if *data.get(pos)? & 0xC0 == 0x40 {
+        pos += 2;
+    }
+    match *data.get(pos)? >> 4 {
+        0x02 => {
+            pts = parse_timestamp(data.get(pos..pos + 5)?);
+            pos += 5;
+        }
+        0x03 => {
+            pts = parse_timestamp(data.get(pos..pos + 5)?);
+            dts = parse_timestamp(data.get(pos + 5..pos + 10)?);
+            pos += 10;
+        }
+        _ if data[pos] == 0x0F => pos += 1,
+        _ => return None,
+    }
+    Some((pos, pts, dts))
+}
+
+/// Codec of a private_stream_1 substream and the length of its header, including the substream ID.
+fn substream_codec(substream_id: u8) -> Option<(CodecId, usize)> {
+    match substream_id {
+        0x20..=0x3F => Some((CodecId::DvdSubtitle, 1)),
+        0x80..=0x87 => Some((CodecId::Ac3, 4)),
+        0x88..=0x8F => Some((CodecId::Dts, 4)),
+        // DVD LPCM, its header holds the audio parameters
+        0xA0..=0xAF => Some((CodecId::PcmS16Be, 7)),
+        0xC0..=0xCF => Some((CodecId::Eac3, 4)),
+        _ => None,
+    }
+}
+
+/// Maps a stream_type of the program stream map to a codec.
+fn codec_from_stream_type(stream_type: u8) -> CodecId {
+    match stream_type {
+        0x01 => CodecId::Mpeg1Video,
+        0x02 => CodecId::Mpeg2Video,
+        0x03 | 0x04 => CodecId::Mp2,
+        0x0F => CodecId::Aac,
+        0x11 => CodecId::AacLatm,
+        0x10 => CodecId::Mpeg4,
+        0x1B => CodecId::H264,
+        0x24 => CodecId::Hevc,
+        0x81 => CodecId::Ac3,
+        _ => CodecId::Unknown,
+    }
+}
+
+/// True if the payload of a video PES packet starts a random access point: a sequence header, or
+/// an IDR picture or parameter set.
+fn is_keyframe(codec_id: CodecId, data: &[u8]) -> bool {
+    let mut start_codes = data.windows(4).filter(|code| code[..3] == [0, 0, 1]).map(|code| code[3]);
+    match codec_id {
+        CodecId::Mpeg1Video | CodecId::Mpeg2Video => start_codes.any(|code| code == 0xB3),
+        CodecId::H264 => start_codes.any(|code| matches!(code & 0x1F, 5 | 7)),
+        CodecId::Hevc => start_codes.any(|code| matches!((code >> 1) & 0x3F, 16..=23 | 32..=34)),
+        _ => true,
+    }
+}
+
+/// A DVD subpicture unit being reassembled from its PES packets.
+#[derive(Debug, Default)]
+struct Subpicture {
+    pts: i64,
+    pos: i64,
+    data: Vec<u8>,
+}
+
+/// Length of a pack header and whether it is MPEG-1, `None` if its marker bits are not set. `header`
+/// holds its first 14 bytes, or 12 for MPEG-1.
+fn pack_header(header: &[u8; MPEG2_PACK_LEN]) -> Option<(usize, bool)> {
+    // the marker bits around the SCR and the mux rate must be set
+    if header[4] & 0xC0 == 0x40 {
+        let markers = header[4] & 0x04 != 0 && header[6] & 0x04 != 0 && header[8] & 0x04 != 0
+            && header[9] & 0x01 != 0 && header[12] & 0x03 == 0x03;
+        markers.then_some((MPEG2_PACK_LEN + (header[13] & 0x07) as usize, false))
+    } else if header[4] & 0xF0 == 0x20 {
+        let markers = header[4] & 0x01 != 0 && header[6] & 0x01 != 0 && header[8] & 0x01 != 0
+            && header[9] & 0x80 != 0 && header[11] & 0x01 != 0;
+        markers.then_some((MPEG1_PACK_LEN, true))
+    } else {
+        None
+    }
+}
+
+/// Probe score of the first bytes of an input, a valid pack header followed by another start code.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    let mut header = [0u8; MPEG2_PACK_LEN];
+    let len = data.len().min(MPEG2_PACK_LEN);
+    header[..len].copy_from_slice(&data[..len]);
+    if !header.starts_with(&[0, 0, 1, PACK_HEADER]) || len < MPEG1_PACK_LEN {
+        return 0;
+    }
+    match pack_header(&header) {
+        Some((len, _)) if data.get(len..len + 3) == Some(&[0, 0, 1]) => PROBE_SCORE_MAX,
+        Some(_) => PROBE_SCORE_MAX / 2,
+        None => 0,
+    }
+}
+
+/// Demuxer for MPEG program streams, as in `.mpg` and `.vob` files. Each PES packet is a packet in the
+/// 90 kHz time base, except for DVD subpictures which are reassembled into whole units. The
+/// private_stream_1 substreams, AC-3, DTS, E-AC-3, LPCM and subpictures, are separate streams with
+/// the substream header removed. [`pes_id`](Self::pes_id) identifies them by `0xBD00` plus the substream ID,
+/// the other streams by the PES stream_id. Data that doesn't start with a valid pack, system header or PES
+/// packet is skipped up to the next start code.
+pub struct DemuxerPs<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    /// Index in `streams` of each PES stream_id, or `0xBD00` plus the substream ID.
+    stream_index: HashMap<u16, usize>,
+    /// stream_type of the elementary streams listed in the program stream map, by stream_id.
+    stream_types: HashMap<u8, u8>,
+    /// The last pack header used the MPEG-1 syntax.
+    mpeg1: bool,
+    subpictures: HashMap<usize, Subpicture>,
+    /// Packets read by `new`.
+    pending: VecDeque<Packet2>,
+    /// False after data was skipped, until a valid unit is read.
+    synced: bool,
+    resyncs: u64,
+}
+
+impl<S: MediaIoBufRead> DemuxerPs<S> {
+    /// Creates the demuxer, reading the start of the stream to find the streams. Streams starting
+    /// later are added when their first packet is read.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            stream_index: HashMap::new(),
+            stream_types: HashMap::new(),
+            mpeg1: false,
+            subpictures: HashMap::new(),
+            pending: VecDeque::new(),
+            synced: true,
+            resyncs: 0,
+        };
+        let probe_end = demuxer.iobuf_reader.pos() + PROBE_SIZE;
+        while demuxer.iobuf_reader.pos() < probe_end {
+            match demuxer.read_unit() {
+                Ok(Some(mut packet)) => {
+                    // the queued packets own their data, so they don't hold the input IoBufs
+                    packet.ioref = IoRef::from(packet.data().to_vec());
+                    demuxer.pending.push_back(packet);
+                }
+                Ok(None) => {}
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        if demuxer.streams.is_empty() {
+            return decode_error("ps: no streams found");
+        }
+        Ok(demuxer)
+    }
+
+    /// PES stream_id of stream `stream_id`, or `0xBD00` plus the substream ID for private_stream_1 substreams.
+    pub fn pes_id(&self, stream_id: usize) -> Option<u16> {
+        self.stream_index.iter().find(|(_, &index)| index == stream_id).map(|(&id, _)| id)
+    }
+
+    /// Number of times data was skipped to find the next start code.
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs
+    }
+
+    /// Skips a byte of data that doesn't start a valid unit.
+    fn skip_invalid(&mut self) -> Result<Option<Packet2>> {
+        if self.synced {
+            self.synced = false;
+            self.resyncs += 1;
+        }
+        self.iobuf_reader.skip(1)?;
+        Ok(None)
+    }
+
+    /// Reads the unit at the current position, returns a packet if it was a PES packet completing one.
+    fn read_unit(&mut self) -> Result<Option<Packet2>> {
+        let mut start = [0u8; 6];
+        self.iobuf_reader.peek_bytes(&mut start[..4])?;
+        if start[..3] != [0, 0, 1] || start[3] < PROGRAM_END {
+            return self.skip_invalid();
+        }
+        match start[3] {
+            PROGRAM_END => {
+                self.iobuf_reader.skip(4)?;
+                return Ok(None);
+            }
+            PACK_HEADER => return self.read_pack_header(),
+            _ => {}
+        }
+
+        self.iobuf_reader.peek_bytes(&mut start)?;
+        let len = 6 + u16::from_be_bytes([start[4], start[5]]) as usize;
+        match start[3] {
+            PROGRAM_STREAM_MAP => {
+                let mut data = vec![0u8; len];
+                self.iobuf_reader.get_bytes(&mut data)?;
+                self.parse_program_stream_map(&data[6..]);
+            }
+            PRIVATE_STREAM_1 | 0xC0..=0xEF | EXTENDED_STREAM => return self.read_pes(start[3], len),
+            // system header, padding, private_stream_2 with the DVD navigation data, and others
+            _ => self.iobuf_reader.skip(len)?,
+        }
+        self.synced = true;
+        Ok(None)
+    }
+
+    fn read_pack_header(&mut self) -> Result<Option<Packet2>> {
+        let mut header = [0u8; MPEG2_PACK_LEN];
+        self.iobuf_reader.peek_bytes(&mut header[..MPEG1_PACK_LEN])?;
+        if header[4] & 0xC0 == 0x40 {
+            self.iobuf_reader.peek_bytes(&mut header)?;
+        }
+        let Some((len, mpeg1)) = pack_header(&header) else {
+            return self.skip_invalid();
+        };
+        self.mpeg1 = mpeg1;
+        self.iobuf_reader.skip(len)?;
+        self.synced = true;
+        Ok(None)
+    }
+
+    /// Keeps the stream_type of the elementary streams, without the PSM header.
+    fn parse_program_stream_map(&mut self, data: &[u8]) {
+        let u16_at = |pos: usize| data.get(pos..pos + 2).map(|value| u16::from_be_bytes([value[0], value[1]]) as usize);
+        let Some(info_len) = u16_at(2) else {
+            return;
+        };
+        let Some(map_len) = u16_at(4 + info_len) else {
+            return;
+        };
+        let Some(mut entries) = data.get(6 + info_len..6 + info_len + map_len) else {
+            return;
+        };
+        while entries.len() >= 4 {
+            let es_info_len = u16::from_be_bytes([entries[2], entries[3]]) as usize;
+            self.stream_types.insert(entries[1], entries[0]);
+            entries = entries.get(4 + es_info_len..).unwrap_or_default();
+        }
+    }
+
+    /// Returns the index of the stream with `id`, adding the stream if needed. `header` is the start
+    /// of the payload, with the substream header.
+    fn stream_index(&mut self, id: u16, codec_id: CodecId, header: &[u8]) -> usize {
+        if let Some(&index) = self.stream_index.get(&id) {
+            return index;
+        }
+        let mut stream = Stream {
+            id: self.streams.len(),
+            codec_id,
+            media_type: codec_id.media_type(),
+            time_base: TimeBase::new(1, 90000),
+            ..Default::default()
+        };
+        if codec_id == CodecId::PcmS16Be && header.len() >= 7 {
+            // quantization, sampling frequency and channels
+            stream.bits_per_sample = [16, 20, 24, 0][(header[5] >> 6) as usize];
+            stream.sample_rate = [48000, 96000, 44100, 32000][((header[5] >> 4) & 0x03) as usize];
+            stream.channels = (header[5] & 0x07) as u16 + 1;
+            // 20 and 24 bit samples are packed in groups
+            if stream.bits_per_sample != 16 {
+                stream.codec_id = CodecId::Unknown;
+            }
+        }
+        self.streams.push(stream);
+        self.stream_index.insert(id, self.streams.len() - 1);
+        self.streams.len() - 1
+    }
+
+    fn read_pes(&mut self, stream_id: u8, len: usize) -> Result<Option<Packet2>> {
+        let pos = self.iobuf_reader.pos();
+        let mut header = vec![0u8; len.min(6 + MAX_PES_HEADER_LEN)];
+        self.iobuf_reader.peek_bytes(&mut header)?;
+        let Some((header_len, pts, dts)) = parse_pes_header(&header) else {
+            return self.skip_invalid();
+        };
+        self.synced = true;
+
+        let (id, codec_id, substream_len) = if stream_id == PRIVATE_STREAM_1 {
+            let substream_id = header.get(header_len).copied().unwrap_or_default();
+            match substream_codec(substream_id) {
+                Some((codec_id, substream_len)) => (0xBD00 | substream_id as u16, codec_id, substream_len),
+                None => {
+                    self.iobuf_reader.skip(len)?;
+                    return Ok(None);
+                }
+            }
+        } else {
+            let codec_id = match (self.stream_types.get(&stream_id), stream_id) {
+                (Some(&stream_type), _) => codec_from_stream_type(stream_type),
+                (None, 0xC0..=0xDF) => CodecId::Mp2,
+                (None, 0xE0..=0xEF) if self.mpeg1 => CodecId::Mpeg1Video,
+                (None, 0xE0..=0xEF) => CodecId::Mpeg2Video,
+                (None, _) => CodecId::Unknown,
+            };
+            (stream_id as u16, codec_id, 0)
+        };
+        let payload_start = header_len + substream_len;
+        // PES packets without payload are dropped
+        if payload_start >= len {
+            self.iobuf_reader.skip(len)?;
+            return Ok(None);
+        }
+        let index = self.stream_index(id, codec_id, &header[header_len..]);
+
+        let dts = if dts == NOPTS_VALUE { pts } else { dts };
+        let mut packet = Packet2 { stream_id: index, pts, dts, pos: pos as i64, ..Default::default() };
+        self.iobuf_reader.skip(payload_start)?;
+        self.iobuf_reader.get_ioref(&mut packet.ioref, len - payload_start)?;
+        let codec_id = self.streams[index].codec_id;
+        if codec_id == CodecId::DvdSubtitle {
+            return Ok(self.add_subpicture(packet));
+        }
+        packet.keyframe = is_keyframe(codec_id, packet.data());
+        Ok(Some(packet))
+    }
+
+    /// Appends a PES payload to the subpicture unit of its stream, returns the unit once complete.
+    /// Units start with their size, and their first PES packet has a timestamp.
+    fn add_subpicture(&mut self, packet: Packet2) -> Option<Packet2> {
+        let unit = self.subpictures.entry(packet.stream_id).or_default();
+        if unit.data.is_empty() || packet.pts != NOPTS_VALUE {
+            *unit = Subpicture { pts: packet.pts, pos: packet.pos, data: Vec::new() };
+        }
+        unit.data.extend_from_slice(packet.data());
+        let size = unit.data.get(..2).map_or(usize::MAX, |size| u16::from_be_bytes([size[0], size[1]]) as usize);
+        if unit.data.len() < size {
+            return None;
+        }
+        let unit = std::mem::take(unit);
+        Some(Packet2 {
+            ioref: IoRef::from(unit.data),
+            stream_id: packet.stream_id,
+            pts: unit.pts,
+            dts: unit.pts,
+            keyframe: true,
+            pos: unit.pos,
+            ..Default::default()
+        })
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerPs<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        if let Some(next) = self.pending.pop_front() {
+            *packet = next;
+            return Ok(());
+        }
+        loop {
+            match self.read_unit() {
+                Ok(Some(next)) => {
+                    *packet = next;
+                    return Ok(());
+                }
+                Ok(None) => {}
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return end_of_stream_error(),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream};
+
+    const PACK: [u8; 14] = [0, 0, 1, 0xBA, 0x44, 0x00, 0x04, 0x00, 0x04, 0x01, 0x01, 0x89, 0xC3, 0xF8];
+
+    fn pes(stream_id: u8, pts: Option<i64>, payload: &[u8]) -> Vec<u8> {
+        let mut header = vec![0x81, if pts.is_some() { 0x80 } else { 0 }, if pts.is_some() { 5 } else { 0 }];
+        if let Some(pts) = pts {
+            header.extend_from_slice(&[
+                0x21 | ((pts >> 29) & 0x0E) as u8,
+                (pts >> 22) as u8,
+                ((pts >> 14) & 0xFE) as u8 | 1,
+                (pts >> 7) as u8,
+                ((pts << 1) & 0xFE) as u8 | 1,
+            ]);
+        }
+        let len = ((header.len() + payload.len()) as u16).to_be_bytes();
+        [&[0, 0, 1, stream_id, len[0], len[1]], &header[..], payload].concat()
+    }
+
+    #[test]
+    fn dvd_substreams_and_resync() {
+        let system_header = [0, 0, 1, 0xBB, 0, 6, 0x80, 0x01, 0x01, 0x04, 0xE1, 0xFF];
+        let psm = [0, 0, 1, 0xBC, 0, 14, 0x80, 0x01, 0, 0, 0, 4, 0x1B, 0xE0, 0, 0, 0xAA, 0xBB, 0xCC, 0xDD];
+        let video = [0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x65, 0x88];
+        let ac3 = [0x0B, 0x77, 0x12, 0x34];
+        let lpcm = [0x01, 0x02, 0x03, 0x04];
+        let spu = [0x00, 0x0A, 1, 2, 3, 4, 5, 6, 7, 8];
+        let data = [
+            &PACK[..], &system_header, &psm,
+            &pes(0xE0, Some(0), &[]),
+            &pes(PRIVATE_STREAM_1, Some(0), &[0x80, 1, 0, 1]),
+            &pes(0xE0, Some(3600), &video),
+            &pes(PRIVATE_STREAM_1, Some(3600), &[&[0x80, 1, 0, 1][..], &ac3].concat()),
+            // a corrupted pack header
+            &[0, 0, 1, 0xBA, 0x00, 0x11, 0x22, 0x33],
+            &PACK,
+            &pes(PRIVATE_STREAM_1, Some(3600), &[&[0xA0, 1, 0, 4, 0x00, 0x01, 0x80][..], &lpcm].concat()),
+            &pes(PRIVATE_STREAM_1, Some(7200), &[&[0x20][..], &spu[..4]].concat()),
+            &pes(0xE0, None, &[0, 0, 1, 0x41, 0x9A]),
+            &pes(PRIVATE_STREAM_1, None, &[&[0x20][..], &spu[4..]].concat()),
+            &[0, 0, 1, 0xB9],
+        ].concat();
+
+        let mut demuxer = DemuxerPs::new(MediaSourceStream::new(IoBufSupplierMem::new(data, 4096))).unwrap();
+        let streams: Vec<_> = demuxer.streams().iter()
+            .map(|stream| (stream.id, demuxer.pes_id(stream.id).unwrap(), stream.codec_id))
+            .collect();
+        assert_eq!(streams, [
+            (0, 0xE0, CodecId::H264),
+            (1, 0xBD80, CodecId::Ac3),
+            (2, 0xBDA0, CodecId::PcmS16Be),
+            (3, 0xBD20, CodecId::DvdSubtitle),
+        ]);
+        let lpcm_stream = &demuxer.streams()[2];
+        assert_eq!((lpcm_stream.sample_rate, lpcm_stream.channels, lpcm_stream.bits_per_sample), (48000, 2, 16));
+        assert_eq!(demuxer.streams()[0].time_base, TimeBase::new(1, 90000));
+
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            packets.push((packet.stream_id, packet.pts, packet.keyframe, packet.data().to_vec()));
+        }
+        assert_eq!(packets, vec![
+            (0, 3600, true, video.to_vec()),
+            (1, 3600, true, ac3.to_vec()),
+            (2, 3600, true, lpcm.to_vec()),
+            (0, NOPTS_VALUE, false, vec![0, 0, 1, 0x41, 0x9A]),
+            (3, 7200, true, spu.to_vec()),
+        ]);
+        assert_eq!(demuxer.resyncs(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::codec::CodecId;
+use crate::error::{decode_error, Result};
+use crate::metadata::SimpleTag;
+
+use super::MediaIoBufRead;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_MPEG: u16 = 0x0050;
+const WAVE_FORMAT_MPEGLAYER3: u16 = 0x0055;
+const WAVE_FORMAT_AAC: u16 = 0x00FF;
+const WAVE_FORMAT_MPEG_ADTS_AAC: u16 = 0x1600;
+const WAVE_FORMAT_MPEG_RAW_AAC: u16 = 0x1601;
+const WAVE_FORMAT_DOLBY_AC3: u16 = 0x2000;
+const WAVE_FORMAT_DTS: u16 = 0x2001;
+const WAVE_FORMAT_FLAC: u16 = 0xF1AC;
+pub(super) const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+pub(super) fn le_u16(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+pub(super) fn le_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+pub(super) fn le_u64(data: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap())
+}
+
+/// Reads the header of a chunk, returns its ID and size. The chunk data is padded to an even size.
+pub(super) fn read_chunk_header<R: MediaIoBufRead + ?Sized>(reader: &mut R) -> Result<([u8; 4], u32)> {
+    let mut header = [0u8; 8];
+    reader.get_bytes(&mut header)?;
+    Ok((header[..4].try_into().unwrap(), le_u32(&header, 4)))
+}
+
+/// Iterates the chunks of a list held in memory, a truncated last chunk is clipped.
+pub(super) fn chunks(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        if data.len() - pos < 8 {
+            return None;
+        }
+        let id = data[pos..pos + 4].try_into().unwrap();
+        let size = le_u32(data, pos + 4) as usize;
+        let start = pos + 8;
+        let end = start + size.min(data.len() - start);
+        pos = (end + (size & 1)).min(data.len());
+        Some((id, &data[start..end]))
+    })
+}
+
+/// Parses the chunks of a `LIST INFO` into tags named after the chunk IDs, e.g. `INAM` or `IART`.
+pub(super) fn parse_info_list(data: &[u8]) -> Vec<SimpleTag> {
+    chunks(data)
+        .map(|(id, value)| {
+            let value = value.split(|&byte| byte == 0).next().unwrap_or_default();
+            SimpleTag {
+                name: String::from_utf8_lossy(&id).into_owned(),
+                value: Some(String::from_utf8_lossy(value).into_owned()),
+                default: true,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// A WAVEFORMATEX structure, including the WAVE_FORMAT_EXTENSIBLE fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct WaveFormat {
+    /// Format tag, the sub format of WAVE_FORMAT_EXTENSIBLE.
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub avg_bytes_per_sec: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    /// Valid bits of the samples, from WAVE_FORMAT_EXTENSIBLE.
+    pub valid_bits: u16,
+    /// Speaker positions of the channels, 0 if not given.
+    pub channel_mask: u32,
+    /// Codec specific data following the structure.
+    pub extra: Vec<u8>,
+}
+
+impl WaveFormat {
+    /// Parses a WAVEFORMAT, PCMWAVEFORMAT, WAVEFORMATEX or WAVEFORMATEXTENSIBLE structure.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 14 {
+            return decode_error("riff: wave format too short");
+        }
+        let mut format = WaveFormat {
+            format_tag: le_u16(data, 0),
+            channels: le_u16(data, 2),
+            sample_rate: le_u32(data, 4),
+            avg_bytes_per_sec: le_u32(data, 8),
+            block_align: le_u16(data, 12),
+            ..Default::default()
+        };
+        if data.len() >= 16 {
+            format.bits_per_sample = le_u16(data, 14);
+        }
+        format.valid_bits = format.bits_per_sample;
+        if data.len() >= 18 {
+            let extra = &data[18..];
+            let extra = &extra[..(le_u16(data, 16) as usize).min(extra.len())];
+            if format.format_tag == WAVE_FORMAT_EXTENSIBLE && extra.len() >= 22 {
+                if le_u16(extra, 0) != 0 {
+                    format.valid_bits = le_u16(extra, 0);
+                }
+                format.channel_mask = le_u32(extra, 2);
+                // the sub format GUID starts with the format tag
+                format.format_tag = le_u16(extra, 6);
+                format.extra = extra[22..].to_vec();
+            } else {
+                format.extra = extra.to_vec();
+            }
+        }
+        Ok(format)
+    }
+
+    pub fn codec_id(&self) -> CodecId {
+        match (self.format_tag, self.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => CodecId::PcmU8,
+            (WAVE_FORMAT_PCM, 16) => CodecId::PcmS16Le,
+            (WAVE_FORMAT_PCM, 24) => CodecId::PcmS24Le,
+            (WAVE_FORMAT_PCM, 32) => CodecId::PcmS32Le,
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => CodecId::PcmF32Le,
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => CodecId::PcmF64Le,
+            (WAVE_FORMAT_MPEG, _) => CodecId::Mp2,
+            (WAVE_FORMAT_MPEGLAYER3, _) => CodecId::Mp3,
+            (WAVE_FORMAT_AAC | WAVE_FORMAT_MPEG_ADTS_AAC | WAVE_FORMAT_MPEG_RAW_AAC, _) => CodecId::Aac,
+            (WAVE_FORMAT_DOLBY_AC3, _) => CodecId::Ac3,
+            (WAVE_FORMAT_DTS, _) => CodecId::Dts,
+            (WAVE_FORMAT_FLAC, _) => CodecId::Flac,
+            _ => CodecId::Unknown,
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+use crate::data::{IoBuf, Packet2, NOPTS_VALUE};
+use crate::error::{decode_error, limit_error, unsupported_error, Result};
+use crate::metadata::{Attachment, Edition, Tag};
+
+use super::{Demux, Stream, TimeBase};
+
+/// Largest manifest, segment or initialization section.
+const MAX_RESOURCE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// A byte range of a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Media initialization section, e.g. the `ftyp` and `moov` boxes of fragmented MP4 segments. `EXT-X-MAP` in HLS
+/// and `Initialization` in DASH.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitSection {
+    pub uri: String,
+    pub byte_range: Option<ByteRange>,
+}
+
+/// Resolves a URI relative to the URI of the manifest referencing it, both can be local paths or `http://` URLs.
+pub(super) fn resolve_uri(base: &str, uri: &str) -> String {
+    if uri.contains("://") {
+        return uri.to_string();
+    }
+    let base = base.split(['?', '#']).next().unwrap_or(base);
+    if uri.starts_with('/') {
+        // keep the scheme and authority of URLs
+        return match base.find("://").map(|pos| pos + 3) {
+            Some(authority) => {
+                let path = base[authority..].find('/').map_or(base.len(), |pos| authority + pos);
+                format!("{}{}", &base[..path], uri)
+            }
+            None => uri.to_string(),
+        };
+    }
+    match base.rfind('/') {
+        Some(pos) => format!("{}{}", &base[..=pos], uri),
+        None => uri.to_string(),
+    }
+}
+
+/// Removes the chunked transfer coding of an HTTP response body.
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    loop {
+        let Some(line_end) = body.windows(2).position(|w| w == b"\r\n") else {
+            return decode_error("http: truncated chunked body");
+        };
+        let size = std::str::from_utf8(&body[..line_end]).ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok());
+        let Some(size) = size else {
+            return decode_error("http: invalid chunk size");
+        };
+        if size == 0 {
+            return Ok(data);
+        }
+        let Some(chunk) = body.get(line_end + 2..line_end + 2 + size) else {
+            return decode_error("http: truncated chunked body");
+        };
+        data.extend_from_slice(chunk);
+        body = body.get(line_end + 4 + size..).unwrap_or(&[]);
+    }
+}
+
+/// Minimal HTTP/1.1 GET, enough for a local server, `url` is without the `http://` prefix.
+fn http_get(url: &str, range: Option<ByteRange>) -> Result<Vec<u8>> {
+    let (authority, path) = url.split_at(url.find('/').unwrap_or(url.len()));
+    let path = if path.is_empty() { "/" } else { path };
+    let address = if authority.contains(':') { authority.to_string() } else { format!("{authority}:80") };
+    let mut stream = TcpStream::connect(address)?;
+    let mut request = format!("GET {path} HTTP/1.1\r\nHost: {authority}\r\nConnection: close\r\n");
+    if let Some(range) = range.filter(|range| range.len > 0) {
+        request += &format!("Range: bytes={}-{}\r\n", range.offset, range.offset + range.len - 1);
+    }
+    request += "\r\n";
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = Vec::new();
+    stream.take(MAX_RESOURCE_SIZE + 64 * 1024).read_to_end(&mut response)?;
+    let Some(header_end) = response.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return decode_error("http: invalid response");
+    };
+    let header = String::from_utf8_lossy(&response[..header_end]).to_ascii_lowercase();
+    let mut lines = header.lines();
+    let status: Option<u32> = lines.next().and_then(|line| line.split_whitespace().nth(1)?.parse().ok());
+    let mut body = &response[header_end + 4..];
+    let mut chunked = false;
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        match (name.trim(), value.trim()) {
+            ("content-length", value) => {
+                let len = value.parse().unwrap_or(body.len());
+                body = &body[..len.min(body.len())];
+            }
+            ("transfer-encoding", value) => chunked = value.contains("chunked"),
+            _ => {}
+        }
+    }
+    let body = if chunked { dechunk(body)? } else { body.to_vec() };
+
+    match (status, range) {
+        (Some(206), _) | (Some(200), None) => Ok(body),
+        // the server ignored the range request
+        (Some(200), Some(range)) => {
+            let start = range.offset as usize;
+            match body.get(start..start + range.len as usize) {
+                Some(data) => Ok(data.to_vec()),
+                None => decode_error("http: byte range beyond resource"),
+            }
+        }
+        _ => decode_error("http: request failed"),
+    }
+}
+
+/// Reads a resource, or a byte range of it, from a local path or an `http://` URL.
+pub(super) fn fetch(uri: &str, range: Option<ByteRange>) -> Result<Vec<u8>> {
+    if range.is_some_and(|range| range.len > MAX_RESOURCE_SIZE) {
+        return limit_error("segmented: resource too large");
+    }
+    if let Some(url) = uri.strip_prefix("http://") {
+        return http_get(url, range);
+    }
+    if uri.contains("://") {
+        return unsupported_error("segmented: uri scheme");
+    }
+
+    let mut file = File::open(uri)?;
+    let mut data = Vec::new();
+    match range {
+        Some(range) => {
+            file.seek(SeekFrom::Start(range.offset))?;
+            data.resize(range.len as usize, 0);
+            file.read_exact(&mut data)?;
+        }
+        None => {
+            if file.metadata()?.len() > MAX_RESOURCE_SIZE {
+                return limit_error("segmented: resource too large");
+            }
+            file.read_to_end(&mut data)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Where a segment starts in the supplied byte stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct SegmentStart {
+    pub(super) pos: usize,
+    pub(super) discontinuity_sequence: u64,
+    /// Start time in seconds on the timeline of the manifest.
+    pub(super) time: f64,
+}
+
+/// Positions of the segments supplied by a segmented input supplier, shared with [`DemuxerSegmented`] to map
+/// packet positions back to segments.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentTimeline(Arc<Mutex<Vec<SegmentStart>>>);
+
+impl SegmentTimeline {
+    fn push(&self, segment: SegmentStart) {
+        self.0.lock().unwrap().push(segment);
+    }
+
+    /// The segment holding the byte at `pos`.
+    pub(super) fn segment_at(&self, pos: usize) -> Option<SegmentStart> {
+        let segments = self.0.lock().unwrap();
+        let index = segments.partition_point(|segment| segment.pos <= pos);
+        index.checked_sub(1).map(|index| segments[index])
+    }
+}
+
+/// Concatenates segments into one byte stream and records where they start.
+#[derive(Debug, Default)]
+pub(super) struct SegmentConcat {
+    /// The last supplied initialization section.
+    init: Option<InitSection>,
+    pos: usize,
+    timeline: SegmentTimeline,
+}
+
+impl SegmentConcat {
+    pub(super) fn timeline(&self) -> SegmentTimeline {
+        self.timeline.clone()
+    }
+
+    /// Reads a segment, preceded by its initialization section when it differs from the previous one.
+    pub(super) fn read(&mut self, init: Option<&InitSection>, uri: &str, byte_range: Option<ByteRange>,
+        discontinuity_sequence: u64, time: f64) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        if init != self.init.as_ref() {
+            if let Some(init) = init {
+                data = fetch(&init.uri, init.byte_range)?;
+            }
+            self.init = init.cloned();
+        }
+        data.extend(fetch(uri, byte_range)?);
+        self.timeline.push(SegmentStart { pos: self.pos, discontinuity_sequence, time });
+        self.pos += data.len();
+        Ok(data)
+    }
+}
+
+/// Supplies one segment per IoBuf from `next_segment` until `len_bytes` are supplied. Empty segments are skipped,
+/// an error after the first segment is returned by the next call.
+pub(super) fn supply_segments(len_bytes: usize, new_iobufs: &mut [IoBuf],
+    mut next_segment: impl FnMut() -> Result<Vec<u8>>) -> Result<usize> {
+    let mut count = 0;
+    let mut len = 0;
+    while count < new_iobufs.len() && (count == 0 || len < len_bytes) {
+        let data = match next_segment() {
+            Ok(data) if data.is_empty() => continue,
+            Ok(data) => data,
+            Err(_) if count > 0 => break,
+            Err(err) => return Err(err),
+        };
+        len += data.len();
+        new_iobufs[count] = IoBuf { len: data.len(), buf: data.into() };
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Timestamp state of a stream.
+#[derive(Debug, Clone, Copy, Default)]
+struct Continuity {
+    /// Discontinuity sequence number of the last packet.
+    discontinuity_sequence: Option<u64>,
+    /// Timestamp of the manifest timeline start, from the first packet.
+    origin: i64,
+    /// Added to the timestamps of the current discontinuity sequence.
+    offset: i64,
+}
+
+/// Wraps the demuxer reading the segments of an HLS or DASH supplier and keeps the timestamps continuous across
+/// HLS discontinuities and DASH periods. After a discontinuity, the timestamps of a stream continue from the
+/// manifest time of the segment.
+pub struct DemuxerSegmented<D: Demux> {
+    demuxer: D,
+    timeline: SegmentTimeline,
+    continuity: Vec<Continuity>,
+    /// Manifest timeline start in seconds on the timestamps of the first packet, with its discontinuity sequence.
+    /// Places the streams added after the first segment, such as the tracks of a new DASH period.
+    origin: Option<(f64, u64)>,
+}
+
+impl<D: Demux> DemuxerSegmented<D> {
+    pub fn new(demuxer: D, timeline: SegmentTimeline) -> Self {
+        Self { demuxer, timeline, continuity: Vec::new(), origin: None }
+    }
+
+    /// The wrapped demuxer.
+    pub fn inner(&self) -> &D {
+        &self.demuxer
+    }
+}
+
+/// Converts seconds into time base units.
+fn seconds_to_ts(seconds: f64, time_base: TimeBase) -> i64 {
+    (seconds * time_base.den as f64 / time_base.num as f64).round() as i64
+}
+
+impl<D: Demux> Demux for DemuxerSegmented<D> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        self.demuxer.read_packet(packet)?;
+        let segment = usize::try_from(packet.pos).ok().and_then(|pos| self.timeline.segment_at(pos));
+        let ts = if packet.dts != NOPTS_VALUE { packet.dts } else { packet.pts };
+        let (Some(segment), Some(stream)) = (segment, self.demuxer.streams().get(packet.stream_id)) else {
+            return Ok(());
+        };
+        if ts == NOPTS_VALUE {
+            return Ok(());
+        }
+
+        if self.continuity.len() <= packet.stream_id {
+            self.continuity.resize(packet.stream_id + 1, Continuity::default());
+        }
+        let continuity = &mut self.continuity[packet.stream_id];
+        let segment_ts = seconds_to_ts(segment.time, stream.time_base);
+        let time_base = stream.time_base;
+        match (continuity.discontinuity_sequence, self.origin) {
+            // a stream starting after a discontinuity, such as a track of a new DASH period, shares the origin
+            // of the first stream
+            (None, Some((seconds, sequence))) if sequence != segment.discontinuity_sequence => {
+                continuity.origin = seconds_to_ts(seconds, time_base);
+                continuity.offset = continuity.origin + segment_ts - ts;
+            }
+            (None, _) => {
+                continuity.origin = ts - segment_ts;
+                if self.origin.is_none() {
+                    let seconds = continuity.origin as f64 * time_base.num as f64 / time_base.den as f64;
+                    self.origin = Some((seconds, segment.discontinuity_sequence));
+                }
+            }
+            (Some(sequence), _) if sequence != segment.discontinuity_sequence => {
+                continuity.offset = continuity.origin + segment_ts - ts;
+            }
+            _ => {}
+        }
+        continuity.discontinuity_sequence = Some(segment.discontinuity_sequence);
+
+        if packet.pts != NOPTS_VALUE {
+            packet.pts += continuity.offset;
+        }
+        if packet.dts != NOPTS_VALUE {
+            packet.dts += continuity.offset;
+        }
+        Ok(())
+    }
+
+    fn streams(&self) -> &[Stream] {
+        self.demuxer.streams()
+    }
+
+    fn chapters(&self) -> &[Edition] {
+        self.demuxer.chapters()
+    }
+
+    fn tags(&self) -> &[Tag] {
+        self.demuxer.tags()
+    }
+
+    fn attachments(&self) -> &[Attachment] {
+        self.demuxer.attachments()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::end_of_stream_error;
+
+    /// Packets of two streams, whose timestamps restart after the discontinuity.
+    struct TestDemuxer {
+        streams: Vec<Stream>,
+        packets: Vec<(usize, i64, i64)>,
+    }
+
+    impl Demux for TestDemuxer {
+        fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+            if self.packets.is_empty() {
+                return end_of_stream_error();
+            }
+            let (stream_id, pts, pos) = self.packets.remove(0);
+            (packet.stream_id, packet.pts, packet.dts, packet.pos) = (stream_id, pts, pts, pos);
+            Ok(())
+        }
+
+        fn streams(&self) -> &[Stream] {
+            &self.streams
+        }
+    }
+
+    #[test]
+    fn timestamps_continue_across_discontinuity() {
+        let timeline = SegmentTimeline::default();
+        timeline.push(SegmentStart { pos: 0, discontinuity_sequence: 0, time: 0.0 });
+        timeline.push(SegmentStart { pos: 100, discontinuity_sequence: 0, time: 2.0 });
+        timeline.push(SegmentStart { pos: 200, discontinuity_sequence: 1, time: 4.0 });
+        let streams = vec![
+            Stream { time_base: TimeBase::new(1, 90000), ..Default::default() },
+            Stream { id: 1, time_base: TimeBase::new(1, 48000), ..Default::default() },
+        ];
+        let packets = vec![(0, 900_000, 10), (1, 480_000, 20), (0, 1_080_000, 110), (0, 0, 210), (1, 960, 220), (0, 3000, 230)];
+        let mut demuxer = DemuxerSegmented::new(TestDemuxer { streams, packets }, timeline);
+        let mut packet = Packet2::default();
+        let mut timestamps = Vec::new();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            timestamps.push((packet.stream_id, packet.pts, packet.dts));
+        }
+        assert_eq!(timestamps, vec![
+            (0, 900_000, 900_000),
+            (1, 480_000, 480_000),
+            (0, 1_080_000, 1_080_000),
+            (0, 1_260_000, 1_260_000),
+            (1, 672_000, 672_000),
+            (0, 1_263_000, 1_263_000),
+        ]);
+    }
+}
@@ -0,0 +1,418 @@
+use crate::codec::{CodecId, MediaType};
+use crate::data::{IoRef, Packet2};
+use crate::error::{
+    decode_error, end_of_stream_error, invalid_input_error, limit_error, unsupported_error, Error, Result,
+};
+use crate::metadata::Tag;
+
+use super::riff::{le_u64, parse_info_list, read_chunk_header, WaveFormat};
+use super::{Demux, MediaIoBufRead, Stream, TimeBase, PROBE_SCORE_MAX};
+
+mod aiff;
+
+use aiff::{be_u32, text_tag, Common};
+
+/// Sample frames in a packet.
+const PACKET_FRAMES: usize = 1024;
+
+/// Largest header chunk loaded in memory.
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Size of a RIFF chunk marking a 64 bit size in the `ds64` chunk, or a stream of unknown length.
+const SIZE_IN_DS64: u32 = u32::MAX;
+
+fn is_pcm(codec_id: CodecId) -> bool {
+    use CodecId::*;
+    matches!(codec_id, PcmS8 | PcmU8 | PcmS16Le | PcmS16Be | PcmS24Le | PcmS24Be | PcmS32Le | PcmS32Be
+        | PcmF32Le | PcmF32Be | PcmF64Le | PcmF64Be)
+}
+
+/// Padded size of a chunk.
+fn padded(size: u32) -> usize {
+    size as usize + (size & 1) as usize
+}
+
+/// Probe score of the first bytes of an input, a RIFF, RF64, BW64 or AIFF header.
+pub(super) fn probe(data: &[u8]) -> u32 {
+    match (data.get(..4), data.get(8..12)) {
+        (Some(b"RIFF" | b"RF64" | b"BW64"), Some(b"WAVE")) => PROBE_SCORE_MAX,
+        (Some(b"FORM"), Some(b"AIFF" | b"AIFC")) => PROBE_SCORE_MAX,
+        _ => 0,
+    }
+}
+
+/// Demuxer for uncompressed audio in WAV, RF64, BW64, AIFF and AIFF-C files. Packets hold a fixed
+/// number of sample frames and reference the input data without copying. Seeking computes the
+/// position of the sample frame directly.
+pub struct DemuxerWav<S: MediaIoBufRead> {
+    iobuf_reader: S,
+    streams: Vec<Stream>,
+    tags: Vec<Tag>,
+    /// Speaker positions of WAVE_FORMAT_EXTENSIBLE, 0 if not given.
+    channel_mask: u32,
+    /// Size of a sample frame in bytes.
+    block_align: usize,
+    /// Position of the first sample frame.
+    data_start: usize,
+    /// Position following the last sample frame, `None` if unknown when streaming.
+    data_end: Option<usize>,
+}
+
+impl<S: MediaIoBufRead> DemuxerWav<S> {
+    /// Creates the demuxer, reading the headers up to the sample data.
+    pub fn new(iobuf_reader: S) -> Result<Self> {
+        let mut demuxer = Self {
+            iobuf_reader,
+            streams: Vec::new(),
+            tags: Vec::new(),
+            channel_mask: 0,
+            block_align: 0,
+            data_start: 0,
+            data_end: None,
+        };
+        let mut header = [0u8; 12];
+        demuxer.iobuf_reader.get_bytes(&mut header)?;
+        match (&header[..4], &header[8..]) {
+            (b"RIFF" | b"RF64" | b"BW64", b"WAVE") => demuxer.read_wave()?,
+            (b"FORM", b"AIFF") => demuxer.read_aiff(false)?,
+            (b"FORM", b"AIFC") => demuxer.read_aiff(true)?,
+            _ => return decode_error("wav: unknown file type"),
+        }
+
+        let stream = &mut demuxer.streams[0];
+        if !is_pcm(stream.codec_id) {
+            return unsupported_error("wav: compressed audio");
+        }
+        if demuxer.block_align == 0 || stream.sample_rate == 0 {
+            return decode_error("wav: invalid audio format");
+        }
+        if let Some(len) = demuxer.iobuf_reader.stream_len() {
+            // truncated files end with the input
+            demuxer.data_end = Some(demuxer.data_end.map_or(len, |end| end.min(len)));
+        }
+        if let Some(end) = demuxer.data_end {
+            stream.duration = (end.saturating_sub(demuxer.data_start) / demuxer.block_align) as i64;
+        }
+        Ok(demuxer)
+    }
+
+    /// Speaker positions of the channels from WAVE_FORMAT_EXTENSIBLE, 0 if not given.
+    pub fn channel_mask(&self) -> u32 {
+        self.channel_mask
+    }
+
+    fn read_chunk(&mut self, size: u32) -> Result<Vec<u8>> {
+        if size > MAX_CHUNK_SIZE {
+            return limit_error("wav: chunk too large");
+        }
+        let mut data = vec![0u8; padded(size)];
+        self.iobuf_reader.get_bytes(&mut data)?;
+        data.truncate(size as usize);
+        Ok(data)
+    }
+
+    fn read_wave(&mut self) -> Result<()> {
+        let mut ds64_data_size = None;
+        let mut format = None;
+        let data_size = loop {
+            let (id, size) = match read_chunk_header(&mut self.iobuf_reader) {
+                Ok(header) => header,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return decode_error("wav: missing data chunk"),
+                Err(err) => return Err(err),
+            };
+            match &id {
+                b"ds64" => {
+                    let ds64 = self.read_chunk(size)?;
+                    if ds64.len() >= 16 {
+                        ds64_data_size = Some(le_u64(&ds64, 8) as usize);
+                    }
+                }
+                b"fmt " => format = Some(WaveFormat::parse(&self.read_chunk(size)?)?),
+                b"LIST" => {
+                    let list = self.read_chunk(size)?;
+                    if list.starts_with(b"INFO") {
+                        self.tags = vec![Tag { simple_tags: parse_info_list(&list[4..]), ..Default::default() }];
+                    }
+                }
+                b"data" if size == SIZE_IN_DS64 => break ds64_data_size,
+                b"data" => break Some(size as usize),
+                _ => self.iobuf_reader.skip(padded(size))?,
+            }
+        };
+        let Some(format) = format else {
+            return decode_error("wav: missing fmt chunk");
+        };
+
+        self.channel_mask = format.channel_mask;
+        self.block_align = format.block_align as usize;
+        self.data_start = self.iobuf_reader.pos();
+        self.data_end = data_size.map(|size| self.data_start + size);
+        self.streams.push(Stream {
+            codec_id: format.codec_id(),
+            media_type: MediaType::Audio,
+            time_base: TimeBase::new(1, format.sample_rate),
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+            bits_per_sample: format.valid_bits,
+            codec_params: format.extra,
+            ..Default::default()
+        });
+
+        // the INFO list often follows the sample data
+        if let (Some(end), Some(len), true) = (self.data_end, self.iobuf_reader.stream_len(), self.tags.is_empty()) {
+            let next = end + (end - self.data_start) % 2;
+            if next < len {
+                self.iobuf_reader.seek(next)?;
+                self.read_trailing_info();
+                self.iobuf_reader.seek(self.data_start)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks for a `LIST INFO` after the sample data, damaged trailing chunks are ignored.
+    fn read_trailing_info(&mut self) {
+        while let Ok((id, size)) = read_chunk_header(&mut self.iobuf_reader) {
+            if &id != b"LIST" {
+                if self.iobuf_reader.skip(padded(size)).is_err() {
+                    return;
+                }
+                continue;
+            }
+            let Ok(list) = self.read_chunk(size) else {
+                return;
+            };
+            if list.starts_with(b"INFO") {
+                self.tags = vec![Tag { simple_tags: parse_info_list(&list[4..]), ..Default::default() }];
+                return;
+            }
+        }
+    }
+
+    fn read_aiff(&mut self, aifc: bool) -> Result<()> {
+        let mut common = None;
+        let mut simple_tags = Vec::new();
+        loop {
+            let mut header = [0u8; 8];
+            match self.iobuf_reader.get_bytes(&mut header) {
+                Ok(()) => {}
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => return decode_error("aiff: missing SSND chunk"),
+                Err(err) => return Err(err),
+            }
+            let id: [u8; 4] = header[..4].try_into().unwrap();
+            let size = be_u32(&header, 4);
+            match &id {
+                b"COMM" => common = Some(Common::parse(&self.read_chunk(size)?, aifc)?),
+                b"SSND" if size >= 8 => {
+                    let mut offsets = [0u8; 8];
+                    self.iobuf_reader.get_bytes(&mut offsets)?;
+                    // the offset skips the padding used to align the sample frames to blocks
+                    let offset = be_u32(&offsets, 0);
+                    self.iobuf_reader.skip(offset as usize)?;
+                    self.data_start = self.iobuf_reader.pos();
+                    self.data_end = Some(self.data_start + (size - 8).saturating_sub(offset) as usize);
+                    break;
+                }
+                b"NAME" | b"AUTH" | b"(c) " | b"ANNO" => {
+                    let data = self.read_chunk(size)?;
+                    simple_tags.push(text_tag(&id, &data));
+                }
+                _ => self.iobuf_reader.skip(padded(size))?,
+            }
+        }
+        let Some(common) = common else {
+            return decode_error("aiff: missing COMM chunk");
+        };
+
+        self.block_align = common.block_align();
+        if let Some(end) = self.data_end.as_mut() {
+            // the frame count excludes the padding of the last block
+            *end = (*end).min(self.data_start + common.frames as usize * self.block_align);
+        }
+        if !simple_tags.is_empty() {
+            self.tags = vec![Tag { simple_tags, ..Default::default() }];
+        }
+        self.streams.push(Stream {
+            codec_id: common.codec_id,
+            media_type: MediaType::Audio,
+            time_base: TimeBase::new(1, common.sample_rate),
+            sample_rate: common.sample_rate,
+            channels: common.channels,
+            bits_per_sample: common.bits_per_sample,
+            ..Default::default()
+        });
+        Ok(())
+    }
+}
+
+impl<S: MediaIoBufRead> Demux for DemuxerWav<S> {
+    fn read_packet(&mut self, packet: &mut Packet2) -> Result<()> {
+        packet.ioref = IoRef::default();
+        let pos = self.iobuf_reader.pos();
+        let remaining = self.data_end.map_or(usize::MAX, |end| end.saturating_sub(pos) / self.block_align);
+        let mut frames = PACKET_FRAMES.min(remaining);
+        loop {
+            if frames == 0 {
+                return end_of_stream_error();
+            }
+            // the length is unknown when streaming, the last packet is shortened to the available frames
+            match self.iobuf_reader.get_ioref(&mut packet.ioref, frames * self.block_align) {
+                Ok(()) => break,
+                Err(Error::IoError(std::io::ErrorKind::UnexpectedEof)) => frames /= 2,
+                Err(err) => return Err(err),
+            }
+        }
+        packet.stream_id = 0;
+        packet.pts = ((pos - self.data_start) / self.block_align) as i64;
+        packet.dts = packet.pts;
+        packet.duration = frames as i64;
+        packet.keyframe = true;
+        packet.pos = pos as i64;
+        Ok(())
+    }
+
+    fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    fn seek(&mut self, stream_id: usize, timestamp: i64) -> Result<i64> {
+        if stream_id != 0 {
+            return invalid_input_error();
+        }
+        let mut frame = timestamp.max(0);
+        if let Some(end) = self.data_end {
+            frame = frame.min((end.saturating_sub(self.data_start) / self.block_align) as i64);
+        }
+        // the frame is not bounded when the data size is unknown
+        let pos = usize::try_from(frame).ok()
+            .and_then(|frame| frame.checked_mul(self.block_align))
+            .and_then(|offset| offset.checked_add(self.data_start));
+        let Some(pos) = pos else {
+            return invalid_input_error();
+        };
+        self.iobuf_reader.seek(pos)?;
+        Ok(frame)
+    }
+
+    fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::riff::WAVE_FORMAT_EXTENSIBLE;
+    use crate::format::{IoBufSupplierMem, MediaSourceStream, StreamingSupplier};
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let padding = vec![0; data.len() & 1];
+        [&id[..], &(data.len() as u32).to_le_bytes(), data, &padding].concat()
+    }
+
+    fn be_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let padding = vec![0; data.len() & 1];
+        [&id[..], &(data.len() as u32).to_be_bytes(), data, &padding].concat()
+    }
+
+    fn samples(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7) as u8).collect()
+    }
+
+    type PacketInfo = (i64, i64, usize, i64);
+
+    fn read_all<S: MediaIoBufRead>(demuxer: &mut DemuxerWav<S>, data: &[u8]) -> Vec<PacketInfo> {
+        let mut packets = Vec::new();
+        let mut packet = Packet2::default();
+        while demuxer.read_packet(&mut packet).is_ok() {
+            let pos = packet.pos as usize;
+            assert_eq!(packet.data(), &data[pos..pos + packet.data().len()]);
+            packets.push((packet.pts, packet.duration, packet.data().len(), packet.pos));
+        }
+        packets
+    }
+
+    #[test]
+    fn extensible_with_trailing_info() {
+        // 24 bit stereo in WAVE_FORMAT_EXTENSIBLE, 20 valid bits
+        let mut format = [WAVE_FORMAT_EXTENSIBLE.to_le_bytes(), 2u16.to_le_bytes()].concat();
+        format.extend_from_slice(&48000u32.to_le_bytes());
+        format.extend_from_slice(&(48000u32 * 6).to_le_bytes());
+        format.extend_from_slice(&[6, 0, 24, 0, 22, 0, 20, 0]);
+        format.extend_from_slice(&3u32.to_le_bytes());
+        format.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71]);
+        let info = [&b"INFO"[..], &chunk(b"INAM", b"Title\0")].concat();
+        let pcm = samples(1500 * 6 + 3);
+        let data = [&b"RIFF\0\0\0\0WAVE"[..], &chunk(b"fmt ", &format), &chunk(b"data", &pcm), &chunk(b"LIST", &info)].concat();
+        let data_pos = 12 + 8 + format.len() + 8;
+
+        let mut demuxer = DemuxerWav::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 4096))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.sample_rate, stream.channels), (CodecId::PcmS24Le, 48000, 2));
+        assert_eq!((stream.bits_per_sample, stream.duration, stream.time_base), (20, 1500, TimeBase::new(1, 48000)));
+        assert_eq!(demuxer.channel_mask(), 3);
+        assert_eq!(demuxer.tags()[0].simple_tags[0].value.as_deref(), Some("Title"));
+
+        // the incomplete last frame is dropped
+        assert_eq!(read_all(&mut demuxer, &data), vec![
+            (0, 1024, 1024 * 6, data_pos as i64),
+            (1024, 476, 476 * 6, (data_pos + 1024 * 6) as i64),
+        ]);
+        assert_eq!(demuxer.seek(0, 1200), Ok(1200));
+        assert_eq!(read_all(&mut demuxer, &data), vec![(1200, 300, 300 * 6, (data_pos + 1200 * 6) as i64)]);
+        assert_eq!(demuxer.seek(0, 5000), Ok(1500));
+        assert!(read_all(&mut demuxer, &data).is_empty());
+    }
+
+    #[test]
+    fn rf64_and_streamed_riff() {
+        let format = [&1u16.to_le_bytes()[..], &1u16.to_le_bytes(), &8000u32.to_le_bytes(), &16000u32.to_le_bytes(), &[2, 0, 16, 0]].concat();
+        let pcm = samples(2 * 1500);
+        let ds64 = [&0u64.to_le_bytes()[..], &(pcm.len() as u64).to_le_bytes(), &1500u64.to_le_bytes(), &[0; 4]].concat();
+        let data_header = [&b"data"[..], &u32::MAX.to_le_bytes()].concat();
+        let data = [&b"RF64\xFF\xFF\xFF\xFFWAVE"[..], &chunk(b"ds64", &ds64), &chunk(b"fmt ", &format), &data_header, &pcm, &[1, 2, 3]].concat();
+        let mut demuxer = DemuxerWav::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 4096))).unwrap();
+        assert_eq!((demuxer.streams()[0].codec_id, demuxer.streams()[0].duration), (CodecId::PcmS16Le, 1500));
+        let packets = read_all(&mut demuxer, &data);
+        assert_eq!(packets.iter().map(|packet| (packet.0, packet.1)).collect::<Vec<_>>(), vec![(0, 1024), (1024, 476)]);
+
+        // the size of a streamed RIFF isn't known, the frames are read up to the end of the input
+        let data = [&b"RIFF\xFF\xFF\xFF\xFFWAVE"[..], &chunk(b"fmt ", &format), &data_header, &pcm].concat();
+        let supplier = StreamingSupplier(IoBufSupplierMem::new(data.clone(), 64));
+        let mut demuxer = DemuxerWav::new(MediaSourceStream::new(supplier)).unwrap();
+        assert_eq!(demuxer.streams()[0].duration, 0);
+        let packets = read_all(&mut demuxer, &data);
+        assert_eq!(packets.iter().map(|packet| packet.1).sum::<i64>(), 1500);
+        assert_eq!(packets[0], (0, 1024, 2048, 44));
+        assert!(demuxer.seek(0, 0).is_err());
+        // the position of a large frame overflows as the data size doesn't bound it
+        assert_eq!(demuxer.seek(0, i64::MAX), Err(Error::InvalidInput));
+        assert_eq!(demuxer.seek(1, 0), Err(Error::InvalidInput));
+    }
+
+    #[test]
+    fn aiff_and_aifc() {
+        // 44100 Hz as an 80 bit extended float
+        let rate = [0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0];
+        let comm = [&2u16.to_be_bytes()[..], &100u32.to_be_bytes(), &16u16.to_be_bytes(), &rate].concat();
+        let pcm = samples(100 * 4);
+        let ssnd = [&4u32.to_be_bytes()[..], &0u32.to_be_bytes(), &[0; 4], &pcm].concat();
+        let data = [&b"FORM\0\0\0\0AIFF"[..], &be_chunk(b"COMM", &comm), &be_chunk(b"NAME", b"Song"), &be_chunk(b"SSND", &ssnd)].concat();
+        let mut demuxer = DemuxerWav::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 4096))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.sample_rate, stream.channels, stream.duration), (CodecId::PcmS16Be, 44100, 2, 100));
+        assert_eq!(demuxer.tags()[0].simple_tags[0].name, "NAME");
+        assert_eq!(demuxer.tags()[0].simple_tags[0].value.as_deref(), Some("Song"));
+        let data_pos = data.len() - pcm.len();
+        assert_eq!(read_all(&mut demuxer, &data), vec![(0, 100, 400, data_pos as i64)]);
+
+        let comm = [&comm[..], b"fl32", b"\x0532bit\0"].concat();
+        let comm = [&1u16.to_be_bytes()[..], &comm[2..]].concat();
+        let ssnd = [&0u32.to_be_bytes()[..], &0u32.to_be_bytes(), &pcm].concat();
+        let data = [&b"FORM\0\0\0\0AIFC"[..], &be_chunk(b"FVER", &[0xA2, 0x80, 0x51, 0x40]), &be_chunk(b"COMM", &comm), &be_chunk(b"SSND", &ssnd)].concat();
+        let mut demuxer = DemuxerWav::new(MediaSourceStream::new(IoBufSupplierMem::new(data.clone(), 4096))).unwrap();
+        let stream = &demuxer.streams()[0];
+        assert_eq!((stream.codec_id, stream.bits_per_sample, stream.channels), (CodecId::PcmF32Be, 32, 1));
+        assert_eq!(read_all(&mut demuxer, &data), vec![(0, 100, 400, (data.len() - pcm.len()) as i64)]);
+    }
+}
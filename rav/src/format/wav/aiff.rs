@@ -0,0 +1,83 @@
+use crate::codec::CodecId;
+use crate::error::{decode_error, unsupported_error, Result};
+use crate::metadata::SimpleTag;
+
+pub(super) fn be_u16(data: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([data[pos], data[pos + 1]])
+}
+
+pub(super) fn be_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+/// Converts an 80 bit IEEE 754 extended precision number, as used for the AIFF sample rate.
+fn extended_to_f64(data: &[u8]) -> f64 {
+    let exponent = (be_u16(data, 0) & 0x7FFF) as i32;
+    let mantissa = u64::from_be_bytes(data[2..10].try_into().unwrap());
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+}
+
+/// Contents of the `COMM` chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Common {
+    pub channels: u16,
+    pub frames: u32,
+    pub bits_per_sample: u16,
+    pub sample_rate: u32,
+    pub codec_id: CodecId,
+}
+
+impl Common {
+    /// Parses a `COMM` chunk, `aifc` is true for AIFF-C files which add a compression type.
+    pub fn parse(data: &[u8], aifc: bool) -> Result<Self> {
+        if data.len() < 18 || (aifc && data.len() < 22) {
+            return decode_error("aiff: COMM chunk too short");
+        }
+        let bits_per_sample = be_u16(data, 6);
+        let compression: [u8; 4] = if aifc { data[18..22].try_into().unwrap() } else { *b"NONE" };
+        let codec_id = match (&compression, bits_per_sample.div_ceil(8)) {
+            (b"NONE" | b"twos", 1) => CodecId::PcmS8,
+            (b"NONE" | b"twos", 2) => CodecId::PcmS16Be,
+            (b"NONE" | b"twos", 3) => CodecId::PcmS24Be,
+            (b"NONE" | b"twos", 4) => CodecId::PcmS32Be,
+            (b"sowt", 2) => CodecId::PcmS16Le,
+            (b"sowt", 3) => CodecId::PcmS24Le,
+            (b"sowt", 4) => CodecId::PcmS32Le,
+            (b"raw ", 1) => CodecId::PcmU8,
+            (b"fl32" | b"FL32", _) => CodecId::PcmF32Be,
+            (b"fl64" | b"FL64", _) => CodecId::PcmF64Be,
+            _ => return unsupported_error("aiff: compression type"),
+        };
+        let bits_per_sample = match codec_id {
+            CodecId::PcmF32Be => 32,
+            CodecId::PcmF64Be => 64,
+            _ => bits_per_sample,
+        };
+        Ok(Common {
+            channels: be_u16(data, 0),
+            frames: be_u32(data, 2),
+            bits_per_sample,
+            sample_rate: extended_to_f64(&data[8..18]).round() as u32,
+            codec_id,
+        })
+    }
+
+    /// Size of a sample frame in bytes, samples are padded to whole bytes.
+    pub fn block_align(&self) -> usize {
+        self.bits_per_sample.div_ceil(8) as usize * self.channels as usize
+    }
+}
+
+/// Converts a `NAME`, `AUTH`, `(c) ` or `ANNO` text chunk into a tag named after the chunk ID.
+pub(super) fn text_tag(id: &[u8; 4], data: &[u8]) -> SimpleTag {
+    let value = data.split(|&byte| byte == 0).next().unwrap_or_default();
+    SimpleTag {
+        name: String::from_utf8_lossy(id).trim_end().to_string(),
+        value: Some(String::from_utf8_lossy(value).into_owned()),
+        default: true,
+        ..Default::default()
+    }
+}
@@ -1,4 +1,7 @@
 pub mod error;
 pub mod data;
 pub mod io;
+pub mod ebml;
+pub mod codec;
 pub mod format;
+pub mod metadata;
@@ -0,0 +1,106 @@
+use crate::data::IoRef;
+
+/// A chapter display string in a given language.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChapterDisplay {
+    pub string: String,
+    pub language: Option<String>,
+    pub country: Option<String>,
+}
+
+/// A chapter, chapters can be nested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub uid: u64,
+    pub string_uid: Option<String>,
+    /// Start time in nanoseconds.
+    pub start: u64,
+    /// End time in nanoseconds.
+    pub end: Option<u64>,
+    pub hidden: bool,
+    pub enabled: bool,
+    pub displays: Vec<ChapterDisplay>,
+    pub children: Vec<Chapter>,
+}
+
+impl Default for Chapter {
+    fn default() -> Self {
+        Self {
+            uid: 0,
+            string_uid: None,
+            start: 0,
+            end: None,
+            hidden: false,
+            enabled: true,
+            displays: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+/// A set of chapters, only one edition is used at a time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Edition {
+    pub uid: u64,
+    pub hidden: bool,
+    pub default: bool,
+    pub ordered: bool,
+    pub chapters: Vec<Chapter>,
+}
+
+/// What a tag applies to, empty lists mean the whole input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagTarget {
+    /// Logical level of the target, e.g. 50 for an album or movie, 30 for a track or chapter.
+    pub type_value: u64,
+    pub target_type: Option<String>,
+    /// Streams the tag applies to, resolved from `track_uids`.
+    pub stream_ids: Vec<usize>,
+    pub track_uids: Vec<u64>,
+    pub edition_uids: Vec<u64>,
+    pub chapter_uids: Vec<u64>,
+    pub attachment_uids: Vec<u64>,
+}
+
+impl Default for TagTarget {
+    fn default() -> Self {
+        Self {
+            type_value: 50,
+            target_type: None,
+            stream_ids: Vec::new(),
+            track_uids: Vec::new(),
+            edition_uids: Vec::new(),
+            chapter_uids: Vec::new(),
+            attachment_uids: Vec::new(),
+        }
+    }
+}
+
+/// A name and value pair, values can have nested tags.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimpleTag {
+    pub name: String,
+    pub language: Option<String>,
+    pub default: bool,
+    pub value: Option<String>,
+    pub binary: Option<Vec<u8>>,
+    pub children: Vec<SimpleTag>,
+}
+
+/// Tags with the target they apply to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tag {
+    pub target: TagTarget,
+    pub simple_tags: Vec<SimpleTag>,
+}
+
+/// A file attached to the input, e.g. a font used by subtitles.
+#[derive(Debug, Default)]
+pub struct Attachment {
+    pub uid: u64,
+    pub filename: String,
+    pub mime_type: String,
+    pub description: Option<String>,
+    /// File content, owned by the attachment so it doesn't hold the input IoBufs.
+    pub data: IoRef,
+}